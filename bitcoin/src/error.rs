@@ -48,6 +48,10 @@ pub enum Error {
     ConnectionRefused,
     #[error("Wallet not found")]
     WalletNotFound,
+    #[error("Wallet has no funds")]
+    EmptyWallet,
+    #[error("Transaction not broadcast in dry-run mode")]
+    DryRun,
     #[error("Invalid Bitcoin network")]
     InvalidBitcoinNetwork,
 }
@@ -195,6 +195,7 @@ mod tests {
             async fn get_block_hash(&self, height: u32) -> Result<BlockHash, Error>;
             async fn is_block_known(&self, block_hash: BlockHash) -> Result<bool, Error>;
             async fn get_new_address<A: PartialAddress + Send + 'static>(&self) -> Result<A, Error>;
+            async fn get_balance(&self, min_confirmations: Option<u32>) -> Result<Amount, Error>;
             async fn get_new_public_key<P: From<[u8; PUBLIC_KEY_SIZE]> + 'static>(&self) -> Result<P, Error>;
             async fn add_new_deposit_key<P: Into<[u8; PUBLIC_KEY_SIZE]> + Send + Sync + 'static>(
                 &self,
@@ -208,6 +209,8 @@ mod tests {
             async fn get_mempool_transactions<'a>(
                 &'a self,
             ) -> Result<Box<dyn Iterator<Item = Result<Transaction, Error>> + Send + 'a>, Error>;
+            async fn get_mempool_txids(&self) -> Result<Vec<Txid>, Error>;
+            async fn get_mempool_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, Error>;
             async fn wait_for_transaction_metadata(
                 &self,
                 txid: Txid,
@@ -233,6 +236,8 @@ mod tests {
                 request_id: Option<H256>,
                 num_confirmations: u32,
             ) -> Result<TransactionMetadata, Error>;
+            async fn sweep_funds<A: PartialAddress + Send + Sync + 'static>(&self, address: A) -> Result<Txid, Error>;
+            async fn find_sent_transaction<A: PartialAddress + Send + Sync + 'static>(&self, address: A, op_return: H256) -> Result<Option<Txid>, Error>;
             async fn create_or_load_wallet(&self) -> Result<(), Error>;
             async fn wallet_has_public_key<P>(&self, public_key: P) -> Result<bool, Error>
                 where
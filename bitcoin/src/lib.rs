@@ -31,7 +31,7 @@ pub use iter::{reverse_stream_transactions, stream_blocks, stream_in_chain_trans
 use log::{info, trace};
 use serde_json::error::Category as SerdeJsonCategory;
 use sp_core::H256;
-use std::{future::Future, io::ErrorKind as IoErrorKind, sync::Arc, time::Duration};
+use std::{future::Future, io::ErrorKind as IoErrorKind, str::FromStr, sync::Arc, time::Duration};
 use tokio::{
     sync::{Mutex, OwnedMutexGuard},
     time::{delay_for, timeout},
@@ -46,6 +46,9 @@ const NOT_IN_MEMPOOL_ERROR_CODE: i32 = BitcoinRpcError::RpcInvalidAddressOrKey a
 
 const RETRY_DURATION: Duration = Duration::from_millis(1000);
 
+/// Number of wallet transactions that are requested at a time when searching the wallet.
+const LIST_TRANSACTIONS_PAGE_SIZE: usize = 100;
+
 #[derive(Debug, Clone)]
 pub struct TransactionMetadata {
     pub txid: Txid,
@@ -57,6 +60,12 @@ pub struct TransactionMetadata {
 
 #[async_trait]
 pub trait BitcoinCoreApi {
+    /// Returns true if nothing is written to the wallet or broadcast, in which case the calls
+    /// that would do so fail with `Error::DryRun`.
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+
     async fn wait_for_block(&self, height: u32, num_confirmations: u32) -> Result<Block, Error>;
 
     async fn get_block_count(&self) -> Result<u64, Error>;
@@ -71,6 +80,8 @@ pub trait BitcoinCoreApi {
 
     async fn get_new_address<A: PartialAddress + Send + 'static>(&self) -> Result<A, Error>;
 
+    async fn get_balance(&self, min_confirmations: Option<u32>) -> Result<Amount, Error>;
+
     async fn get_new_public_key<P: From<[u8; PUBLIC_KEY_SIZE]> + 'static>(&self) -> Result<P, Error>;
 
     async fn add_new_deposit_key<P: Into<[u8; PUBLIC_KEY_SIZE]> + Send + Sync + 'static>(
@@ -91,6 +102,10 @@ pub trait BitcoinCoreApi {
         &'a self,
     ) -> Result<Box<dyn Iterator<Item = Result<Transaction, Error>> + Send + 'a>, Error>;
 
+    async fn get_mempool_txids(&self) -> Result<Vec<Txid>, Error>;
+
+    async fn get_mempool_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, Error>;
+
     async fn wait_for_transaction_metadata(
         &self,
         txid: Txid,
@@ -121,6 +136,16 @@ pub trait BitcoinCoreApi {
         num_confirmations: u32,
     ) -> Result<TransactionMetadata, Error>;
 
+    async fn sweep_funds<A: PartialAddress + Send + Sync + 'static>(&self, address: A) -> Result<Txid, Error>;
+
+    /// Returns a transaction of the wallet that pays to `address` and includes `op_return`,
+    /// and that may still be confirmed, i.e. does not conflict with the chain.
+    async fn find_sent_transaction<A: PartialAddress + Send + Sync + 'static>(
+        &self,
+        address: A,
+        op_return: H256,
+    ) -> Result<Option<Txid>, Error>;
+
     async fn create_or_load_wallet(&self) -> Result<(), Error>;
 
     async fn wallet_has_public_key<P>(&self, public_key: P) -> Result<bool, Error>
@@ -163,10 +188,13 @@ fn get_exponential_backoff() -> ExponentialBackoff {
 #[derive(Clone)]
 pub struct BitcoinCore {
     rpc: Arc<Client>,
+    url: String,
+    auth: Auth,
     wallet_name: Option<String>,
     network: Network,
     transaction_creation_lock: Arc<Mutex<()>>,
     connection_timeout: Duration,
+    dry_run: bool,
 }
 
 impl BitcoinCore {
@@ -177,19 +205,45 @@ impl BitcoinCore {
         network: Network,
         connection_timeout: Duration,
     ) -> Result<Self, Error> {
-        let url = match wallet_name {
+        let wallet_url = match wallet_name {
             Some(ref x) => format!("{}/wallet/{}", url, x),
-            None => url,
+            None => url.clone(),
         };
         Ok(Self {
-            rpc: Arc::new(Client::new(url, auth)?),
+            rpc: Arc::new(Client::new(wallet_url, auth.clone())?),
+            url,
+            auth,
             wallet_name,
             network,
             transaction_creation_lock: Arc::new(Mutex::new(())),
             connection_timeout,
+            dry_run: false,
         })
     }
 
+    /// In dry-run mode, transactions are never broadcast to the network.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// The network the node is expected to run on.
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Create a client for another wallet on the same bitcoin-core node.
+    pub fn for_wallet(&self, wallet_name: String) -> Result<Self, Error> {
+        Ok(Self::new(
+            self.url.clone(),
+            self.auth.clone(),
+            Some(wallet_name),
+            self.network,
+            self.connection_timeout,
+        )?
+        .with_dry_run(self.dry_run))
+    }
+
     /// Connect to a bitcoin-core full node or timeout.
     pub async fn connect(&self) -> Result<(), Error> {
         info!("Connecting to bitcoin-core...");
@@ -319,6 +373,10 @@ fn err_not_in_mempool(err: &bitcoincore_rpc::Error) -> bool {
 
 #[async_trait]
 impl BitcoinCoreApi for BitcoinCore {
+    fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
     /// Wait for a specified height to return a `BlockHash` or
     /// exit on error.
     ///
@@ -408,12 +466,29 @@ impl BitcoinCoreApi for BitcoinCore {
 
     /// Gets a new address from the wallet
     async fn get_new_address<A: PartialAddress + Send + 'static>(&self) -> Result<A, Error> {
+        if self.dry_run {
+            info!("Dry run: not generating a new address");
+            return Err(Error::DryRun);
+        }
         let address = self.rpc.get_new_address(None, Some(AddressType::Bech32))?;
         Ok(A::decode_str(&address.to_string())?)
     }
 
+    /// Gets the trusted balance of the wallet
+    ///
+    /// # Arguments
+    /// * `min_confirmations` - only include transactions confirmed at least this many times
+    async fn get_balance(&self, min_confirmations: Option<u32>) -> Result<Amount, Error> {
+        self.with_wallet(|| async { Ok(self.rpc.get_balance(min_confirmations.map(|x| x as usize), None)?) })
+            .await
+    }
+
     /// Gets a new public key for an address in the wallet
     async fn get_new_public_key<P: From<[u8; PUBLIC_KEY_SIZE]> + 'static>(&self) -> Result<P, Error> {
+        if self.dry_run {
+            info!("Dry run: not generating a new public key");
+            return Err(Error::DryRun);
+        }
         let address = self.rpc.get_new_address(None, Some(AddressType::Bech32))?;
         let address_info = self.rpc.get_address_info(&address)?;
         let public_key = address_info.pubkey.ok_or(Error::MissingPublicKey)?;
@@ -426,6 +501,10 @@ impl BitcoinCoreApi for BitcoinCore {
         public_key: P,
         secret_key: Vec<u8>,
     ) -> Result<(), Error> {
+        if self.dry_run {
+            info!("Dry run: not importing deposit key");
+            return Err(Error::DryRun);
+        }
         let address = Address::p2wpkh(&PublicKey::from_slice(&public_key.into())?, self.network)
             .map_err(ConversionError::from)?;
         let private_key = self.rpc.dump_private_key(&address)?;
@@ -478,6 +557,20 @@ impl BitcoinCoreApi for BitcoinCore {
         Ok(Box::new(iterator))
     }
 
+    /// Get the ids of the transactions that are currently in the mempool.
+    async fn get_mempool_txids(&self) -> Result<Vec<Txid>, Error> {
+        Ok(self.rpc.get_raw_mempool()?)
+    }
+
+    /// Get an unconfirmed transaction, returns `None` if it is no longer in the mempool.
+    async fn get_mempool_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
+        match self.rpc.get_raw_transaction_info(txid, None) {
+            Ok(info) => Ok(Some(info.transaction()?)),
+            Err(e) if err_not_in_mempool(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Waits for the required number of confirmations, and collects data about the
     /// transaction
     ///
@@ -539,6 +632,10 @@ impl BitcoinCoreApi for BitcoinCore {
         sat: u64,
         request_id: Option<H256>,
     ) -> Result<LockedTransaction, Error> {
+        if self.dry_run {
+            info!("Dry run: not funding transaction");
+            return Err(Error::DryRun);
+        }
         self.with_wallet(|| async {
             let address_string = address.encode_str(self.network)?;
 
@@ -579,6 +676,14 @@ impl BitcoinCoreApi for BitcoinCore {
     /// # Arguments
     /// * `transaction` - The transaction created by create_transaction
     async fn send_transaction(&self, transaction: LockedTransaction) -> Result<Txid, Error> {
+        if self.dry_run {
+            info!(
+                "Dry run: not broadcasting transaction {}",
+                transaction.transaction.txid()
+            );
+            return Err(Error::DryRun);
+        }
+
         // place the transaction into the mempool, this is fine to retry
         let txid = self
             .with_wallet(|| async { Ok(self.rpc.send_raw_transaction(&transaction.transaction)?) })
@@ -629,6 +734,74 @@ impl BitcoinCoreApi for BitcoinCore {
         Ok(self.wait_for_transaction_metadata(txid, num_confirmations).await?)
     }
 
+    /// Transfer the entire confirmed balance of the wallet to a single address. The fee is
+    /// subtracted from the transferred amount, so the transaction has no change output.
+    ///
+    /// # Arguments
+    /// * `address` - Bitcoin address to receive the funds
+    async fn sweep_funds<A: PartialAddress + Send + Sync + 'static>(&self, address: A) -> Result<Txid, Error> {
+        if self.dry_run {
+            info!("Dry run: not sweeping funds");
+            return Err(Error::DryRun);
+        }
+
+        self.with_wallet(|| async {
+            let address = Address::from_str(&address.encode_str(self.network)?).map_err(ConversionError::from)?;
+
+            // don't allow other transactions to select the same inputs
+            let _lock = self.transaction_creation_lock.lock().await;
+
+            let balance = self.rpc.get_balance(None, None)?;
+            if balance.as_sat() == 0 {
+                return Err(Error::EmptyWallet);
+            }
+
+            Ok(self
+                .rpc
+                .send_to_address(&address, balance, None, None, Some(true), None, None, None)?)
+        })
+        .await
+    }
+
+    /// Searches the wallet for a payment to `address` with the given OP_RETURN, most recent
+    /// transactions first, ignoring transactions that conflict with the chain.
+    ///
+    /// # Arguments
+    /// * `address` - Bitcoin address the payment was sent to
+    /// * `op_return` - the issue/redeem/replace id for which the payment was made
+    async fn find_sent_transaction<A: PartialAddress + Send + Sync + 'static>(
+        &self,
+        address: A,
+        op_return: H256,
+    ) -> Result<Option<Txid>, Error> {
+        let address = Address::from_str(&address.encode_str(self.network)?).map_err(ConversionError::from)?;
+        self.with_wallet(|| async {
+            let mut skip = 0;
+            loop {
+                let page =
+                    self.rpc
+                        .list_transactions(None, Some(LIST_TRANSACTIONS_PAGE_SIZE), Some(skip), Some(true))?;
+                for entry in page.iter().rev() {
+                    if entry.detail.category != json::GetTransactionResultDetailCategory::Send
+                        || entry.detail.address.as_ref() != Some(&address)
+                        || entry.info.confirmations < 0
+                    {
+                        continue;
+                    }
+                    let transaction = self.rpc.get_transaction(&entry.info.txid, None)?.transaction()?;
+                    if transaction.get_op_return() == Some(op_return) {
+                        return Ok(Some(entry.info.txid));
+                    }
+                }
+                if page.len() < LIST_TRANSACTIONS_PAGE_SIZE {
+                    return Ok(None);
+                }
+                skip += page.len();
+            }
+        })
+        .await
+    }
+
     /// Create or load a wallet on Bitcoin Core.
     async fn create_or_load_wallet(&self) -> Result<(), Error> {
         let wallet_name = if let Some(ref wallet_name) = self.wallet_name {
@@ -643,6 +816,10 @@ impl BitcoinCoreApi for BitcoinCore {
             return Ok(());
         }
         // wallet does not exist, create
+        if self.dry_run {
+            info!("Dry run: not creating wallet {}", wallet_name);
+            return Err(Error::DryRun);
+        }
         self.rpc.create_wallet(wallet_name, None, None, None, None)?;
         Ok(())
     }
@@ -662,6 +839,10 @@ impl BitcoinCoreApi for BitcoinCore {
     }
 
     async fn import_private_key(&self, privkey: PrivateKey) -> Result<(), Error> {
+        if self.dry_run {
+            info!("Dry run: not importing private key");
+            return Err(Error::DryRun);
+        }
         self.with_wallet(|| async { Ok(self.rpc.import_private_key(&privkey, None, None)?) })
             .await
     }
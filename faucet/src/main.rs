@@ -4,8 +4,8 @@ mod http;
 use clap::Clap;
 use error::Error;
 use git_version::git_version;
-use runtime::{substrate_subxt::PairSigner, InterBtcRuntime};
-use service::{on_shutdown, wait_or_shutdown};
+use runtime::{substrate_subxt::PairSigner, InterBtcRuntime, Subsystem};
+use service::{on_shutdown, wait_or_shutdown, MetricsConfig};
 use std::net::SocketAddr;
 
 const VERSION: &str = git_version!(args = ["--tags"]);
@@ -27,6 +27,10 @@ struct Opts {
     /// Settings specific to the faucet client.
     #[clap(flatten)]
     faucet: FaucetConfig,
+
+    /// Prometheus metrics settings.
+    #[clap(flatten)]
+    metrics: MetricsConfig,
 }
 
 #[derive(Clap, Clone)]
@@ -54,6 +58,7 @@ async fn main() -> Result<(), Error> {
         env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, log::LevelFilter::Info.as_str()),
     );
     let opts: Opts = Opts::parse();
+    opts.metrics.spawn_server();
 
     let (key_pair, _) = opts.account_info.get_key_pair()?;
     let signer = PairSigner::<InterBtcRuntime, _>::new(key_pair);
@@ -65,6 +70,9 @@ async fn main() -> Result<(), Error> {
 
     loop {
         let btc_parachain = parachain_config.try_connect(signer.clone()).await?;
+        let compatibility = btc_parachain.check_compatibility().await?;
+        log::info!("Connected to {}", compatibility);
+        compatibility.require(&[Subsystem::Core, Subsystem::Transfer])?;
 
         let close_handle = http::start_http(
             btc_parachain.clone(),
@@ -75,6 +83,8 @@ async fn main() -> Result<(), Error> {
         )
         .await;
 
+        let upgrade_provider = btc_parachain.clone();
+
         // run block listener to restart faucet on disconnect
         let block_listener = wait_or_shutdown(shutdown_tx.clone(), async move {
             btc_parachain
@@ -86,10 +96,16 @@ async fn main() -> Result<(), Error> {
             Ok(())
         });
 
+        // reconnect after a runtime upgrade to use the new metadata
+        let upgrade_listener = wait_or_shutdown(shutdown_tx.clone(), async move {
+            upgrade_provider.wait_for_runtime_upgrade().await?;
+            Ok(())
+        });
+
         let http_server = on_shutdown(shutdown_tx.clone(), async move {
             close_handle.close();
         });
 
-        let _ = futures::future::join(block_listener, http_server).await;
+        let _ = futures::future::join3(block_listener, upgrade_listener, http_server).await;
     }
 }
@@ -6,7 +6,7 @@ use git_version::git_version;
 use log::{error, info};
 use runtime::{
     substrate_subxt::PairSigner, ExchangeRateOraclePallet, FixedPointNumber, FixedPointTraits::CheckedMul, FixedU128,
-    InterBtcParachain, InterBtcRuntime,
+    InterBtcParachain, InterBtcRuntime, Subsystem,
 };
 use std::{collections::HashMap, time::Duration};
 use tokio::time::delay_for;
@@ -67,6 +67,10 @@ struct Opts {
     /// Timeout in milliseconds to wait for connection to btc-parachain.
     #[clap(long, default_value = "60000")]
     connection_timeout_ms: u64,
+
+    /// Prometheus metrics settings.
+    #[clap(flatten)]
+    metrics: service::MetricsConfig,
 }
 
 #[tokio::main]
@@ -75,6 +79,7 @@ async fn main() -> Result<(), Error> {
         env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, log::LevelFilter::Info.as_str()),
     );
     let opts: Opts = Opts::parse();
+    opts.metrics.spawn_server();
 
     let (key_pair, _) = opts.account_info.get_key_pair()?;
     let signer = PairSigner::<InterBtcRuntime, _>::new(key_pair);
@@ -115,14 +120,20 @@ async fn main() -> Result<(), Error> {
             chrono::offset::Local::now()
         );
 
-        let result = InterBtcParachain::from_url_with_retry(
+        let parachain_rpc = InterBtcParachain::from_url_with_retry(
             &opts.btc_parachain_url.clone(),
             signer.clone(),
             Duration::from_millis(opts.connection_timeout_ms),
         )
-        .await?
-        .set_exchange_rate_info(exchange_rate)
-        .await;
+        .await?;
+
+        // every update uses a new connection, so runtime upgrades are checked here as well
+        parachain_rpc
+            .check_compatibility()
+            .await?
+            .require(&[Subsystem::Oracle])?;
+
+        let result = parachain_rpc.set_exchange_rate_info(exchange_rate).await;
 
         if let Err(e) = result {
             error!("Error: {}", e.to_string());
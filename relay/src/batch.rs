@@ -0,0 +1,71 @@
+/// Number of block headers per batch. The weight and length of a batch grow with the number of
+/// headers, so the size is halved when a batch does not fit into a parachain block, and grows
+/// back up to the largest size that is not known to exceed the limits.
+pub struct BatchSize {
+    current: u32,
+    limit: u32,
+}
+
+impl BatchSize {
+    pub fn new(max_batch_size: u32) -> Self {
+        let limit = max_batch_size.max(1);
+        Self { current: limit, limit }
+    }
+
+    pub fn get(&self) -> u32 {
+        self.current
+    }
+
+    /// A batch of `size` headers exceeded the block limits.
+    pub fn shrink(&mut self, size: u32) {
+        self.limit = self.limit.min(size.saturating_sub(1)).max(1);
+        self.current = (size / 2).max(1).min(self.limit);
+    }
+
+    /// A batch of `size` headers was included.
+    pub fn grow(&mut self, size: u32) {
+        if size >= self.current {
+            self.current = self.current.saturating_mul(2).min(self.limit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shrinks_below_failed_size() {
+        let mut batch_size = BatchSize::new(16);
+        batch_size.shrink(16);
+        assert_eq!(batch_size.get(), 8);
+        batch_size.shrink(8);
+        assert_eq!(batch_size.get(), 4);
+
+        // never grows back to a size that failed
+        batch_size.grow(4);
+        assert_eq!(batch_size.get(), 7);
+        batch_size.grow(7);
+        assert_eq!(batch_size.get(), 7);
+    }
+
+    #[test]
+    fn grows_only_after_full_batches() {
+        let mut batch_size = BatchSize::new(16);
+        batch_size.shrink(16);
+        batch_size.grow(3);
+        assert_eq!(batch_size.get(), 8);
+        batch_size.grow(8);
+        assert_eq!(batch_size.get(), 15);
+    }
+
+    #[test]
+    fn never_drops_to_zero() {
+        let mut batch_size = BatchSize::new(0);
+        assert_eq!(batch_size.get(), 1);
+        batch_size.shrink(1);
+        assert_eq!(batch_size.get(), 1);
+        batch_size.grow(1);
+        assert_eq!(batch_size.get(), 1);
+    }
+}
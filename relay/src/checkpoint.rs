@@ -0,0 +1,81 @@
+use super::{parse_header, Error};
+use bitcoin::serialize;
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// Block at which the relay is initialized, e.g. for a testnet whose relay should start at a
+/// known block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub height: u32,
+    /// Hash of the block (little endian), like the hashes returned by the backing chain.
+    pub hash: Vec<u8>,
+    /// Raw block header.
+    pub header: Vec<u8>,
+}
+
+/// Checkpoint file contents, with the hash in the usual (reversed) hex format.
+#[derive(Deserialize)]
+struct CheckpointFile {
+    height: u32,
+    hash: String,
+    header: String,
+}
+
+impl Checkpoint {
+    /// Reads a checkpoint from a json file of the form
+    /// `{ "height": <height>, "hash": "<block hash>", "header": "<raw header in hex>" }`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    fn from_json(json: &str) -> Result<Self, Error> {
+        let file: CheckpointFile = serde_json::from_str(json)?;
+        let header = hex::decode(&file.header).map_err(|_| Error::SerializeHeader)?;
+        let hash = parse_header(&header)?.block_hash();
+        if !hash.to_string().eq_ignore_ascii_case(&file.hash) {
+            return Err(Error::InvalidCheckpoint(file.height));
+        }
+
+        Ok(Self {
+            height: file.height,
+            hash: serialize(&hash),
+            header,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+    const GENESIS_HASH: &str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+    #[test]
+    fn parses_checkpoint() {
+        let checkpoint = Checkpoint::from_json(&format!(
+            r#"{{ "height": 0, "hash": "{}", "header": "{}" }}"#,
+            GENESIS_HASH, GENESIS_HEADER
+        ))
+        .unwrap();
+        assert_eq!(checkpoint.height, 0);
+        assert_eq!(checkpoint.header, hex::decode(GENESIS_HEADER).unwrap());
+
+        let mut hash = hex::decode(GENESIS_HASH).unwrap();
+        hash.reverse();
+        assert_eq!(checkpoint.hash, hash);
+    }
+
+    #[test]
+    fn rejects_hash_of_other_header() {
+        let other_hash = "00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048";
+        assert_eq!(
+            Checkpoint::from_json(&format!(
+                r#"{{ "height": 1, "hash": "{}", "header": "{}" }}"#,
+                other_hash, GENESIS_HEADER
+            )),
+            Err(Error::InvalidCheckpoint(1))
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use super::HeaderError;
+use bitcoin::Error as BitcoinError;
+use hyper::{http::Error as HyperHttpError, Error as HyperError};
+use runtime::Error as InterBtcError;
+use serde_json::Error as SerdeJsonError;
+use std::io::Error as IoError;
+use thiserror::Error;
+use tokio::task::JoinError;
+
+#[cfg(test)]
+use std::mem::discriminant;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Client already initialized")]
+    AlreadyInitialized,
+    #[error("Client has not been initialized")]
+    NotInitialized,
+    #[error("Block already submitted")]
+    BlockExists,
+    #[error("Cannot read the best height")]
+    CannotFetchBestHeight,
+    #[error("Block hash not found for the given height")]
+    BlockHashNotFound,
+    #[error("Failed to decode hash")]
+    DecodeHash,
+    #[error("Failed to serialize block header")]
+    SerializeHeader,
+    #[error("Invalid block header at height {0}: {1}")]
+    InvalidHeader(u32, HeaderError),
+    #[error("Quorum of {0} is invalid for {1} Bitcoin sources")]
+    InvalidQuorum(usize, usize),
+    #[error("Bitcoin sources do not agree on the block at height {0}")]
+    NoQuorum(u32),
+    #[error("Only {0} Bitcoin sources responded, {1} are required")]
+    NotEnoughSources(usize, usize),
+    #[error("Received an invalid response from {0}")]
+    InvalidResponse(String),
+    #[error("Batch of {0} block headers exceeds the block limits")]
+    BatchTooLarge(usize),
+    #[error("Checkpoint hash at height {0} does not match its header")]
+    InvalidCheckpoint(u32),
+    #[error("Checkpoint at height {0} is not on the Bitcoin main chain")]
+    CheckpointNotInMainChain(u32),
+
+    #[error("BitcoinError: {0}")]
+    BitcoinError(#[from] BitcoinError),
+    #[error("InterBtcError: {0}")]
+    InterBtcError(#[from] InterBtcError),
+    #[error("HyperError: {0}")]
+    HyperError(#[from] HyperError),
+    #[error("HyperHttpError: {0}")]
+    HyperHttpError(#[from] HyperHttpError),
+    #[error("JoinError: {0}")]
+    JoinError(#[from] JoinError),
+    #[error("SerdeJsonError: {0}")]
+    SerdeJsonError(#[from] SerdeJsonError),
+    #[error("IoError: {0}")]
+    IoError(#[from] IoError),
+}
+
+#[cfg(test)]
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        discriminant(self) == discriminant(other)
+    }
+}
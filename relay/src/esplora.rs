@@ -0,0 +1,81 @@
+use super::{Backing, Error};
+use async_trait::async_trait;
+use hyper::{client::HttpConnector, Body, Client, Method, Request, StatusCode};
+use hyper_tls::HttpsConnector;
+
+/// Client for the REST API of an Esplora instance (e.g. https://blockstream.info/api).
+pub struct Esplora {
+    url: String,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl Esplora {
+    pub fn new(url: String) -> Self {
+        let https = HttpsConnector::new();
+        let client = Client::builder().build::<_, Body>(https);
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            client,
+        }
+    }
+
+    /// Returns the body of the response to a GET request for `path`, or `None` if
+    /// the resource does not exist.
+    async fn get(&self, path: &str) -> Result<Option<String>, Error> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(format!("{}{}", self.url, path))
+            .body(Body::empty())?;
+        let response = self.client.request(request).await?;
+
+        match response.status() {
+            StatusCode::OK => {
+                let body = hyper::body::to_bytes(response.into_body()).await?;
+                String::from_utf8(body.to_vec())
+                    .map(|body| Some(body.trim().to_string()))
+                    .map_err(|_| Error::InvalidResponse(self.url.clone()))
+            }
+            StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(Error::InvalidResponse(self.url.clone())),
+        }
+    }
+
+    /// Returns the hash of the block at `height` in the format of Bitcoin Core, i.e. reversed.
+    async fn get_block_hash_hex(&self, height: u32) -> Result<Option<String>, Error> {
+        self.get(&format!("/block-height/{}", height)).await
+    }
+}
+
+#[async_trait]
+impl Backing for Esplora {
+    async fn get_block_count(&self) -> Result<u32, Error> {
+        self.get("/blocks/tip/height")
+            .await?
+            .and_then(|height| height.parse().ok())
+            .ok_or_else(|| Error::InvalidResponse(self.url.clone()))
+    }
+
+    async fn get_block_header(&self, height: u32) -> Result<Option<Vec<u8>>, Error> {
+        let hash = match self.get_block_hash_hex(height).await? {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
+        match self.get(&format!("/block/{}/header", hash)).await? {
+            Some(header) => Ok(Some(
+                hex::decode(header).map_err(|_| Error::InvalidResponse(self.url.clone()))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<Vec<u8>, Error> {
+        let hash = self.get_block_hash_hex(height).await?.ok_or(Error::BlockHashNotFound)?;
+        let mut hash = hex::decode(hash).map_err(|_| Error::DecodeHash)?;
+        if hash.len() != 32 {
+            return Err(Error::DecodeHash);
+        }
+        // little endian, like the hashes returned for Bitcoin Core
+        hash.reverse();
+        Ok(hash)
+    }
+}
@@ -1,6 +1,10 @@
 use super::Error;
 use async_trait::async_trait;
-use runtime::{BtcRelayPallet, H256Le, InterBtcParachain, RawBlockHeader, RelayPallet};
+use futures::{future::BoxFuture, FutureExt};
+use runtime::{BtcRelayPallet, Error as InterBtcError, H256Le, InterBtcParachain, RawBlockHeader, RelayPallet};
+
+/// Resolves once a submitted batch of block headers is included.
+pub type PendingSubmission = BoxFuture<'static, Result<(), Error>>;
 
 #[async_trait]
 pub trait Issuing {
@@ -22,12 +26,17 @@ pub trait Issuing {
     /// * `header` - Raw block header
     async fn submit_block_header(&self, header: Vec<u8>) -> Result<(), Error>;
 
-    /// Submit a batch of block headers and wait for inclusion
+    /// Submit a batch of block headers without waiting for inclusion. Returns once the batch
+    /// is ordered after all earlier submissions, with a future that resolves once it is included
     ///
     /// # Arguments
     ///
     /// * `headers` - Raw block headers (multiple of 80 bytes)
-    async fn submit_block_header_batch(&self, headers: Vec<Vec<u8>>) -> Result<(), Error>;
+    async fn submit_block_header_batch(&self, headers: Vec<Vec<u8>>) -> Result<PendingSubmission, Error>;
+
+    /// Resynchronize the nonce of later submissions with the chain, after a submission failed
+    /// and all submissions after it have resolved
+    async fn refresh_nonce(&self);
 
     /// Returns the light client's chain tip
     async fn get_best_height(&self) -> Result<u32, Error>;
@@ -53,6 +62,14 @@ fn encode_raw_header(bytes: Vec<u8>) -> Result<RawBlockHeader, Error> {
     RawBlockHeader::from_bytes(bytes).map_err(|_| Error::SerializeHeader)
 }
 
+fn batch_error(err: InterBtcError, batch_size: usize) -> Error {
+    if err.is_exhausted_resources() {
+        Error::BatchTooLarge(batch_size)
+    } else {
+        err.into()
+    }
+}
+
 #[async_trait]
 impl Issuing for InterBtcParachain {
     async fn is_initialized(&self) -> Result<bool, Error> {
@@ -81,16 +98,24 @@ impl Issuing for InterBtcParachain {
     }
 
     #[tracing::instrument(name = "submit_block_header_batch", skip(self, headers))]
-    async fn submit_block_header_batch(&self, headers: Vec<Vec<u8>>) -> Result<(), Error> {
-        RelayPallet::store_block_headers(
+    async fn submit_block_header_batch(&self, headers: Vec<Vec<u8>>) -> Result<PendingSubmission, Error> {
+        let batch_size = headers.len();
+        let pending = RelayPallet::store_block_headers_pipelined(
             self,
             headers
-                .iter()
-                .map(|header| encode_raw_header(header.to_vec()))
+                .into_iter()
+                .map(encode_raw_header)
                 .collect::<Result<Vec<_>, _>>()?,
         )
         .await
-        .map_err(Into::into)
+        .map_err(|err| batch_error(err, batch_size))?;
+        Ok(pending
+            .map(move |result| result.map_err(|err| batch_error(err, batch_size)))
+            .boxed())
+    }
+
+    async fn refresh_nonce(&self) {
+        InterBtcParachain::refresh_nonce(self).await
     }
 
     async fn get_best_height(&self) -> Result<u32, Error> {
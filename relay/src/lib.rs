@@ -0,0 +1,1529 @@
+use crate::metrics::{RELAY_BEST_HEIGHT, RELAY_BITCOIN_HEIGHT, RELAY_FORK_DEPTH, RELAY_INVALID_HEADERS, RELAY_REORGS};
+use bitcoin::{deserialize, serialize, BlockHeader};
+use futures::{stream, StreamExt, TryStreamExt};
+use service::Error as ServiceError;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::delay_for;
+
+mod backing;
+mod batch;
+mod checkpoint;
+mod error;
+mod esplora;
+mod issuing;
+mod metrics;
+mod monitor;
+mod quorum;
+mod validation;
+
+pub use backing::Backing;
+use batch::BatchSize;
+pub use checkpoint::Checkpoint;
+pub use error::Error;
+pub use esplora::Esplora;
+pub use issuing::{Issuing, PendingSubmission};
+pub use monitor::{run_relay_monitor, AlertLevel, RelayMonitor, RelayMonitorConfig, RelayStatus};
+pub use quorum::{BackingSource, QuorumBacking};
+pub use validation::{ChainParams, HeaderError};
+use validation::{HeaderChain, DIFFICULTY_ADJUSTMENT_INTERVAL};
+
+// 10 minutes = 600 seconds
+const SLEEP_TIME: Duration = Duration::from_secs(600);
+
+/// Minimum number of confirmations of the block the relay is initialized at by default.
+const INITIALIZATION_CONFIRMATIONS: u32 = 6;
+
+/// Number of block headers that are requested from the backing chain at the same time.
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// Position of the main chain of the relay relative to the Bitcoin main chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainStatus {
+    /// The main chain of the relay is a prefix of the Bitcoin main chain.
+    InSync,
+    /// The main chain of the relay contains `depth` blocks after `ancestor_height` that are not
+    /// on the Bitcoin main chain, i.e. the relay has yet to follow a reorg.
+    Diverged { ancestor_height: u32, depth: u32 },
+}
+
+/// Where the relayer continues submitting headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SyncPoint {
+    /// Height of the next Bitcoin main chain block that is not stored in the relay.
+    start_height: u32,
+    /// Height of the best block of the relay.
+    best_height: u32,
+    status: ChainStatus,
+}
+
+/// Retrieves `batch` blocks starting at block `height` from the backing blockchain
+async fn collect_headers(height: u32, batch: u32, cli: &impl Backing) -> Result<Vec<Vec<u8>>, Error> {
+    stream::iter(height..height + batch)
+        .map(|h| async move { cli.get_block_header(h).await?.ok_or(Error::BlockHashNotFound) })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .try_collect()
+        .await
+}
+
+/// Returns the height of the last block that the main chain of the relay has in common with the
+/// Bitcoin main chain, together with the best height of the relay.
+async fn find_common_ancestor(backing: &impl Backing, issuing: &impl Issuing) -> Result<(u32, u32), Error> {
+    let best_height = issuing.get_best_height().await?;
+    // the Bitcoin main chain may have become shorter in a reorg
+    let mut height = best_height.min(backing.get_block_count().await?);
+
+    // backwards pass
+    while issuing.get_block_hash(height).await? != backing.get_block_hash(height).await? {
+        height = height.checked_sub(1).ok_or(Error::NotInitialized)?;
+    }
+    Ok((height, best_height))
+}
+
+/// Computes the height at which the relayer should start to submit blocks.
+/// In most cases it should be from the next block after the highest block
+/// stored by the issuing blockchain. After a reorg, it is the first block of
+/// the Bitcoin main chain after the common ancestor that is not yet stored
+/// on a fork of the relay.
+async fn compute_start_height(backing: &impl Backing, issuing: &impl Issuing) -> Result<SyncPoint, Error> {
+    let (ancestor_height, best_height) = find_common_ancestor(backing, issuing).await?;
+    let status = if ancestor_height < best_height {
+        ChainStatus::Diverged {
+            ancestor_height,
+            depth: best_height - ancestor_height,
+        }
+    } else {
+        ChainStatus::InSync
+    };
+
+    Ok(SyncPoint {
+        start_height: skip_stored_blocks(backing, issuing, ancestor_height).await?,
+        best_height,
+        status,
+    })
+}
+
+/// Returns the height of the first block of the Bitcoin main chain after `height` that is not
+/// stored in the relay, on its main chain or on a fork.
+async fn skip_stored_blocks(backing: &impl Backing, issuing: &impl Issuing, mut height: u32) -> Result<u32, Error> {
+    // forward pass (possible forks)
+    loop {
+        match backing.get_block_hash(height).await {
+            Ok(h) if issuing.is_block_stored(h.clone()).await? => {
+                height = height.saturating_add(1);
+            }
+            _ => break,
+        }
+    }
+
+    // found matching parent start on next
+    Ok(height)
+}
+
+/// Time of the last successful iteration of the relayer, shared with health checks.
+#[derive(Clone, Default)]
+pub struct Heartbeat(Arc<Mutex<Option<Instant>>>);
+
+impl Heartbeat {
+    fn beat(&self) {
+        *self.0.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Returns the time since the last successful iteration, if any.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.0.lock().unwrap().map(|instant| instant.elapsed())
+    }
+}
+
+#[derive(Default)]
+pub struct Config {
+    /// Initialization height, if unset will use the last difficulty adjustment before the
+    /// confirmed tip
+    pub start_height: Option<u32>,
+    /// Block to initialize the relay at, takes precedence over `start_height`
+    pub checkpoint: Option<Checkpoint>,
+    /// Maximum number of headers to collect on catchup
+    pub max_batch_size: u32,
+    /// Maximum number of batches that are submitted before the first one is included
+    pub max_batches_in_flight: u32,
+    /// Thread sleep duration
+    pub interval: Option<Duration>,
+    /// Number of confirmations a block needs to have before it is submitted.
+    pub btc_confirmations: u32,
+    /// Consensus parameters to validate block headers with before submitting them,
+    /// headers are not validated if unset
+    pub chain_params: Option<ChainParams>,
+}
+
+/// Runner implements the main loop for the relayer
+pub struct Runner<B: Backing, I: Issuing> {
+    backing: B,
+    issuing: I,
+    start_height: Option<u32>,
+    checkpoint: Option<Checkpoint>,
+    batch_size: Mutex<BatchSize>,
+    max_batches_in_flight: usize,
+    interval: Duration,
+    btc_confirmations: u32,
+    chain_params: Option<ChainParams>,
+    status: Mutex<ChainStatus>,
+    heartbeat: Heartbeat,
+}
+
+impl<B: Backing, I: Issuing> Runner<B, I> {
+    pub fn new(backing: B, issuing: I, conf: Config) -> Runner<B, I> {
+        Runner {
+            backing,
+            issuing,
+            start_height: conf.start_height,
+            checkpoint: conf.checkpoint,
+            batch_size: Mutex::new(BatchSize::new(conf.max_batch_size)),
+            max_batches_in_flight: conf.max_batches_in_flight.max(1) as usize,
+            interval: conf.interval.unwrap_or_else(|| SLEEP_TIME),
+            btc_confirmations: conf.btc_confirmations,
+            chain_params: conf.chain_params,
+            status: Mutex::new(ChainStatus::InSync),
+            heartbeat: Heartbeat::default(),
+        }
+    }
+
+    /// Report successful iterations to `heartbeat`.
+    pub fn with_heartbeat(self, heartbeat: Heartbeat) -> Self {
+        Self { heartbeat, ..self }
+    }
+
+    /// Returns the position of the relay's main chain as of the last submission.
+    pub fn chain_status(&self) -> ChainStatus {
+        *self.status.lock().unwrap()
+    }
+
+    /// Records the position of the relay's main chain and reports reorgs.
+    fn update_chain_status(&self, status: ChainStatus) {
+        let previous = std::mem::replace(&mut *self.status.lock().unwrap(), status);
+        if previous == status {
+            return;
+        }
+
+        match status {
+            ChainStatus::Diverged { ancestor_height, depth } => {
+                // the depth changes if the diverging branch is extended, but it is the same reorg
+                let same_reorg = matches!(
+                    previous,
+                    ChainStatus::Diverged { ancestor_height: height, .. } if height == ancestor_height
+                );
+                if !same_reorg {
+                    RELAY_REORGS.inc();
+                }
+                tracing::warn!(
+                    "Bitcoin reorg: the relay main chain has {} blocks after height {} that are not on the Bitcoin main chain",
+                    depth,
+                    ancestor_height
+                );
+                RELAY_FORK_DEPTH.set(depth as i64);
+            }
+            ChainStatus::InSync => {
+                tracing::info!("Relay main chain follows the Bitcoin main chain again");
+                RELAY_FORK_DEPTH.set(0);
+            }
+        }
+    }
+
+    /// Returns the block header at `height`
+    async fn get_block_header(&self, height: u32) -> Result<Vec<u8>, Error> {
+        loop {
+            match self.backing.get_block_header(height).await? {
+                Some(header) => return Ok(header),
+                None => {
+                    tracing::trace!("No block found at height {}, sleeping for {:?}", height, self.interval);
+                    delay_for(self.interval).await
+                }
+            };
+        }
+    }
+
+    async fn get_num_confirmed_blocks(&self) -> Result<u32, Error> {
+        Ok(self
+            .backing
+            .get_block_count()
+            .await?
+            .saturating_sub(self.btc_confirmations))
+    }
+
+    /// Returns the header and height of the block to initialize the relay at: the checkpoint if
+    /// it is on the Bitcoin main chain, otherwise the block at `start_height`, or by default the
+    /// first block of the difficulty period of the confirmed tip, so that the relay can check the
+    /// next difficulty adjustment.
+    async fn initialization_point(&self) -> Result<(Vec<u8>, u32), Error> {
+        if let Some(checkpoint) = &self.checkpoint {
+            if self.backing.get_block_hash(checkpoint.height).await? != checkpoint.hash {
+                return Err(Error::CheckpointNotInMainChain(checkpoint.height));
+            }
+            return Ok((checkpoint.header.clone(), checkpoint.height));
+        }
+
+        let height = match self.start_height {
+            Some(height) => height,
+            None => {
+                let depth = self.btc_confirmations.max(INITIALIZATION_CONFIRMATIONS);
+                let confirmed_height = self.backing.get_block_count().await?.saturating_sub(depth);
+                confirmed_height - confirmed_height % DIFFICULTY_ADJUSTMENT_INTERVAL
+            }
+        };
+        let header = self
+            .backing
+            .get_block_header(height)
+            .await?
+            .ok_or(Error::BlockHashNotFound)?;
+        Ok((header, height))
+    }
+
+    /// Returns the number of headers to submit in the next batch from `height` on.
+    fn next_batch_size(&self, height: u32, max_height: u32) -> u32 {
+        let batch_size = self.batch_size.lock().unwrap().get();
+        max_height.saturating_add(1).saturating_sub(height).min(batch_size)
+    }
+
+    /// Submit the next block(s) or initialize the relay,
+    /// may submit up to `max_batches_in_flight` batches at a time
+    pub async fn submit_next(&self) -> Result<(), Error> {
+        if !self.issuing.is_initialized().await? {
+            let (header, start_height) = self.initialization_point().await?;
+            tracing::info!("Initializing at height {}", start_height);
+            self.issuing.initialize(header, start_height).await?;
+        }
+
+        let max_height = self.get_num_confirmed_blocks().await?;
+        tracing::trace!("Backing height: {}", max_height);
+        RELAY_BITCOIN_HEIGHT.set(max_height as i64);
+        let sync_point = compute_start_height(&self.backing, &self.issuing).await?;
+        self.update_chain_status(sync_point.status);
+        let current_height = sync_point.start_height;
+        tracing::trace!("Issuing height: {}", current_height);
+        RELAY_BEST_HEIGHT.set(sync_point.best_height as i64);
+
+        match self.next_batch_size(current_height, max_height) {
+            0 => {
+                // nothing to submit right now. Wait a little while
+                tracing::trace!("Waiting for the next Bitcoin block...");
+                delay_for(self.interval).await;
+                Ok(())
+            }
+            1 => self.submit_header(current_height).await,
+            _ => self.submit_batches(current_height, max_height).await,
+        }
+    }
+
+    /// Submits the single block header at `height` and waits for its inclusion.
+    async fn submit_header(&self, height: u32) -> Result<(), Error> {
+        let header = self.get_block_header(height).await?;
+        let mut chain = self.load_validation_chain(height).await?;
+        // submit the headers before an invalid header, but not the invalid header itself
+        let (headers, invalid) = validate_headers(chain.as_mut(), height, vec![header]);
+        if let Some(header) = headers.into_iter().next() {
+            tracing::info!("Processing block at height {}", height);
+            // TODO: check if block already stored
+            self.issuing.submit_block_header(header).await?;
+            tracing::info!("Submitted block at height {}", height);
+        }
+        invalid.map_or(Ok(()), Err)
+    }
+
+    /// Submits the block headers from `height` up to `max_height` in up to
+    /// `max_batches_in_flight` batches. Each batch is fetched and validated while the previous
+    /// batches are in flight, and is ordered after them, so it can build on their headers.
+    async fn submit_batches(&self, mut height: u32, max_height: u32) -> Result<(), Error> {
+        let mut chain = self.load_validation_chain(height).await?;
+        let mut in_flight = VecDeque::new();
+        let mut invalid = None;
+
+        while in_flight.len() < self.max_batches_in_flight && invalid.is_none() {
+            let batch_size = self.next_batch_size(height, max_height);
+            if batch_size == 0 {
+                break;
+            }
+            let headers = collect_headers(height, batch_size, &self.backing).await?;
+            // submit the headers before an invalid header, but not the invalid header itself
+            let (headers, err) = validate_headers(chain.as_mut(), height, headers);
+            invalid = err;
+            if headers.is_empty() {
+                break;
+            }
+
+            let batch_size = headers.len() as u32;
+            tracing::info!(
+                "Processing blocks {} -> {} [{}]",
+                height,
+                height + batch_size,
+                batch_size
+            );
+            let pending = match self.issuing.submit_block_header_batch(headers).await {
+                Err(Error::BatchTooLarge(_)) => {
+                    self.reduce_batch_size(batch_size);
+                    break;
+                }
+                result => result?,
+            };
+            // the batch makes progress while the next one is prepared
+            in_flight.push_back((height, batch_size, tokio::spawn(pending)));
+            height += batch_size;
+        }
+
+        while let Some((height, batch_size, pending)) = in_flight.pop_front() {
+            let result = match pending.await {
+                Ok(result) => result,
+                Err(err) => Err(err.into()),
+            };
+            match result {
+                Err(Error::BatchTooLarge(_)) => {
+                    // later batches build on this one, they are interrupted at their first header
+                    self.reduce_batch_size(batch_size);
+                    return Ok(());
+                }
+                Err(err) => {
+                    // later batches build on this one and their nonces follow its nonce, so wait
+                    // for them to settle before resynchronizing, the next call then resubmits
+                    // from the first header that is not stored
+                    tracing::warn!("Batch at height {} failed, awaiting the later batches", height);
+                    for (_, _, pending) in in_flight {
+                        let _ = pending.await;
+                    }
+                    self.issuing.refresh_nonce().await;
+                    return Err(err);
+                }
+                Ok(()) => (),
+            }
+            self.check_batch_stored(height, batch_size).await?;
+            self.batch_size.lock().unwrap().grow(batch_size);
+            tracing::info!(
+                "Submitted blocks {} -> {} [{}]",
+                height,
+                height + batch_size,
+                batch_size
+            );
+        }
+
+        invalid.map_or(Ok(()), Err)
+    }
+
+    fn reduce_batch_size(&self, batch_size: u32) {
+        self.batch_size.lock().unwrap().shrink(batch_size);
+        tracing::warn!(
+            "Batch of {} headers exceeds the block limits, reducing the batch size",
+            batch_size
+        );
+    }
+
+    /// Loads the chain to validate the headers from `height` on with, if chain parameters are
+    /// configured.
+    async fn load_validation_chain(&self, height: u32) -> Result<Option<HeaderChain>, Error> {
+        let params = match self.chain_params {
+            Some(params) => params,
+            None => return Ok(None),
+        };
+
+        match self.load_header_chain(params, height.saturating_sub(1)).await {
+            Err(err @ Error::InvalidHeader(..)) => {
+                RELAY_INVALID_HEADERS.inc();
+                tracing::error!("Bitcoin Core returned inconsistent block headers: {}", err);
+                Err(err)
+            }
+            result => result.map(Some),
+        }
+    }
+
+    /// Loads the headers up to `tip_height` from the backing chain, to validate the headers after
+    /// them. The headers are trusted because the one at `tip_height` is stored in the relay, and
+    /// they are linked to it.
+    async fn load_header_chain(&self, params: ChainParams, tip_height: u32) -> Result<HeaderChain, Error> {
+        let mut recent: Vec<BlockHeader> = Vec::new();
+        for height in tip_height.saturating_sub(10)..=tip_height {
+            let header = parse_header(&self.get_block_header(height).await?)?;
+            if matches!(recent.last(), Some(prev) if header.prev_blockhash != prev.block_hash()) {
+                return Err(Error::InvalidHeader(height, HeaderError::InvalidLinkage));
+            }
+            recent.push(header);
+        }
+        let tip_hash = serialize(&recent.last().ok_or(Error::BlockHashNotFound)?.block_hash());
+        if !self.issuing.is_block_stored(tip_hash).await? {
+            return Err(Error::InvalidHeader(tip_height, HeaderError::InvalidLinkage));
+        }
+
+        // the first header of the difficulty period is needed to check the next adjustment,
+        // it is trusted if it is stored in the relay
+        let period_start_height = tip_height - tip_height % DIFFICULTY_ADJUSTMENT_INTERVAL;
+        let period_start = match tip_height - period_start_height {
+            offset if (offset as usize) < recent.len() => Some(recent[recent.len() - 1 - offset as usize]),
+            _ => {
+                let header = parse_header(&self.get_block_header(period_start_height).await?)?;
+                let hash = serialize(&header.block_hash());
+                if self.issuing.is_block_stored(hash).await? {
+                    Some(header)
+                } else {
+                    None
+                }
+            }
+        };
+
+        Ok(HeaderChain::new(params, tip_height, recent, period_start))
+    }
+
+    /// A batch stops at the first header that cannot be stored without failing as a whole, so
+    /// check that the last header is stored, and otherwise resubmit the first missing header on
+    /// its own to surface the error.
+    async fn check_batch_stored(&self, height: u32, batch_size: u32) -> Result<(), Error> {
+        let last_hash = self.backing.get_block_hash(height + batch_size - 1).await?;
+        if self.issuing.is_block_stored(last_hash).await? {
+            return Ok(());
+        }
+
+        let failed_height = skip_stored_blocks(&self.backing, &self.issuing, height).await?;
+        tracing::warn!(
+            "Batch was interrupted at height {}, submitting the header individually",
+            failed_height
+        );
+        let header = self.get_block_header(failed_height).await?;
+        self.issuing.submit_block_header(header).await
+    }
+}
+
+/// Validates the `headers` from `height` on against the `chain`, if any. Returns the headers
+/// before the first invalid header, and the error for the invalid header.
+fn validate_headers(
+    chain: Option<&mut HeaderChain>,
+    height: u32,
+    mut headers: Vec<Vec<u8>>,
+) -> (Vec<Vec<u8>>, Option<Error>) {
+    let chain = match chain {
+        Some(chain) => chain,
+        None => return (headers, None),
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as u32)
+        .unwrap_or_default();
+    for (index, header) in headers.iter().enumerate() {
+        let header_height = height + index as u32;
+        let result = parse_header(header).and_then(|header| {
+            chain
+                .push(header, now)
+                .map_err(|err| Error::InvalidHeader(header_height, err))
+        });
+        if let Err(err) = result {
+            RELAY_INVALID_HEADERS.inc();
+            tracing::error!("Refusing to relay block header: {}", err);
+            headers.truncate(index);
+            return (headers, Some(err));
+        }
+    }
+    (headers, None)
+}
+
+fn parse_header(header: &[u8]) -> Result<BlockHeader, Error> {
+    deserialize(header).map_err(|_| Error::SerializeHeader)
+}
+
+pub async fn run_relayer<B: Backing, I: Issuing>(runner: Runner<B, I>) -> Result<(), ServiceError> {
+    loop {
+        match runner.submit_next().await {
+            Ok(_) => runner.heartbeat.beat(),
+            Err(Error::InterBtcError(ref err)) if err.is_duplicate_block() => {
+                tracing::info!("Attempted to submit block that already exists");
+                runner.heartbeat.beat();
+            }
+            Err(Error::InterBtcError(ref err)) if err.is_rpc_disconnect_error() => {
+                return Err(ServiceError::ClientShutdown);
+            }
+            Err(Error::BitcoinError(err)) if err.is_connection_refused() => {
+                return Err(ServiceError::ClientShutdown);
+            }
+            Err(Error::InvalidHeader(..)) => {
+                // already reported, wait for Bitcoin Core to return a valid header
+                delay_for(runner.interval).await;
+            }
+            Err(err @ Error::NoQuorum(_)) | Err(err @ Error::NotEnoughSources(..)) => {
+                // wait for the Bitcoin sources to sync or come back
+                tracing::error!("Failed to submit_next: {}", err);
+                delay_for(runner.interval).await;
+            }
+            Err(err @ Error::CheckpointNotInMainChain(_)) => {
+                // Bitcoin Core may still be syncing
+                tracing::error!("Cannot initialize the relay: {}", err);
+                delay_for(runner.interval).await;
+            }
+            Err(err) => {
+                tracing::error!("Failed to submit_next: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use futures::{future, FutureExt};
+    use std::{
+        cell::{Cell, Ref, RefCell, RefMut},
+        collections::HashMap,
+        rc::Rc,
+    };
+
+    /// Blocks stored by the dummy relay. The hash of a header is the header itself.
+    #[derive(Default)]
+    struct DummyRelay {
+        /// Blocks of the main chain, by height.
+        main_chain: HashMap<u32, Vec<u8>>,
+        /// Height and parent of the blocks that are not (or no longer) on the main chain.
+        blocks: HashMap<Vec<u8>, (u32, Vec<u8>)>,
+    }
+
+    impl DummyRelay {
+        fn best_height(&self) -> Option<u32> {
+            self.main_chain.keys().max().copied()
+        }
+
+        fn height_of(&self, hash: &[u8]) -> Option<u32> {
+            self.main_chain
+                .iter()
+                .find(|&(_, h)| &h[..] == hash)
+                .map(|(height, _)| *height)
+                .or_else(|| self.blocks.get(hash).map(|(height, _)| *height))
+        }
+
+        /// Make the fork ending in `tip` the main chain.
+        fn reorg(&mut self, tip: Vec<u8>) {
+            let tip_height = self.height_of(&tip).unwrap();
+            let mut hash = tip;
+            while let Some((height, parent)) = self.blocks.remove(&hash) {
+                if let Some(replaced) = self.main_chain.insert(height, hash) {
+                    self.blocks.insert(replaced, (height, Vec::new()));
+                }
+                hash = parent;
+            }
+            for height in self
+                .main_chain
+                .keys()
+                .copied()
+                .filter(|h| *h > tip_height)
+                .collect::<Vec<_>>()
+            {
+                let replaced = self.main_chain.remove(&height).unwrap();
+                self.blocks.insert(replaced, (height, Vec::new()));
+            }
+        }
+    }
+
+    pub(super) struct DummyIssuing {
+        relay: Rc<RefCell<DummyRelay>>,
+        /// Parents of headers that do not extend the main chain of the relay.
+        parents: HashMap<Vec<u8>, Vec<u8>>,
+        /// A fork becomes the main chain once it is this many blocks higher than the main chain.
+        reorg_threshold: u32,
+        /// Like `utility.batch`, stop a batch at the first header that fails without an error.
+        interrupt_batches: bool,
+        /// Batches with more headers do not fit into a block.
+        max_batch_len: Option<usize>,
+        /// Index of the batch that fails after it was submitted.
+        failing_batch: Option<usize>,
+        submitted_batches: Cell<usize>,
+        /// Set when a batch failed, later batches fail as well until the nonce is refreshed.
+        stalled: Cell<bool>,
+        nonce_refreshes: Cell<u32>,
+    }
+
+    unsafe impl Sync for DummyIssuing {}
+
+    impl DummyIssuing {
+        pub(super) fn new(headers: HashMap<u32, Vec<u8>>) -> DummyIssuing {
+            DummyIssuing {
+                relay: Rc::new(RefCell::new(DummyRelay {
+                    main_chain: headers,
+                    ..Default::default()
+                })),
+                parents: HashMap::new(),
+                reorg_threshold: 1,
+                interrupt_batches: false,
+                max_batch_len: None,
+                failing_batch: None,
+                submitted_batches: Cell::new(0),
+                stalled: Cell::new(false),
+                nonce_refreshes: Cell::new(0),
+            }
+        }
+
+        fn with_forks(headers: HashMap<u32, Vec<u8>>, parents: Vec<(&str, &str)>, reorg_threshold: u32) -> Self {
+            DummyIssuing {
+                parents: parents
+                    .into_iter()
+                    .map(|(hash, parent)| (make_hash(hash), make_hash(parent)))
+                    .collect(),
+                reorg_threshold,
+                ..Self::new(headers)
+            }
+        }
+
+        fn get_relay(&self) -> Ref<DummyRelay> {
+            self.relay.borrow()
+        }
+
+        fn get_relay_mut(&self) -> RefMut<DummyRelay> {
+            self.relay.borrow_mut()
+        }
+    }
+
+    #[async_trait]
+    impl Issuing for DummyIssuing {
+        async fn is_initialized(&self) -> Result<bool, Error> {
+            Ok(!self.get_relay().main_chain.is_empty())
+        }
+
+        async fn initialize(&self, header: Vec<u8>, height: u32) -> Result<(), Error> {
+            if self.get_relay().main_chain.is_empty() {
+                self.get_relay_mut().main_chain.insert(height, header);
+                Ok(())
+            } else {
+                Err(Error::AlreadyInitialized)
+            }
+        }
+
+        async fn submit_block_header(&self, header: Vec<u8>) -> Result<(), Error> {
+            let is_stored = self.is_block_stored(header.clone()).await?;
+            if is_stored {
+                return Err(Error::BlockExists);
+            }
+
+            let mut relay = self.get_relay_mut();
+            let best_height = relay.best_height().ok_or(Error::CannotFetchBestHeight)?;
+            let best_hash = relay.main_chain[&best_height].clone();
+            // NOTE: assume hash(header) == header
+            let parent = self.parents.get(&header).cloned().unwrap_or(best_hash.clone());
+            let height = relay.height_of(&parent).ok_or(Error::BlockHashNotFound)? + 1;
+            if parent == best_hash {
+                relay.main_chain.insert(height, header);
+            } else {
+                relay.blocks.insert(header.clone(), (height, parent));
+                if height >= best_height + self.reorg_threshold {
+                    relay.reorg(header);
+                }
+            }
+            Ok(())
+        }
+
+        async fn submit_block_header_batch(&self, headers: Vec<Vec<u8>>) -> Result<PendingSubmission, Error> {
+            if matches!(self.max_batch_len, Some(len) if headers.len() > len) {
+                return Ok(future::ready(Err(Error::BatchTooLarge(headers.len()))).boxed());
+            }
+            let index = self.submitted_batches.replace(self.submitted_batches.get() + 1);
+            if self.failing_batch == Some(index) {
+                self.stalled.set(true);
+            }
+            if self.stalled.get() {
+                return Ok(future::ready(Err(Error::BlockHashNotFound)).boxed());
+            }
+            // the headers are stored right away, which keeps the batches in order
+            let mut result = Ok(());
+            for header in headers {
+                match self.submit_block_header(header.to_vec()).await {
+                    Err(_) if self.interrupt_batches => break,
+                    Err(err) => {
+                        result = Err(err);
+                        break;
+                    }
+                    Ok(()) => (),
+                }
+            }
+            Ok(future::ready(result).boxed())
+        }
+
+        async fn refresh_nonce(&self) {
+            self.stalled.set(false);
+            self.nonce_refreshes.set(self.nonce_refreshes.get() + 1);
+        }
+
+        async fn get_best_height(&self) -> Result<u32, Error> {
+            self.get_relay().best_height().ok_or(Error::CannotFetchBestHeight)
+        }
+
+        async fn get_block_hash(&self, height: u32) -> Result<Vec<u8>, Error> {
+            self.get_relay()
+                .main_chain
+                .get(&height)
+                .map(|v| v.clone())
+                .ok_or(Error::BlockHashNotFound)
+        }
+
+        async fn is_block_stored(&self, hash: Vec<u8>) -> Result<bool, Error> {
+            Ok(self.get_relay().height_of(&hash).is_some())
+        }
+    }
+
+    pub(super) struct DummyBacking {
+        hashes: RefCell<HashMap<u32, Vec<u8>>>,
+        /// Raw headers by height, for the blocks whose header differs from their hash.
+        headers: HashMap<u32, Vec<u8>>,
+    }
+
+    unsafe impl Sync for DummyBacking {}
+
+    impl DummyBacking {
+        pub(super) fn new(hashes: HashMap<u32, Vec<u8>>) -> DummyBacking {
+            DummyBacking {
+                hashes: RefCell::new(hashes),
+                headers: HashMap::new(),
+            }
+        }
+
+        /// Serve the given headers, e.g. to validate them against real chain parameters.
+        fn with_headers(headers: &[BlockHeader]) -> DummyBacking {
+            DummyBacking {
+                hashes: RefCell::new(
+                    (0..)
+                        .zip(headers)
+                        .map(|(height, header)| (height, serialize(&header.block_hash())))
+                        .collect(),
+                ),
+                headers: (0..)
+                    .zip(headers)
+                    .map(|(height, header)| (height, serialize(header)))
+                    .collect(),
+            }
+        }
+
+        /// Replace the main chain, e.g. to simulate a reorg.
+        fn set_chain(&self, hashes: HashMap<u32, Vec<u8>>) {
+            self.hashes.replace(hashes);
+        }
+    }
+
+    #[async_trait]
+    impl Backing for DummyBacking {
+        async fn get_block_count(&self) -> Result<u32, Error> {
+            self.hashes
+                .borrow()
+                .keys()
+                .max()
+                .map(|v| *v)
+                .ok_or(Error::CannotFetchBestHeight)
+        }
+
+        async fn get_block_header(&self, height: u32) -> Result<Option<Vec<u8>>, Error> {
+            match self.headers.get(&height) {
+                Some(header) => Ok(Some(header.clone())),
+                None => Ok(self.hashes.borrow().get(&height).map(|v| v.clone())),
+            }
+        }
+
+        async fn get_block_hash(&self, height: u32) -> Result<Vec<u8>, Error> {
+            self.hashes
+                .borrow()
+                .get(&height)
+                .map(|v| v.clone())
+                .ok_or(Error::BlockHashNotFound)
+        }
+    }
+
+    pub(super) fn make_hash(hash_hex: &str) -> Vec<u8> {
+        hash_hex.as_bytes().to_vec()
+    }
+
+    pub(super) fn make_hashes(hashes: Vec<(u32, &str)>) -> HashMap<u32, Vec<u8>> {
+        hashes.iter().map(|(k, v)| (*k, make_hash(v))).collect()
+    }
+
+    #[tokio::test]
+    async fn test_dummy_issuing() {
+        let hashes = make_hashes(vec![(2, "a"), (3, "b"), (4, "c")]);
+        let issuing = DummyIssuing::new(hashes);
+
+        assert_eq!(
+            issuing.initialize(make_hash("x"), 1).await,
+            Err(Error::AlreadyInitialized)
+        );
+        assert_eq!(issuing.get_best_height().await, Ok(4));
+        assert_eq!(issuing.get_block_hash(2).await, Ok(make_hash("a")));
+        assert_eq!(issuing.get_block_hash(5).await, Err(Error::BlockHashNotFound));
+        assert_eq!(issuing.is_block_stored(make_hash("a")).await, Ok(true));
+        assert_eq!(issuing.is_block_stored(make_hash("x")).await, Ok(false));
+        assert_eq!(
+            issuing.submit_block_header(make_hash("a")).await,
+            Err(Error::BlockExists)
+        );
+        assert_eq!(issuing.submit_block_header(make_hash("d")).await, Ok(()));
+        assert_eq!(issuing.get_best_height().await, Ok(5));
+    }
+
+    #[tokio::test]
+    async fn compute_start_height_simple() {
+        let hashes = make_hashes(vec![(2, "a"), (3, "b"), (4, "c")]);
+        let backing = DummyBacking::new(hashes.clone());
+        let issuing = DummyIssuing::new(hashes);
+        assert_eq!(
+            Ok(SyncPoint {
+                start_height: 5,
+                best_height: 4,
+                status: ChainStatus::InSync
+            }),
+            compute_start_height(&backing, &issuing).await
+        );
+    }
+
+    #[tokio::test]
+    async fn compute_start_height_missing_blocks() {
+        let backing_hashes = make_hashes(vec![(2, "a"), (3, "b"), (4, "c")]);
+        let issuing_hashes = make_hashes(vec![(2, "a"), (3, "b")]);
+        let backing = DummyBacking::new(backing_hashes);
+        let issuing = DummyIssuing::new(issuing_hashes);
+        assert_eq!(
+            Ok(4),
+            compute_start_height(&backing, &issuing)
+                .await
+                .map(|sync_point| sync_point.start_height)
+        );
+    }
+
+    #[tokio::test]
+    async fn compute_start_height_with_fork() {
+        // height of c should also be 4 but we cannot model fork with this dummy implementation
+        let backing_hashes = make_hashes(vec![(2, "a"), (3, "b"), (4, "c")]);
+        let issuing_hashes = make_hashes(vec![(2, "a"), (3, "b"), (4, "d"), (0, "c")]);
+        let backing = DummyBacking::new(backing_hashes);
+        let issuing = DummyIssuing::new(issuing_hashes);
+        assert_eq!(
+            Ok(SyncPoint {
+                start_height: 5,
+                best_height: 4,
+                status: ChainStatus::Diverged {
+                    ancestor_height: 3,
+                    depth: 1
+                }
+            }),
+            compute_start_height(&backing, &issuing).await
+        );
+    }
+
+    #[tokio::test]
+    async fn new_runner_with_best() -> Result<(), Error> {
+        let hashes = make_hashes(vec![(2, "a"), (3, "b"), (4, "c")]);
+        let backing = DummyBacking::new(hashes.clone());
+        let issuing = DummyIssuing::new(hashes);
+        let runner = Runner::new(
+            backing,
+            issuing,
+            Config {
+                start_height: None,
+                checkpoint: None,
+                max_batch_size: 1,
+                interval: None,
+                btc_confirmations: 0,
+                chain_params: None,
+                max_batches_in_flight: 1,
+            },
+        );
+
+        assert_eq!(runner.issuing.get_best_height().await.unwrap(), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn catchup_when_out_of_sync() -> Result<(), Error> {
+        let backing_hashes = make_hashes(vec![(2, "a"), (3, "b"), (4, "c"), (5, "d"), (6, "e")]);
+        let issuing_hashes = make_hashes(vec![(2, "a"), (3, "b")]);
+        let backing = DummyBacking::new(backing_hashes);
+        let issuing = DummyIssuing::new(issuing_hashes);
+        let runner = Runner::new(
+            backing,
+            issuing,
+            Config {
+                start_height: Some(0),
+                checkpoint: None,
+                max_batch_size: 16,
+                interval: None,
+                btc_confirmations: 0,
+                chain_params: None,
+                max_batches_in_flight: 1,
+            },
+        );
+
+        let height_before = runner.issuing.get_best_height().await?;
+        assert_eq!(height_before, 3);
+
+        runner.submit_next().await?;
+        let height_after = runner.issuing.get_best_height().await?;
+        assert_eq!(height_after, 6);
+
+        let best_height = runner.backing.get_block_count().await?;
+        assert_eq!(height_after, best_height);
+
+        assert!(runner.issuing.is_block_stored(make_hash("c")).await?);
+        assert!(runner.issuing.is_block_stored(make_hash("d")).await?);
+        assert!(runner.issuing.is_block_stored(make_hash("e")).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_success() -> Result<(), Error> {
+        let backing_hashes = make_hashes(vec![(2, "a"), (3, "b"), (4, "c"), (5, "d")]);
+        let issuing_hashes = make_hashes(vec![(2, "a"), (3, "b")]);
+        let backing = DummyBacking::new(backing_hashes);
+        let issuing = DummyIssuing::new(issuing_hashes);
+        let runner = Runner::new(
+            backing,
+            issuing,
+            Config {
+                start_height: None,
+                checkpoint: None,
+                max_batch_size: 1,
+                interval: None,
+                btc_confirmations: 0,
+                chain_params: None,
+                max_batches_in_flight: 1,
+            },
+        );
+
+        let height_before = runner.issuing.get_best_height().await?;
+        assert_eq!(height_before, 3);
+
+        runner.submit_next().await?;
+        let height_after = runner.issuing.get_best_height().await?;
+        assert_eq!(height_after, 4);
+
+        assert!(runner.issuing.is_block_stored(make_hash("c")).await?);
+        assert!(!runner.issuing.is_block_stored(make_hash("d")).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_with_1_confirmation_batch_submission_succeeds() -> Result<(), Error> {
+        let backing_hashes = make_hashes(vec![(2, "a"), (3, "b"), (4, "c"), (5, "d")]);
+        let issuing_hashes = make_hashes(vec![(2, "a")]);
+        let backing = DummyBacking::new(backing_hashes);
+        let issuing = DummyIssuing::new(issuing_hashes);
+        let runner = Runner::new(
+            backing,
+            issuing,
+            Config {
+                start_height: None,
+                checkpoint: None,
+                interval: Some(Duration::from_secs(0)),
+                max_batch_size: 16,
+                btc_confirmations: 1,
+                chain_params: None,
+                max_batches_in_flight: 1,
+            },
+        );
+
+        let height_before = runner.issuing.get_best_height().await?;
+        assert_eq!(height_before, 2);
+
+        runner.submit_next().await?;
+        runner.submit_next().await?;
+
+        let height_after = runner.issuing.get_best_height().await?;
+        assert_eq!(height_after, 4);
+
+        assert!(runner.issuing.is_block_stored(make_hash("c")).await?);
+        // this block has not been confirmed yet, so we should not have submitted it
+        assert!(!runner.issuing.is_block_stored(make_hash("d")).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_with_1_confirmation_single_submission_succeeds() -> Result<(), Error> {
+        let backing_hashes = make_hashes(vec![(2, "a"), (3, "b"), (4, "c"), (5, "d")]);
+        let issuing_hashes = make_hashes(vec![(2, "a")]);
+        let backing = DummyBacking::new(backing_hashes);
+        let issuing = DummyIssuing::new(issuing_hashes);
+        let runner = Runner::new(
+            backing,
+            issuing,
+            Config {
+                start_height: None,
+                checkpoint: None,
+                max_batch_size: 1,
+                interval: Some(Duration::from_secs(0)),
+                btc_confirmations: 1,
+                chain_params: None,
+                max_batches_in_flight: 1,
+            },
+        );
+
+        let height_before = runner.issuing.get_best_height().await?;
+        assert_eq!(height_before, 2);
+
+        for _ in 0..10 {
+            runner.submit_next().await?;
+        }
+
+        let height_after = runner.issuing.get_best_height().await?;
+        assert_eq!(height_after, 4);
+
+        assert!(runner.issuing.is_block_stored(make_hash("c")).await?);
+        // this block has not been confirmed yet, so we should not have submitted it
+        assert!(!runner.issuing.is_block_stored(make_hash("d")).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_with_2_confirmation_succeeds() -> Result<(), Error> {
+        let backing_hashes = make_hashes(vec![(2, "a"), (3, "b"), (4, "c"), (5, "d")]);
+        let issuing_hashes = make_hashes(vec![(2, "a")]);
+        let backing = DummyBacking::new(backing_hashes);
+        let issuing = DummyIssuing::new(issuing_hashes);
+        let runner = Runner::new(
+            backing,
+            issuing,
+            Config {
+                start_height: None,
+                checkpoint: None,
+                max_batch_size: 1,
+                interval: Some(Duration::from_secs(0)),
+                btc_confirmations: 2,
+                chain_params: None,
+                max_batches_in_flight: 1,
+            },
+        );
+
+        let height_before = runner.issuing.get_best_height().await?;
+        assert_eq!(height_before, 2);
+
+        for _ in 0..10 {
+            runner.submit_next().await?;
+        }
+
+        let height_after = runner.issuing.get_best_height().await?;
+        assert_eq!(height_after, 3);
+
+        assert!(runner.issuing.is_block_stored(make_hash("b")).await?);
+
+        // these blocks have not been confirmed yet, so we should not have submitted it
+        assert!(!runner.issuing.is_block_stored(make_hash("c")).await?);
+        assert!(!runner.issuing.is_block_stored(make_hash("d")).await?);
+        Ok(())
+    }
+
+    /// Mines a regtest header on top of `prev_blockhash`.
+    fn mine(prev_blockhash: bitcoin::BlockHash, time: u32, bits: u32) -> BlockHeader {
+        let mut header = BlockHeader {
+            version: 4,
+            prev_blockhash,
+            merkle_root: Default::default(),
+            time,
+            bits,
+            nonce: 0,
+        };
+        while header.validate_pow(&header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    #[tokio::test]
+    async fn submit_next_refuses_header_with_unexpected_difficulty() -> Result<(), Error> {
+        let start = 1_600_000_000;
+        let mut headers = vec![mine(Default::default(), start, 0x207fffff)];
+        for i in 1..=10 {
+            let prev = headers.last().unwrap().block_hash();
+            headers.push(mine(prev, start + i * 600, 0x207fffff));
+        }
+        // the proof of work is valid, but regtest expects the pow limit
+        let prev = headers.last().unwrap().block_hash();
+        headers.push(mine(prev, start + 11 * 600, 0x2000ffff));
+
+        let backing = DummyBacking::with_headers(&headers);
+        let stored = (0..)
+            .zip(&headers[..=10])
+            .map(|(height, header)| (height, serialize(&header.block_hash())))
+            .collect();
+        let issuing = DummyIssuing::new(stored);
+        let runner = Runner::new(
+            backing,
+            issuing,
+            Config {
+                max_batch_size: 16,
+                interval: Some(Duration::from_secs(0)),
+                chain_params: Some(ChainParams::new(bitcoin::Network::Regtest)),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            runner.submit_next().await,
+            Err(Error::InvalidHeader(
+                11,
+                HeaderError::UnexpectedBits {
+                    expected: 0x207fffff,
+                    actual: 0x2000ffff
+                }
+            ))
+        );
+        assert_eq!(runner.issuing.get_best_height().await?, 10);
+        assert!(
+            !runner
+                .issuing
+                .is_block_stored(serialize(&headers[11].block_hash()))
+                .await?
+        );
+        Ok(())
+    }
+
+    fn new_runner(
+        backing: DummyBacking,
+        issuing: DummyIssuing,
+        max_batch_size: u32,
+    ) -> Runner<DummyBacking, DummyIssuing> {
+        Runner::new(
+            backing,
+            issuing,
+            Config {
+                start_height: None,
+                checkpoint: None,
+                max_batch_size,
+                interval: Some(Duration::from_secs(0)),
+                btc_confirmations: 0,
+                chain_params: None,
+                max_batches_in_flight: 1,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn submit_next_follows_reorg() -> Result<(), Error> {
+        let hashes = make_hashes(vec![(1, "a"), (2, "b"), (3, "c"), (4, "d")]);
+        let backing = DummyBacking::new(hashes.clone());
+        let issuing = DummyIssuing::with_forks(hashes, vec![("x", "b"), ("y", "x"), ("z", "y")], 1);
+        let runner = new_runner(backing, issuing, 16);
+
+        runner.submit_next().await?;
+        assert_eq!(runner.chain_status(), ChainStatus::InSync);
+
+        runner
+            .backing
+            .set_chain(make_hashes(vec![(1, "a"), (2, "b"), (3, "x"), (4, "y"), (5, "z")]));
+        runner.submit_next().await?;
+        assert_eq!(
+            runner.chain_status(),
+            ChainStatus::Diverged {
+                ancestor_height: 2,
+                depth: 2
+            }
+        );
+        assert_eq!(runner.issuing.get_best_height().await?, 5);
+        assert_eq!(runner.issuing.get_block_hash(3).await?, make_hash("x"));
+
+        // the stale blocks remain stored in the relay
+        assert!(runner.issuing.is_block_stored(make_hash("c")).await?);
+        assert!(runner.issuing.is_block_stored(make_hash("d")).await?);
+
+        runner.submit_next().await?;
+        assert_eq!(runner.chain_status(), ChainStatus::InSync);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_extends_fork_until_relay_switches() -> Result<(), Error> {
+        let hashes = make_hashes(vec![(1, "a"), (2, "b"), (3, "c"), (4, "d")]);
+        let backing = DummyBacking::new(make_hashes(vec![
+            (1, "a"),
+            (2, "b"),
+            (3, "x"),
+            (4, "y"),
+            (5, "z"),
+            (6, "w"),
+            (7, "v"),
+        ]));
+        let parents = vec![("x", "b"), ("y", "x"), ("z", "y"), ("w", "z"), ("v", "w")];
+        let issuing = DummyIssuing::with_forks(hashes, parents, 3);
+        let runner = new_runner(backing, issuing, 2);
+
+        // the fork is submitted in batches, but the relay keeps its main chain
+        for _ in 0..2 {
+            runner.submit_next().await?;
+            assert_eq!(
+                runner.chain_status(),
+                ChainStatus::Diverged {
+                    ancestor_height: 2,
+                    depth: 2
+                }
+            );
+            assert_eq!(runner.issuing.get_best_height().await?, 4);
+            assert_eq!(runner.issuing.get_block_hash(4).await?, make_hash("d"));
+        }
+        assert!(runner.issuing.is_block_stored(make_hash("w")).await?);
+
+        runner.submit_next().await?;
+        assert_eq!(runner.issuing.get_best_height().await?, 7);
+        assert_eq!(runner.issuing.get_block_hash(3).await?, make_hash("x"));
+
+        runner.submit_next().await?;
+        assert_eq!(runner.chain_status(), ChainStatus::InSync);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_surfaces_interrupted_batch() -> Result<(), Error> {
+        let backing = DummyBacking::new(make_hashes(vec![(1, "a"), (2, "b"), (3, "c"), (4, "d"), (5, "e")]));
+        let issuing = DummyIssuing {
+            interrupt_batches: true,
+            ..DummyIssuing::with_forks(make_hashes(vec![(1, "a"), (2, "b")]), vec![("d", "unknown")], 1)
+        };
+        let runner = new_runner(backing, issuing, 16);
+
+        assert_eq!(runner.submit_next().await, Err(Error::BlockHashNotFound));
+        assert!(runner.issuing.is_block_stored(make_hash("c")).await?);
+        assert!(!runner.issuing.is_block_stored(make_hash("d")).await?);
+        assert!(!runner.issuing.is_block_stored(make_hash("e")).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_with_shorter_bitcoin_chain() -> Result<(), Error> {
+        let hashes = make_hashes(vec![(1, "a"), (2, "b"), (3, "c"), (4, "d")]);
+        let backing = DummyBacking::new(make_hashes(vec![(1, "a"), (2, "b"), (3, "x")]));
+        let issuing = DummyIssuing::with_forks(hashes, vec![("x", "b")], 1);
+        let runner = new_runner(backing, issuing, 16);
+
+        runner.submit_next().await?;
+        assert_eq!(
+            runner.chain_status(),
+            ChainStatus::Diverged {
+                ancestor_height: 2,
+                depth: 2
+            }
+        );
+        assert!(runner.issuing.is_block_stored(make_hash("x")).await?);
+        // the relay needs a longer chain before it switches
+        assert_eq!(runner.issuing.get_block_hash(4).await?, make_hash("d"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_initializes_at_difficulty_adjustment() -> Result<(), Error> {
+        let hashes: Vec<(u32, String)> = (0..4100).map(|height| (height, format!("h{}", height))).collect();
+        let backing = DummyBacking::new(make_hashes(
+            hashes.iter().map(|(height, hash)| (*height, hash.as_str())).collect(),
+        ));
+        let issuing = DummyIssuing::new(HashMap::new());
+        let runner = new_runner(backing, issuing, 16);
+
+        runner.submit_next().await?;
+        // 4093 is the highest block with 6 confirmations
+        assert_eq!(runner.issuing.get_block_hash(4032).await?, make_hash("h4032"));
+        assert!(!runner.issuing.is_block_stored(make_hash("h4031")).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_initializes_at_checkpoint_on_main_chain() -> Result<(), Error> {
+        let hashes = make_hashes(vec![(1, "a"), (2, "b"), (3, "c"), (4, "d")]);
+        let new_runner = |hash: &str| {
+            Runner::new(
+                DummyBacking::new(hashes.clone()),
+                DummyIssuing::new(HashMap::new()),
+                Config {
+                    checkpoint: Some(Checkpoint {
+                        height: 2,
+                        hash: make_hash(hash),
+                        header: make_hash(hash),
+                    }),
+                    max_batch_size: 16,
+                    interval: Some(Duration::from_secs(0)),
+                    ..Default::default()
+                },
+            )
+        };
+
+        let runner = new_runner("x");
+        assert_eq!(runner.submit_next().await, Err(Error::CheckpointNotInMainChain(2)));
+        assert!(!runner.issuing.is_initialized().await?);
+
+        let runner = new_runner("b");
+        runner.submit_next().await?;
+        assert_eq!(runner.issuing.get_block_hash(2).await?, make_hash("b"));
+        assert!(!runner.issuing.is_block_stored(make_hash("a")).await?);
+        assert_eq!(runner.issuing.get_best_height().await?, 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn collect_headers_preserves_order() -> Result<(), Error> {
+        let hashes: Vec<(u32, String)> = (0..20).map(|height| (height, format!("h{}", height))).collect();
+        let backing = DummyBacking::new(make_hashes(
+            hashes.iter().map(|(height, hash)| (*height, hash.as_str())).collect(),
+        ));
+
+        let headers = collect_headers(3, 15, &backing).await?;
+        assert_eq!(
+            headers,
+            (3..18)
+                .map(|height| make_hash(&format!("h{}", height)))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(collect_headers(18, 3, &backing).await, Err(Error::BlockHashNotFound));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_pipelines_batches() -> Result<(), Error> {
+        let backing = DummyBacking::new(make_hashes(vec![
+            (1, "a"),
+            (2, "b"),
+            (3, "c"),
+            (4, "d"),
+            (5, "e"),
+            (6, "f"),
+            (7, "g"),
+            (8, "h"),
+        ]));
+        let issuing = DummyIssuing::new(make_hashes(vec![(1, "a")]));
+        let runner = Runner::new(
+            backing,
+            issuing,
+            Config {
+                start_height: None,
+                checkpoint: None,
+                max_batch_size: 2,
+                interval: Some(Duration::from_secs(0)),
+                btc_confirmations: 0,
+                chain_params: None,
+                max_batches_in_flight: 3,
+            },
+        );
+
+        runner.submit_next().await?;
+        assert_eq!(runner.issuing.get_best_height().await?, 7);
+        assert_eq!(runner.issuing.get_block_hash(7).await?, make_hash("g"));
+
+        runner.submit_next().await?;
+        assert_eq!(runner.issuing.get_best_height().await?, 8);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_shrinks_batches_exceeding_block_limits() -> Result<(), Error> {
+        let backing = DummyBacking::new(make_hashes(vec![
+            (1, "a"),
+            (2, "b"),
+            (3, "c"),
+            (4, "d"),
+            (5, "e"),
+            (6, "f"),
+            (7, "g"),
+            (8, "h"),
+            (9, "i"),
+            (10, "j"),
+        ]));
+        let issuing = DummyIssuing {
+            max_batch_len: Some(3),
+            ..DummyIssuing::new(make_hashes(vec![(1, "a")]))
+        };
+        let runner = new_runner(backing, issuing, 8);
+
+        // 8 and 4 headers do not fit
+        for _ in 0..2 {
+            runner.submit_next().await?;
+            assert_eq!(runner.issuing.get_best_height().await?, 1);
+        }
+
+        runner.submit_next().await?;
+        assert_eq!(runner.issuing.get_best_height().await?, 3);
+
+        // grows back to the largest size below the one that failed
+        runner.submit_next().await?;
+        assert_eq!(runner.issuing.get_best_height().await?, 6);
+        runner.submit_next().await?;
+        assert_eq!(runner.issuing.get_best_height().await?, 9);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn submit_next_resubmits_after_failure_in_pipeline() -> Result<(), Error> {
+        let backing = DummyBacking::new(make_hashes(vec![
+            (1, "a"),
+            (2, "b"),
+            (3, "c"),
+            (4, "d"),
+            (5, "e"),
+            (6, "f"),
+            (7, "g"),
+        ]));
+        let issuing = DummyIssuing {
+            failing_batch: Some(1),
+            ..DummyIssuing::new(make_hashes(vec![(1, "a")]))
+        };
+        let runner = Runner::new(
+            backing,
+            issuing,
+            Config {
+                max_batch_size: 2,
+                interval: Some(Duration::from_secs(0)),
+                max_batches_in_flight: 3,
+                ..Default::default()
+            },
+        );
+
+        // the batch after the failed one is awaited before the nonce is refreshed
+        assert_eq!(runner.submit_next().await, Err(Error::BlockHashNotFound));
+        assert_eq!(runner.issuing.submitted_batches.get(), 3);
+        assert_eq!(runner.issuing.nonce_refreshes.get(), 1);
+        assert_eq!(runner.issuing.get_best_height().await?, 3);
+
+        // resubmitted in order from the first header that is not stored
+        runner.submit_next().await?;
+        assert_eq!(runner.issuing.get_best_height().await?, 7);
+        for (height, hash) in vec![(4, "d"), (5, "e"), (6, "f"), (7, "g")] {
+            assert_eq!(runner.issuing.get_block_hash(height).await?, make_hash(hash));
+        }
+        assert_eq!(runner.issuing.nonce_refreshes.get(), 1);
+        Ok(())
+    }
+
+    /// Backing whose Bitcoin sources never agree.
+    #[derive(Default)]
+    struct DisagreeingBacking {
+        requests: Rc<Cell<u32>>,
+    }
+
+    unsafe impl Sync for DisagreeingBacking {}
+
+    #[async_trait]
+    impl Backing for DisagreeingBacking {
+        async fn get_block_count(&self) -> Result<u32, Error> {
+            self.requests.set(self.requests.get() + 1);
+            // let the timeout of the test fire, even if the relayer does not wait
+            tokio::task::yield_now().await;
+            Err(Error::NotEnoughSources(1, 2))
+        }
+
+        async fn get_block_header(&self, height: u32) -> Result<Option<Vec<u8>>, Error> {
+            Err(Error::NoQuorum(height))
+        }
+
+        async fn get_block_hash(&self, height: u32) -> Result<Vec<u8>, Error> {
+            Err(Error::NoQuorum(height))
+        }
+    }
+
+    #[tokio::test]
+    async fn run_relayer_waits_without_quorum() {
+        let backing = DisagreeingBacking::default();
+        let requests = backing.requests.clone();
+        let runner = Runner::new(
+            backing,
+            DummyIssuing::new(make_hashes(vec![(2, "a")])),
+            issuing,
+            Config {
+                interval: Some(Duration::from_millis(100)),
+                max_batch_size: 1,
+                ..Default::default()
+            },
+        );
+
+        let result = tokio::time::timeout(Duration::from_millis(250), run_relayer(runner)).await;
+        assert!(result.is_err());
+        assert!(requests.get() <= 3);
+    }
+}
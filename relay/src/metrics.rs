@@ -0,0 +1,50 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    register_int_counter, register_int_counter_vec, register_int_gauge, IntCounter, IntCounterVec, IntGauge,
+};
+
+lazy_static! {
+    pub(crate) static ref RELAY_BEST_HEIGHT: IntGauge =
+        register_int_gauge!("relay_best_height", "Height of the best block stored in the relay").unwrap();
+    pub(crate) static ref RELAY_BITCOIN_HEIGHT: IntGauge = register_int_gauge!(
+        "relay_bitcoin_height",
+        "Height of the best Bitcoin block with enough confirmations to be relayed"
+    )
+    .unwrap();
+    pub(crate) static ref RELAY_REORGS: IntCounter = register_int_counter!(
+        "relay_reorgs_total",
+        "Number of Bitcoin reorgs that the relay main chain had to follow"
+    )
+    .unwrap();
+    pub(crate) static ref RELAY_FORK_DEPTH: IntGauge = register_int_gauge!(
+        "relay_fork_depth",
+        "Number of blocks on the relay main chain that are not on the Bitcoin main chain"
+    )
+    .unwrap();
+    pub(crate) static ref RELAY_INVALID_HEADERS: IntCounter = register_int_counter!(
+        "relay_invalid_headers_total",
+        "Number of block headers from Bitcoin Core that failed local validation"
+    )
+    .unwrap();
+    pub(crate) static ref RELAY_BACKING_DISAGREEMENTS: IntCounter = register_int_counter!(
+        "relay_backing_disagreements_total",
+        "Number of times the Bitcoin sources of the relayer returned different blocks at the same height"
+    )
+    .unwrap();
+    pub(crate) static ref RELAY_LAG: IntGauge = register_int_gauge!(
+        "relay_lag_blocks",
+        "Number of Bitcoin main chain blocks that are not on the relay main chain"
+    )
+    .unwrap();
+    pub(crate) static ref RELAY_ALERT_LEVEL: IntGauge = register_int_gauge!(
+        "relay_alert_level",
+        "Alert level of the relay monitor: 0 (ok), 1 (warning) or 2 (critical)"
+    )
+    .unwrap();
+    pub(crate) static ref RELAY_ALERTS: IntCounterVec = register_int_counter_vec!(
+        "relay_alerts_total",
+        "Number of times the relay monitor raised its alert level",
+        &["level"]
+    )
+    .unwrap();
+}
@@ -0,0 +1,382 @@
+use super::{find_common_ancestor, Backing, Error, Heartbeat, Issuing};
+use crate::metrics::{RELAY_ALERTS, RELAY_ALERT_LEVEL, RELAY_LAG};
+use clap::Clap;
+use hyper::{client::HttpConnector, Body, Client, Method, Request};
+use hyper_tls::HttpsConnector;
+use runtime::{cli::parse_duration_ms, InterBtcParachain};
+use serde::Serialize;
+use service::Error as ServiceError;
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::time::delay_for;
+
+#[derive(Clap, Clone, Debug)]
+pub struct RelayMonitorConfig {
+    /// Monitor whether the relay keeps up with the Bitcoin main chain.
+    #[clap(long)]
+    pub relay_monitor: bool,
+
+    /// Interval in milliseconds at which the relay is compared with Bitcoin.
+    #[clap(long, parse(try_from_str = parse_duration_ms), default_value = "60000")]
+    pub relay_monitor_interval_ms: Duration,
+
+    /// Number of Bitcoin main chain blocks missing from the relay at which a warning is raised.
+    #[clap(long, default_value = "3")]
+    pub relay_lag_warning: u32,
+
+    /// Number of Bitcoin main chain blocks missing from the relay at which a critical alert is
+    /// raised.
+    #[clap(long, default_value = "6")]
+    pub relay_lag_critical: u32,
+
+    /// Time in milliseconds after which a warning is raised if the best block of the relay has
+    /// not changed although Bitcoin has newer blocks.
+    #[clap(long, parse(try_from_str = parse_duration_ms), default_value = "3600000")]
+    pub relay_stale_tip_ms: Duration,
+
+    /// URL that alerts are posted to as json whenever the alert level changes.
+    #[clap(long)]
+    pub relay_alert_webhook: Option<String>,
+
+    /// Number of confirmations a block needs to have before it is relayed, as given by
+    /// `--bitcoin-relay-confirmations`. Blocks with fewer confirmations are not missing yet.
+    #[clap(skip)]
+    pub btc_confirmations: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertLevel {
+    Ok,
+    Warning,
+    Critical,
+}
+
+impl AlertLevel {
+    fn name(&self) -> &'static str {
+        match self {
+            AlertLevel::Ok => "ok",
+            AlertLevel::Warning => "warning",
+            AlertLevel::Critical => "critical",
+        }
+    }
+}
+
+/// Position of the relay relative to the Bitcoin main chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RelayStatus {
+    /// Height of the best Bitcoin block with enough confirmations to be relayed.
+    pub bitcoin_height: u32,
+    pub relay_height: u32,
+    /// Number of Bitcoin main chain blocks that are not on the relay main chain.
+    pub lag: u32,
+    /// Number of relay main chain blocks that are not on the Bitcoin main chain.
+    pub fork_depth: u32,
+    /// Seconds since the monitor has first seen the current best block of the relay.
+    pub tip_age_secs: u64,
+}
+
+impl RelayStatus {
+    fn is_stale(&self, config: &RelayMonitorConfig) -> bool {
+        self.lag > 0 && self.tip_age_secs >= config.relay_stale_tip_ms.as_secs()
+    }
+
+    pub fn level(&self, config: &RelayMonitorConfig) -> AlertLevel {
+        if self.lag >= config.relay_lag_critical {
+            AlertLevel::Critical
+        } else if self.lag >= config.relay_lag_warning || self.fork_depth > 0 || self.is_stale(config) {
+            AlertLevel::Warning
+        } else {
+            AlertLevel::Ok
+        }
+    }
+}
+
+impl fmt::Display for RelayStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the relay lacks {} Bitcoin main chain blocks (relay at {}, Bitcoin at {})",
+            self.lag, self.relay_height, self.bitcoin_height
+        )?;
+        if self.fork_depth > 0 {
+            write!(
+                f,
+                ", its last {} blocks are not on the Bitcoin main chain",
+                self.fork_depth
+            )?;
+        }
+        if self.lag > 0 {
+            write!(f, ", its best block has not changed for {}s", self.tip_age_secs)?;
+        }
+        Ok(())
+    }
+}
+
+/// Body of the requests to the alert webhook.
+#[derive(Serialize)]
+struct Alert<'a> {
+    level: AlertLevel,
+    message: String,
+    #[serde(flatten)]
+    status: &'a RelayStatus,
+}
+
+struct Webhook {
+    url: String,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl Webhook {
+    fn new(url: String) -> Self {
+        let https = HttpsConnector::new();
+        let client = Client::builder().build::<_, Body>(https);
+        Self { url, client }
+    }
+
+    async fn post(&self, alert: &Alert<'_>) -> Result<(), Error> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(&self.url)
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_string(alert)?))?;
+        let response = self.client.request(request).await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::InvalidResponse(self.url.clone()))
+        }
+    }
+}
+
+/// Compares the relay with the Bitcoin main chain and raises alerts if it falls behind or
+/// follows another chain.
+pub struct RelayMonitor<B: Backing, I: Issuing> {
+    backing: B,
+    issuing: I,
+    config: RelayMonitorConfig,
+    webhook: Option<Webhook>,
+    /// Best height of the relay and when it was first seen.
+    tip: Mutex<Option<(u32, Instant)>>,
+    level: Mutex<AlertLevel>,
+    heartbeat: Heartbeat,
+}
+
+impl<B: Backing, I: Issuing> RelayMonitor<B, I> {
+    pub fn new(backing: B, issuing: I, config: RelayMonitorConfig) -> Self {
+        Self {
+            backing,
+            issuing,
+            webhook: config.relay_alert_webhook.clone().map(Webhook::new),
+            config,
+            tip: Mutex::new(None),
+            level: Mutex::new(AlertLevel::Ok),
+            heartbeat: Heartbeat::default(),
+        }
+    }
+
+    /// Report successful checks to `heartbeat`.
+    pub fn with_heartbeat(self, heartbeat: Heartbeat) -> Self {
+        Self { heartbeat, ..self }
+    }
+
+    /// Returns the alert level as of the last report.
+    pub fn alert_level(&self) -> AlertLevel {
+        *self.level.lock().unwrap()
+    }
+
+    pub async fn check(&self) -> Result<RelayStatus, Error> {
+        let bitcoin_height = self
+            .backing
+            .get_block_count()
+            .await?
+            .saturating_sub(self.config.btc_confirmations);
+        let (ancestor_height, relay_height) = find_common_ancestor(&self.backing, &self.issuing).await?;
+
+        let tip_age = {
+            let mut tip = self.tip.lock().unwrap();
+            match *tip {
+                Some((height, since)) if height == relay_height => since.elapsed(),
+                _ => {
+                    *tip = Some((relay_height, Instant::now()));
+                    Duration::from_secs(0)
+                }
+            }
+        };
+
+        Ok(RelayStatus {
+            bitcoin_height,
+            relay_height,
+            lag: bitcoin_height.saturating_sub(ancestor_height),
+            fork_depth: relay_height - ancestor_height,
+            tip_age_secs: tip_age.as_secs(),
+        })
+    }
+
+    /// Updates the metrics, and logs and posts an alert if the alert level changed.
+    pub async fn report(&self, status: &RelayStatus) {
+        let level = status.level(&self.config);
+        RELAY_LAG.set(status.lag as i64);
+        RELAY_ALERT_LEVEL.set(level as i64);
+
+        let previous = std::mem::replace(&mut *self.level.lock().unwrap(), level);
+        if previous == level {
+            return;
+        }
+        match level {
+            AlertLevel::Critical => tracing::error!("Relay alert: {}", status),
+            AlertLevel::Warning => tracing::warn!("Relay warning: {}", status),
+            AlertLevel::Ok => tracing::info!("Relay recovered: {}", status),
+        }
+        if level != AlertLevel::Ok {
+            RELAY_ALERTS.with_label_values(&[level.name()]).inc();
+        }
+
+        if let Some(webhook) = &self.webhook {
+            let alert = Alert {
+                level,
+                message: status.to_string(),
+                status,
+            };
+            if let Err(err) = webhook.post(&alert).await {
+                tracing::error!("Failed to post relay alert to {}: {}", webhook.url, err);
+            }
+        }
+    }
+}
+
+pub async fn run_relay_monitor<B: Backing + Send + Sync>(
+    monitor: RelayMonitor<B, InterBtcParachain>,
+) -> Result<(), ServiceError> {
+    loop {
+        match monitor.check().await {
+            Ok(status) => {
+                monitor.report(&status).await;
+                monitor.heartbeat.beat();
+            }
+            Err(Error::InterBtcError(ref err)) if err.is_rpc_disconnect_error() => {
+                return Err(ServiceError::ClientShutdown);
+            }
+            Err(Error::BitcoinError(err)) if err.is_connection_refused() => {
+                return Err(ServiceError::ClientShutdown);
+            }
+            Err(err) => {
+                tracing::error!("Failed to check the relay: {}", err);
+            }
+        }
+        delay_for(monitor.config.relay_monitor_interval_ms).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::tests::{make_hash, make_hashes, DummyBacking, DummyIssuing},
+        *,
+    };
+
+    fn config() -> RelayMonitorConfig {
+        RelayMonitorConfig {
+            relay_monitor: true,
+            relay_monitor_interval_ms: Duration::from_secs(60),
+            relay_lag_warning: 3,
+            relay_lag_critical: 6,
+            relay_stale_tip_ms: Duration::from_secs(3600),
+            relay_alert_webhook: None,
+            btc_confirmations: 0,
+        }
+    }
+
+    fn status(lag: u32, fork_depth: u32, tip_age_secs: u64) -> RelayStatus {
+        RelayStatus {
+            bitcoin_height: 100,
+            relay_height: 100 - lag + fork_depth,
+            lag,
+            fork_depth,
+            tip_age_secs,
+        }
+    }
+
+    #[test]
+    fn level_depends_on_lag_fork_and_tip_age() {
+        let config = config();
+        assert_eq!(status(0, 0, 7200).level(&config), AlertLevel::Ok);
+        assert_eq!(status(2, 0, 0).level(&config), AlertLevel::Ok);
+        assert_eq!(status(3, 0, 0).level(&config), AlertLevel::Warning);
+        assert_eq!(status(1, 1, 0).level(&config), AlertLevel::Warning);
+        assert_eq!(status(1, 0, 3600).level(&config), AlertLevel::Warning);
+        assert_eq!(status(6, 0, 0).level(&config), AlertLevel::Critical);
+        assert_eq!(status(7, 2, 0).level(&config), AlertLevel::Critical);
+    }
+
+    #[tokio::test]
+    async fn check_reports_lag_and_fork_depth() -> Result<(), Error> {
+        let backing = DummyBacking::new(make_hashes(vec![(1, "a"), (2, "b"), (3, "c"), (4, "d")]));
+        let issuing = DummyIssuing::new(make_hashes(vec![(1, "a"), (2, "b"), (3, "x")]));
+        let monitor = RelayMonitor::new(backing, issuing, config());
+
+        let status = monitor.check().await?;
+        assert_eq!(status.relay_height, 3);
+        assert_eq!(status.lag, 2);
+        assert_eq!(status.fork_depth, 1);
+
+        monitor.report(&status).await;
+        assert_eq!(monitor.alert_level(), AlertLevel::Warning);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn check_ignores_unconfirmed_blocks() -> Result<(), Error> {
+        let backing = DummyBacking::new(make_hashes(vec![(1, "a"), (2, "b"), (3, "c"), (4, "d")]));
+        let issuing = DummyIssuing::new(make_hashes(vec![(1, "a"), (2, "b")]));
+        let monitor = RelayMonitor::new(
+            backing,
+            issuing,
+            RelayMonitorConfig {
+                btc_confirmations: 2,
+                ..config()
+            },
+        );
+
+        let status = monitor.check().await?;
+        assert_eq!(status.bitcoin_height, 2);
+        assert_eq!(status.lag, 0);
+        monitor.report(&status).await;
+        assert_eq!(monitor.alert_level(), AlertLevel::Ok);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn report_recovers_once_relay_catches_up() -> Result<(), Error> {
+        let hashes = make_hashes(vec![
+            (1, "a"),
+            (2, "b"),
+            (3, "c"),
+            (4, "d"),
+            (5, "e"),
+            (6, "f"),
+            (7, "g"),
+        ]);
+        let backing = DummyBacking::new(hashes);
+        let issuing = DummyIssuing::new(make_hashes(vec![(1, "a")]));
+        let monitor = RelayMonitor::new(backing, issuing, config());
+
+        let status = monitor.check().await?;
+        monitor.report(&status).await;
+        assert_eq!(status.lag, 6);
+        assert_eq!(monitor.alert_level(), AlertLevel::Critical);
+
+        for hash in &["b", "c", "d", "e", "f", "g"] {
+            monitor.issuing.submit_block_header(make_hash(hash)).await?;
+        }
+        let status = monitor.check().await?;
+        monitor.report(&status).await;
+        assert_eq!(status.lag, 0);
+        assert_eq!(monitor.alert_level(), AlertLevel::Ok);
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+use super::{parse_header, Backing, Error, Esplora};
+use crate::metrics::RELAY_BACKING_DISAGREEMENTS;
+use async_trait::async_trait;
+use bitcoin::{serialize, Auth, BitcoinCore};
+use futures::future::join_all;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+/// Timeout to wait for the connection to an additional bitcoind node.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An additional source of Bitcoin block headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackingSource {
+    /// The REST API of an Esplora instance.
+    Esplora { url: String },
+    /// The RPC interface of a bitcoind node. The password is read from a file, so that it
+    /// does not show up in the process list.
+    BitcoinCore {
+        url: String,
+        user: String,
+        password_file: PathBuf,
+    },
+}
+
+impl FromStr for BackingSource {
+    type Err = String;
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source.splitn(2, '=').collect::<Vec<_>>()[..] {
+            ["esplora", url] => Ok(BackingSource::Esplora { url: url.to_string() }),
+            ["bitcoind", args] => match args.splitn(3, ';').collect::<Vec<_>>()[..] {
+                [url, user, password_file] => Ok(BackingSource::BitcoinCore {
+                    url: url.to_string(),
+                    user: user.to_string(),
+                    password_file: PathBuf::from(password_file),
+                }),
+                _ => Err("Expected bitcoind=<url>;<user>;<password-file>".to_string()),
+            },
+            _ => Err("Expected esplora=<url> or bitcoind=<url>;<user>;<password-file>".to_string()),
+        }
+    }
+}
+
+/// Reads the password in `path`, without the trailing newline.
+fn read_password(path: &Path) -> Result<String, Error> {
+    let password = std::fs::read_to_string(path)?;
+    Ok(password.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+type BoxedBacking = Box<dyn Backing + Send + Sync>;
+
+/// Combines several sources of Bitcoin block headers, and only returns the blocks that at
+/// least `quorum` of them agree on. Disagreements are reported as incidents.
+pub struct QuorumBacking {
+    /// The sources with their names, the first one is the primary source whose
+    /// errors are returned.
+    sources: Vec<(String, BoxedBacking)>,
+    quorum: usize,
+}
+
+impl QuorumBacking {
+    /// Creates a backing from the `sources`, the first of which is the primary source.
+    pub fn new(sources: Vec<(String, BoxedBacking)>, quorum: usize) -> Result<Self, Error> {
+        if quorum == 0 || quorum > sources.len() {
+            return Err(Error::InvalidQuorum(quorum, sources.len()));
+        }
+        Ok(QuorumBacking { sources, quorum })
+    }
+
+    /// Creates a backing from the node of the vault or relayer and the additional `sources`.
+    /// By default, all sources need to agree.
+    pub fn connect(primary: BitcoinCore, sources: &[BackingSource], quorum: Option<usize>) -> Result<Self, Error> {
+        let network = primary.network();
+        let primary: BoxedBacking = Box::new(primary);
+        let mut backings = vec![("bitcoind".to_string(), primary)];
+        for source in sources {
+            match source {
+                BackingSource::Esplora { url } => backings.push((url.clone(), Box::new(Esplora::new(url.clone())))),
+                BackingSource::BitcoinCore {
+                    url,
+                    user,
+                    password_file,
+                } => backings.push((
+                    url.clone(),
+                    Box::new(BitcoinCore::new(
+                        url.clone(),
+                        Auth::UserPass(user.clone(), read_password(password_file)?),
+                        None,
+                        network,
+                        CONNECTION_TIMEOUT,
+                    )?),
+                )),
+            }
+        }
+        let quorum = quorum.unwrap_or_else(|| backings.len());
+        Self::new(backings, quorum)
+    }
+
+    /// Returns the value that at least `quorum` sources agree on, or `None` if too few sources
+    /// returned a value. Values are compared by the hash given by `key`.
+    fn tally<F>(
+        &self,
+        height: u32,
+        votes: Vec<Result<Option<Vec<u8>>, Error>>,
+        key: F,
+    ) -> Result<Option<Vec<u8>>, Error>
+    where
+        F: Fn(&[u8]) -> Vec<u8>,
+    {
+        // (hash, value, names of the sources)
+        let mut groups: Vec<(Vec<u8>, Vec<u8>, Vec<&str>)> = Vec::new();
+        let mut missing = 0;
+        for (index, ((name, _), vote)) in self.sources.iter().zip(votes).enumerate() {
+            let value = match vote {
+                Ok(Some(value)) => value,
+                Err(err) if index == 0 => return Err(err),
+                Ok(None) => {
+                    missing += 1;
+                    continue;
+                }
+                Err(err) => {
+                    tracing::debug!("Failed to query {} for the block at height {}: {}", name, height, err);
+                    missing += 1;
+                    continue;
+                }
+            };
+            let hash = key(value.as_slice());
+            match groups.iter_mut().find(|(other, _, _)| other == &hash) {
+                Some((_, _, names)) => names.push(name.as_str()),
+                None => groups.push((hash, value, vec![name.as_str()])),
+            }
+        }
+
+        if groups.len() > 1 {
+            RELAY_BACKING_DISAGREEMENTS.inc();
+            let hashes: Vec<_> = groups
+                .iter()
+                .map(|(hash, _, names)| format!("{} ({})", display_hash(hash), names.join(", ")))
+                .collect();
+            tracing::error!(
+                "Bitcoin sources disagree on the block at height {}: {}",
+                height,
+                hashes.join(", ")
+            );
+        }
+
+        match groups.into_iter().max_by_key(|(_, _, names)| names.len()) {
+            Some((_, value, names)) if names.len() >= self.quorum => Ok(Some(value)),
+            // the quorum may still be reached once the other sources have the block
+            Some((_, _, names)) if names.len() + missing >= self.quorum => Ok(None),
+            Some(_) => Err(Error::NoQuorum(height)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Formats a little endian hash like Bitcoin Core does.
+fn display_hash(hash: &[u8]) -> String {
+    let mut hash = hash.to_vec();
+    hash.reverse();
+    hex::encode(hash)
+}
+
+#[async_trait]
+impl Backing for QuorumBacking {
+    async fn get_block_count(&self) -> Result<u32, Error> {
+        let results = join_all(self.sources.iter().map(|(_, source)| source.get_block_count())).await;
+        let mut counts = Vec::new();
+        for (index, ((name, _), result)) in self.sources.iter().zip(results).enumerate() {
+            match result {
+                Ok(count) => counts.push(count),
+                Err(err) if index == 0 => return Err(err),
+                Err(err) => tracing::warn!("Failed to query the block count of {}: {}", name, err),
+            }
+        }
+        // the highest block that at least `quorum` sources have
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        counts
+            .get(self.quorum - 1)
+            .copied()
+            .ok_or(Error::NotEnoughSources(counts.len(), self.quorum))
+    }
+
+    async fn get_block_header(&self, height: u32) -> Result<Option<Vec<u8>>, Error> {
+        let votes = join_all(self.sources.iter().map(|(_, source)| source.get_block_header(height))).await;
+        self.tally(height, votes, |header| match parse_header(header) {
+            Ok(header) => serialize(&header.block_hash()),
+            Err(_) => header.to_vec(),
+        })
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<Vec<u8>, Error> {
+        let votes = join_all(
+            self.sources
+                .iter()
+                .map(|(_, source)| async move { source.get_block_hash(height).await.map(Some) }),
+        )
+        .await;
+        self.tally(height, votes, |hash| hash.to_vec())?
+            .ok_or(Error::BlockHashNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Serves the blocks at the given heights, the hash of a header is the header itself.
+    struct DummyBacking {
+        blocks: Option<HashMap<u32, Vec<u8>>>,
+    }
+
+    #[async_trait]
+    impl Backing for DummyBacking {
+        async fn get_block_count(&self) -> Result<u32, Error> {
+            let blocks = self.blocks.as_ref().ok_or(Error::CannotFetchBestHeight)?;
+            blocks.keys().max().copied().ok_or(Error::CannotFetchBestHeight)
+        }
+
+        async fn get_block_header(&self, height: u32) -> Result<Option<Vec<u8>>, Error> {
+            let blocks = self.blocks.as_ref().ok_or(Error::CannotFetchBestHeight)?;
+            Ok(blocks.get(&height).cloned())
+        }
+
+        async fn get_block_hash(&self, height: u32) -> Result<Vec<u8>, Error> {
+            let blocks = self.blocks.as_ref().ok_or(Error::CannotFetchBestHeight)?;
+            blocks.get(&height).cloned().ok_or(Error::BlockHashNotFound)
+        }
+    }
+
+    fn source(name: &str, blocks: Vec<(u32, &str)>) -> (String, BoxedBacking) {
+        let blocks = blocks.into_iter().map(|(k, v)| (k, v.as_bytes().to_vec())).collect();
+        (name.to_string(), Box::new(DummyBacking { blocks: Some(blocks) }))
+    }
+
+    fn failing_source(name: &str) -> (String, BoxedBacking) {
+        (name.to_string(), Box::new(DummyBacking { blocks: None }))
+    }
+
+    #[test]
+    fn test_parse_backing_source() {
+        assert_eq!(
+            "esplora=https://blockstream.info/api".parse(),
+            Ok(BackingSource::Esplora {
+                url: "https://blockstream.info/api".to_string()
+            })
+        );
+        assert_eq!(
+            "bitcoind=http://localhost:8332;user;/run/secrets/rpc;password".parse(),
+            Ok(BackingSource::BitcoinCore {
+                url: "http://localhost:8332".to_string(),
+                user: "user".to_string(),
+                password_file: PathBuf::from("/run/secrets/rpc;password"),
+            })
+        );
+        assert!("bitcoind=http://localhost:8332".parse::<BackingSource>().is_err());
+        assert!("electrum=localhost:50001".parse::<BackingSource>().is_err());
+    }
+
+    #[test]
+    fn test_read_password_from_file() {
+        let path = std::env::temp_dir().join(format!("rpc-password-{}", std::process::id()));
+        std::fs::write(&path, "pass;word\n").unwrap();
+        assert_eq!(read_password(&path).unwrap(), "pass;word");
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_password(&path).is_err());
+    }
+
+    #[test]
+    fn test_invalid_quorum() {
+        let sources = || vec![source("a", vec![]), source("b", vec![])];
+        assert!(QuorumBacking::new(sources(), 0).is_err());
+        assert!(QuorumBacking::new(sources(), 3).is_err());
+        assert!(QuorumBacking::new(sources(), 2).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_agreeing_sources() -> Result<(), Error> {
+        let backing = QuorumBacking::new(
+            vec![
+                source("a", vec![(1, "x"), (2, "y")]),
+                source("b", vec![(1, "x"), (2, "y")]),
+            ],
+            2,
+        )?;
+        assert_eq!(backing.get_block_count().await?, 2);
+        assert_eq!(backing.get_block_hash(2).await?, b"y".to_vec());
+        assert_eq!(backing.get_block_header(2).await?, Some(b"y".to_vec()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_majority_wins() -> Result<(), Error> {
+        let backing = QuorumBacking::new(
+            vec![
+                source("a", vec![(1, "x")]),
+                source("b", vec![(1, "eclipsed")]),
+                source("c", vec![(1, "x")]),
+            ],
+            2,
+        )?;
+        assert_eq!(backing.get_block_hash(1).await?, b"x".to_vec());
+        assert_eq!(backing.get_block_header(1).await?, Some(b"x".to_vec()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disagreement_without_quorum() -> Result<(), Error> {
+        let backing = QuorumBacking::new(vec![source("a", vec![(1, "x")]), source("b", vec![(1, "z")])], 2)?;
+        assert_eq!(backing.get_block_hash(1).await, Err(Error::NoQuorum(1)));
+        assert_eq!(backing.get_block_header(1).await, Err(Error::NoQuorum(1)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lagging_source() -> Result<(), Error> {
+        let backing = QuorumBacking::new(
+            vec![source("a", vec![(1, "x"), (2, "y")]), source("b", vec![(1, "x")])],
+            2,
+        )?;
+        // only blocks that enough sources have are relayed
+        assert_eq!(backing.get_block_count().await?, 1);
+        assert_eq!(backing.get_block_header(2).await?, None);
+        assert_eq!(backing.get_block_hash(2).await, Err(Error::BlockHashNotFound));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failing_sources() -> Result<(), Error> {
+        let backing = QuorumBacking::new(vec![source("a", vec![(1, "x")]), failing_source("b")], 1)?;
+        assert_eq!(backing.get_block_count().await?, 1);
+        assert_eq!(backing.get_block_hash(1).await?, b"x".to_vec());
+
+        let backing = QuorumBacking::new(vec![source("a", vec![(1, "x")]), failing_source("b")], 2)?;
+        assert_eq!(backing.get_block_count().await, Err(Error::NotEnoughSources(1, 2)));
+
+        // errors of the primary source are returned
+        let backing = QuorumBacking::new(vec![failing_source("a"), source("b", vec![(1, "x")])], 1)?;
+        assert_eq!(backing.get_block_count().await, Err(Error::CannotFetchBestHeight));
+        assert_eq!(backing.get_block_hash(1).await, Err(Error::CannotFetchBestHeight));
+        Ok(())
+    }
+}
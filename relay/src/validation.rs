@@ -0,0 +1,368 @@
+use bitcoin::{BlockHeader, Network, Uint256};
+use std::collections::VecDeque;
+use thiserror::Error;
+
+/// Number of blocks after which the difficulty is adjusted.
+pub const DIFFICULTY_ADJUSTMENT_INTERVAL: u32 = 2016;
+/// Expected duration of a difficulty period in seconds (two weeks).
+const TARGET_TIMESPAN: u32 = 14 * 24 * 60 * 60;
+/// Expected time between two blocks in seconds.
+const TARGET_SPACING: u32 = 10 * 60;
+/// Number of previous blocks whose median time a new block must exceed.
+const MEDIAN_TIME_SPAN: usize = 11;
+/// Maximum time in seconds that a block may be ahead of the local clock.
+const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    #[error("header does not extend the previous header")]
+    InvalidLinkage,
+    #[error("proof of work does not meet the target")]
+    InsufficientWork,
+    #[error("target {0:#010x} exceeds the proof of work limit")]
+    TargetAboveLimit(u32),
+    #[error("unexpected difficulty bits {actual:#010x}, expected {expected:#010x}")]
+    UnexpectedBits { expected: u32, actual: u32 },
+    #[error("timestamp is not after the median time of the previous blocks")]
+    TimeTooOld,
+    #[error("timestamp is too far in the future")]
+    TimeTooNew,
+}
+
+/// Consensus parameters for the proof of work of a Bitcoin network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainParams {
+    /// Compact encoding of the highest allowed target.
+    pow_limit_bits: u32,
+    /// Blocks may use the lowest difficulty if no block was found for 20 minutes (testnet).
+    allow_min_difficulty_blocks: bool,
+    /// The difficulty is never adjusted (regtest).
+    no_retargeting: bool,
+}
+
+impl ChainParams {
+    pub fn new(network: Network) -> Self {
+        match network {
+            Network::Bitcoin => ChainParams {
+                pow_limit_bits: 0x1d00ffff,
+                allow_min_difficulty_blocks: false,
+                no_retargeting: false,
+            },
+            Network::Testnet => ChainParams {
+                pow_limit_bits: 0x1d00ffff,
+                allow_min_difficulty_blocks: true,
+                no_retargeting: false,
+            },
+            Network::Signet => ChainParams {
+                pow_limit_bits: 0x1e0377ae,
+                allow_min_difficulty_blocks: false,
+                no_retargeting: false,
+            },
+            Network::Regtest => ChainParams {
+                pow_limit_bits: 0x207fffff,
+                allow_min_difficulty_blocks: true,
+                no_retargeting: true,
+            },
+        }
+    }
+
+    fn pow_limit(&self) -> Uint256 {
+        BlockHeader::u256_from_compact_target(self.pow_limit_bits)
+    }
+
+    /// Computes the bits of the first block of a difficulty period, given the time of the first
+    /// block of the previous period and the last block before the adjustment.
+    fn retarget(&self, first_time: u32, last_time: u32, last_bits: u32) -> u32 {
+        let timespan = (last_time as i64 - first_time as i64)
+            .max(TARGET_TIMESPAN as i64 / 4)
+            .min(TARGET_TIMESPAN as i64 * 4) as u32;
+        let target = BlockHeader::u256_from_compact_target(last_bits).mul_u32(timespan)
+            / Uint256::from_u64(TARGET_TIMESPAN as u64).unwrap();
+        compact_from_target(&target.min(self.pow_limit()))
+    }
+}
+
+/// Encodes a target in the compact format used in block headers, as Bitcoin Core does.
+fn compact_from_target(target: &Uint256) -> u32 {
+    let mut size = (target.bits() + 7) / 8;
+    let mut compact = if size <= 3 {
+        (target.low_u64() << (8 * (3 - size))) as u32
+    } else {
+        (*target >> (8 * (size - 3))).low_u32()
+    };
+    // the mantissa is signed, so avoid setting the sign bit
+    if compact & 0x0080_0000 != 0 {
+        compact >>= 8;
+        size += 1;
+    }
+    compact | ((size as u32) << 24)
+}
+
+/// The most recent headers of a chain, which are needed to validate the next header.
+pub struct HeaderChain {
+    params: ChainParams,
+    /// Height of the last header.
+    tip_height: u32,
+    /// Up to `MEDIAN_TIME_SPAN` headers ending at the tip, oldest first.
+    recent: VecDeque<BlockHeader>,
+    /// First header of the difficulty period of the tip, if known.
+    period_start: Option<BlockHeader>,
+}
+
+impl HeaderChain {
+    /// Starts a chain at the last of the `recent` headers (which must be linked and not be
+    /// empty), at `tip_height`. Difficulty adjustments are only checked if `period_start`,
+    /// the first header of the difficulty period of the tip, is given.
+    pub fn new(
+        params: ChainParams,
+        tip_height: u32,
+        recent: Vec<BlockHeader>,
+        period_start: Option<BlockHeader>,
+    ) -> Self {
+        let mut recent: VecDeque<_> = recent.into();
+        while recent.len() > MEDIAN_TIME_SPAN {
+            recent.pop_front();
+        }
+        HeaderChain {
+            params,
+            tip_height,
+            recent,
+            period_start,
+        }
+    }
+
+    fn tip(&self) -> &BlockHeader {
+        self.recent.back().expect("chain is not empty")
+    }
+
+    /// Returns the bits the next header must have, if they can be determined.
+    fn expected_bits(&self, header: &BlockHeader) -> Option<u32> {
+        let height = self.tip_height + 1;
+        let tip = self.tip();
+        if height % DIFFICULTY_ADJUSTMENT_INTERVAL == 0 {
+            if self.params.no_retargeting {
+                return Some(tip.bits);
+            }
+            return self
+                .period_start
+                .as_ref()
+                .map(|first| self.params.retarget(first.time, tip.time, tip.bits));
+        }
+
+        if !self.params.allow_min_difficulty_blocks {
+            return Some(tip.bits);
+        }
+        if header.time > tip.time + 2 * TARGET_SPACING {
+            return Some(self.params.pow_limit_bits);
+        }
+        // otherwise the bits of the last block that did not use the lowest difficulty apply
+        let last_regular = self
+            .recent
+            .iter()
+            .rev()
+            .zip((0..=self.tip_height).rev())
+            .find(|(prev, height)| {
+                prev.bits != self.params.pow_limit_bits || height % DIFFICULTY_ADJUSTMENT_INTERVAL == 0
+            });
+        match last_regular {
+            Some((prev, _)) => Some(prev.bits),
+            // all recent blocks used the lowest difficulty
+            None if self.params.no_retargeting => Some(self.params.pow_limit_bits),
+            None => None,
+        }
+    }
+
+    fn median_time_past(&self) -> Option<u32> {
+        if self.recent.len() < MEDIAN_TIME_SPAN {
+            return None;
+        }
+        let mut times: Vec<_> = self.recent.iter().map(|header| header.time).collect();
+        times.sort_unstable();
+        Some(times[times.len() / 2])
+    }
+
+    /// Checks that `header` extends the chain, and appends it if it does. `now` is the current
+    /// unix time in seconds.
+    pub fn push(&mut self, header: BlockHeader, now: u32) -> Result<(), HeaderError> {
+        if header.prev_blockhash != self.tip().block_hash() {
+            return Err(HeaderError::InvalidLinkage);
+        }
+
+        if header.target() > self.params.pow_limit() {
+            return Err(HeaderError::TargetAboveLimit(header.bits));
+        }
+        if header.validate_pow(&header.target()).is_err() {
+            return Err(HeaderError::InsufficientWork);
+        }
+        match self.expected_bits(&header) {
+            Some(expected) if expected != header.bits => {
+                return Err(HeaderError::UnexpectedBits {
+                    expected,
+                    actual: header.bits,
+                })
+            }
+            Some(_) => (),
+            None => tracing::debug!(
+                "Cannot check the difficulty of the block at height {}",
+                self.tip_height + 1
+            ),
+        }
+
+        if matches!(self.median_time_past(), Some(median) if header.time <= median) {
+            return Err(HeaderError::TimeTooOld);
+        }
+        if header.time > now.saturating_add(MAX_FUTURE_BLOCK_TIME) {
+            return Err(HeaderError::TimeTooNew);
+        }
+
+        self.tip_height += 1;
+        if self.tip_height % DIFFICULTY_ADJUSTMENT_INTERVAL == 0 {
+            self.period_start = Some(header);
+        }
+        self.recent.push_back(header);
+        if self.recent.len() > MEDIAN_TIME_SPAN {
+            self.recent.pop_front();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{BlockHash, TxMerkleNode};
+
+    const NOW: u32 = 1_600_000_000;
+
+    fn regtest() -> ChainParams {
+        ChainParams::new(Network::Regtest)
+    }
+
+    fn mine(prev_blockhash: BlockHash, time: u32, bits: u32) -> BlockHeader {
+        let mut header = BlockHeader {
+            version: 4,
+            prev_blockhash,
+            merkle_root: TxMerkleNode::default(),
+            time,
+            bits,
+            nonce: 0,
+        };
+        while header.validate_pow(&header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    /// Returns a chain of `length` regtest headers, one every ten minutes.
+    fn regtest_chain(length: u32) -> Vec<BlockHeader> {
+        let mut headers = vec![mine(BlockHash::default(), NOW - 100_000, 0x207fffff)];
+        for i in 1..length {
+            let prev = headers.last().unwrap().block_hash();
+            headers.push(mine(prev, NOW - 100_000 + i * TARGET_SPACING, 0x207fffff));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_compact_from_target() {
+        for bits in [0x1d00ffff, 0x1c7fff80, 0x1b0404cb, 0x207fffff, 0x1e0377ae].iter() {
+            assert_eq!(
+                compact_from_target(&BlockHeader::u256_from_compact_target(*bits)),
+                *bits
+            );
+        }
+    }
+
+    #[test]
+    fn test_retarget() {
+        // test vectors of Bitcoin Core (src/test/pow_tests.cpp)
+        let mainnet = ChainParams::new(Network::Bitcoin);
+        assert_eq!(mainnet.retarget(1261130161, 1262152739, 0x1d00ffff), 0x1d00d86a);
+        // the target is capped at the proof of work limit
+        assert_eq!(mainnet.retarget(1231006505, 1233061996, 0x1d00ffff), 0x1d00ffff);
+        // the timespan is limited to a quarter and four times the target timespan
+        assert_eq!(mainnet.retarget(1279008237, 1279297671, 0x1c05a3f4), 0x1c0168fd);
+        assert_eq!(mainnet.retarget(1263163443, 1269211443, 0x1c387f6f), 0x1d00e1fd);
+    }
+
+    #[test]
+    fn test_push_valid_headers() {
+        let headers = regtest_chain(20);
+        let mut chain = HeaderChain::new(regtest(), 100, headers[..12].to_vec(), None);
+        for header in headers[12..].iter() {
+            assert_eq!(chain.push(*header, NOW), Ok(()));
+        }
+        assert_eq!(chain.tip_height, 108);
+        assert_eq!(chain.recent.len(), MEDIAN_TIME_SPAN);
+    }
+
+    #[test]
+    fn test_push_rejects_invalid_linkage() {
+        let headers = regtest_chain(3);
+        let mut chain = HeaderChain::new(regtest(), 100, headers[..1].to_vec(), None);
+        assert_eq!(chain.push(headers[2], NOW), Err(HeaderError::InvalidLinkage));
+    }
+
+    #[test]
+    fn test_push_rejects_insufficient_work() {
+        let headers = regtest_chain(2);
+        let mut chain = HeaderChain::new(regtest(), 100, headers[..1].to_vec(), None);
+        let mut header = headers[1];
+        while header.validate_pow(&header.target()).is_ok() {
+            header.nonce += 1;
+        }
+        assert_eq!(chain.push(header, NOW), Err(HeaderError::InsufficientWork));
+    }
+
+    #[test]
+    fn test_push_rejects_unexpected_bits() {
+        let headers = regtest_chain(1);
+        let mut chain = HeaderChain::new(regtest(), 100, headers, None);
+        let header = mine(chain.tip().block_hash(), NOW, 0x2000ffff);
+        assert_eq!(
+            chain.push(header, NOW),
+            Err(HeaderError::UnexpectedBits {
+                expected: 0x207fffff,
+                actual: 0x2000ffff
+            })
+        );
+
+        let header = mine(chain.tip().block_hash(), NOW, 0x2100ffff);
+        assert_eq!(chain.push(header, NOW), Err(HeaderError::TargetAboveLimit(0x2100ffff)));
+    }
+
+    #[test]
+    fn test_push_checks_difficulty_adjustment() {
+        let mainnet = ChainParams::new(Network::Bitcoin);
+        let first = BlockHeader {
+            version: 1,
+            prev_blockhash: BlockHash::default(),
+            merkle_root: TxMerkleNode::default(),
+            time: 1261130161,
+            bits: 0x1d00ffff,
+            nonce: 0,
+        };
+        let last = BlockHeader {
+            time: 1262152739,
+            ..first
+        };
+        let chain = HeaderChain::new(mainnet, 2 * DIFFICULTY_ADJUSTMENT_INTERVAL - 1, vec![last], Some(first));
+        assert_eq!(chain.expected_bits(&last), Some(0x1d00d86a));
+
+        // without the first header of the period the adjustment cannot be checked
+        let chain = HeaderChain::new(mainnet, 2 * DIFFICULTY_ADJUSTMENT_INTERVAL - 1, vec![last], None);
+        assert_eq!(chain.expected_bits(&last), None);
+    }
+
+    #[test]
+    fn test_push_rejects_invalid_time() {
+        let headers = regtest_chain(11);
+        let mut chain = HeaderChain::new(regtest(), 100, headers.clone(), None);
+        let median = headers[5].time;
+        let header = mine(chain.tip().block_hash(), median, 0x207fffff);
+        assert_eq!(chain.push(header, NOW), Err(HeaderError::TimeTooOld));
+
+        let header = mine(chain.tip().block_hash(), NOW + MAX_FUTURE_BLOCK_TIME + 1, 0x207fffff);
+        assert_eq!(chain.push(header, NOW), Err(HeaderError::TimeTooNew));
+    }
+}
@@ -0,0 +1,11 @@
+use runtime::Error as RuntimeError;
+use service::Error as ServiceError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("ServiceError: {0}")]
+    ServiceError(#[from] ServiceError),
+    #[error("RuntimeError: {0}")]
+    RuntimeError(#[from] RuntimeError),
+}
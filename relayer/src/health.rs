@@ -0,0 +1,39 @@
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use relay::Heartbeat;
+use service::Error;
+use std::{net::SocketAddr, time::Duration};
+
+/// Serve the health of the relayer on `/health`: `200 OK` if the last successful iteration was
+/// at most `timeout` ago, `503 Service Unavailable` otherwise.
+pub async fn serve_health(addr: SocketAddr, heartbeat: Heartbeat, timeout: Duration) -> Result<(), Error> {
+    let make_service = make_service_fn(move |_| {
+        let heartbeat = heartbeat.clone();
+        async move {
+            Ok::<_, Error>(service_fn(move |request| {
+                handle_request(request, heartbeat.clone(), timeout)
+            }))
+        }
+    });
+    tracing::info!("Serving health on {}", addr);
+    Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    heartbeat: Heartbeat,
+    timeout: Duration,
+) -> Result<Response<Body>, Error> {
+    if request.method() != Method::GET || request.uri().path() != "/health" {
+        return Ok(Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty())?);
+    }
+
+    let status = match heartbeat.elapsed() {
+        Some(elapsed) if elapsed <= timeout => StatusCode::OK,
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    Ok(Response::builder().status(status).body(Body::empty())?)
+}
@@ -0,0 +1,81 @@
+mod error;
+mod health;
+mod system;
+
+use clap::Clap;
+use error::Error;
+use relay::Heartbeat;
+use runtime::{substrate_subxt::PairSigner, InterBtcRuntime};
+use service::{ConnectionManager, ServiceConfig};
+use system::{RelayerConfig, RelayerService, RelayerServiceConfig, ABOUT, AUTHORS, NAME, VERSION};
+
+#[derive(Clap, Debug, Clone)]
+#[clap(name = NAME, version = VERSION, author = AUTHORS, about = ABOUT)]
+pub struct Opts {
+    /// Keyring / keyfile options.
+    #[clap(flatten)]
+    pub account_info: runtime::cli::ProviderUserOpts,
+
+    /// Connection settings for the BTC Parachain.
+    #[clap(flatten)]
+    pub parachain: runtime::cli::ConnectionOpts,
+
+    /// Connection settings for Bitcoin Core.
+    #[clap(flatten)]
+    pub bitcoin: bitcoin::cli::BitcoinOpts,
+
+    /// Settings specific to the relayer.
+    #[clap(flatten)]
+    pub relayer: RelayerServiceConfig,
+
+    /// General service settings.
+    #[clap(flatten)]
+    pub service: ServiceConfig,
+}
+
+async fn start() -> Result<(), Error> {
+    let opts: Opts = Opts::parse();
+    opts.service.logging_format.init_subscriber();
+
+    let (pair, _) = opts.account_info.get_key_pair()?;
+    let signer = PairSigner::<InterBtcRuntime, _>::new(pair);
+
+    let heartbeat = Heartbeat::default();
+    if let Some(addr) = opts.relayer.health_addr {
+        let heartbeat = heartbeat.clone();
+        let timeout = opts.relayer.health_timeout_ms;
+        tokio::spawn(async move {
+            if let Err(err) = health::serve_health(addr, heartbeat, timeout).await {
+                tracing::error!("Health server failed: {}", err);
+            }
+        });
+    }
+
+    // block headers can be submitted by any account, so no bitcoin wallet is loaded
+    ConnectionManager::<_, RelayerService>::new(
+        signer,
+        None,
+        opts.bitcoin,
+        opts.parachain,
+        opts.service,
+        RelayerConfig {
+            service: opts.relayer,
+            heartbeat,
+        },
+    )
+    .start()
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let exit_code = if let Err(err) = start().await {
+        eprintln!("Error: {}", err);
+        1
+    } else {
+        0
+    };
+    std::process::exit(exit_code);
+}
@@ -0,0 +1,365 @@
+use async_trait::async_trait;
+use bitcoin::{BitcoinCore, Network};
+use clap::Clap;
+use git_version::git_version;
+use relay::{
+    run_relay_monitor, run_relayer, BackingSource, ChainParams, Checkpoint, Config, Heartbeat, QuorumBacking,
+    RelayMonitor, RelayMonitorConfig, Runner,
+};
+use runtime::{cli::parse_duration_ms, InterBtcParachain, Subsystem};
+use service::{wait_or_shutdown, Error, Service, ShutdownSender};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+pub const VERSION: &str = git_version!(args = ["--tags"]);
+pub const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
+pub const NAME: &str = env!("CARGO_PKG_NAME");
+pub const ABOUT: &str = env!("CARGO_PKG_DESCRIPTION");
+
+#[derive(Clap, Clone, Debug)]
+pub struct RelayerServiceConfig {
+    /// Height to initialize the relay at, if it is not initialized yet. Defaults to the last
+    /// difficulty adjustment at least 6 blocks below the Bitcoin tip.
+    #[clap(long)]
+    pub bitcoin_relay_start_height: Option<u32>,
+
+    /// Json file with the `height`, `hash` and `header` of the block to initialize the relay
+    /// at, if it is not initialized yet. The block must be on the Bitcoin main chain.
+    #[clap(long, conflicts_with("bitcoin-relay-start-height"))]
+    pub bitcoin_relay_checkpoint: Option<PathBuf>,
+
+    /// Max batch size for combined block header submission.
+    #[clap(long, default_value = "16")]
+    pub max_batch_size: u32,
+
+    /// Max number of block header batches that are submitted before the first is included.
+    #[clap(long, default_value = "4")]
+    pub max_batches_in_flight: u32,
+
+    /// Number of confirmations a block needs to have before it is submitted.
+    #[clap(long, default_value = "0")]
+    pub bitcoin_relay_confirmations: u32,
+
+    /// Timeout in milliseconds to poll Bitcoin.
+    #[clap(long, parse(try_from_str = parse_duration_ms), default_value = "6000")]
+    pub bitcoin_poll_interval_ms: Duration,
+
+    /// Additional Bitcoin sources to cross-check relayed block headers against, either
+    /// `esplora=<url>` or `bitcoind=<url>;<user>;<password-file>`, where the file contains the
+    /// RPC password. Can be given multiple times.
+    #[clap(long)]
+    pub relay_backing: Vec<BackingSource>,
+
+    /// Number of Bitcoin sources, including the node given by `--bitcoin-rpc-url`, that must
+    /// agree on a block for it to be relayed. Defaults to all sources.
+    #[clap(long)]
+    pub relay_quorum: Option<usize>,
+
+    /// Address to serve the health check on, e.g. 0.0.0.0:3034. Disabled if unset.
+    #[clap(long)]
+    pub health_addr: Option<SocketAddr>,
+
+    /// Time in milliseconds since the last successful relayer iteration (or monitor check, with
+    /// `--monitor-only`) after which the relayer is reported as unhealthy.
+    #[clap(long, parse(try_from_str = parse_duration_ms), default_value = "600000")]
+    pub health_timeout_ms: Duration,
+
+    /// Only monitor the relay, without submitting block headers. Implies `--relay-monitor`.
+    #[clap(long)]
+    pub monitor_only: bool,
+
+    /// Alerting on relay lag and divergence from Bitcoin.
+    #[clap(flatten)]
+    pub relay_monitor: RelayMonitorConfig,
+}
+
+impl RelayerServiceConfig {
+    /// Settings of the runner that relays the block headers of the given Bitcoin network.
+    fn runner_config(&self, network: Network) -> Result<Config, Error> {
+        let checkpoint = self
+            .bitcoin_relay_checkpoint
+            .as_ref()
+            .map(Checkpoint::load)
+            .transpose()
+            .map_err(|err| Error::Other(err.to_string()))?;
+        Ok(Config {
+            start_height: self.bitcoin_relay_start_height,
+            checkpoint,
+            max_batch_size: self.max_batch_size,
+            max_batches_in_flight: self.max_batches_in_flight,
+            interval: Some(self.bitcoin_poll_interval_ms),
+            btc_confirmations: self.bitcoin_relay_confirmations,
+            chain_params: Some(ChainParams::new(network)),
+        })
+    }
+}
+
+/// Relayer settings together with the heartbeat that is shared with the health check,
+/// which outlives the reconnections of the service.
+#[derive(Clone)]
+pub struct RelayerConfig {
+    pub service: RelayerServiceConfig,
+    pub heartbeat: Heartbeat,
+}
+
+pub struct RelayerService {
+    btc_parachain: InterBtcParachain,
+    bitcoin_core: BitcoinCore,
+    config: RelayerConfig,
+    shutdown: ShutdownSender,
+}
+
+#[async_trait]
+impl Service<RelayerConfig> for RelayerService {
+    const NAME: &'static str = NAME;
+    const VERSION: &'static str = VERSION;
+
+    fn new_service(
+        btc_parachain: InterBtcParachain,
+        bitcoin_core: BitcoinCore,
+        config: RelayerConfig,
+        shutdown: ShutdownSender,
+    ) -> Self {
+        RelayerService {
+            btc_parachain,
+            bitcoin_core,
+            config,
+            shutdown,
+        }
+    }
+
+    async fn start(&self) -> Result<(), Error> {
+        let config = &self.config.service;
+        let compatibility = self.btc_parachain.check_compatibility().await?;
+        tracing::info!("Connected to {}", compatibility);
+        if config.monitor_only {
+            compatibility.require(&[Subsystem::Core])?;
+        } else {
+            compatibility.require(&[Subsystem::Core, Subsystem::Relay])?;
+        }
+
+        let monitor = RelayMonitor::new(
+            self.bitcoin_core.clone(),
+            self.btc_parachain.clone(),
+            RelayMonitorConfig {
+                btc_confirmations: config.bitcoin_relay_confirmations,
+                ..config.relay_monitor.clone()
+            },
+        );
+        if config.monitor_only {
+            let monitor = monitor.with_heartbeat(self.config.heartbeat.clone());
+            wait_or_shutdown(self.shutdown.clone(), run_relay_monitor(monitor)).await;
+            return Ok(());
+        }
+
+        let backing = QuorumBacking::connect(self.bitcoin_core.clone(), &config.relay_backing, config.relay_quorum)
+            .map_err(|err| Error::Other(err.to_string()))?;
+        let runner = Runner::new(
+            backing,
+            self.btc_parachain.clone(),
+            config.runner_config(self.bitcoin_core.network())?,
+        )
+        .with_heartbeat(self.config.heartbeat.clone());
+
+        let relayer = wait_or_shutdown(self.shutdown.clone(), run_relayer(runner));
+        if config.relay_monitor.relay_monitor {
+            let monitor = wait_or_shutdown(self.shutdown.clone(), run_relay_monitor(monitor));
+            tokio::join!(relayer, monitor);
+        } else {
+            relayer.await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{deserialize, serialize, BlockHash, BlockHeader};
+    use relay::{Backing, Error as RelayError, Issuing, PendingSubmission};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    /// Bitcoin main chain of the given headers, starting at height 0.
+    struct DummyBacking {
+        headers: Vec<BlockHeader>,
+    }
+
+    #[async_trait]
+    impl Backing for DummyBacking {
+        async fn get_block_count(&self) -> Result<u32, RelayError> {
+            Ok(self.headers.len() as u32 - 1)
+        }
+
+        async fn get_block_header(&self, height: u32) -> Result<Option<Vec<u8>>, RelayError> {
+            Ok(self.headers.get(height as usize).map(serialize))
+        }
+
+        async fn get_block_hash(&self, height: u32) -> Result<Vec<u8>, RelayError> {
+            self.headers
+                .get(height as usize)
+                .map(|header| serialize(&header.block_hash()))
+                .ok_or(RelayError::BlockHashNotFound)
+        }
+    }
+
+    /// Relay that stores the hashes of its main chain by height. Clones share the relay.
+    #[derive(Clone, Default)]
+    struct DummyIssuing {
+        main_chain: Arc<Mutex<HashMap<u32, Vec<u8>>>>,
+    }
+
+    impl DummyIssuing {
+        fn store(&self, header: Vec<u8>, height: Option<u32>) -> Result<(), RelayError> {
+            let header: BlockHeader = deserialize(&header).map_err(|_| RelayError::SerializeHeader)?;
+            let mut main_chain = self.main_chain.lock().unwrap();
+            let height = height.unwrap_or_else(|| main_chain.keys().max().map_or(0, |height| height + 1));
+            main_chain.insert(height, serialize(&header.block_hash()));
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Issuing for DummyIssuing {
+        async fn is_initialized(&self) -> Result<bool, RelayError> {
+            Ok(!self.main_chain.lock().unwrap().is_empty())
+        }
+
+        async fn initialize(&self, header: Vec<u8>, height: u32) -> Result<(), RelayError> {
+            self.store(header, Some(height))
+        }
+
+        async fn submit_block_header(&self, header: Vec<u8>) -> Result<(), RelayError> {
+            self.store(header, None)
+        }
+
+        async fn submit_block_header_batch(&self, headers: Vec<Vec<u8>>) -> Result<PendingSubmission, RelayError> {
+            for header in headers {
+                self.store(header, None)?;
+            }
+            Ok(Box::pin(async { Ok(()) }))
+        }
+
+        async fn refresh_nonce(&self) {}
+
+        async fn get_best_height(&self) -> Result<u32, RelayError> {
+            self.main_chain
+                .lock()
+                .unwrap()
+                .keys()
+                .max()
+                .copied()
+                .ok_or(RelayError::CannotFetchBestHeight)
+        }
+
+        async fn get_block_hash(&self, height: u32) -> Result<Vec<u8>, RelayError> {
+            self.main_chain
+                .lock()
+                .unwrap()
+                .get(&height)
+                .cloned()
+                .ok_or(RelayError::BlockHashNotFound)
+        }
+
+        async fn is_block_stored(&self, hash_le: Vec<u8>) -> Result<bool, RelayError> {
+            Ok(self.main_chain.lock().unwrap().values().any(|hash| *hash == hash_le))
+        }
+    }
+
+    /// Returns a chain of `length` regtest headers, one every ten minutes.
+    fn regtest_chain(length: u32) -> Vec<BlockHeader> {
+        let mut headers: Vec<BlockHeader> = vec![];
+        for i in 0..length {
+            let mut header = BlockHeader {
+                version: 4,
+                prev_blockhash: headers.last().map_or(BlockHash::default(), |prev| prev.block_hash()),
+                merkle_root: Default::default(),
+                time: 1_600_000_000 + i * 600,
+                bits: 0x207fffff,
+                nonce: 0,
+            };
+            while header.validate_pow(&header.target()).is_err() {
+                header.nonce += 1;
+            }
+            headers.push(header);
+        }
+        headers
+    }
+
+    #[test]
+    fn test_parse_relayer_config() {
+        let config = RelayerServiceConfig::try_parse_from(&[
+            "relayer",
+            "--max-batch-size",
+            "8",
+            "--relay-backing",
+            "esplora=https://blockstream.info/api",
+            "--relay-backing",
+            "bitcoind=http://localhost:18443;user;/run/secrets/rpc-password",
+            "--monitor-only",
+        ])
+        .unwrap();
+
+        assert_eq!(config.max_batch_size, 8);
+        assert_eq!(config.max_batches_in_flight, 4);
+        assert_eq!(config.bitcoin_poll_interval_ms, Duration::from_millis(6000));
+        assert_eq!(
+            config.relay_backing,
+            vec![
+                BackingSource::Esplora {
+                    url: "https://blockstream.info/api".to_string()
+                },
+                BackingSource::BitcoinCore {
+                    url: "http://localhost:18443".to_string(),
+                    user: "user".to_string(),
+                    password_file: PathBuf::from("/run/secrets/rpc-password"),
+                },
+            ]
+        );
+        assert!(config.monitor_only);
+        assert!(config.health_addr.is_none());
+    }
+
+    #[test]
+    fn test_parse_relayer_config_rejects_invalid_options() {
+        assert!(RelayerServiceConfig::try_parse_from(&[
+            "relayer",
+            "--bitcoin-relay-start-height",
+            "100",
+            "--bitcoin-relay-checkpoint",
+            "checkpoint.json",
+        ])
+        .is_err());
+        assert!(RelayerServiceConfig::try_parse_from(&["relayer", "--relay-backing", "electrum=localhost"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_runner_relays_missing_headers() -> Result<(), RelayError> {
+        let headers = regtest_chain(6);
+        let issuing = DummyIssuing::default();
+        issuing.initialize(serialize(&headers[0]), 0).await?;
+        issuing.submit_block_header(serialize(&headers[1])).await?;
+
+        let config = RelayerServiceConfig::try_parse_from(&["relayer", "--max-batch-size", "2"]).unwrap();
+        let runner = Runner::new(
+            DummyBacking {
+                headers: headers.clone(),
+            },
+            issuing.clone(),
+            config.runner_config(Network::Regtest).unwrap(),
+        );
+
+        // the four missing headers are validated and submitted in two batches
+        runner.submit_next().await?;
+
+        assert_eq!(issuing.get_best_height().await?, 5);
+        for (height, header) in headers.iter().enumerate() {
+            assert_eq!(
+                issuing.get_block_hash(height as u32).await?,
+                serialize(&header.block_hash())
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use clap::Clap;
+use runtime::{cli::read_password, encrypt_keyfile, Error, KEYFILE_PASSWORD_ENV};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+/// Encrypt a plaintext keyfile with a password, such that it can be passed to `--keyfile`.
+#[derive(Clap)]
+#[clap(name = "encrypt-keyfile", author = env!("CARGO_PKG_AUTHORS"))]
+struct Opts {
+    /// The plaintext keyfile, a json map of names to mnemonics.
+    #[clap(long)]
+    input: PathBuf,
+
+    /// Path to write the encrypted keyfile to, which must not exist yet.
+    #[clap(long)]
+    output: PathBuf,
+
+    /// File descriptor to read the password from (unix only). If not set, the password is read
+    /// from the `KEYFILE_PASSWORD` environment variable or prompted for.
+    #[clap(long)]
+    password_fd: Option<i32>,
+}
+
+fn main() -> Result<(), Error> {
+    let opts: Opts = Opts::parse();
+
+    let content = std::fs::read_to_string(&opts.input)?;
+    let password = read_password(opts.password_fd)?;
+    if opts.password_fd.is_none() && std::env::var(KEYFILE_PASSWORD_ENV).is_err() {
+        let repeated = rpassword::read_password_from_tty(Some("Repeat password: "))?;
+        if password != repeated {
+            eprintln!("Passwords do not match");
+            std::process::exit(1);
+        }
+    }
+
+    let encrypted = encrypt_keyfile(&content, &password)?;
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    // only the owner may read the keyfile
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(&opts.output)?.write_all(encrypted.as_bytes())?;
+
+    println!(
+        "Encrypted keyfile written to {}, the plaintext keyfile can now be deleted",
+        opts.output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,223 @@
+use crate::{write_atomically, BlockNumber, Error};
+use futures::channel::oneshot;
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{Error as IoError, ErrorKind},
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
+};
+
+/// File that stores the number of the last finalized block whose events have been dispatched,
+/// such that the events of blocks finalized while the client was offline can be replayed.
+#[derive(Debug, Clone)]
+pub struct BlockCheckpoint {
+    path: PathBuf,
+}
+
+impl BlockCheckpoint {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Read the stored block number, returns `None` if nothing has been stored yet.
+    pub fn load(&self) -> Result<Option<BlockNumber>, Error> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => content
+                .trim()
+                .parse()
+                .map(Some)
+                .map_err(|err| IoError::new(ErrorKind::InvalidData, err).into()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Store the block number.
+    pub fn store(&self, number: BlockNumber) -> Result<(), Error> {
+        write_atomically(&self.path, number.to_string())?;
+        Ok(())
+    }
+}
+
+/// Advances the event checkpoint to the last block whose events, and the events of all blocks
+/// before it, have been processed by every listener.
+pub(crate) struct CheckpointTracker {
+    checkpoint: Option<BlockCheckpoint>,
+    /// Blocks whose events are still being processed.
+    pending: BTreeSet<BlockNumber>,
+    /// Last block whose events have been dispatched.
+    dispatched: BlockNumber,
+    stored: BlockNumber,
+}
+
+impl CheckpointTracker {
+    /// Tracks the blocks after `processed`, whose events have all been processed.
+    pub(crate) fn new(checkpoint: Option<BlockCheckpoint>, processed: BlockNumber) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            checkpoint,
+            pending: BTreeSet::new(),
+            dispatched: processed,
+            stored: processed,
+        }))
+    }
+
+    /// Returns the acknowledgement for the events of the block with the given number, which are
+    /// dispatched in order of the block numbers.
+    pub(crate) fn dispatch(tracker: &Arc<Mutex<Self>>, number: BlockNumber) -> Arc<BlockAck> {
+        let mut inner = tracker.lock().unwrap();
+        inner.pending.insert(number);
+        inner.dispatched = inner.dispatched.max(number);
+        Arc::new(BlockAck {
+            number,
+            tracker: tracker.clone(),
+        })
+    }
+
+    fn processed(&mut self, number: BlockNumber) {
+        self.pending.remove(&number);
+        let processed = match self.pending.iter().next() {
+            Some(first_pending) => first_pending.saturating_sub(1),
+            None => self.dispatched,
+        };
+        if processed <= self.stored {
+            return;
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            if let Err(err) = checkpoint.store(processed) {
+                log::warn!("Failed to store event checkpoint: {}", err);
+                return;
+            }
+        }
+        self.stored = processed;
+    }
+}
+
+/// Shared by the listeners of the events of a block, which hold it until they have processed
+/// the event. The block counts as processed once all of them have dropped it.
+pub(crate) struct BlockAck {
+    number: BlockNumber,
+    tracker: Arc<Mutex<CheckpointTracker>>,
+}
+
+impl BlockAck {
+    pub(crate) fn number(&self) -> BlockNumber {
+        self.number
+    }
+}
+
+impl Drop for BlockAck {
+    fn drop(&mut self) {
+        self.tracker.lock().unwrap().processed(self.number);
+    }
+}
+
+/// Holds back the dispatch of live blocks on a connection while an `EventHold` exists, so that
+/// the listeners that are still being set up receive the replayed events and all live blocks.
+#[derive(Clone, Default)]
+pub(crate) struct EventGate(Arc<Mutex<GateState>>);
+
+#[derive(Default)]
+struct GateState {
+    hold: Weak<oneshot::Sender<()>>,
+    /// Resolves once the current hold has been dropped, taken by the dispatcher.
+    release: Option<oneshot::Receiver<()>>,
+}
+
+impl EventGate {
+    /// Returns the current hold of the gate, or a new one if it is open.
+    pub(crate) fn hold(&self) -> EventHold {
+        let mut state = self.0.lock().unwrap();
+        if let Some(hold) = state.hold.upgrade() {
+            return EventHold(hold);
+        }
+        let (sender, receiver) = oneshot::channel();
+        let hold = Arc::new(sender);
+        state.hold = Arc::downgrade(&hold);
+        state.release = Some(receiver);
+        EventHold(hold)
+    }
+
+    /// Waits until all clones of the hold have been dropped, returns immediately if there is none.
+    pub(crate) async fn released(&self) {
+        let release = self.0.lock().unwrap().release.take();
+        if let Some(release) = release {
+            // the sender is never used, so this resolves once it is dropped
+            let _ = release.await;
+        }
+    }
+}
+
+/// Keeps the event subscription of a connection from dispatching live blocks, and from dropping
+/// the replayed events, until it and all its clones have been dropped.
+#[derive(Clone)]
+pub struct EventHold(Arc<oneshot::Sender<()>>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_load_checkpoint() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}", std::process::id()));
+        let checkpoint = BlockCheckpoint::new(path.clone());
+        assert_eq!(checkpoint.load().unwrap(), None);
+
+        checkpoint.store(42).unwrap();
+        assert_eq!(checkpoint.load().unwrap(), Some(42));
+
+        checkpoint.store(43).unwrap();
+        assert_eq!(checkpoint.load().unwrap(), Some(43));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gate_waits_for_all_holds() {
+        let gate = EventGate::default();
+        // an open gate does not wait
+        gate.released().await;
+
+        let hold = gate.hold();
+        let listener = gate.hold();
+        let released = gate.released();
+        futures::pin_mut!(released);
+        assert!(futures::poll!(&mut released).is_pending());
+
+        drop(hold);
+        assert!(futures::poll!(&mut released).is_pending());
+        drop(listener);
+        released.await;
+
+        // the gate can be held again once it has been released
+        let hold = gate.hold();
+        let released = gate.released();
+        futures::pin_mut!(released);
+        assert!(futures::poll!(&mut released).is_pending());
+        drop(hold);
+        released.await;
+    }
+
+    #[test]
+    fn test_checkpoint_waits_for_earlier_blocks() {
+        let path = std::env::temp_dir().join(format!("checkpoint-tracker-{}", std::process::id()));
+        let checkpoint = BlockCheckpoint::new(path.clone());
+        let tracker = CheckpointTracker::new(Some(checkpoint.clone()), 10);
+
+        let first = CheckpointTracker::dispatch(&tracker, 11);
+        let listener = first.clone();
+        let second = CheckpointTracker::dispatch(&tracker, 12);
+        drop((first, second));
+        // a listener is still processing block 11
+        assert_eq!(checkpoint.load().unwrap(), None);
+
+        drop(listener);
+        assert_eq!(checkpoint.load().unwrap(), Some(12));
+
+        // blocks without listeners are processed right away
+        drop(CheckpointTracker::dispatch(&tracker, 13));
+        assert_eq!(checkpoint.load().unwrap(), Some(13));
+
+        fs::remove_file(path).unwrap();
+    }
+}
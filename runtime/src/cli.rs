@@ -1,11 +1,19 @@
 use crate::{
+    conn::{check_endpoint_health, new_websocket_client},
     error::{Error, KeyLoadingError},
-    InterBtcParachain, InterBtcSigner,
+    BlockCheckpoint, InterBtcParachain, InterBtcSigner, Keystore, KEYFILE_PASSWORD_ENV,
 };
 use clap::Clap;
-use sp_core::{sr25519::Pair, Pair as _};
+use futures::future;
+use sp_core::{sr25519::Pair, Pair as _, H256};
 use sp_keyring::AccountKeyring;
-use std::{collections::HashMap, num::ParseIntError, time::Duration};
+#[cfg(unix)]
+use std::{fs::File, io::Read, os::unix::io::FromRawFd};
+use std::{num::ParseIntError, path::PathBuf, time::Duration};
+use substrate_subxt::RpcClient;
+use tokio::time::{delay_for, timeout};
+
+const RETRY_TIMEOUT: Duration = Duration::from_millis(1000);
 
 #[derive(Clap, Debug, Clone)]
 pub struct ProviderUserOpts {
@@ -16,26 +24,43 @@ pub struct ProviderUserOpts {
     /// Path to the json file containing key pairs in a map.
     /// Valid content of this file is e.g.
     /// `{ "MyUser1": "<Polkadot Account Mnemonic>", "MyUser2": "<Polkadot Account Mnemonic>" }`.
+    /// The file may also be encrypted with `encrypt-keyfile`, or be an encrypted account
+    /// exported from polkadot-js, in which case the keyname is the name of the account.
     #[clap(long, conflicts_with = "keyring", requires = "keyname")]
     pub keyfile: Option<String>,
 
     /// The name of the account from the keyfile to use.
     #[clap(long, conflicts_with = "keyring", requires = "keyfile")]
     pub keyname: Option<String>,
+
+    /// File descriptor to read the password of an encrypted keyfile from (unix only). If not
+    /// set, the password is read from the `KEYFILE_PASSWORD` environment variable or prompted for.
+    #[clap(long, requires = "keyfile")]
+    pub keyfile_password_fd: Option<i32>,
 }
 
 impl ProviderUserOpts {
     /// Get the key pair and the username, the latter of which is used for wallet selection.
     pub fn get_key_pair(&self) -> Result<(Pair, String), Error> {
+        Ok(self.get_key_pairs(&[])?.remove(0))
+    }
+
+    /// Same as `get_key_pair`, followed by the key pairs of `additional_keynames` from the
+    /// same keyfile, which is only read and decrypted once.
+    pub fn get_key_pairs(&self, additional_keynames: &[String]) -> Result<Vec<(Pair, String)>, Error> {
         // load parachain credentials
-        let (pair, user_name) = match (self.keyfile.as_ref(), self.keyname.as_ref(), &self.keyring) {
+        match (self.keyfile.as_ref(), self.keyname.as_ref(), &self.keyring) {
             (Some(file_path), Some(keyname), None) => {
-                (get_credentials_from_file(&file_path, &keyname)?, keyname.to_string())
+                let keystore = load_keystore(file_path, self.keyfile_password_fd)?;
+                let mut key_pairs = Vec::new();
+                for keyname in std::iter::once(keyname).chain(additional_keynames) {
+                    key_pairs.push((keystore.get_pair(keyname)?, keyname.to_string()));
+                }
+                Ok(key_pairs)
             }
-            (None, None, Some(keyring)) => (keyring.pair(), format!("{}", keyring)),
+            (None, None, Some(keyring)) => Ok(vec![(keyring.pair(), format!("{}", keyring))]),
             _ => panic!("Invalid arguments"), // should never occur, due to clap constraints
-        };
-        Ok((pair, user_name))
+        }
     }
 }
 
@@ -45,13 +70,44 @@ impl ProviderUserOpts {
 ///
 /// * `file_path` - path to the json file containing the credentials
 /// * `keyname` - name of the key to get
-fn get_credentials_from_file(file_path: &str, keyname: &str) -> Result<Pair, KeyLoadingError> {
-    let file = std::fs::File::open(file_path)?;
-    let reader = std::io::BufReader::new(file);
-    let map: HashMap<String, String> = serde_json::from_reader(reader)?;
-    let pair_str = map.get(keyname).ok_or(KeyLoadingError::KeyNotFound)?;
-    let pair = Pair::from_string(pair_str, None).map_err(KeyLoadingError::SecretStringError)?;
-    Ok(pair)
+pub fn get_credentials_from_file(file_path: &str, keyname: &str) -> Result<Pair, KeyLoadingError> {
+    load_keystore(file_path, None)?.get_pair(keyname)
+}
+
+/// Loads the keyfile, decrypting it if needed with the password from `read_password`.
+pub fn load_keystore(file_path: &str, password_fd: Option<i32>) -> Result<Keystore, KeyLoadingError> {
+    let content = std::fs::read_to_string(file_path)?;
+    Keystore::parse(&content, || read_password(password_fd))
+}
+
+/// Reads the password of an encrypted keyfile from the given file descriptor if set, else from
+/// the `KEYFILE_PASSWORD` environment variable, else from a prompt on the terminal.
+pub fn read_password(password_fd: Option<i32>) -> Result<String, KeyLoadingError> {
+    if let Some(fd) = password_fd {
+        return read_password_fd(fd);
+    }
+    if let Ok(password) = std::env::var(KEYFILE_PASSWORD_ENV) {
+        return Ok(password);
+    }
+    Ok(rpassword::read_password_from_tty(Some("Keyfile password: "))?)
+}
+
+#[cfg(unix)]
+fn read_password_fd(fd: i32) -> Result<String, KeyLoadingError> {
+    // the descriptor is passed to us for this purpose only, so we take ownership of it
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    let mut password = String::new();
+    file.read_to_string(&mut password)?;
+    Ok(password.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+#[cfg(not(unix))]
+fn read_password_fd(_fd: i32) -> Result<String, KeyLoadingError> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "reading the password from a file descriptor is only supported on unix",
+    )
+    .into())
 }
 
 pub fn parse_duration_ms(src: &str) -> Result<Duration, ParseIntError> {
@@ -64,14 +120,24 @@ pub fn parse_duration_minutes(src: &str) -> Result<Duration, ParseIntError> {
 
 #[derive(Clap, Debug, Clone)]
 pub struct ConnectionOpts {
-    /// Parachain websocket URL.
-    #[clap(long, default_value = "ws://127.0.0.1:9944")]
-    pub btc_parachain_url: String,
+    /// Parachain websocket URLs, in order of preference. If the connection fails or the
+    /// endpoint becomes unhealthy, the next healthy endpoint is used.
+    #[clap(long, default_value = "ws://127.0.0.1:9944", use_delimiter = true)]
+    pub btc_parachain_url: Vec<String>,
 
     /// Timeout in milliseconds to wait for connection to btc-parachain.
     #[clap(long, parse(try_from_str = parse_duration_ms), default_value = "60000")]
     pub btc_parachain_connection_timeout_ms: Duration,
 
+    /// Maximum age in milliseconds of the latest finalized block for an endpoint to be
+    /// considered healthy. Only checked if multiple endpoints are given.
+    #[clap(long, parse(try_from_str = parse_duration_ms), default_value = "60000")]
+    pub btc_parachain_max_head_age_ms: Duration,
+
+    /// Interval in milliseconds at which the health of the endpoints is checked.
+    #[clap(long, parse(try_from_str = parse_duration_ms), default_value = "30000")]
+    pub btc_parachain_health_check_interval_ms: Duration,
+
     /// Maximum number of concurrent requests
     #[clap(long)]
     pub max_concurrent_requests: Option<usize>,
@@ -79,17 +145,113 @@ pub struct ConnectionOpts {
     /// Maximum notification capacity for each subscription
     #[clap(long)]
     pub max_notifs_per_subscription: Option<usize>,
+
+    /// File to store the last finalized block whose events have been processed. If set, the
+    /// events of blocks finalized while the client was offline are replayed on restart.
+    #[clap(long)]
+    pub event_checkpoint_file: Option<PathBuf>,
+
+    /// Amount of the free balance (in planck) to keep for paying extrinsic fees. It is never
+    /// locked as collateral.
+    #[clap(long, default_value = "0")]
+    pub fee_reserve: u128,
 }
 
 impl ConnectionOpts {
     pub async fn try_connect(&self, signer: InterBtcSigner) -> Result<InterBtcParachain, Error> {
-        InterBtcParachain::from_url_and_config_with_retry(
-            &self.btc_parachain_url,
-            signer,
-            self.max_concurrent_requests,
-            self.max_notifs_per_subscription,
-            self.btc_parachain_connection_timeout_ms,
-        )
-        .await
+        let (_, parachain_rpc) = self.try_connect_healthy(signer, None).await?;
+        Ok(parachain_rpc)
+    }
+
+    /// Connect to the most preferred healthy endpoint, retrying until the connection timeout.
+    /// Endpoints serving a chain other than `genesis_hash` are skipped. Returns the index of
+    /// the endpoint along with the connection. The nonce of the signer is refreshed from the
+    /// new endpoint, as on any other connection.
+    pub async fn try_connect_healthy(
+        &self,
+        signer: InterBtcSigner,
+        genesis_hash: Option<H256>,
+    ) -> Result<(usize, InterBtcParachain), Error> {
+        log::info!("Connecting to the btc-parachain...");
+        timeout(self.btc_parachain_connection_timeout_ms, async {
+            loop {
+                for (index, url) in self.btc_parachain_url.iter().enumerate() {
+                    let result = match self.connect_endpoint(url, genesis_hash).await {
+                        Ok(rpc_client) => InterBtcParachain::new(rpc_client, signer.clone())
+                            .await
+                            .map(|parachain_rpc| parachain_rpc.with_fee_reserve(self.fee_reserve)),
+                        Err(err) => Err(err),
+                    };
+                    match result {
+                        Ok(parachain_rpc) => {
+                            log::info!("Connected to {}", url);
+                            return Ok((index, parachain_rpc));
+                        }
+                        Err(err @ Error::GenesisHashMismatch(_)) | Err(err @ Error::StaleFinalizedHead(_)) => {
+                            log::warn!("Skipping unhealthy parachain endpoint {}: {}", url, err);
+                        }
+                        Err(err) => log::trace!("could not connect to parachain endpoint {}: {}", url, err),
+                    }
+                }
+                delay_for(RETRY_TIMEOUT).await;
+            }
+        })
+        .await?
+    }
+
+    /// Resolves when the connection to the endpoint with the given index should be replaced,
+    /// i.e. when it is no longer healthy or when a more preferred endpoint has recovered.
+    /// Never resolves if only a single endpoint is configured.
+    pub async fn wait_for_failover(&self, parachain_rpc: &InterBtcParachain, index: usize) {
+        if self.btc_parachain_url.len() < 2 {
+            return future::pending().await;
+        }
+
+        loop {
+            delay_for(self.btc_parachain_health_check_interval_ms).await;
+
+            if let Err(err) = parachain_rpc.check_health(None, self.max_head_age()).await {
+                log::warn!(
+                    "Parachain endpoint {} is unhealthy: {}",
+                    self.btc_parachain_url[index],
+                    err
+                );
+                return;
+            }
+
+            let genesis_hash = match parachain_rpc.get_genesis_hash().await {
+                Ok(genesis_hash) => genesis_hash,
+                Err(_) => continue,
+            };
+            for url in &self.btc_parachain_url[..index] {
+                if self.connect_endpoint(url, Some(genesis_hash)).await.is_ok() {
+                    log::info!("Preferred parachain endpoint {} has recovered", url);
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn event_checkpoint(&self) -> Option<BlockCheckpoint> {
+        self.event_checkpoint_file.clone().map(BlockCheckpoint::new)
+    }
+
+    /// The freshness of the finalized head is only relevant if there is another endpoint
+    /// to fail over to.
+    fn max_head_age(&self) -> Option<Duration> {
+        if self.btc_parachain_url.len() > 1 {
+            Some(self.btc_parachain_max_head_age_ms)
+        } else {
+            None
+        }
+    }
+
+    async fn connect_endpoint(&self, url: &str, genesis_hash: Option<H256>) -> Result<RpcClient, Error> {
+        let rpc_client: RpcClient =
+            new_websocket_client(url, self.max_concurrent_requests, self.max_notifs_per_subscription)
+                .await?
+                .into();
+        check_endpoint_health(&rpc_client, genesis_hash, self.max_head_age()).await?;
+        Ok(rpc_client)
     }
 }
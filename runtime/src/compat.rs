@@ -0,0 +1,445 @@
+use crate::{
+    btc_relay::*, exchange_rate_oracle::*, fee::*, frame_system::*, issue::*, redeem::*, refund::*, relay::*,
+    replace::*, security::*, sla::*, timestamp::*, tokens::*, utility::*, vault_registry::*, Error, InterBtcRuntime,
+};
+use codec::Decode;
+use frame_metadata::{DecodeDifferent, RuntimeMetadata, RuntimeMetadataPrefixed, StorageEntryType, META_RESERVED};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt};
+use substrate_subxt::{Call, Event, Store};
+
+pub(crate) const SYSTEM_MODULE: &str = "System";
+pub(crate) const CODE_UPDATED_EVENT: &str = "CodeUpdated";
+
+/// Group of calls, events and storage items that is needed by a part of a client, such that
+/// the client can disable that part if the runtime no longer supports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Subsystem {
+    /// Parachain status, balances and the relay confirmation settings, used by all clients.
+    Core,
+    /// Vault registration and collateral management.
+    Vault,
+    Issue,
+    Redeem,
+    Replace,
+    Refund,
+    /// Relaying bitcoin block headers.
+    Relay,
+    /// Reporting transactions of vaults that are not allowed.
+    TheftReporting,
+    /// Setting the exchange rate and bitcoin fee estimates.
+    Oracle,
+    /// Transferring tokens.
+    Transfer,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ItemKind {
+    /// Call with the given number of arguments.
+    Call(usize),
+    /// Event with the given number of fields.
+    Event(usize),
+    /// Storage item with the given number of keys.
+    Storage(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Requirement {
+    subsystem: Subsystem,
+    module: &'static str,
+    name: &'static str,
+    kind: ItemKind,
+}
+
+macro_rules! call {
+    ($subsystem:ident, $ty:ty, $args:expr) => {
+        Requirement {
+            subsystem: Subsystem::$subsystem,
+            module: <$ty as Call<InterBtcRuntime>>::MODULE,
+            name: <$ty as Call<InterBtcRuntime>>::FUNCTION,
+            kind: ItemKind::Call($args),
+        }
+    };
+}
+
+macro_rules! event {
+    ($subsystem:ident, $ty:ty, $args:expr) => {
+        Requirement {
+            subsystem: Subsystem::$subsystem,
+            module: <$ty as Event<InterBtcRuntime>>::MODULE,
+            name: <$ty as Event<InterBtcRuntime>>::EVENT,
+            kind: ItemKind::Event($args),
+        }
+    };
+}
+
+macro_rules! storage {
+    ($subsystem:ident, $ty:ty, $keys:expr) => {
+        Requirement {
+            subsystem: Subsystem::$subsystem,
+            module: <$ty as Store<InterBtcRuntime>>::MODULE,
+            name: <$ty as Store<InterBtcRuntime>>::FIELD,
+            kind: ItemKind::Storage($keys),
+        }
+    };
+}
+
+type R = InterBtcRuntime;
+
+/// The calls, events and storage items declared in the pallets of this crate, with the number
+/// of arguments, fields or keys they are encoded with.
+fn requirements() -> Vec<Requirement> {
+    vec![
+        // core
+        storage!(Core, AccountStore<R>, 1),
+        storage!(Core, NowStore<R>, 0),
+        storage!(Core, AccountsStore<R>, 2),
+        storage!(Core, ParachainStatusStore<R>, 0),
+        storage!(Core, ErrorsStore<R>, 0),
+        storage!(Core, NonceStore<R>, 0),
+        storage!(Core, ActiveBlockCountStore<R>, 0),
+        event!(Core, UpdateActiveBlockEvent<R>, 1),
+        event!(Core, RecoverFromErrorsEvent<R>, 2),
+        storage!(Core, StableBitcoinConfirmationsStore<R>, 0),
+        storage!(Core, StableParachainConfirmationsStore<R>, 0),
+        storage!(Core, BestBlockHeightStore<R>, 0),
+        call!(Core, BatchCall<R>, 1),
+        call!(Core, RemarkCall<R>, 1),
+        // vault
+        call!(Vault, RegisterVaultCall<R>, 2),
+        call!(Vault, DepositCollateralCall<R>, 1),
+        call!(Vault, WithdrawCollateralCall<R>, 1),
+        call!(Vault, UpdatePublicKeyCall<R>, 1),
+        call!(Vault, RegisterAddressCall<R>, 1),
+        event!(Vault, RegisterVaultEvent<R>, 2),
+        event!(Vault, DepositCollateralEvent<R>, 4),
+        event!(Vault, WithdrawCollateralEvent<R>, 3),
+        event!(Vault, IncreaseToBeIssuedTokensEvent<R>, 2),
+        event!(Vault, UpdatePublicKeyEvent<R>, 2),
+        event!(Vault, LiquidateVaultEvent<R>, 8),
+        event!(Vault, UpdateVaultSLAEvent<R>, 3),
+        storage!(Vault, VaultsStore<R>, 1),
+        storage!(Vault, LiquidationCollateralThresholdStore<R>, 0),
+        storage!(Vault, ExchangeRateStore<R>, 0),
+        storage!(Vault, SatoshiPerBytesStore<R>, 0),
+        // issue
+        call!(Issue, RequestIssueCall<R>, 3),
+        call!(Issue, ExecuteIssueCall<R>, 3),
+        call!(Issue, CancelIssueCall<R>, 1),
+        event!(Issue, RequestIssueEvent<R>, 8),
+        event!(Issue, ExecuteIssueEvent<R>, 5),
+        event!(Issue, CancelIssueEvent<R>, 3),
+        storage!(Issue, IssueRequestsStore<R>, 1),
+        storage!(Issue, IssuePeriodStore<R>, 0),
+        storage!(Issue, IssueGriefingCollateralStore<R>, 0),
+        storage!(Issue, IssueFeeStore<R>, 0),
+        // redeem
+        call!(Redeem, RequestRedeemCall<R>, 3),
+        call!(Redeem, ExecuteRedeemCall<R>, 3),
+        call!(Redeem, CancelRedeemCall<R>, 2),
+        event!(Redeem, RequestRedeemEvent<R>, 8),
+        event!(Redeem, ExecuteRedeemEvent<R>, 6),
+        event!(Redeem, CancelRedeemEvent<R>, 5),
+        storage!(Redeem, RedeemRequestsStore<R>, 1),
+        storage!(Redeem, RedeemPeriodStore<R>, 0),
+        // replace
+        call!(Replace, RequestReplaceCall<R>, 2),
+        call!(Replace, WithdrawReplaceCall<R>, 1),
+        call!(Replace, AcceptReplaceCall<R>, 4),
+        call!(Replace, ExecuteReplaceCall<R>, 3),
+        call!(Replace, CancelReplaceCall<R>, 1),
+        event!(Replace, RequestReplaceEvent<R>, 3),
+        event!(Replace, WithdrawReplaceEvent<R>, 3),
+        event!(Replace, AcceptReplaceEvent<R>, 6),
+        event!(Replace, ExecuteReplaceEvent<R>, 3),
+        event!(Replace, CancelReplaceEvent<R>, 4),
+        storage!(Replace, ReplacePeriodStore<R>, 0),
+        storage!(Replace, ReplaceBtcDustValueStore<R>, 0),
+        storage!(Replace, ReplaceRequestsStore<R>, 1),
+        storage!(Replace, ReplaceGriefingCollateralStore<R>, 0),
+        // refund
+        call!(Refund, ExecuteRefundCall<R>, 3),
+        event!(Refund, RequestRefundEvent<R>, 7),
+        event!(Refund, ExecuteRefundEvent<R>, 5),
+        storage!(Refund, RefundRequestsStore<R>, 1),
+        // relay
+        call!(Relay, InitializeCall<R>, 2),
+        call!(Relay, StoreBlockHeaderCall<R>, 1),
+        event!(Relay, InitializedEvent<R>, 3),
+        event!(Relay, StoreMainChainHeaderEvent<R>, 3),
+        storage!(Relay, BestBlockStore<R>, 0),
+        storage!(Relay, ChainsHashesStore<R>, 2),
+        storage!(Relay, BlockHeadersStore<R>, 1),
+        // theft reporting
+        call!(TheftReporting, ReportVaultTheftCall<R>, 3),
+        event!(TheftReporting, VaultTheftEvent<R>, 2),
+        event!(TheftReporting, RegisterVaultEvent<R>, 2),
+        event!(TheftReporting, RegisterAddressEvent<R>, 2),
+        storage!(TheftReporting, VaultsStore<R>, 1),
+        // oracle
+        call!(Oracle, SetExchangeRateCall<R>, 1),
+        call!(Oracle, SetBtcTxFeesPerByteCall<R>, 3),
+        event!(Oracle, SetExchangeRateEvent<R>, 2),
+        event!(Oracle, SetBtcTxFeesPerByteEvent<R>, 4),
+        storage!(Oracle, ExchangeRateStore<R>, 0),
+        storage!(Oracle, LastExchangeRateTimeStore<R>, 0),
+        storage!(Oracle, MaxDelayStore<R>, 0),
+        // transfer
+        call!(Transfer, TransferCall<R>, 3),
+        event!(Transfer, TransferEvent<R>, 4),
+    ]
+}
+
+/// Version of the runtime, as returned by `state_getRuntimeVersion`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RuntimeVersion {
+    pub spec_name: String,
+    pub spec_version: u32,
+    pub transaction_version: u32,
+}
+
+/// The calls, events and storage items of a single pallet.
+#[derive(Debug, Default, Clone)]
+struct PalletShape {
+    calls: HashMap<String, usize>,
+    events: HashMap<String, usize>,
+    storage: HashMap<String, usize>,
+}
+
+/// The parts of the runtime metadata that are needed to check compatibility.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RuntimeShape {
+    pallets: HashMap<String, PalletShape>,
+}
+
+fn decoded<B: 'static, O: 'static>(value: DecodeDifferent<B, O>) -> Result<O, Error> {
+    match value {
+        DecodeDifferent::Decoded(value) => Ok(value),
+        DecodeDifferent::Encode(_) => Err(Error::UnsupportedMetadata),
+    }
+}
+
+impl RuntimeShape {
+    /// Decode the SCALE encoded metadata, as returned by `state_getMetadata`.
+    pub(crate) fn decode(encoded: &[u8]) -> Result<Self, Error> {
+        let prefixed = RuntimeMetadataPrefixed::decode(&mut &encoded[..])?;
+        let metadata = match prefixed {
+            RuntimeMetadataPrefixed(META_RESERVED, RuntimeMetadata::V13(metadata)) => metadata,
+            _ => return Err(Error::UnsupportedMetadata),
+        };
+
+        let mut pallets = HashMap::new();
+        for module in decoded(metadata.modules)? {
+            let mut pallet = PalletShape::default();
+            for call in module.calls.map(decoded).transpose()?.unwrap_or_default() {
+                pallet.calls.insert(decoded(call.name)?, decoded(call.arguments)?.len());
+            }
+            for event in module.event.map(decoded).transpose()?.unwrap_or_default() {
+                pallet
+                    .events
+                    .insert(decoded(event.name)?, decoded(event.arguments)?.len());
+            }
+            if let Some(storage) = module.storage.map(decoded).transpose()? {
+                for entry in decoded(storage.entries)? {
+                    let keys = match entry.ty {
+                        StorageEntryType::Plain(_) => 0,
+                        StorageEntryType::Map { .. } => 1,
+                        StorageEntryType::DoubleMap { .. } => 2,
+                        StorageEntryType::NMap { keys, .. } => decoded(keys)?.len(),
+                    };
+                    pallet.storage.insert(decoded(entry.name)?, keys);
+                }
+            }
+            pallets.insert(decoded(module.name)?, pallet);
+        }
+        Ok(Self { pallets })
+    }
+
+    /// Number of arguments, fields or keys of the required item, if the runtime has it.
+    fn find(&self, requirement: &Requirement) -> Option<usize> {
+        let pallet = self.pallets.get(requirement.module)?;
+        let items = match requirement.kind {
+            ItemKind::Call(_) => &pallet.calls,
+            ItemKind::Event(_) => &pallet.events,
+            ItemKind::Storage(_) => &pallet.storage,
+        };
+        items.get(requirement.name).copied()
+    }
+}
+
+/// A call, event or storage item that is used by a subsystem, but that is missing from the
+/// runtime or has a different shape.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Incompatibility {
+    pub subsystem: Subsystem,
+    pub description: String,
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.subsystem, self.description)
+    }
+}
+
+/// Result of checking the calls, events and storage items used by the clients against the
+/// metadata of the runtime.
+#[derive(Debug, Clone)]
+pub struct CompatibilityReport {
+    pub spec_name: String,
+    pub spec_version: u32,
+    pub transaction_version: u32,
+    pub incompatibilities: Vec<Incompatibility>,
+}
+
+impl CompatibilityReport {
+    pub(crate) fn new(version: RuntimeVersion, shape: &RuntimeShape) -> Self {
+        let incompatibilities = requirements()
+            .into_iter()
+            .filter_map(|requirement| {
+                let (kind, expected, unit) = match requirement.kind {
+                    ItemKind::Call(x) => ("call", x, "arguments"),
+                    ItemKind::Event(x) => ("event", x, "fields"),
+                    ItemKind::Storage(x) => ("storage item", x, "keys"),
+                };
+                let description = match shape.find(&requirement) {
+                    Some(found) if found == expected => return None,
+                    Some(found) => format!(
+                        "{} {}.{} has {} {}, expected {}",
+                        kind, requirement.module, requirement.name, found, unit, expected
+                    ),
+                    None => format!("{} {}.{} is missing", kind, requirement.module, requirement.name),
+                };
+                Some(Incompatibility {
+                    subsystem: requirement.subsystem,
+                    description,
+                })
+            })
+            .collect();
+
+        Self {
+            spec_name: version.spec_name,
+            spec_version: version.spec_version,
+            transaction_version: version.transaction_version,
+            incompatibilities,
+        }
+    }
+
+    pub fn is_compatible(&self) -> bool {
+        self.incompatibilities.is_empty()
+    }
+
+    /// Whether the runtime supports everything that the subsystem uses.
+    pub fn supports(&self, subsystem: Subsystem) -> bool {
+        !self.incompatibilities.iter().any(|x| x.subsystem == subsystem)
+    }
+
+    /// Fail with `Error::IncompatibleRuntime` if any of the subsystems is not supported.
+    pub fn require(&self, subsystems: &[Subsystem]) -> Result<(), Error> {
+        let unsupported: Vec<_> = self
+            .incompatibilities
+            .iter()
+            .filter(|x| subsystems.contains(&x.subsystem))
+            .map(ToString::to_string)
+            .collect();
+        if unsupported.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::IncompatibleRuntime(self.spec_version, unsupported.join("; ")))
+        }
+    }
+}
+
+impl fmt::Display for CompatibilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "runtime {} (spec version {}, transaction version {})",
+            self.spec_name, self.spec_version, self.transaction_version
+        )?;
+        if self.is_compatible() {
+            write!(f, " is compatible")
+        } else {
+            write!(f, " is incompatible:")?;
+            for incompatibility in self.incompatibilities.iter() {
+                write!(f, "\n  {}", incompatibility)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version() -> RuntimeVersion {
+        RuntimeVersion {
+            spec_name: "interbtc-parachain".to_string(),
+            spec_version: 1,
+            transaction_version: 1,
+        }
+    }
+
+    /// Shape of a runtime that supports all requirements.
+    fn compatible_shape() -> RuntimeShape {
+        let mut shape = RuntimeShape::default();
+        for requirement in requirements() {
+            let pallet = shape.pallets.entry(requirement.module.to_string()).or_default();
+            let name = requirement.name.to_string();
+            match requirement.kind {
+                ItemKind::Call(x) => pallet.calls.insert(name, x),
+                ItemKind::Event(x) => pallet.events.insert(name, x),
+                ItemKind::Storage(x) => pallet.storage.insert(name, x),
+            };
+        }
+        shape
+    }
+
+    #[test]
+    fn test_compatible_runtime() {
+        let report = CompatibilityReport::new(version(), &compatible_shape());
+        assert!(report.is_compatible());
+        assert!(report.require(&[Subsystem::Core, Subsystem::Vault]).is_ok());
+    }
+
+    #[test]
+    fn test_missing_call_disables_subsystem() {
+        let mut shape = compatible_shape();
+        shape
+            .pallets
+            .get_mut(<StoreBlockHeaderCall<R> as Call<R>>::MODULE)
+            .unwrap()
+            .calls
+            .remove(<StoreBlockHeaderCall<R> as Call<R>>::FUNCTION);
+
+        let report = CompatibilityReport::new(version(), &shape);
+        assert!(!report.supports(Subsystem::Relay));
+        assert!(report.supports(Subsystem::Issue));
+        assert!(report.require(&[Subsystem::Issue]).is_ok());
+        assert!(matches!(
+            report.require(&[Subsystem::Issue, Subsystem::Relay]),
+            Err(Error::IncompatibleRuntime(1, _))
+        ));
+    }
+
+    #[test]
+    fn test_changed_event_shape_is_reported() {
+        let mut shape = compatible_shape();
+        shape
+            .pallets
+            .get_mut(<RequestIssueEvent<R> as Event<R>>::MODULE)
+            .unwrap()
+            .events
+            .insert(<RequestIssueEvent<R> as Event<R>>::EVENT.to_string(), 9);
+
+        let report = CompatibilityReport::new(version(), &shape);
+        assert_eq!(
+            report.incompatibilities,
+            vec![Incompatibility {
+                subsystem: Subsystem::Issue,
+                description: "event Issue.RequestIssue has 9 fields, expected 8".to_string(),
+            }]
+        );
+    }
+}
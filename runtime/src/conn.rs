@@ -1,6 +1,13 @@
 use crate::{error::JsonRpseeError, Error};
+use codec::Decode;
+use jsonrpsee_types::to_json_value;
 use jsonrpsee_ws_client::{WsClient, WsClientBuilder};
-use std::time::Duration;
+use sp_core::{
+    storage::{StorageData, StorageKey},
+    H256,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use substrate_subxt::RpcClient;
 use tokio::time::{delay_for, timeout};
 
 const RETRY_TIMEOUT: Duration = Duration::from_millis(1000);
@@ -49,3 +56,48 @@ pub(crate) async fn new_websocket_client_with_retry(
     })
     .await?
 }
+
+/// Check that the endpoint serves the chain with the given genesis hash and, if `max_head_age`
+/// is set, that its latest finalized block was produced at most that long ago.
+pub(crate) async fn check_endpoint_health(
+    rpc_client: &RpcClient,
+    genesis_hash: Option<H256>,
+    max_head_age: Option<Duration>,
+) -> Result<(), Error> {
+    if let Some(expected) = genesis_hash {
+        let actual: Option<H256> = rpc_client
+            .request("chain_getBlockHash", &[to_json_value(0u32)?])
+            .await?;
+        match actual {
+            Some(actual) if actual == expected => {}
+            Some(actual) => return Err(Error::GenesisHashMismatch(actual)),
+            None => return Err(Error::BlockNotFound),
+        }
+    }
+
+    if let Some(max_head_age) = max_head_age {
+        let head: H256 = rpc_client.request("chain_getFinalizedHead", &[]).await?;
+        let mut key = sp_core::twox_128(b"Timestamp").to_vec();
+        key.extend(sp_core::twox_128(b"Now").iter());
+        let data: Option<StorageData> = rpc_client
+            .request(
+                "state_getStorage",
+                &[to_json_value(StorageKey(key))?, to_json_value(head)?],
+            )
+            .await?;
+        let head_time = match data {
+            Some(data) => u64::decode(&mut &data.0[..])?,
+            None => 0,
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let head_age = Duration::from_millis(now.saturating_sub(head_time));
+        if head_age > max_head_age {
+            return Err(Error::StaleFinalizedHead(head_age.as_secs()));
+        }
+    }
+
+    Ok(())
+}
@@ -2,8 +2,8 @@ pub use jsonrpsee_types::error::Error as JsonRpseeError;
 pub use substrate_subxt::Error as SubxtError;
 
 use crate::{
-    BTC_RELAY_MODULE, COMMIT_PERIOD_EXPIRED_ERROR, DUPLICATE_BLOCK_ERROR, INVALID_CHAIN_ID_ERROR,
-    ISSUE_COMPLETED_ERROR, ISSUE_MODULE, REDEEM_MODULE,
+    module_error::{BtcRelayError, IssueError, ModuleError, RedeemError, RelayError},
+    Index,
 };
 use codec::Error as CodecError;
 use jsonrpsee_types::{
@@ -12,9 +12,9 @@ use jsonrpsee_types::{
 };
 use jsonrpsee_ws_client::transport::WsConnectError;
 use serde_json::{value::Value as JsonValue, Error as SerdeJsonError};
-use sp_core::crypto::SecretStringError;
+use sp_core::{crypto::SecretStringError, H256};
 use std::{array::TryFromSliceError, io::Error as IoError, num::TryFromIntError};
-use substrate_subxt::{ModuleError as SubxtModuleError, RuntimeError as SubxtRuntimeError};
+use substrate_subxt::RuntimeError as SubxtRuntimeError;
 use thiserror::Error;
 use tokio::time::Elapsed;
 use url::ParseError as UrlParseError;
@@ -45,6 +45,24 @@ pub enum Error {
     Timeout,
     #[error("Block is not in the relay main chain")]
     BlockNotInRelayMainChain,
+    #[error("Extrinsic not submitted in dry-run mode")]
+    DryRun,
+    #[error("Endpoint serves a different chain with genesis hash {0}")]
+    GenesisHashMismatch(H256),
+    #[error("Latest finalized block is {0} seconds old")]
+    StaleFinalizedHead(u64),
+    #[error("Unsupported runtime metadata version")]
+    UnsupportedMetadata,
+    #[error("Runtime with spec version {0} is incompatible: {1}")]
+    IncompatibleRuntime(u32, String),
+    #[error("Estimated fee of {0} exceeds the free balance of {1}")]
+    InsufficientFeeBalance(u128, u128),
+    #[error("Could not parse the fee estimate: {0}")]
+    InvalidFeeEstimate(String),
+    #[error("Extrinsic with nonce {0} was dropped and resubmitting it failed")]
+    ExtrinsicDropped(Index),
+    #[error("Nonce {0} was used by an extrinsic that was not seen to be finalized")]
+    ExtrinsicNonceUsed(Index),
 
     #[error("Failed to load credentials from file: {0}")]
     KeyLoadingFailure(#[from] KeyLoadingError),
@@ -58,6 +76,8 @@ pub enum Error {
     CodecError(#[from] CodecError),
     #[error("Error encoding json data: {0}")]
     SerdeJsonError(#[from] SerdeJsonError),
+    #[error("Io error: {0}")]
+    IoError(#[from] IoError),
     #[error("Error getting json-rpsee data: {0}")]
     JsonRpseeError(#[from] JsonRpseeError),
     /// Occurs during websocket handshake
@@ -70,30 +90,32 @@ pub enum Error {
 }
 
 impl Error {
+    /// The typed dispatch error, if an extrinsic failed in a pallet.
+    pub fn module_error(&self) -> Option<ModuleError> {
+        match self {
+            Error::SubxtError(SubxtError::Runtime(SubxtRuntimeError::Module(err))) => Some(err.into()),
+            _ => None,
+        }
+    }
+
     pub fn is_duplicate_block(&self) -> bool {
-        matches!(self,
-            Error::SubxtError(SubxtError::Runtime(SubxtRuntimeError::Module(SubxtModuleError {
-                ref module,
-                ref error,
-            }))) if module == BTC_RELAY_MODULE && error == DUPLICATE_BLOCK_ERROR
+        matches!(
+            self.module_error(),
+            Some(ModuleError::BtcRelay(BtcRelayError::DuplicateBlock))
         )
     }
 
     pub fn is_invalid_chain_id(&self) -> bool {
-        matches!(self,
-            Error::SubxtError(SubxtError::Runtime(SubxtRuntimeError::Module(SubxtModuleError {
-                ref module,
-                ref error,
-            }))) if module == BTC_RELAY_MODULE && error == INVALID_CHAIN_ID_ERROR
+        matches!(
+            self.module_error(),
+            Some(ModuleError::BtcRelay(BtcRelayError::InvalidChainID))
         )
     }
 
     pub fn is_issue_completed(&self) -> bool {
-        matches!(self,
-            Error::SubxtError(SubxtError::Runtime(SubxtRuntimeError::Module(SubxtModuleError {
-                ref module,
-                ref error,
-            }))) if module == ISSUE_MODULE && error == ISSUE_COMPLETED_ERROR
+        matches!(
+            self.module_error(),
+            Some(ModuleError::Issue(IssueError::IssueCompleted))
         )
     }
 
@@ -106,12 +128,34 @@ impl Error {
         )
     }
 
-    pub fn is_commit_period_expired(&self) -> bool {
+    /// The extrinsic does not fit into a block, because of its weight or its length.
+    pub fn is_exhausted_resources(&self) -> bool {
+        matches!(self,
+            Error::SubxtError(SubxtError::Rpc(RequestError::Request(JsonRpcError { error, .. })))
+                if error.code == JsonRpcErrorCode::ServerError(POOL_INVALID_TX) &&
+                error.data == Some(JsonValue::String(EXHAUSTS_RESOURCES_DATA_STR.to_string()))
+        )
+    }
+
+    /// The extrinsic is no longer in the transaction pool, or it is not finalized in time.
+    pub fn is_dropped_extrinsic(&self) -> bool {
         matches!(self,
-            Error::SubxtError(SubxtError::Runtime(SubxtRuntimeError::Module(SubxtModuleError {
-                ref module,
-                ref error,
-            }))) if module == REDEEM_MODULE && error == COMMIT_PERIOD_EXPIRED_ERROR
+            Error::SubxtError(SubxtError::Other(message)) if DROPPED_EXTRINSIC_MESSAGES.contains(&message.as_str())
+        ) || matches!(self, Error::TimeElapsed(_))
+    }
+
+    pub fn is_commit_period_expired(&self) -> bool {
+        matches!(
+            self.module_error(),
+            Some(ModuleError::Redeem(RedeemError::CommitPeriodExpired))
+        )
+    }
+
+    /// The vault has already been reported for the transaction, e.g. by another client.
+    pub fn is_vault_already_reported(&self) -> bool {
+        matches!(
+            self.module_error(),
+            Some(ModuleError::Relay(RelayError::VaultAlreadyReported))
         )
     }
 
@@ -137,6 +181,14 @@ pub enum KeyLoadingError {
     IoError(#[from] IoError),
     #[error("Invalid secret string: {0:?}")]
     SecretStringError(SecretStringError),
+    #[error("Base64 decoding error: {0}")]
+    Base64Error(#[from] base64::DecodeError),
+    #[error("Unsupported keyfile encoding: {0}")]
+    UnsupportedEncoding(String),
+    #[error("Invalid keyfile encoding")]
+    InvalidEncoding,
+    #[error("Failed to decrypt keyfile, the password may be wrong")]
+    DecryptionFailed,
 }
 
 // https://github.com/paritytech/substrate/blob/e60597dff0aa7ffad623be2cc6edd94c7dc51edd/client/rpc-api/src/author/error.rs#L80
@@ -144,3 +196,12 @@ const BASE_ERROR: i32 = 1000;
 const POOL_INVALID_TX: i32 = BASE_ERROR + 10;
 const OUTDATED_NONCE_MESSAGE: &str = "Invalid Transaction";
 const OUTDATED_NONCE_DATA_STR: &str = "Transaction is outdated";
+const EXHAUSTS_RESOURCES_DATA_STR: &str = "Transaction would exhaust the block limits";
+
+// https://github.com/paritytech/substrate-subxt/blob/v0.15.0/src/rpc.rs
+const DROPPED_EXTRINSIC_MESSAGES: &[&str] = &[
+    "Extrinsic Invalid",
+    "Extrinsic Usurped",
+    "Extrinsic Dropped",
+    "Extrinsic FinalityTimeout",
+];
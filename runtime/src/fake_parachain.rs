@@ -0,0 +1,1758 @@
+use crate::{
+    btc_relay::StoreMainChainHeaderEvent,
+    issue::*,
+    module_error::{
+        BtcRelayError, ExchangeRateOracleError, IssueError, ModuleError, RedeemError, RefundError, RelayError,
+        ReplaceError, TokensError, VaultRegistryError,
+    },
+    pallets::*,
+    redeem::*,
+    refund::*,
+    replace::*,
+    rpc::*,
+    security::UpdateActiveBlockEvent,
+    types::*,
+    vault_registry::*,
+    AccountId, BlockNumber, Error, InterBtcRuntime, MILLISECS_PER_BLOCK,
+};
+use async_trait::async_trait;
+use core::marker::PhantomData;
+use module_bitcoin::parser::parse_block_header;
+use sp_arithmetic::{FixedPointNumber, FixedU128};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+use substrate_subxt::{Error as SubxtError, RuntimeError as SubxtRuntimeError};
+use tokio::{sync::broadcast, time::delay_for};
+
+/// Number of events buffered for each subscriber.
+const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// Interval at which `wait_for_block_in_relay` re-checks the relay.
+const BLOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+const DEFAULT_PERIOD: BlockNumber = 100;
+const DEFAULT_MAX_DELAY: u64 = 3_600_000;
+
+/// Events emitted by the [`FakeParachain`], in the order the calls were made.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FakeEvent {
+    RegisterVault(RegisterVaultEvent<InterBtcRuntime>),
+    DepositCollateral(DepositCollateralEvent<InterBtcRuntime>),
+    WithdrawCollateral(WithdrawCollateralEvent<InterBtcRuntime>),
+    UpdatePublicKey(UpdatePublicKeyEvent<InterBtcRuntime>),
+    RegisterAddress(RegisterAddressEvent<InterBtcRuntime>),
+    RequestIssue(RequestIssueEvent<InterBtcRuntime>),
+    ExecuteIssue(ExecuteIssueEvent<InterBtcRuntime>),
+    CancelIssue(CancelIssueEvent<InterBtcRuntime>),
+    RequestRedeem(RequestRedeemEvent<InterBtcRuntime>),
+    ExecuteRedeem(ExecuteRedeemEvent<InterBtcRuntime>),
+    CancelRedeem(CancelRedeemEvent<InterBtcRuntime>),
+    RequestReplace(RequestReplaceEvent<InterBtcRuntime>),
+    WithdrawReplace(WithdrawReplaceEvent<InterBtcRuntime>),
+    AcceptReplace(AcceptReplaceEvent<InterBtcRuntime>),
+    ExecuteReplace(ExecuteReplaceEvent<InterBtcRuntime>),
+    CancelReplace(CancelReplaceEvent<InterBtcRuntime>),
+    ExecuteRefund(ExecuteRefundEvent<InterBtcRuntime>),
+    StoreMainChainHeader(StoreMainChainHeaderEvent<InterBtcRuntime>),
+    UpdateActiveBlock(UpdateActiveBlockEvent<InterBtcRuntime>),
+}
+
+fn module_error(err: ModuleError) -> Error {
+    Error::SubxtError(SubxtError::Runtime(SubxtRuntimeError::Module(err.into())))
+}
+
+/// Arithmetic overflow in the pallet that does the computation, e.g. `ModuleError::Issue`.
+fn overflow(err: ModuleError) -> impl FnOnce() -> Error {
+    move || module_error(err)
+}
+
+struct State {
+    height: BlockNumber,
+    active_block: BlockNumber,
+    time: u64,
+    free: BTreeMap<AccountId, u128>,
+    reserved: BTreeMap<AccountId, u128>,
+    wrapped: BTreeMap<AccountId, u128>,
+    vaults: BTreeMap<AccountId, InterBtcVault>,
+    /// Backing collateral of each vault, also included in its reserved balance.
+    collateral: BTreeMap<AccountId, u128>,
+    /// Griefing collateral locked by vaults requesting to be replaced.
+    replace_collateral: BTreeMap<AccountId, u128>,
+    issue_requests: BTreeMap<H256, InterBtcIssueRequest>,
+    redeem_requests: BTreeMap<H256, InterBtcRedeemRequest>,
+    replace_requests: BTreeMap<H256, InterBtcReplaceRequest>,
+    refund_requests: BTreeMap<H256, (AccountId, InterBtcRefundRequest)>,
+    issue_period: BlockNumber,
+    redeem_period: BlockNumber,
+    replace_period: BlockNumber,
+    exchange_rate: FixedU128,
+    exchange_rate_time: u64,
+    btc_tx_fees: BtcTxFeesPerByte,
+    oracles: BTreeMap<AccountId, String>,
+    issue_fee: FixedU128,
+    redeem_fee: FixedU128,
+    issue_griefing_collateral: FixedU128,
+    replace_griefing_collateral: FixedU128,
+    secure_collateral_threshold: FixedU128,
+    replace_dust_amount: u128,
+    relay_start_height: u32,
+    relay_chain: Vec<H256Le>,
+    relay_headers: BTreeMap<H256Le, InterBtcRichBlockHeader>,
+    invalid_transactions: BTreeSet<Vec<u8>>,
+    bitcoin_confirmations: u32,
+    parachain_confirmations: BlockNumber,
+    status_code: StatusCode,
+    error_codes: BTreeSet<ErrorCode>,
+    nonce: u64,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            height: 1,
+            active_block: 1,
+            time: 0,
+            free: Default::default(),
+            reserved: Default::default(),
+            wrapped: Default::default(),
+            vaults: Default::default(),
+            collateral: Default::default(),
+            replace_collateral: Default::default(),
+            issue_requests: Default::default(),
+            redeem_requests: Default::default(),
+            replace_requests: Default::default(),
+            refund_requests: Default::default(),
+            issue_period: DEFAULT_PERIOD,
+            redeem_period: DEFAULT_PERIOD,
+            replace_period: DEFAULT_PERIOD,
+            exchange_rate: FixedU128::one(),
+            exchange_rate_time: 0,
+            btc_tx_fees: Default::default(),
+            oracles: Default::default(),
+            issue_fee: FixedU128::saturating_from_rational(5, 1000),
+            redeem_fee: FixedU128::saturating_from_rational(5, 1000),
+            issue_griefing_collateral: FixedU128::saturating_from_rational(5, 100_000),
+            replace_griefing_collateral: FixedU128::saturating_from_rational(1, 10),
+            secure_collateral_threshold: FixedU128::saturating_from_rational(150, 100),
+            replace_dust_amount: 0,
+            relay_start_height: 0,
+            relay_chain: Default::default(),
+            relay_headers: Default::default(),
+            invalid_transactions: Default::default(),
+            bitcoin_confirmations: 0,
+            parachain_confirmations: 0,
+            status_code: StatusCode::Running,
+            error_codes: Default::default(),
+            nonce: 0,
+        }
+    }
+}
+
+impl State {
+    fn next_id(&mut self) -> H256 {
+        self.nonce += 1;
+        H256::from_low_u64_be(self.nonce)
+    }
+
+    fn best_block_height(&self) -> u32 {
+        (self.relay_start_height + self.relay_chain.len() as u32).saturating_sub(1)
+    }
+
+    fn has_expired(&self, opentime: BlockNumber, period: BlockNumber) -> bool {
+        self.height > opentime.saturating_add(period)
+    }
+
+    fn reserve(&mut self, account_id: &AccountId, amount: u128) -> Result<(), Error> {
+        let free = self.free.entry(account_id.clone()).or_default();
+        *free = free
+            .checked_sub(amount)
+            .ok_or_else(|| module_error(ModuleError::Tokens(TokensError::BalanceTooLow)))?;
+        *self.reserved.entry(account_id.clone()).or_default() += amount;
+        Ok(())
+    }
+
+    fn unreserve(&mut self, account_id: &AccountId, amount: u128) {
+        self.slash(account_id, account_id, amount);
+    }
+
+    /// Moves reserved funds of `from` to the free balance of `to`.
+    fn slash(&mut self, from: &AccountId, to: &AccountId, amount: u128) {
+        let reserved = self.reserved.entry(from.clone()).or_default();
+        let amount = amount.min(*reserved);
+        *reserved -= amount;
+        *self.free.entry(to.clone()).or_default() += amount;
+    }
+
+    fn wrapped_to_collateral(&self, amount: u128) -> Result<u128, Error> {
+        self.exchange_rate
+            .checked_mul_int(amount)
+            .ok_or_else(overflow(ModuleError::ExchangeRateOracle(
+                ExchangeRateOracleError::ArithmeticOverflow,
+            )))
+    }
+
+    fn collateral_to_wrapped(&self, amount: u128) -> Result<u128, Error> {
+        self.exchange_rate
+            .reciprocal()
+            .and_then(|rate| rate.checked_mul_int(amount))
+            .ok_or_else(overflow(ModuleError::ExchangeRateOracle(
+                ExchangeRateOracleError::ArithmeticOverflow,
+            )))
+    }
+
+    fn required_collateral_for_wrapped(&self, amount: u128) -> Result<u128, Error> {
+        let collateral = self.wrapped_to_collateral(amount)?;
+        self.secure_collateral_threshold
+            .checked_mul_int(collateral)
+            .ok_or_else(overflow(ModuleError::VaultRegistry(
+                VaultRegistryError::ArithmeticOverflow,
+            )))
+    }
+
+    fn required_collateral_for_vault(&self, vault_id: &AccountId) -> Result<u128, Error> {
+        let vault = self.vault(vault_id)?;
+        self.required_collateral_for_wrapped(vault.issued_tokens + vault.to_be_issued_tokens)
+    }
+
+    fn total_collateral(&self, vault_id: &AccountId) -> u128 {
+        self.collateral.get(vault_id).copied().unwrap_or_default()
+    }
+
+    fn vault(&self, vault_id: &AccountId) -> Result<&InterBtcVault, Error> {
+        self.vaults.get(vault_id).ok_or(Error::VaultNotFound)
+    }
+
+    /// Returns the vault if it has not been liquidated or reported for theft.
+    fn active_vault_mut(&mut self, vault_id: &AccountId) -> Result<&mut InterBtcVault, Error> {
+        let vault = self.vaults.get_mut(vault_id).ok_or(Error::VaultNotFound)?;
+        match vault.status {
+            VaultStatus::Liquidated => Err(Error::VaultLiquidated),
+            VaultStatus::CommittedTheft => Err(Error::VaultCommittedTheft),
+            _ => Ok(vault),
+        }
+    }
+
+    fn free_wrapped_capacity(&self, vault_id: &AccountId) -> Result<u128, Error> {
+        let vault = self.vault(vault_id)?;
+        let backed = self.collateral_to_wrapped(
+            self.secure_collateral_threshold
+                .reciprocal()
+                .and_then(|threshold| threshold.checked_mul_int(self.total_collateral(vault_id)))
+                .ok_or_else(overflow(ModuleError::VaultRegistry(
+                    VaultRegistryError::ArithmeticOverflow,
+                )))?,
+        )?;
+        Ok(backed.saturating_sub(vault.issued_tokens + vault.to_be_issued_tokens))
+    }
+
+    fn advance_block(&mut self) -> Option<FakeEvent> {
+        self.height += 1;
+        self.time += MILLISECS_PER_BLOCK;
+        if self.status_code != StatusCode::Running {
+            return None;
+        }
+        self.active_block += 1;
+        Some(FakeEvent::UpdateActiveBlock(UpdateActiveBlockEvent {
+            height: self.active_block,
+        }))
+    }
+
+    fn register_vault(
+        &mut self,
+        account_id: &AccountId,
+        collateral: u128,
+        public_key: BtcPublicKey,
+    ) -> Result<FakeEvent, Error> {
+        if self.vaults.contains_key(account_id) {
+            return Err(module_error(ModuleError::VaultRegistry(
+                VaultRegistryError::VaultAlreadyRegistered,
+            )));
+        }
+        self.reserve(account_id, collateral)?;
+        self.collateral.insert(account_id.clone(), collateral);
+
+        let mut vault = InterBtcVault {
+            id: account_id.clone(),
+            status: VaultStatus::Active(true),
+            ..Default::default()
+        };
+        vault.wallet.public_key = public_key;
+        self.vaults.insert(account_id.clone(), vault);
+
+        Ok(FakeEvent::RegisterVault(RegisterVaultEvent {
+            account_id: account_id.clone(),
+            collateral,
+        }))
+    }
+
+    fn deposit_collateral(&mut self, vault_id: &AccountId, amount: u128) -> Result<FakeEvent, Error> {
+        self.active_vault_mut(vault_id)?;
+        self.reserve(vault_id, amount)?;
+        let total_collateral = {
+            let collateral = self.collateral.entry(vault_id.clone()).or_default();
+            *collateral += amount;
+            *collateral
+        };
+        let required = self.required_collateral_for_vault(vault_id)?;
+
+        Ok(FakeEvent::DepositCollateral(DepositCollateralEvent {
+            vault_id: vault_id.clone(),
+            new_collateral: amount,
+            total_collateral,
+            free_collateral: total_collateral.saturating_sub(required),
+        }))
+    }
+
+    fn withdraw_collateral(&mut self, vault_id: &AccountId, amount: u128) -> Result<FakeEvent, Error> {
+        self.active_vault_mut(vault_id)?;
+        let total_collateral = self
+            .total_collateral(vault_id)
+            .checked_sub(amount)
+            .ok_or_else(|| module_error(ModuleError::VaultRegistry(VaultRegistryError::InsufficientCollateral)))?;
+        if total_collateral < self.required_collateral_for_vault(vault_id)? {
+            return Err(module_error(ModuleError::VaultRegistry(
+                VaultRegistryError::InsufficientCollateral,
+            )));
+        }
+        self.collateral.insert(vault_id.clone(), total_collateral);
+        self.unreserve(vault_id, amount);
+
+        Ok(FakeEvent::WithdrawCollateral(WithdrawCollateralEvent {
+            vault_id: vault_id.clone(),
+            withdrawn_collateral: amount,
+            total_collateral,
+        }))
+    }
+
+    fn request_issue(
+        &mut self,
+        requester: &AccountId,
+        amount: u128,
+        vault_id: &AccountId,
+        griefing_collateral: u128,
+    ) -> Result<RequestIssueEvent<InterBtcRuntime>, Error> {
+        let required_griefing_collateral = self
+            .issue_griefing_collateral
+            .checked_mul_int(self.wrapped_to_collateral(amount)?)
+            .ok_or_else(overflow(ModuleError::Issue(IssueError::ArithmeticOverflow)))?;
+        if griefing_collateral < required_griefing_collateral {
+            return Err(module_error(ModuleError::Issue(IssueError::InsufficientCollateral)));
+        }
+        let vault = self.active_vault_mut(vault_id)?;
+        if vault.status != VaultStatus::Active(true) {
+            return Err(module_error(ModuleError::VaultRegistry(
+                VaultRegistryError::VaultNotAcceptingIssueRequests,
+            )));
+        }
+        if amount > self.free_wrapped_capacity(vault_id)? {
+            return Err(module_error(ModuleError::VaultRegistry(
+                VaultRegistryError::ExceedingVaultLimit,
+            )));
+        }
+        self.reserve(requester, griefing_collateral)?;
+
+        let issue_id = self.next_id();
+        let fee = self
+            .issue_fee
+            .checked_mul_int(amount)
+            .ok_or_else(overflow(ModuleError::Issue(IssueError::ArithmeticOverflow)))?;
+        // deterministic stand-in for the deposit address derived by the vault registry
+        let vault_btc_address = BtcAddress::P2PKH(H160::from_slice(&issue_id.as_bytes()[12..]));
+        let btc_height = self.best_block_height();
+        let opentime = self.height;
+        let period = self.issue_period;
+
+        let vault = self.active_vault_mut(vault_id)?;
+        vault.to_be_issued_tokens += amount;
+        let vault_public_key = vault.wallet.public_key.clone();
+
+        self.issue_requests.insert(
+            issue_id,
+            InterBtcIssueRequest {
+                vault: vault_id.clone(),
+                opentime,
+                period,
+                griefing_collateral,
+                amount: amount - fee,
+                fee,
+                requester: requester.clone(),
+                btc_address: vault_btc_address,
+                btc_public_key: vault_public_key.clone(),
+                btc_height,
+                status: IssueRequestStatus::Pending,
+            },
+        );
+
+        Ok(RequestIssueEvent {
+            issue_id,
+            requester: requester.clone(),
+            amount_btc: amount - fee,
+            fee,
+            griefing_collateral,
+            vault_id: vault_id.clone(),
+            vault_btc_address,
+            vault_public_key,
+        })
+    }
+
+    fn pending_issue(&self, issue_id: H256) -> Result<InterBtcIssueRequest, Error> {
+        let request = self
+            .issue_requests
+            .get(&issue_id)
+            .cloned()
+            .ok_or_else(|| module_error(ModuleError::Issue(IssueError::IssueIdNotFound)))?;
+        match request.status {
+            IssueRequestStatus::Pending => Ok(request),
+            IssueRequestStatus::Completed(_) => Err(module_error(ModuleError::Issue(IssueError::IssueCompleted))),
+            IssueRequestStatus::Cancelled => Err(module_error(ModuleError::Issue(IssueError::IssueCancelled))),
+        }
+    }
+
+    fn execute_issue(&mut self, issue_id: H256) -> Result<FakeEvent, Error> {
+        let request = self.pending_issue(issue_id)?;
+        if self.has_expired(request.opentime, request.period) {
+            return Err(module_error(ModuleError::Issue(IssueError::CommitPeriodExpired)));
+        }
+        let vault = self.active_vault_mut(&request.vault)?;
+        let total = request.amount + request.fee;
+        vault.to_be_issued_tokens = vault.to_be_issued_tokens.saturating_sub(total);
+        vault.issued_tokens += total;
+
+        *self.wrapped.entry(request.requester.clone()).or_default() += request.amount;
+        self.unreserve(&request.requester, request.griefing_collateral);
+        self.issue_requests.insert(
+            issue_id,
+            InterBtcIssueRequest {
+                status: IssueRequestStatus::Completed(None),
+                ..request.clone()
+            },
+        );
+
+        Ok(FakeEvent::ExecuteIssue(ExecuteIssueEvent {
+            issue_id,
+            requester: request.requester,
+            executed_amount: request.amount,
+            vault_id: request.vault,
+            fee: request.fee,
+        }))
+    }
+
+    fn cancel_issue(&mut self, issue_id: H256) -> Result<FakeEvent, Error> {
+        let request = self.pending_issue(issue_id)?;
+        if !self.has_expired(request.opentime, request.period) {
+            return Err(module_error(ModuleError::Issue(IssueError::TimeNotExpired)));
+        }
+        if let Some(vault) = self.vaults.get_mut(&request.vault) {
+            vault.to_be_issued_tokens = vault.to_be_issued_tokens.saturating_sub(request.amount + request.fee);
+        }
+        self.slash(&request.requester, &request.vault, request.griefing_collateral);
+        self.issue_requests.insert(
+            issue_id,
+            InterBtcIssueRequest {
+                status: IssueRequestStatus::Cancelled,
+                ..request.clone()
+            },
+        );
+
+        Ok(FakeEvent::CancelIssue(CancelIssueEvent {
+            issue_id,
+            requester: request.requester,
+            griefing_collateral: request.griefing_collateral,
+        }))
+    }
+
+    fn request_redeem(
+        &mut self,
+        redeemer: &AccountId,
+        amount: u128,
+        btc_address: BtcAddress,
+        vault_id: &AccountId,
+    ) -> Result<RequestRedeemEvent<InterBtcRuntime>, Error> {
+        if self.wrapped.get(redeemer).copied().unwrap_or_default() < amount {
+            return Err(module_error(ModuleError::Redeem(RedeemError::AmountExceedsUserBalance)));
+        }
+        let fee = self
+            .redeem_fee
+            .checked_mul_int(amount)
+            .ok_or_else(overflow(ModuleError::Redeem(RedeemError::ArithmeticOverflow)))?;
+        let amount_btc = amount - fee;
+        let vault = self.active_vault_mut(vault_id)?;
+        if vault.issued_tokens.saturating_sub(vault.to_be_redeemed_tokens) < amount_btc {
+            return Err(module_error(ModuleError::Redeem(
+                RedeemError::AmountExceedsVaultBalance,
+            )));
+        }
+        vault.to_be_redeemed_tokens += amount_btc;
+        *self.wrapped.entry(redeemer.clone()).or_default() -= amount;
+
+        let redeem_id = self.next_id();
+        let request = InterBtcRedeemRequest {
+            vault: vault_id.clone(),
+            opentime: self.height,
+            period: self.redeem_period,
+            fee,
+            amount_btc,
+            redeemer: redeemer.clone(),
+            btc_address,
+            btc_height: self.best_block_height(),
+            status: RedeemRequestStatus::Pending,
+            ..Default::default()
+        };
+        self.redeem_requests.insert(redeem_id, request);
+
+        Ok(RequestRedeemEvent {
+            redeem_id,
+            redeemer: redeemer.clone(),
+            amount: amount_btc,
+            fee,
+            premium: 0,
+            vault_id: vault_id.clone(),
+            user_btc_address: btc_address,
+            transfer_fee: 0,
+        })
+    }
+
+    fn pending_redeem(&self, redeem_id: H256) -> Result<InterBtcRedeemRequest, Error> {
+        let request = self
+            .redeem_requests
+            .get(&redeem_id)
+            .cloned()
+            .ok_or_else(|| module_error(ModuleError::Redeem(RedeemError::RedeemIdNotFound)))?;
+        match request.status {
+            RedeemRequestStatus::Pending => Ok(request),
+            RedeemRequestStatus::Completed => Err(module_error(ModuleError::Redeem(RedeemError::RedeemCompleted))),
+            RedeemRequestStatus::Reimbursed(_) | RedeemRequestStatus::Retried => {
+                Err(module_error(ModuleError::Redeem(RedeemError::RedeemCancelled)))
+            }
+        }
+    }
+
+    fn execute_redeem(&mut self, redeem_id: H256) -> Result<FakeEvent, Error> {
+        let request = self.pending_redeem(redeem_id)?;
+        if self.has_expired(request.opentime, request.period) {
+            return Err(module_error(ModuleError::Redeem(RedeemError::CommitPeriodExpired)));
+        }
+        let vault = self.active_vault_mut(&request.vault)?;
+        vault.to_be_redeemed_tokens = vault.to_be_redeemed_tokens.saturating_sub(request.amount_btc);
+        vault.issued_tokens = vault.issued_tokens.saturating_sub(request.amount_btc);
+        self.redeem_requests.insert(
+            redeem_id,
+            InterBtcRedeemRequest {
+                status: RedeemRequestStatus::Completed,
+                ..request.clone()
+            },
+        );
+
+        Ok(FakeEvent::ExecuteRedeem(ExecuteRedeemEvent {
+            redeem_id,
+            redeemer: request.redeemer,
+            amount: request.amount_btc,
+            fee: request.fee,
+            vault_id: request.vault,
+            transfer_fee_btc: request.transfer_fee_btc,
+        }))
+    }
+
+    fn cancel_redeem(&mut self, caller: &AccountId, redeem_id: H256, reimburse: bool) -> Result<FakeEvent, Error> {
+        let request = self.pending_redeem(redeem_id)?;
+        if &request.redeemer != caller {
+            return Err(module_error(ModuleError::Redeem(RedeemError::UnauthorizedUser)));
+        }
+        if !self.has_expired(request.opentime, request.period) {
+            return Err(module_error(ModuleError::Redeem(RedeemError::TimeNotExpired)));
+        }
+
+        // the redeemer is compensated with collateral worth the redeemed amount
+        let slashing_amount = self
+            .wrapped_to_collateral(request.amount_btc)?
+            .min(self.total_collateral(&request.vault));
+        *self.collateral.entry(request.vault.clone()).or_default() -= slashing_amount;
+        self.slash(&request.vault, &request.redeemer, slashing_amount);
+
+        let vault = self.vaults.get_mut(&request.vault).ok_or(Error::VaultNotFound)?;
+        vault.to_be_redeemed_tokens = vault.to_be_redeemed_tokens.saturating_sub(request.amount_btc);
+        let status = if reimburse {
+            // the vault keeps the tokens, which are no longer backed by the slashed collateral
+            vault.issued_tokens = vault.issued_tokens.saturating_sub(request.amount_btc);
+            RedeemRequestStatus::Reimbursed(true)
+        } else {
+            *self.wrapped.entry(request.redeemer.clone()).or_default() += request.amount_btc + request.fee;
+            RedeemRequestStatus::Retried
+        };
+        self.redeem_requests.insert(
+            redeem_id,
+            InterBtcRedeemRequest {
+                status,
+                ..request.clone()
+            },
+        );
+
+        Ok(FakeEvent::CancelRedeem(CancelRedeemEvent {
+            redeem_id,
+            redeemer: request.redeemer,
+            vault_id: request.vault,
+            slashing_amount,
+            reimburse,
+        }))
+    }
+
+    fn request_replace(
+        &mut self,
+        vault_id: &AccountId,
+        amount: u128,
+        griefing_collateral: u128,
+    ) -> Result<FakeEvent, Error> {
+        let required_griefing_collateral = self
+            .replace_griefing_collateral
+            .checked_mul_int(self.wrapped_to_collateral(amount)?)
+            .ok_or_else(overflow(ModuleError::Replace(ReplaceError::ArithmeticOverflow)))?;
+        if griefing_collateral < required_griefing_collateral {
+            return Err(module_error(ModuleError::Replace(ReplaceError::InsufficientCollateral)));
+        }
+        let vault = self.active_vault_mut(vault_id)?;
+        let replaceable = vault
+            .issued_tokens
+            .saturating_sub(vault.to_be_redeemed_tokens)
+            .saturating_sub(vault.to_be_replaced_tokens);
+        if amount > replaceable {
+            return Err(module_error(ModuleError::Replace(
+                ReplaceError::InsufficientTokensCommitted,
+            )));
+        }
+        vault.to_be_replaced_tokens += amount;
+        self.reserve(vault_id, griefing_collateral)?;
+        *self.replace_collateral.entry(vault_id.clone()).or_default() += griefing_collateral;
+
+        Ok(FakeEvent::RequestReplace(RequestReplaceEvent {
+            old_vault_id: vault_id.clone(),
+            amount_btc: amount,
+            griefing_collateral,
+        }))
+    }
+
+    /// Removes up to `amount` tokens from the vault's replace request, returning the removed
+    /// tokens and the proportional share of the griefing collateral.
+    fn take_to_be_replaced(&mut self, vault_id: &AccountId, amount: u128) -> Result<(u128, u128), Error> {
+        let vault = self.active_vault_mut(vault_id)?;
+        let to_be_replaced = vault.to_be_replaced_tokens;
+        let amount = amount.min(to_be_replaced);
+        vault.to_be_replaced_tokens -= amount;
+
+        let replace_collateral = self.replace_collateral.entry(vault_id.clone()).or_default();
+        let griefing_collateral = if amount == to_be_replaced {
+            *replace_collateral
+        } else {
+            FixedU128::saturating_from_rational(amount, to_be_replaced).saturating_mul_int(*replace_collateral)
+        };
+        *replace_collateral -= griefing_collateral;
+        Ok((amount, griefing_collateral))
+    }
+
+    fn withdraw_replace(&mut self, vault_id: &AccountId, amount: u128) -> Result<FakeEvent, Error> {
+        let (withdrawn_tokens, withdrawn_griefing_collateral) = self.take_to_be_replaced(vault_id, amount)?;
+        if withdrawn_tokens == 0 {
+            return Err(module_error(ModuleError::Replace(ReplaceError::NoPendingRequest)));
+        }
+        self.unreserve(vault_id, withdrawn_griefing_collateral);
+
+        Ok(FakeEvent::WithdrawReplace(WithdrawReplaceEvent {
+            old_vault_id: vault_id.clone(),
+            withdrawn_tokens,
+            withdrawn_griefing_collateral,
+        }))
+    }
+
+    fn accept_replace(
+        &mut self,
+        new_vault_id: &AccountId,
+        old_vault_id: &AccountId,
+        amount_btc: u128,
+        collateral: u128,
+        btc_address: BtcAddress,
+    ) -> Result<FakeEvent, Error> {
+        if new_vault_id == old_vault_id {
+            return Err(module_error(ModuleError::Replace(ReplaceError::ReplaceSelfNotAllowed)));
+        }
+        self.active_vault_mut(new_vault_id)?;
+        let replaceable = self.active_vault_mut(old_vault_id)?.to_be_replaced_tokens;
+        let amount = amount_btc.min(replaceable);
+        if amount == 0 || amount < self.replace_dust_amount {
+            return Err(module_error(ModuleError::Replace(ReplaceError::AmountBelowDustAmount)));
+        }
+        if collateral < self.required_collateral_for_wrapped(amount)? {
+            return Err(module_error(ModuleError::VaultRegistry(
+                VaultRegistryError::ExceedingVaultLimit,
+            )));
+        }
+        self.reserve(new_vault_id, collateral)?;
+        *self.collateral.entry(new_vault_id.clone()).or_default() += collateral;
+
+        let (amount, griefing_collateral) = self.take_to_be_replaced(old_vault_id, amount)?;
+        self.active_vault_mut(old_vault_id)?.to_be_redeemed_tokens += amount;
+        self.active_vault_mut(new_vault_id)?.to_be_issued_tokens += amount;
+
+        let replace_id = self.next_id();
+        let request = InterBtcReplaceRequest {
+            old_vault: old_vault_id.clone(),
+            new_vault: new_vault_id.clone(),
+            amount,
+            griefing_collateral,
+            collateral,
+            accept_time: self.height,
+            period: self.replace_period,
+            btc_address,
+            btc_height: self.best_block_height(),
+            status: ReplaceRequestStatus::Pending,
+        };
+        self.replace_requests.insert(replace_id, request);
+
+        Ok(FakeEvent::AcceptReplace(AcceptReplaceEvent {
+            replace_id,
+            old_vault_id: old_vault_id.clone(),
+            new_vault_id: new_vault_id.clone(),
+            amount_btc: amount,
+            collateral,
+            btc_address,
+        }))
+    }
+
+    fn pending_replace(&self, replace_id: H256) -> Result<InterBtcReplaceRequest, Error> {
+        let request = self
+            .replace_requests
+            .get(&replace_id)
+            .cloned()
+            .ok_or_else(|| module_error(ModuleError::Replace(ReplaceError::ReplaceIdNotFound)))?;
+        match request.status {
+            ReplaceRequestStatus::Pending => Ok(request),
+            ReplaceRequestStatus::Completed => Err(module_error(ModuleError::Replace(ReplaceError::ReplaceCompleted))),
+            ReplaceRequestStatus::Cancelled => Err(module_error(ModuleError::Replace(ReplaceError::ReplaceCancelled))),
+        }
+    }
+
+    fn execute_replace(&mut self, replace_id: H256) -> Result<FakeEvent, Error> {
+        let request = self.pending_replace(replace_id)?;
+        if self.has_expired(request.accept_time, request.period) {
+            return Err(module_error(ModuleError::Replace(ReplaceError::ReplacePeriodExpired)));
+        }
+        let old_vault = self.active_vault_mut(&request.old_vault)?;
+        old_vault.to_be_redeemed_tokens = old_vault.to_be_redeemed_tokens.saturating_sub(request.amount);
+        old_vault.issued_tokens = old_vault.issued_tokens.saturating_sub(request.amount);
+        let new_vault = self.active_vault_mut(&request.new_vault)?;
+        new_vault.to_be_issued_tokens = new_vault.to_be_issued_tokens.saturating_sub(request.amount);
+        new_vault.issued_tokens += request.amount;
+
+        self.unreserve(&request.old_vault, request.griefing_collateral);
+        self.replace_requests.insert(
+            replace_id,
+            InterBtcReplaceRequest {
+                status: ReplaceRequestStatus::Completed,
+                ..request.clone()
+            },
+        );
+
+        Ok(FakeEvent::ExecuteReplace(ExecuteReplaceEvent {
+            replace_id,
+            old_vault_id: request.old_vault,
+            new_vault_id: request.new_vault,
+        }))
+    }
+
+    fn cancel_replace(&mut self, caller: &AccountId, replace_id: H256) -> Result<FakeEvent, Error> {
+        let request = self.pending_replace(replace_id)?;
+        if &request.new_vault != caller {
+            return Err(module_error(ModuleError::Replace(ReplaceError::UnauthorizedVault)));
+        }
+        if !self.has_expired(request.accept_time, request.period) {
+            return Err(module_error(ModuleError::Replace(
+                ReplaceError::ReplacePeriodNotExpired,
+            )));
+        }
+        if let Some(old_vault) = self.vaults.get_mut(&request.old_vault) {
+            old_vault.to_be_redeemed_tokens = old_vault.to_be_redeemed_tokens.saturating_sub(request.amount);
+        }
+        if let Some(new_vault) = self.vaults.get_mut(&request.new_vault) {
+            new_vault.to_be_issued_tokens = new_vault.to_be_issued_tokens.saturating_sub(request.amount);
+        }
+        self.slash(&request.old_vault, &request.new_vault, request.griefing_collateral);
+        self.replace_requests.insert(
+            replace_id,
+            InterBtcReplaceRequest {
+                status: ReplaceRequestStatus::Cancelled,
+                ..request.clone()
+            },
+        );
+
+        Ok(FakeEvent::CancelReplace(CancelReplaceEvent {
+            replace_id,
+            new_vault_id: request.new_vault,
+            old_vault_id: request.old_vault,
+            griefing_collateral: request.griefing_collateral,
+        }))
+    }
+
+    fn execute_refund(&mut self, refund_id: H256) -> Result<FakeEvent, Error> {
+        let (refundee, request) = self
+            .refund_requests
+            .get_mut(&refund_id)
+            .ok_or_else(|| module_error(ModuleError::Refund(RefundError::RefundIdNotFound)))?;
+        if request.completed {
+            return Err(module_error(ModuleError::Refund(RefundError::RefundCompleted)));
+        }
+        request.completed = true;
+
+        Ok(FakeEvent::ExecuteRefund(ExecuteRefundEvent {
+            refund_id,
+            refundee: refundee.clone(),
+            vault_id: request.vault.clone(),
+            amount: request.amount_btc,
+            fee: 0,
+        }))
+    }
+
+    fn rich_header(&self, header: &RawBlockHeader, height: u32) -> Result<InterBtcRichBlockHeader, Error> {
+        let block_header = parse_block_header(header)
+            .map_err(|_| module_error(ModuleError::BtcRelay(BtcRelayError::InvalidHeaderSize)))?;
+        Ok(InterBtcRichBlockHeader {
+            block_header,
+            block_height: height,
+            chain_ref: MAIN_CHAIN_ID,
+            para_height: self.height,
+            ..Default::default()
+        })
+    }
+
+    fn initialize_btc_relay(
+        &mut self,
+        account_id: &AccountId,
+        header: RawBlockHeader,
+        height: u32,
+    ) -> Result<FakeEvent, Error> {
+        if !self.relay_chain.is_empty() {
+            return Err(module_error(ModuleError::BtcRelay(BtcRelayError::AlreadyInitialized)));
+        }
+        self.relay_start_height = height;
+        self.push_header(account_id, header, height)
+    }
+
+    fn store_block_header(&mut self, account_id: &AccountId, header: RawBlockHeader) -> Result<FakeEvent, Error> {
+        let hash_prev_block = self.rich_header(&header, 0)?.block_header.hash_prev_block;
+        if self.relay_headers.contains_key(&header.hash()) {
+            return Err(module_error(ModuleError::BtcRelay(BtcRelayError::DuplicateBlock)));
+        }
+        // forks are not tracked, only headers extending the main chain are accepted
+        if self.relay_chain.last() != Some(&hash_prev_block) {
+            return Err(module_error(ModuleError::BtcRelay(BtcRelayError::PrevBlock)));
+        }
+        let height = self.best_block_height() + 1;
+        self.push_header(account_id, header, height)
+    }
+
+    fn push_header(&mut self, account_id: &AccountId, header: RawBlockHeader, height: u32) -> Result<FakeEvent, Error> {
+        let block_header_hash = header.hash();
+        let rich_header = self.rich_header(&header, height)?;
+        self.relay_chain.push(block_header_hash);
+        self.relay_headers.insert(block_header_hash, rich_header);
+
+        Ok(FakeEvent::StoreMainChainHeader(StoreMainChainHeaderEvent {
+            _runtime: PhantomData,
+            block_height: height,
+            block_header_hash,
+            account_id: account_id.clone(),
+        }))
+    }
+
+    fn verify_block_header_inclusion(&self, block_hash: H256Le) -> Result<(), Error> {
+        let header = self
+            .relay_headers
+            .get(&block_hash)
+            .ok_or_else(|| module_error(ModuleError::BtcRelay(BtcRelayError::BlockNotFound)))?;
+        if header.block_height + self.bitcoin_confirmations > self.best_block_height() + 1 {
+            return Err(module_error(ModuleError::BtcRelay(BtcRelayError::BitcoinConfirmations)));
+        }
+        if header.para_height + self.parachain_confirmations > self.height {
+            return Err(module_error(ModuleError::BtcRelay(
+                BtcRelayError::ParachainConfirmations,
+            )));
+        }
+        Ok(())
+    }
+
+    fn report_vault_theft(&mut self, vault_id: &AccountId, raw_tx: &[u8]) -> Result<(), Error> {
+        if !self.invalid_transactions.contains(raw_tx) {
+            return Err(module_error(ModuleError::Relay(RelayError::ValidRedeemTransaction)));
+        }
+        if matches!(self.vault(vault_id)?.status, VaultStatus::CommittedTheft) {
+            return Err(module_error(ModuleError::Relay(RelayError::VaultAlreadyReported)));
+        }
+        let vault = self.active_vault_mut(vault_id)?;
+        vault.status = VaultStatus::CommittedTheft;
+        Ok(())
+    }
+}
+
+/// Stateful in-memory implementation of the parachain pallets, to exercise client logic in
+/// unit tests without a full node.
+///
+/// Issue, redeem, replace and refund requests go through the same state transitions as on
+/// chain, including period expiry, griefing collateral and vault token accounting, and the
+/// corresponding events are broadcast to all subscribers. Bitcoin inclusion proofs are NOT
+/// verified: any executed request is assumed to have been paid, and theft reports are only
+/// accepted for transactions marked invalid with [`FakeParachain::mark_transaction_invalid`].
+/// Calls are not permissioned, so any account can set periods, rates and confirmations.
+#[derive(Clone)]
+pub struct FakeParachain {
+    account_id: AccountId,
+    state: Arc<Mutex<State>>,
+    events: broadcast::Sender<FakeEvent>,
+}
+
+impl FakeParachain {
+    pub fn new(account_id: AccountId) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        Self {
+            account_id,
+            state: Default::default(),
+            events,
+        }
+    }
+
+    /// Get a handle to the same chain that signs as `account_id`.
+    pub fn with_account(&self, account_id: AccountId) -> Self {
+        Self {
+            account_id,
+            ..self.clone()
+        }
+    }
+
+    /// Receive all events emitted after this call.
+    pub fn subscribe(&self) -> broadcast::Receiver<FakeEvent> {
+        self.events.subscribe()
+    }
+
+    /// Produce `count` new blocks, advancing the time and the active block number.
+    pub fn advance_blocks(&self, count: BlockNumber) {
+        for _ in 0..count {
+            if let Some(event) = self.lock().advance_block() {
+                self.emit(event);
+            }
+        }
+    }
+
+    pub fn set_free_balance(&self, account_id: AccountId, amount: u128) {
+        self.lock().free.insert(account_id, amount);
+    }
+
+    pub fn set_wrapped_balance(&self, account_id: AccountId, amount: u128) {
+        self.lock().wrapped.insert(account_id, amount);
+    }
+
+    pub fn get_wrapped_balance_for_id(&self, account_id: &AccountId) -> u128 {
+        self.lock().wrapped.get(account_id).copied().unwrap_or_default()
+    }
+
+    pub fn set_parachain_status(&self, status_code: StatusCode, error_codes: BTreeSet<ErrorCode>) {
+        let mut state = self.lock();
+        state.status_code = status_code;
+        state.error_codes = error_codes;
+    }
+
+    /// Open a refund request, as the issue pallet does when a user overpays.
+    pub fn add_refund_request(&self, refundee: AccountId, vault_id: AccountId, amount_btc: u128) -> H256 {
+        let mut state = self.lock();
+        let refund_id = state.next_id();
+        let request = InterBtcRefundRequest {
+            vault: vault_id,
+            amount_btc,
+            completed: false,
+            ..Default::default()
+        };
+        state.refund_requests.insert(refund_id, (refundee, request));
+        refund_id
+    }
+
+    /// Mark a transaction as not permitted for any vault, such that it can be reported as theft.
+    pub fn mark_transaction_invalid(&self, raw_tx: Vec<u8>) {
+        self.lock().invalid_transactions.insert(raw_tx);
+    }
+
+    fn lock(&self) -> MutexGuard<State> {
+        self.state.lock().expect("fake parachain state poisoned")
+    }
+
+    fn emit(&self, event: FakeEvent) {
+        // there may not be any subscribers
+        let _ = self.events.send(event);
+    }
+
+    fn apply(&self, call: impl FnOnce(&mut State) -> Result<FakeEvent, Error>) -> Result<(), Error> {
+        let event = call(&mut self.lock())?;
+        self.emit(event);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UtilFuncs for FakeParachain {
+    async fn get_current_chain_height(&self) -> Result<u32, Error> {
+        Ok(self.lock().height)
+    }
+
+    fn get_account_id(&self) -> &AccountId {
+        &self.account_id
+    }
+}
+
+#[async_trait]
+impl CollateralBalancesPallet for FakeParachain {
+    async fn get_free_balance(&self) -> Result<InterBtcBalance, Error> {
+        self.get_free_balance_for_id(self.account_id.clone()).await
+    }
+
+    async fn get_spendable_balance(&self) -> Result<InterBtcBalance, Error> {
+        // extrinsics are free on the fake parachain, so there is no fee reserve
+        self.get_free_balance().await
+    }
+
+    async fn get_free_balance_for_id(&self, id: AccountId) -> Result<InterBtcBalance, Error> {
+        Ok(self.lock().free.get(&id).copied().unwrap_or_default())
+    }
+
+    async fn get_reserved_balance(&self) -> Result<InterBtcBalance, Error> {
+        self.get_reserved_balance_for_id(self.account_id.clone()).await
+    }
+
+    async fn get_reserved_balance_for_id(&self, id: AccountId) -> Result<InterBtcBalance, Error> {
+        Ok(self.lock().reserved.get(&id).copied().unwrap_or_default())
+    }
+
+    async fn transfer_to(&self, recipient: &AccountId, amount: u128) -> Result<(), Error> {
+        let mut state = self.lock();
+        let free = state.free.entry(self.account_id.clone()).or_default();
+        *free = free
+            .checked_sub(amount)
+            .ok_or_else(|| module_error(ModuleError::Tokens(TokensError::BalanceTooLow)))?;
+        *state.free.entry(recipient.clone()).or_default() += amount;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ReplacePallet for FakeParachain {
+    async fn request_replace(&self, amount: u128, griefing_collateral: u128) -> Result<(), Error> {
+        self.apply(|state| state.request_replace(&self.account_id, amount, griefing_collateral))
+    }
+
+    async fn withdraw_replace(&self, amount: u128) -> Result<(), Error> {
+        self.apply(|state| state.withdraw_replace(&self.account_id, amount))
+    }
+
+    async fn accept_replace(
+        &self,
+        old_vault: &AccountId,
+        amount_btc: u128,
+        collateral: u128,
+        btc_address: BtcAddress,
+    ) -> Result<(), Error> {
+        self.apply(|state| state.accept_replace(&self.account_id, old_vault, amount_btc, collateral, btc_address))
+    }
+
+    async fn execute_replace(&self, replace_id: H256, _merkle_proof: &[u8], _raw_tx: &[u8]) -> Result<(), Error> {
+        self.apply(|state| state.execute_replace(replace_id))
+    }
+
+    async fn cancel_replace(&self, replace_id: H256) -> Result<(), Error> {
+        self.apply(|state| state.cancel_replace(&self.account_id, replace_id))
+    }
+
+    async fn get_new_vault_replace_requests(
+        &self,
+        account_id: AccountId,
+    ) -> Result<Vec<(H256, InterBtcReplaceRequest)>, Error> {
+        Ok(self
+            .lock()
+            .replace_requests
+            .iter()
+            .filter(|(_, request)| request.new_vault == account_id)
+            .map(|(id, request)| (*id, request.clone()))
+            .collect())
+    }
+
+    async fn get_old_vault_replace_requests(
+        &self,
+        account_id: AccountId,
+    ) -> Result<Vec<(H256, InterBtcReplaceRequest)>, Error> {
+        Ok(self
+            .lock()
+            .replace_requests
+            .iter()
+            .filter(|(_, request)| request.old_vault == account_id)
+            .map(|(id, request)| (*id, request.clone()))
+            .collect())
+    }
+
+    async fn get_replace_period(&self) -> Result<u32, Error> {
+        Ok(self.lock().replace_period)
+    }
+
+    async fn set_replace_period(&self, period: u32) -> Result<(), Error> {
+        self.lock().replace_period = period;
+        Ok(())
+    }
+
+    async fn get_replace_request(&self, replace_id: H256) -> Result<InterBtcReplaceRequest, Error> {
+        self.lock()
+            .replace_requests
+            .get(&replace_id)
+            .cloned()
+            .ok_or(Error::RequestReplaceIDNotFound)
+    }
+
+    async fn get_replace_dust_amount(&self) -> Result<u128, Error> {
+        Ok(self.lock().replace_dust_amount)
+    }
+}
+
+#[async_trait]
+impl TimestampPallet for FakeParachain {
+    async fn get_time_now(&self) -> Result<u64, Error> {
+        Ok(self.lock().time)
+    }
+}
+
+#[async_trait]
+impl ExchangeRateOraclePallet for FakeParachain {
+    async fn get_exchange_rate_info(&self) -> Result<(FixedU128, u64, u64), Error> {
+        let state = self.lock();
+        Ok((state.exchange_rate, state.exchange_rate_time, DEFAULT_MAX_DELAY))
+    }
+
+    async fn set_exchange_rate_info(&self, collateral_per_wrapped: FixedU128) -> Result<(), Error> {
+        let mut state = self.lock();
+        if !state.oracles.contains_key(&self.account_id) {
+            return Err(module_error(ModuleError::ExchangeRateOracle(
+                ExchangeRateOracleError::InvalidOracleSource,
+            )));
+        }
+        state.exchange_rate = collateral_per_wrapped;
+        state.exchange_rate_time = state.time;
+        Ok(())
+    }
+
+    async fn insert_authorized_oracle(&self, account_id: AccountId, name: String) -> Result<(), Error> {
+        self.lock().oracles.insert(account_id, name);
+        Ok(())
+    }
+
+    async fn set_btc_tx_fees_per_byte(&self, fast: u32, half: u32, hour: u32) -> Result<(), Error> {
+        let mut state = self.lock();
+        if !state.oracles.contains_key(&self.account_id) {
+            return Err(module_error(ModuleError::ExchangeRateOracle(
+                ExchangeRateOracleError::InvalidOracleSource,
+            )));
+        }
+        state.btc_tx_fees = BtcTxFeesPerByte { fast, half, hour };
+        Ok(())
+    }
+
+    async fn get_btc_tx_fees_per_byte(&self) -> Result<BtcTxFeesPerByte, Error> {
+        Ok(self.lock().btc_tx_fees.clone())
+    }
+
+    async fn wrapped_to_collateral(&self, amount: u128) -> Result<u128, Error> {
+        self.lock().wrapped_to_collateral(amount)
+    }
+
+    async fn collateral_to_wrapped(&self, amount: u128) -> Result<u128, Error> {
+        self.lock().collateral_to_wrapped(amount)
+    }
+}
+
+#[async_trait]
+impl RelayPallet for FakeParachain {
+    async fn report_vault_theft(&self, vault_id: &AccountId, _merkle_proof: &[u8], raw_tx: &[u8]) -> Result<(), Error> {
+        self.lock().report_vault_theft(vault_id, raw_tx)
+    }
+
+    async fn is_transaction_invalid(&self, _vault_id: &AccountId, raw_tx: &[u8]) -> Result<bool, Error> {
+        Ok(self.lock().invalid_transactions.contains(raw_tx))
+    }
+
+    async fn initialize_btc_relay(&self, header: RawBlockHeader, height: BitcoinBlockHeight) -> Result<(), Error> {
+        self.apply(|state| state.initialize_btc_relay(&self.account_id, header, height))
+    }
+
+    async fn store_block_header(&self, header: RawBlockHeader) -> Result<(), Error> {
+        self.apply(|state| state.store_block_header(&self.account_id, header))
+    }
+
+    async fn store_block_headers(&self, headers: Vec<RawBlockHeader>) -> Result<(), Error> {
+        for header in headers {
+            self.store_block_header(header).await?;
+        }
+        Ok(())
+    }
+
+    async fn store_block_headers_pipelined(&self, headers: Vec<RawBlockHeader>) -> Result<PendingSubmission, Error> {
+        // the headers are stored right away, so the batch is always ordered after earlier ones
+        let result = self.store_block_headers(headers).await;
+        Ok(Box::pin(futures::future::ready(result)))
+    }
+}
+
+#[async_trait]
+impl SecurityPallet for FakeParachain {
+    async fn get_parachain_status(&self) -> Result<StatusCode, Error> {
+        Ok(self.lock().status_code.clone())
+    }
+
+    async fn get_error_codes(&self) -> Result<BTreeSet<ErrorCode>, Error> {
+        Ok(self.lock().error_codes.clone())
+    }
+
+    async fn get_current_active_block_number(&self) -> Result<u32, Error> {
+        Ok(self.lock().active_block)
+    }
+}
+
+#[async_trait]
+impl IssuePallet for FakeParachain {
+    async fn request_issue(
+        &self,
+        amount: u128,
+        vault_id: &AccountId,
+        griefing_collateral: u128,
+    ) -> Result<InterBtcRequestIssueEvent, Error> {
+        let event = self
+            .lock()
+            .request_issue(&self.account_id, amount, vault_id, griefing_collateral)?;
+        self.emit(FakeEvent::RequestIssue(event.clone()));
+        Ok(event)
+    }
+
+    async fn execute_issue(&self, issue_id: H256, _merkle_proof: &[u8], _raw_tx: &[u8]) -> Result<(), Error> {
+        self.apply(|state| state.execute_issue(issue_id))
+    }
+
+    async fn cancel_issue(&self, issue_id: H256) -> Result<(), Error> {
+        self.apply(|state| state.cancel_issue(issue_id))
+    }
+
+    async fn get_issue_request(&self, issue_id: H256) -> Result<InterBtcIssueRequest, Error> {
+        self.lock()
+            .issue_requests
+            .get(&issue_id)
+            .cloned()
+            .ok_or(Error::RequestIssueIDNotFound)
+    }
+
+    async fn get_vault_issue_requests(
+        &self,
+        account_id: AccountId,
+    ) -> Result<Vec<(H256, InterBtcIssueRequest)>, Error> {
+        Ok(self
+            .lock()
+            .issue_requests
+            .iter()
+            .filter(|(_, request)| request.vault == account_id)
+            .map(|(id, request)| (*id, request.clone()))
+            .collect())
+    }
+
+    async fn get_issue_period(&self) -> Result<u32, Error> {
+        Ok(self.lock().issue_period)
+    }
+
+    async fn set_issue_period(&self, period: u32) -> Result<(), Error> {
+        self.lock().issue_period = period;
+        Ok(())
+    }
+
+    async fn get_all_active_issues(&self) -> Result<Vec<(H256, InterBtcIssueRequest)>, Error> {
+        let state = self.lock();
+        Ok(state
+            .issue_requests
+            .iter()
+            .filter(|(_, request)| {
+                request.status == IssueRequestStatus::Pending && !state.has_expired(request.opentime, request.period)
+            })
+            .map(|(id, request)| (*id, request.clone()))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl RedeemPallet for FakeParachain {
+    async fn request_redeem(&self, amount: u128, btc_address: BtcAddress, vault_id: &AccountId) -> Result<H256, Error> {
+        let event = self
+            .lock()
+            .request_redeem(&self.account_id, amount, btc_address, vault_id)?;
+        let redeem_id = event.redeem_id;
+        self.emit(FakeEvent::RequestRedeem(event));
+        Ok(redeem_id)
+    }
+
+    async fn execute_redeem(&self, redeem_id: H256, _merkle_proof: &[u8], _raw_tx: &[u8]) -> Result<(), Error> {
+        self.apply(|state| state.execute_redeem(redeem_id))
+    }
+
+    async fn cancel_redeem(&self, redeem_id: H256, reimburse: bool) -> Result<(), Error> {
+        self.apply(|state| state.cancel_redeem(&self.account_id, redeem_id, reimburse))
+    }
+
+    async fn get_redeem_request(&self, redeem_id: H256) -> Result<InterBtcRedeemRequest, Error> {
+        self.lock()
+            .redeem_requests
+            .get(&redeem_id)
+            .cloned()
+            .ok_or(Error::RequestRedeemIDNotFound)
+    }
+
+    async fn get_vault_redeem_requests(
+        &self,
+        account_id: AccountId,
+    ) -> Result<Vec<(H256, InterBtcRedeemRequest)>, Error> {
+        Ok(self
+            .lock()
+            .redeem_requests
+            .iter()
+            .filter(|(_, request)| request.vault == account_id)
+            .map(|(id, request)| (*id, request.clone()))
+            .collect())
+    }
+
+    async fn get_redeem_period(&self) -> Result<BlockNumber, Error> {
+        Ok(self.lock().redeem_period)
+    }
+
+    async fn set_redeem_period(&self, period: u32) -> Result<(), Error> {
+        self.lock().redeem_period = period;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RefundPallet for FakeParachain {
+    async fn execute_refund(&self, refund_id: H256, _merkle_proof: &[u8], _raw_tx: &[u8]) -> Result<(), Error> {
+        self.apply(|state| state.execute_refund(refund_id))
+    }
+
+    async fn get_vault_refund_requests(
+        &self,
+        account_id: AccountId,
+    ) -> Result<Vec<(H256, InterBtcRefundRequest)>, Error> {
+        Ok(self
+            .lock()
+            .refund_requests
+            .iter()
+            .filter(|(_, (_, request))| request.vault == account_id)
+            .map(|(id, (_, request))| (*id, request.clone()))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl BtcRelayPallet for FakeParachain {
+    async fn get_best_block(&self) -> Result<H256Le, Error> {
+        self.lock().relay_chain.last().copied().ok_or(Error::BlockNotFound)
+    }
+
+    async fn get_best_block_height(&self) -> Result<u32, Error> {
+        Ok(self.lock().best_block_height())
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<H256Le, Error> {
+        let state = self.lock();
+        height
+            .checked_sub(state.relay_start_height)
+            .and_then(|index| state.relay_chain.get(index as usize))
+            .copied()
+            .ok_or(Error::BlockNotFound)
+    }
+
+    async fn get_block_header(&self, hash: H256Le) -> Result<InterBtcRichBlockHeader, Error> {
+        self.lock()
+            .relay_headers
+            .get(&hash)
+            .cloned()
+            .ok_or(Error::BlockNotFound)
+    }
+
+    async fn get_bitcoin_confirmations(&self) -> Result<u32, Error> {
+        Ok(self.lock().bitcoin_confirmations)
+    }
+
+    async fn set_bitcoin_confirmations(&self, value: u32) -> Result<(), Error> {
+        self.lock().bitcoin_confirmations = value;
+        Ok(())
+    }
+
+    async fn get_parachain_confirmations(&self) -> Result<BlockNumber, Error> {
+        Ok(self.lock().parachain_confirmations)
+    }
+
+    async fn set_parachain_confirmations(&self, value: BlockNumber) -> Result<(), Error> {
+        self.lock().parachain_confirmations = value;
+        Ok(())
+    }
+
+    async fn wait_for_block_in_relay(
+        &self,
+        block_hash: H256Le,
+        _btc_confirmations: Option<BlockNumber>,
+    ) -> Result<(), Error> {
+        loop {
+            match self.verify_block_header_inclusion(block_hash).await {
+                Ok(_) => return Ok(()),
+                Err(e) if e.is_invalid_chain_id() => return Err(e),
+                _ => delay_for(BLOCK_POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    async fn verify_block_header_inclusion(&self, block_hash: H256Le) -> Result<(), Error> {
+        self.lock().verify_block_header_inclusion(block_hash)
+    }
+}
+
+#[async_trait]
+impl VaultRegistryPallet for FakeParachain {
+    async fn get_vault(&self, vault_id: AccountId) -> Result<InterBtcVault, Error> {
+        let vault = self.lock().vault(&vault_id)?.clone();
+        match vault.status {
+            VaultStatus::Liquidated => Err(Error::VaultLiquidated),
+            VaultStatus::CommittedTheft => Err(Error::VaultCommittedTheft),
+            _ => Ok(vault),
+        }
+    }
+
+    async fn get_all_vaults(&self) -> Result<Vec<InterBtcVault>, Error> {
+        Ok(self.lock().vaults.values().cloned().collect())
+    }
+
+    async fn register_vault(&self, collateral: u128, public_key: BtcPublicKey) -> Result<(), Error> {
+        self.apply(|state| state.register_vault(&self.account_id, collateral, public_key))
+    }
+
+    async fn deposit_collateral(&self, amount: u128) -> Result<(), Error> {
+        self.apply(|state| state.deposit_collateral(&self.account_id, amount))
+    }
+
+    async fn withdraw_collateral(&self, amount: u128) -> Result<(), Error> {
+        self.apply(|state| state.withdraw_collateral(&self.account_id, amount))
+    }
+
+    async fn update_public_key(&self, public_key: BtcPublicKey) -> Result<(), Error> {
+        self.apply(|state| {
+            state.active_vault_mut(&self.account_id)?.wallet.public_key = public_key.clone();
+            Ok(FakeEvent::UpdatePublicKey(UpdatePublicKeyEvent {
+                vault_id: self.account_id.clone(),
+                public_key,
+            }))
+        })
+    }
+
+    async fn register_address(&self, btc_address: BtcAddress) -> Result<(), Error> {
+        self.apply(|state| {
+            let vault = state.active_vault_mut(&self.account_id)?;
+            if vault.wallet.has_btc_address(&btc_address) {
+                return Err(module_error(ModuleError::VaultRegistry(
+                    VaultRegistryError::ReservedDepositAddress,
+                )));
+            }
+            vault.wallet.addresses.insert(btc_address);
+            Ok(FakeEvent::RegisterAddress(RegisterAddressEvent {
+                vault_id: self.account_id.clone(),
+                btc_address,
+            }))
+        })
+    }
+
+    async fn get_required_collateral_for_wrapped(&self, amount_btc: u128) -> Result<u128, Error> {
+        self.lock().required_collateral_for_wrapped(amount_btc)
+    }
+
+    async fn get_required_collateral_for_vault(&self, vault_id: AccountId) -> Result<u128, Error> {
+        self.lock().required_collateral_for_vault(&vault_id)
+    }
+
+    async fn get_vault_total_collateral(&self, vault_id: AccountId) -> Result<u128, Error> {
+        let state = self.lock();
+        state.vault(&vault_id)?;
+        Ok(state.total_collateral(&vault_id))
+    }
+}
+
+#[async_trait]
+impl FeePallet for FakeParachain {
+    async fn get_issue_griefing_collateral(&self) -> Result<FixedU128, Error> {
+        Ok(self.lock().issue_griefing_collateral)
+    }
+
+    async fn get_issue_fee(&self) -> Result<FixedU128, Error> {
+        Ok(self.lock().issue_fee)
+    }
+
+    async fn get_replace_griefing_collateral(&self) -> Result<FixedU128, Error> {
+        Ok(self.lock().replace_griefing_collateral)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use module_bitcoin::{formatter::TryFormattable, types::BlockBuilder};
+    use sp_core::U256;
+    use sp_keyring::AccountKeyring;
+
+    const COLLATERAL: u128 = 1_000_000;
+
+    fn dummy_public_key() -> BtcPublicKey {
+        BtcPublicKey([
+            2, 205, 114, 218, 156, 16, 235, 172, 106, 37, 18, 153, 202, 140, 176, 91, 207, 51, 187, 55, 18, 45, 222,
+            180, 119, 54, 243, 97, 173, 150, 161, 169, 230,
+        ])
+    }
+
+    async fn setup_vault(account: AccountKeyring) -> (FakeParachain, AccountId) {
+        let vault_id = account.to_account_id();
+        let parachain = FakeParachain::new(vault_id.clone());
+        parachain.set_free_balance(vault_id.clone(), 2 * COLLATERAL);
+        parachain.register_vault(COLLATERAL, dummy_public_key()).await.unwrap();
+        (parachain, vault_id)
+    }
+
+    async fn issue_tokens(parachain: &FakeParachain, vault_id: &AccountId, amount: u128) -> AccountId {
+        let user_id = AccountKeyring::Dave.to_account_id();
+        let user = parachain.with_account(user_id.clone());
+        user.set_free_balance(user_id.clone(), COLLATERAL);
+        let event = user.request_issue(amount, vault_id, 100).await.unwrap();
+        parachain.execute_issue(event.issue_id, &[], &[]).await.unwrap();
+        user_id
+    }
+
+    fn raw_header(previous_hash: H256Le) -> RawBlockHeader {
+        let address = BtcAddress::P2PKH(H160::from([0; 20]));
+        let block = BlockBuilder::new()
+            .with_previous_hash(previous_hash)
+            .with_version(4)
+            .with_coinbase(&address, 50, 3)
+            .with_timestamp(1588813835)
+            .mine(U256::from(2).pow(254.into()))
+            .unwrap();
+        RawBlockHeader::from_bytes(&block.header.try_format().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_register_vault_reserves_collateral() {
+        let (parachain, vault_id) = setup_vault(AccountKeyring::Bob).await;
+
+        assert_eq!(parachain.get_free_balance().await.unwrap(), COLLATERAL);
+        assert_eq!(parachain.get_reserved_balance().await.unwrap(), COLLATERAL);
+        assert_eq!(
+            parachain.get_vault_total_collateral(vault_id.clone()).await.unwrap(),
+            COLLATERAL
+        );
+        assert!(parachain.register_vault(COLLATERAL, dummy_public_key()).await.is_err());
+        assert!(matches!(
+            parachain.get_vault(AccountKeyring::Eve.to_account_id()).await,
+            Err(Error::VaultNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_issue_lifecycle() {
+        let (parachain, vault_id) = setup_vault(AccountKeyring::Bob).await;
+        let mut events = parachain.subscribe();
+
+        let user_id = issue_tokens(&parachain, &vault_id, 10_000).await;
+
+        let fee = 50;
+        assert_eq!(parachain.get_wrapped_balance_for_id(&user_id), 10_000 - fee);
+        let vault = parachain.get_vault(vault_id).await.unwrap();
+        assert_eq!(vault.issued_tokens, 10_000);
+        assert_eq!(vault.to_be_issued_tokens, 0);
+
+        let issue_id = match events.recv().await.unwrap() {
+            FakeEvent::RequestIssue(event) => event.issue_id,
+            event => panic!("unexpected event {:?}", event),
+        };
+        assert!(matches!(events.recv().await.unwrap(), FakeEvent::ExecuteIssue(_)));
+        assert!(parachain
+            .execute_issue(issue_id, &[], &[])
+            .await
+            .unwrap_err()
+            .is_issue_completed());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_issue_after_period() {
+        let (parachain, vault_id) = setup_vault(AccountKeyring::Bob).await;
+        let user_id = AccountKeyring::Dave.to_account_id();
+        let user = parachain.with_account(user_id.clone());
+        user.set_free_balance(user_id.clone(), 1_000);
+        let issue_id = user.request_issue(10_000, &vault_id, 100).await.unwrap().issue_id;
+
+        assert!(user.cancel_issue(issue_id).await.is_err());
+        parachain.advance_blocks(DEFAULT_PERIOD + 1);
+        assert!(parachain.execute_issue(issue_id, &[], &[]).await.is_err());
+        user.cancel_issue(issue_id).await.unwrap();
+
+        // the griefing collateral is slashed to the vault
+        assert_eq!(user.get_free_balance().await.unwrap(), 900);
+        assert_eq!(parachain.get_free_balance().await.unwrap(), COLLATERAL + 100);
+        assert_eq!(parachain.get_vault(vault_id).await.unwrap().to_be_issued_tokens, 0);
+        assert_eq!(
+            user.get_issue_request(issue_id).await.unwrap().status,
+            IssueRequestStatus::Cancelled
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_issue_exceeding_vault_limit_fails() {
+        let (parachain, vault_id) = setup_vault(AccountKeyring::Bob).await;
+        let user = parachain.with_account(AccountKeyring::Dave.to_account_id());
+        user.set_free_balance(AccountKeyring::Dave.to_account_id(), COLLATERAL);
+
+        assert!(user.request_issue(COLLATERAL, &vault_id, COLLATERAL).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_redeem_lifecycle() {
+        let (parachain, vault_id) = setup_vault(AccountKeyring::Bob).await;
+        let user_id = issue_tokens(&parachain, &vault_id, 10_000).await;
+        let user = parachain.with_account(user_id.clone());
+
+        let redeem_id = user
+            .request_redeem(1_000, BtcAddress::default(), &vault_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            parachain
+                .get_vault(vault_id.clone())
+                .await
+                .unwrap()
+                .to_be_redeemed_tokens,
+            995
+        );
+        assert_eq!(
+            parachain
+                .get_vault_redeem_requests(vault_id.clone())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        parachain.execute_redeem(redeem_id, &[], &[]).await.unwrap();
+        let vault = parachain.get_vault(vault_id).await.unwrap();
+        assert_eq!(vault.issued_tokens, 10_000 - 995);
+        assert_eq!(vault.to_be_redeemed_tokens, 0);
+        assert_eq!(
+            user.get_redeem_request(redeem_id).await.unwrap().status,
+            RedeemRequestStatus::Completed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expired_redeem_is_reimbursed() {
+        let (parachain, vault_id) = setup_vault(AccountKeyring::Bob).await;
+        let user_id = issue_tokens(&parachain, &vault_id, 10_000).await;
+        let user = parachain.with_account(user_id.clone());
+        let redeem_id = user
+            .request_redeem(1_000, BtcAddress::default(), &vault_id)
+            .await
+            .unwrap();
+
+        parachain.advance_blocks(DEFAULT_PERIOD + 1);
+        assert!(parachain
+            .execute_redeem(redeem_id, &[], &[])
+            .await
+            .unwrap_err()
+            .is_commit_period_expired());
+        assert!(parachain.cancel_redeem(redeem_id, true).await.is_err());
+        user.cancel_redeem(redeem_id, true).await.unwrap();
+
+        assert_eq!(user.get_free_balance().await.unwrap(), COLLATERAL + 995);
+        assert_eq!(
+            parachain.get_vault_total_collateral(vault_id).await.unwrap(),
+            COLLATERAL - 995
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replace_lifecycle() {
+        let (old_vault, old_vault_id) = setup_vault(AccountKeyring::Bob).await;
+        issue_tokens(&old_vault, &old_vault_id, 10_000).await;
+        let new_vault_id = AccountKeyring::Charlie.to_account_id();
+        let new_vault = old_vault.with_account(new_vault_id.clone());
+        new_vault.set_free_balance(new_vault_id.clone(), 2 * COLLATERAL);
+        new_vault.register_vault(COLLATERAL, dummy_public_key()).await.unwrap();
+
+        old_vault.request_replace(5_000, 500).await.unwrap();
+        new_vault
+            .accept_replace(&old_vault_id, 10_000, 10_000, BtcAddress::default())
+            .await
+            .unwrap();
+
+        let (replace_id, request) = old_vault
+            .get_old_vault_replace_requests(old_vault_id.clone())
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(request.amount, 5_000);
+        assert_eq!(request.griefing_collateral, 500);
+
+        new_vault.execute_replace(replace_id, &[], &[]).await.unwrap();
+        assert_eq!(old_vault.get_vault(old_vault_id).await.unwrap().issued_tokens, 5_000);
+        assert_eq!(new_vault.get_vault(new_vault_id).await.unwrap().issued_tokens, 5_000);
+        assert_eq!(old_vault.get_reserved_balance().await.unwrap(), COLLATERAL);
+    }
+
+    #[tokio::test]
+    async fn test_relay_main_chain() {
+        let (parachain, _) = setup_vault(AccountKeyring::Bob).await;
+        parachain.set_bitcoin_confirmations(2).await.unwrap();
+
+        let genesis = raw_header(Default::default());
+        let genesis_hash = genesis.hash();
+        parachain.initialize_btc_relay(genesis, 100).await.unwrap();
+        let next_hash = raw_header(genesis_hash).hash();
+        parachain.store_block_header(raw_header(genesis_hash)).await.unwrap();
+
+        assert_eq!(parachain.get_best_block().await.unwrap(), next_hash);
+        assert_eq!(parachain.get_best_block_height().await.unwrap(), 101);
+        assert_eq!(parachain.get_block_hash(100).await.unwrap(), genesis_hash);
+        assert!(parachain
+            .store_block_header(raw_header(genesis_hash))
+            .await
+            .unwrap_err()
+            .is_duplicate_block());
+
+        assert!(parachain.verify_block_header_inclusion(genesis_hash).await.is_ok());
+        assert!(parachain.verify_block_header_inclusion(next_hash).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_theft_report() {
+        let (parachain, vault_id) = setup_vault(AccountKeyring::Bob).await;
+        let raw_tx = vec![1, 2, 3];
+
+        assert!(parachain.report_vault_theft(&vault_id, &[], &raw_tx).await.is_err());
+        parachain.mark_transaction_invalid(raw_tx.clone());
+        parachain.report_vault_theft(&vault_id, &[], &raw_tx).await.unwrap();
+
+        assert!(matches!(
+            parachain.get_vault(vault_id.clone()).await,
+            Err(Error::VaultCommittedTheft)
+        ));
+        assert!(parachain
+            .report_vault_theft(&vault_id, &[], &raw_tx)
+            .await
+            .unwrap_err()
+            .is_vault_already_reported());
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Distinguishes the temporary files of concurrent writes within the process.
+static TMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Replace the file at `path` with `contents`. The contents are written to a temporary file
+/// next to it that is then renamed over it, so a crash cannot leave a partially written file
+/// behind.
+pub fn write_atomically(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp_path = tmp_path(path);
+    let result = File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(contents.as_ref())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Name of a temporary file in the directory of `path` that is unique to this write, unlike
+/// e.g. `path.with_extension("tmp")`, which is shared by all files with the same stem.
+fn tmp_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        file_name,
+        process::id(),
+        TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files_with_same_stem_do_not_collide() {
+        let dir = std::env::temp_dir().join(format!("write-atomically-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_ne!(tmp_path(&dir.join("index.json")), tmp_path(&dir.join("index.bin")));
+
+        write_atomically(dir.join("index.json"), "json").unwrap();
+        write_atomically(dir.join("index.bin"), "bin").unwrap();
+        write_atomically(dir.join("index.json"), "json2").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("index.json")).unwrap(), "json2");
+        assert_eq!(fs::read_to_string(dir.join("index.bin")).unwrap(), "bin");
+        // no temporary files are left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
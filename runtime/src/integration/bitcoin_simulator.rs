@@ -6,9 +6,9 @@ use crate::{rpc::RelayPallet, BtcAddress, BtcRelayPallet, InterBtcParachain};
 use async_trait::async_trait;
 use bitcoin::{
     secp256k1::{rand::rngs::OsRng, PublicKey, Secp256k1, SecretKey},
-    serialize, BitcoinCoreApi, Block, BlockHash, BlockHeader, Error as BitcoinError, GetBlockResult, Hash,
+    serialize, Amount, BitcoinCoreApi, Block, BlockHash, BlockHeader, Error as BitcoinError, GetBlockResult, Hash,
     LockedTransaction, Network, OutPoint, PartialAddress, PartialMerkleTree, PrivateKey, Script, Transaction,
-    TransactionMetadata, TxIn, TxOut, Txid, Uint256, PUBLIC_KEY_SIZE,
+    TransactionExt, TransactionMetadata, TxIn, TxOut, Txid, Uint256, PUBLIC_KEY_SIZE,
 };
 use rand::{thread_rng, Rng};
 use sp_core::{H160, H256, U256};
@@ -263,6 +263,10 @@ impl BitcoinCoreApi for MockBitcoinCore {
         let address = BtcAddress::P2PKH(H160::from(bytes));
         Ok(A::decode_str(&address.encode_str(Network::Regtest)?)?)
     }
+    async fn get_balance(&self, _min_confirmations: Option<u32>) -> Result<Amount, BitcoinError> {
+        // the simulator does not keep track of the wallet balance
+        Ok(Amount::from_sat(0))
+    }
     async fn get_new_public_key<P: From<[u8; PUBLIC_KEY_SIZE]> + 'static>(&self) -> Result<P, BitcoinError> {
         let secp = Secp256k1::new();
         let mut rng = OsRng::new().unwrap();
@@ -333,6 +337,12 @@ impl BitcoinCoreApi for MockBitcoinCore {
         let transactions = (*self.mempool.read().await).clone();
         Ok(Box::new(transactions.into_iter().map(Ok)))
     }
+    async fn get_mempool_txids(&self) -> Result<Vec<Txid>, BitcoinError> {
+        Ok(self.mempool.read().await.iter().map(|tx| tx.txid()).collect())
+    }
+    async fn get_mempool_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, BitcoinError> {
+        Ok(self.mempool.read().await.iter().find(|tx| &tx.txid() == txid).cloned())
+    }
     async fn wait_for_transaction_metadata(
         &self,
         txid: Txid,
@@ -416,6 +426,25 @@ impl BitcoinCoreApi for MockBitcoinCore {
             .unwrap();
         Ok(metadata)
     }
+    async fn sweep_funds<A: PartialAddress + Send + Sync + 'static>(&self, _address: A) -> Result<Txid, BitcoinError> {
+        // the simulator does not keep track of the wallet balance
+        Err(BitcoinError::EmptyWallet)
+    }
+    async fn find_sent_transaction<A: PartialAddress + Send + Sync + 'static>(
+        &self,
+        _address: A,
+        op_return: H256,
+    ) -> Result<Option<Txid>, BitcoinError> {
+        // all generated transactions are sent by the wallet
+        Ok(self
+            .blocks
+            .read()
+            .await
+            .iter()
+            .flat_map(|block| block.txdata.iter())
+            .find(|tx| tx.get_op_return() == Some(op_return))
+            .map(|tx| tx.txid()))
+    }
     async fn create_or_load_wallet(&self) -> Result<(), BitcoinError> {
         Ok(())
     }
@@ -0,0 +1,271 @@
+use crate::error::KeyLoadingError;
+use rand::{rngs::OsRng, RngCore};
+use schnorrkel::SecretKey;
+use scrypt::{scrypt, ScryptParams};
+use serde::{Deserialize, Serialize};
+use sp_core::{sr25519::Pair, Pair as _};
+use std::collections::HashMap;
+use xsalsa20poly1305::{
+    aead::{generic_array::GenericArray, Aead, NewAead},
+    XSalsa20Poly1305,
+};
+
+/// Environment variable to read the password of an encrypted keyfile from.
+pub const KEYFILE_PASSWORD_ENV: &str = "KEYFILE_PASSWORD";
+
+const ENCRYPTION_VERSION: &str = "3";
+const ENCRYPTION_TYPE: [&str; 2] = ["scrypt", "xsalsa20-poly1305"];
+/// Content type of the native format, whose plaintext is a json map of names to secret uris.
+const KEYFILE_CONTENT: &str = "keyfile";
+/// Content type of a single account exported from polkadot-js.
+const PKCS8_CONTENT: &str = "pkcs8";
+const SR25519_CONTENT: &str = "sr25519";
+
+const SALT_LENGTH: usize = 32;
+const SCRYPT_PARAMS_LENGTH: usize = SALT_LENGTH + 12;
+const NONCE_LENGTH: usize = 24;
+const KEY_LENGTH: usize = 32;
+
+// parameters used by polkadot-js
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_P: u32 = 1;
+const SCRYPT_R: u32 = 8;
+
+const PKCS8_HEADER: [u8; 16] = [48, 83, 2, 1, 1, 48, 5, 6, 3, 43, 101, 112, 4, 34, 4, 32];
+const PKCS8_DIVIDER: [u8; 5] = [161, 35, 3, 33, 0];
+const SECRET_KEY_LENGTH: usize = 64;
+const PUBLIC_KEY_LENGTH: usize = 32;
+
+/// Envelope of an encrypted keyfile, as exported by polkadot-js.
+#[derive(Serialize, Deserialize)]
+struct EncryptedJson {
+    /// Base64 encoding of the scrypt salt and parameters, the nonce and the ciphertext.
+    encoded: String,
+    encoding: Encoding,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    meta: Option<Meta>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Encoding {
+    content: Vec<String>,
+    #[serde(rename = "type")]
+    kind: Vec<String>,
+    version: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Meta {
+    name: Option<String>,
+}
+
+enum KeyEntry {
+    SecretUri(String),
+    Pair(Pair),
+}
+
+/// The accounts in a keyfile, which is either a plaintext json map of names to secret uris
+/// (e.g. mnemonics), the same map encrypted with a password, or a single encrypted sr25519
+/// account exported from polkadot-js, which is named by its `meta.name`.
+pub struct Keystore {
+    entries: HashMap<String, KeyEntry>,
+}
+
+impl Keystore {
+    /// Parse the content of a keyfile. `get_password` is only called if the keyfile is encrypted.
+    pub fn parse<F>(content: &str, get_password: F) -> Result<Self, KeyLoadingError>
+    where
+        F: FnOnce() -> Result<String, KeyLoadingError>,
+    {
+        if let Ok(map) = serde_json::from_str::<HashMap<String, String>>(content) {
+            return Ok(Self {
+                entries: map
+                    .into_iter()
+                    .map(|(name, secret_uri)| (name, KeyEntry::SecretUri(secret_uri)))
+                    .collect(),
+            });
+        }
+
+        let encrypted: EncryptedJson = serde_json::from_str(content)?;
+        let encoding = &encrypted.encoding;
+        if encoding.version != ENCRYPTION_VERSION || encoding.kind != ENCRYPTION_TYPE {
+            return Err(KeyLoadingError::UnsupportedEncoding(format!(
+                "{:?} version {}",
+                encoding.kind, encoding.version
+            )));
+        }
+        let plaintext = decrypt(&base64::decode(&encrypted.encoded)?, get_password()?.as_bytes())?;
+
+        match encoding.content.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            [KEYFILE_CONTENT] => {
+                let map: HashMap<String, String> = serde_json::from_slice(&plaintext)?;
+                Ok(Self {
+                    entries: map
+                        .into_iter()
+                        .map(|(name, secret_uri)| (name, KeyEntry::SecretUri(secret_uri)))
+                        .collect(),
+                })
+            }
+            [PKCS8_CONTENT, SR25519_CONTENT] => {
+                let name = encrypted.meta.and_then(|meta| meta.name).unwrap_or_default();
+                let pair = decode_pkcs8(&plaintext)?;
+                Ok(Self {
+                    entries: vec![(name, KeyEntry::Pair(pair))].into_iter().collect(),
+                })
+            }
+            _ => Err(KeyLoadingError::UnsupportedEncoding(encoding.content.join(","))),
+        }
+    }
+
+    pub fn get_pair(&self, keyname: &str) -> Result<Pair, KeyLoadingError> {
+        match self.entries.get(keyname).ok_or(KeyLoadingError::KeyNotFound)? {
+            KeyEntry::SecretUri(secret_uri) => {
+                Pair::from_string(secret_uri, None).map_err(KeyLoadingError::SecretStringError)
+            }
+            KeyEntry::Pair(pair) => Ok(pair.clone()),
+        }
+    }
+}
+
+/// Encrypt the content of a plaintext keyfile into the native encrypted format.
+pub fn encrypt_keyfile(content: &str, password: &str) -> Result<String, KeyLoadingError> {
+    // only accept valid plaintext keyfiles
+    serde_json::from_str::<HashMap<String, String>>(content)?;
+
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let key = derive_key(password.as_bytes(), &salt, SCRYPT_LOG_N, SCRYPT_P, SCRYPT_R)?;
+    let ciphertext = XSalsa20Poly1305::new(GenericArray::from_slice(&key))
+        .encrypt(GenericArray::from_slice(&nonce), content.as_bytes())
+        .map_err(|_| KeyLoadingError::InvalidEncoding)?;
+
+    let mut encoded = salt.to_vec();
+    encoded.extend_from_slice(&(1u32 << SCRYPT_LOG_N).to_le_bytes());
+    encoded.extend_from_slice(&SCRYPT_P.to_le_bytes());
+    encoded.extend_from_slice(&SCRYPT_R.to_le_bytes());
+    encoded.extend_from_slice(&nonce);
+    encoded.extend_from_slice(&ciphertext);
+
+    Ok(serde_json::to_string_pretty(&EncryptedJson {
+        encoded: base64::encode(&encoded),
+        encoding: Encoding {
+            content: vec![KEYFILE_CONTENT.to_string()],
+            kind: ENCRYPTION_TYPE.iter().map(|x| x.to_string()).collect(),
+            version: ENCRYPTION_VERSION.to_string(),
+        },
+        meta: None,
+    })?)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(&bytes[..4]);
+    u32::from_le_bytes(buf)
+}
+
+fn derive_key(password: &[u8], salt: &[u8], log_n: u8, p: u32, r: u32) -> Result<[u8; KEY_LENGTH], KeyLoadingError> {
+    let params = ScryptParams::new(log_n, r, p).map_err(|_| KeyLoadingError::InvalidEncoding)?;
+    let mut key = [0u8; KEY_LENGTH];
+    scrypt(password, salt, &params, &mut key).map_err(|_| KeyLoadingError::InvalidEncoding)?;
+    Ok(key)
+}
+
+fn decrypt(encoded: &[u8], password: &[u8]) -> Result<Vec<u8>, KeyLoadingError> {
+    if encoded.len() < SCRYPT_PARAMS_LENGTH + NONCE_LENGTH {
+        return Err(KeyLoadingError::InvalidEncoding);
+    }
+    let (params, encrypted) = encoded.split_at(SCRYPT_PARAMS_LENGTH);
+    let (salt, params) = params.split_at(SALT_LENGTH);
+    let n = read_u32(&params[0..4]);
+    if !n.is_power_of_two() {
+        return Err(KeyLoadingError::InvalidEncoding);
+    }
+    let key = derive_key(
+        password,
+        salt,
+        n.trailing_zeros() as u8,
+        read_u32(&params[4..8]),
+        read_u32(&params[8..12]),
+    )?;
+
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_LENGTH);
+    XSalsa20Poly1305::new(GenericArray::from_slice(&key))
+        .decrypt(GenericArray::from_slice(nonce), ciphertext)
+        .map_err(|_| KeyLoadingError::DecryptionFailed)
+}
+
+/// Decode the sr25519 key pair from the pkcs8 encoding used by polkadot-js, whose secret key
+/// is in the ed25519-expanded format.
+fn decode_pkcs8(plaintext: &[u8]) -> Result<Pair, KeyLoadingError> {
+    let secret_start = PKCS8_HEADER.len();
+    let divider_start = secret_start + SECRET_KEY_LENGTH;
+    let public_start = divider_start + PKCS8_DIVIDER.len();
+    if plaintext.len() < public_start + PUBLIC_KEY_LENGTH
+        || plaintext[..secret_start] != PKCS8_HEADER
+        || plaintext[divider_start..public_start] != PKCS8_DIVIDER
+    {
+        return Err(KeyLoadingError::InvalidEncoding);
+    }
+
+    let secret_key = SecretKey::from_ed25519_bytes(&plaintext[secret_start..divider_start])
+        .map_err(|_| KeyLoadingError::InvalidEncoding)?;
+    let pair = Pair::from_seed_slice(&secret_key.to_bytes()).map_err(|_| KeyLoadingError::InvalidEncoding)?;
+    if pair.public().0[..] != plaintext[public_start..public_start + PUBLIC_KEY_LENGTH] {
+        return Err(KeyLoadingError::InvalidEncoding);
+    }
+    Ok(pair)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sp_keyring::AccountKeyring;
+
+    const PLAINTEXT_KEYFILE: &str = r#"{ "alice": "//Alice", "bob": "//Bob" }"#;
+
+    #[test]
+    fn test_parse_plaintext_keyfile() {
+        let keystore = Keystore::parse(PLAINTEXT_KEYFILE, || panic!("password requested")).unwrap();
+        assert_eq!(
+            keystore.get_pair("alice").unwrap().public(),
+            AccountKeyring::Alice.public()
+        );
+        assert!(matches!(keystore.get_pair("eve"), Err(KeyLoadingError::KeyNotFound)));
+    }
+
+    #[test]
+    fn test_encrypted_keyfile_roundtrip() {
+        let encrypted = encrypt_keyfile(PLAINTEXT_KEYFILE, "password").unwrap();
+        assert!(!encrypted.contains("//Alice"));
+
+        let keystore = Keystore::parse(&encrypted, || Ok("password".to_string())).unwrap();
+        assert_eq!(keystore.get_pair("bob").unwrap().public(), AccountKeyring::Bob.public());
+
+        assert!(matches!(
+            Keystore::parse(&encrypted, || Ok("wrong".to_string())),
+            Err(KeyLoadingError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_encrypt_invalid_keyfile_fails() {
+        assert!(encrypt_keyfile("not a keyfile", "password").is_err());
+    }
+
+    #[test]
+    fn test_decode_pkcs8() {
+        let pair = AccountKeyring::Alice.pair();
+        let mut plaintext = PKCS8_HEADER.to_vec();
+        plaintext.extend_from_slice(&SecretKey::from_bytes(&pair.to_raw_vec()).unwrap().to_ed25519_bytes());
+        plaintext.extend_from_slice(&PKCS8_DIVIDER);
+        plaintext.extend_from_slice(&pair.public().0);
+
+        assert_eq!(decode_pkcs8(&plaintext).unwrap().public(), pair.public());
+
+        plaintext[0] = 0;
+        assert!(decode_pkcs8(&plaintext).is_err());
+    }
+}
@@ -1,10 +1,17 @@
 pub mod cli;
+pub mod module_error;
 pub mod pallets;
 
+mod checkpoint;
+mod compat;
 mod conn;
 mod error;
+mod file;
+mod keystore;
+mod metrics;
 mod retry;
 mod rpc;
+mod transactions;
 mod types;
 
 #[cfg(test)]
@@ -13,13 +20,23 @@ mod tests;
 #[cfg(feature = "testing-utils")]
 pub mod integration;
 
+#[cfg(any(test, feature = "fake-parachain"))]
+mod fake_parachain;
+
+pub use checkpoint::{BlockCheckpoint, EventHold};
+pub use compat::{CompatibilityReport, Incompatibility, Subsystem};
 pub use error::{Error, SubxtError};
+#[cfg(any(test, feature = "fake-parachain"))]
+pub use fake_parachain::{FakeEvent, FakeParachain};
+pub use file::write_atomically;
+pub use keystore::{encrypt_keyfile, Keystore, KEYFILE_PASSWORD_ENV};
+pub use module_error::{ModuleError, BTC_RELAY_MODULE, ISSUE_MODULE, REDEEM_MODULE};
 pub use pallets::*;
 pub use retry::{notify_retry, RetryPolicy};
 pub use rpc::{
     BtcRelayPallet, BtcTxFeesPerByte, CollateralBalancesPallet, ExchangeRateOraclePallet, FeePallet, InterBtcParachain,
-    IssuePallet, RedeemPallet, RefundPallet, RelayPallet, ReplacePallet, SecurityPallet, TimestampPallet, UtilFuncs,
-    VaultRegistryPallet,
+    IssuePallet, PendingSubmission, RedeemPallet, RefundPallet, RelayPallet, ReplacePallet, SecurityPallet,
+    TimestampPallet, UtilFuncs, VaultRegistryPallet,
 };
 pub use sp_arithmetic::{traits as FixedPointTraits, FixedI128, FixedPointNumber, FixedU128};
 pub use sp_runtime;
@@ -169,14 +186,5 @@ impl sla::Sla for InterBtcRuntime {}
 
 impl utility::Utility for InterBtcRuntime {}
 
-pub const BTC_RELAY_MODULE: &str = "BTCRelay";
-pub const ISSUE_MODULE: &str = "Issue";
-pub const REDEEM_MODULE: &str = "Redeem";
-
 pub const STABLE_BITCOIN_CONFIRMATIONS: &str = "StableBitcoinConfirmations";
 pub const STABLE_PARACHAIN_CONFIRMATIONS: &str = "StableParachainConfirmations";
-
-pub const DUPLICATE_BLOCK_ERROR: &str = "DuplicateBlock";
-pub const INVALID_CHAIN_ID_ERROR: &str = "InvalidChainID";
-pub const ISSUE_COMPLETED_ERROR: &str = "IssueCompleted";
-pub const COMMIT_PERIOD_EXPIRED_ERROR: &str = "CommitPeriodExpired";
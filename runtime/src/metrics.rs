@@ -0,0 +1,34 @@
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter, register_int_counter_vec, IntCounter, IntCounterVec};
+
+lazy_static! {
+    pub(crate) static ref EXTRINSIC_RETRIES: IntCounter =
+        register_int_counter!("extrinsic_retries_total", "Number of calls retried by notify_retry").unwrap();
+    pub(crate) static ref NONCE_REFRESHES: IntCounter = register_int_counter!(
+        "nonce_refreshes_total",
+        "Number of times the signer nonce was refreshed"
+    )
+    .unwrap();
+    pub(crate) static ref EXTRINSIC_RESUBMISSIONS: IntCounter = register_int_counter!(
+        "extrinsic_resubmissions_total",
+        "Number of extrinsics resubmitted after they were dropped"
+    )
+    .unwrap();
+    pub(crate) static ref NONCE_GAPS_FILLED: IntCounter = register_int_counter!(
+        "nonce_gaps_filled_total",
+        "Number of unused nonces that were filled with a remark"
+    )
+    .unwrap();
+    pub(crate) static ref EXTRINSIC_FEES: IntCounterVec = register_int_counter_vec!(
+        "extrinsic_fees_total",
+        "Estimated fees paid for submitted extrinsics, in planck",
+        &["call"]
+    )
+    .unwrap();
+    pub(crate) static ref EVENT_LISTENER_LAG: IntCounterVec = register_int_counter_vec!(
+        "event_listener_lag_total",
+        "Number of times the event subscription waited for a listener whose buffer was full",
+        &["listener"]
+    )
+    .unwrap();
+}
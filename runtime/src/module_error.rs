@@ -0,0 +1,315 @@
+//! Typed dispatch errors of the pallets used by the clients.
+//!
+//! Substrate reports a failed extrinsic as the index of the pallet and the index of the error in
+//! the pallet's `Error` enum, which subxt resolves to names with the runtime metadata. The names
+//! are parsed into the enums below, so that callers can match on variants. Errors that are not
+//! listed here (e.g. added by a runtime upgrade) are kept as `ModuleError::Other`.
+
+use crate::{Error, SubxtError};
+use std::fmt;
+use substrate_subxt::{Metadata, ModuleError as SubxtModuleError};
+
+pub const BTC_RELAY_MODULE: &str = "BTCRelay";
+pub const ISSUE_MODULE: &str = "Issue";
+pub const REDEEM_MODULE: &str = "Redeem";
+pub const REPLACE_MODULE: &str = "Replace";
+pub const REFUND_MODULE: &str = "Refund";
+pub const RELAY_MODULE: &str = "Relay";
+pub const VAULT_REGISTRY_MODULE: &str = "VaultRegistry";
+pub const TOKENS_MODULE: &str = "Tokens";
+pub const EXCHANGE_RATE_ORACLE_MODULE: &str = "ExchangeRateOracle";
+
+macro_rules! pallet_errors {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident),* $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[allow(clippy::upper_case_acronyms)]
+        pub enum $name {
+            $($variant),*
+        }
+
+        impl $name {
+            fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $(stringify!($variant) => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+
+            /// Name of the error in the runtime metadata.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$variant => stringify!($variant),)*
+                }
+            }
+        }
+    };
+}
+
+pallet_errors!(
+    /// Errors of the `BTCRelay` pallet.
+    BtcRelayError {
+        AlreadyInitialized,
+        NotMainChain,
+        ForkPrevBlock,
+        NotFork,
+        InvalidForkId,
+        MissingBlockHeight,
+        InvalidHeaderSize,
+        DuplicateBlock,
+        PrevBlock,
+        InvalidChainID,
+        LowDiff,
+        DiffTargetHeader,
+        MalformedTxid,
+        BitcoinConfirmations,
+        ParachainConfirmations,
+        OngoingFork,
+        InvalidMerkleProof,
+        BlockNotFound,
+    }
+);
+
+pallet_errors!(
+    /// Errors of the `Issue` pallet.
+    IssueError {
+        InsufficientCollateral,
+        IssueIdNotFound,
+        IssueCompleted,
+        IssueCancelled,
+        CommitPeriodExpired,
+        TimeNotExpired,
+        ArithmeticOverflow,
+    }
+);
+
+pallet_errors!(
+    /// Errors of the `Redeem` pallet.
+    RedeemError {
+        AmountExceedsUserBalance,
+        AmountExceedsVaultBalance,
+        AmountBelowDustAmount,
+        RedeemIdNotFound,
+        RedeemCompleted,
+        RedeemCancelled,
+        CommitPeriodExpired,
+        UnauthorizedUser,
+        TimeNotExpired,
+        ArithmeticOverflow,
+    }
+);
+
+pallet_errors!(
+    /// Errors of the `Replace` pallet.
+    ReplaceError {
+        InsufficientCollateral,
+        InsufficientTokensCommitted,
+        NoPendingRequest,
+        ReplaceSelfNotAllowed,
+        AmountBelowDustAmount,
+        ReplaceIdNotFound,
+        ReplaceCompleted,
+        ReplaceCancelled,
+        ReplacePeriodExpired,
+        ReplacePeriodNotExpired,
+        UnauthorizedVault,
+        ArithmeticOverflow,
+    }
+);
+
+pallet_errors!(
+    /// Errors of the `Refund` pallet.
+    RefundError {
+        RefundIdNotFound,
+        RefundCompleted,
+    }
+);
+
+pallet_errors!(
+    /// Errors of the `Relay` pallet.
+    RelayError {
+        ValidRedeemTransaction,
+        ValidReplaceTransaction,
+        ValidRefundTransaction,
+        ValidMergeTransaction,
+        VaultNoInputToTransaction,
+        VaultAlreadyReported,
+    }
+);
+
+pallet_errors!(
+    /// Errors of the `VaultRegistry` pallet.
+    VaultRegistryError {
+        InsufficientCollateral,
+        VaultAlreadyRegistered,
+        VaultNotFound,
+        VaultBanned,
+        VaultNotAcceptingIssueRequests,
+        ExceedingVaultLimit,
+        ReservedDepositAddress,
+        ArithmeticOverflow,
+    }
+);
+
+pallet_errors!(
+    /// Errors of the `Tokens` pallet.
+    TokensError {
+        BalanceTooLow,
+        TotalIssuanceOverflow,
+        LiquidityRestrictions,
+    }
+);
+
+pallet_errors!(
+    /// Errors of the `ExchangeRateOracle` pallet.
+    ExchangeRateOracleError {
+        InvalidOracleSource,
+        ArithmeticOverflow,
+        ArithmeticUnderflow,
+        MissingExchangeRate,
+    }
+);
+
+/// Dispatch error of a pallet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleError {
+    BtcRelay(BtcRelayError),
+    Issue(IssueError),
+    Redeem(RedeemError),
+    Replace(ReplaceError),
+    Refund(RefundError),
+    Relay(RelayError),
+    VaultRegistry(VaultRegistryError),
+    Tokens(TokensError),
+    ExchangeRateOracle(ExchangeRateOracleError),
+    /// Error of another pallet, or one that is not known to this client.
+    Other {
+        module: String,
+        error: String,
+    },
+}
+
+impl ModuleError {
+    /// Parse the error from the names of the pallet and the error.
+    pub fn from_names(module: &str, error: &str) -> Self {
+        let known = match module {
+            BTC_RELAY_MODULE => BtcRelayError::from_name(error).map(Self::BtcRelay),
+            ISSUE_MODULE => IssueError::from_name(error).map(Self::Issue),
+            REDEEM_MODULE => RedeemError::from_name(error).map(Self::Redeem),
+            REPLACE_MODULE => ReplaceError::from_name(error).map(Self::Replace),
+            REFUND_MODULE => RefundError::from_name(error).map(Self::Refund),
+            RELAY_MODULE => RelayError::from_name(error).map(Self::Relay),
+            VAULT_REGISTRY_MODULE => VaultRegistryError::from_name(error).map(Self::VaultRegistry),
+            TOKENS_MODULE => TokensError::from_name(error).map(Self::Tokens),
+            EXCHANGE_RATE_ORACLE_MODULE => ExchangeRateOracleError::from_name(error).map(Self::ExchangeRateOracle),
+            _ => None,
+        };
+        known.unwrap_or_else(|| Self::Other {
+            module: module.to_string(),
+            error: error.to_string(),
+        })
+    }
+
+    /// Decode the error from the index of the pallet and the index of the error within the
+    /// pallet, as in `DispatchError::Module`.
+    pub fn from_indices(metadata: &Metadata, module_index: u8, error_index: u8) -> Result<Self, Error> {
+        let module = metadata.module_with_errors(module_index).map_err(SubxtError::from)?;
+        let error = module.error(error_index).map_err(SubxtError::from)?;
+        Ok(Self::from_names(module.name(), error))
+    }
+
+    pub fn module(&self) -> &str {
+        match self {
+            Self::BtcRelay(_) => BTC_RELAY_MODULE,
+            Self::Issue(_) => ISSUE_MODULE,
+            Self::Redeem(_) => REDEEM_MODULE,
+            Self::Replace(_) => REPLACE_MODULE,
+            Self::Refund(_) => REFUND_MODULE,
+            Self::Relay(_) => RELAY_MODULE,
+            Self::VaultRegistry(_) => VAULT_REGISTRY_MODULE,
+            Self::Tokens(_) => TOKENS_MODULE,
+            Self::ExchangeRateOracle(_) => EXCHANGE_RATE_ORACLE_MODULE,
+            Self::Other { module, .. } => module,
+        }
+    }
+
+    pub fn error(&self) -> &str {
+        match self {
+            Self::BtcRelay(err) => err.name(),
+            Self::Issue(err) => err.name(),
+            Self::Redeem(err) => err.name(),
+            Self::Replace(err) => err.name(),
+            Self::Refund(err) => err.name(),
+            Self::Relay(err) => err.name(),
+            Self::VaultRegistry(err) => err.name(),
+            Self::Tokens(err) => err.name(),
+            Self::ExchangeRateOracle(err) => err.name(),
+            Self::Other { error, .. } => error,
+        }
+    }
+}
+
+impl From<&SubxtModuleError> for ModuleError {
+    fn from(err: &SubxtModuleError) -> Self {
+        Self::from_names(&err.module, &err.error)
+    }
+}
+
+impl From<ModuleError> for SubxtModuleError {
+    fn from(err: ModuleError) -> Self {
+        SubxtModuleError {
+            module: err.module().to_string(),
+            error: err.error().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::{}", self.module(), self.error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_errors_are_typed() {
+        assert_eq!(
+            ModuleError::from_names("BTCRelay", "DuplicateBlock"),
+            ModuleError::BtcRelay(BtcRelayError::DuplicateBlock)
+        );
+        assert_eq!(
+            ModuleError::from_names("Redeem", "CommitPeriodExpired"),
+            ModuleError::Redeem(RedeemError::CommitPeriodExpired)
+        );
+        // the same name in a different pallet is a different error
+        assert_eq!(
+            ModuleError::from_names("Issue", "CommitPeriodExpired"),
+            ModuleError::Issue(IssueError::CommitPeriodExpired)
+        );
+    }
+
+    #[test]
+    fn test_unknown_errors_are_kept() {
+        let err = ModuleError::from_names("Issue", "SomeNewError");
+        assert_eq!(
+            err,
+            ModuleError::Other {
+                module: "Issue".to_string(),
+                error: "SomeNewError".to_string()
+            }
+        );
+        assert_eq!(err.to_string(), "Issue::SomeNewError");
+    }
+
+    #[test]
+    fn test_subxt_error_roundtrip() {
+        let err = ModuleError::BtcRelay(BtcRelayError::InvalidChainID);
+        let subxt_err = SubxtModuleError::from(err.clone());
+        assert_eq!(subxt_err.module, "BTCRelay");
+        assert_eq!(subxt_err.error, "InvalidChainID");
+        assert_eq!(ModuleError::from(&subxt_err), err);
+    }
+}
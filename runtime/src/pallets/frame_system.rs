@@ -30,3 +30,9 @@ pub struct SetStorageCall<T: System> {
     pub items: Vec<(Vec<u8>, Vec<u8>)>,
     pub _runtime: PhantomData<T>,
 }
+
+#[derive(Clone, Debug, PartialEq, Call, Encode)]
+pub struct RemarkCall<T: System> {
+    pub _runtime: PhantomData<T>,
+    pub remark: Vec<u8>,
+}
@@ -1,4 +1,4 @@
-use crate::Error;
+use crate::{metrics::EXTRINSIC_RETRIES, Error};
 use backoff::{backoff::Backoff, ExponentialBackoff};
 use futures::Future;
 use std::{fmt::Debug, time::Duration};
@@ -44,6 +44,7 @@ where
             Some(wait) => {
                 // error occurred, sleep before retrying
                 log::warn!("{:?} - next retry in {:.3} s", err, wait.as_secs_f64());
+                EXTRINSIC_RETRIES.inc();
                 tokio::time::delay_for(wait).await;
             }
             None => break Err(Error::Timeout),
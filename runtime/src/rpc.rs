@@ -3,32 +3,142 @@ pub use module_exchange_rate_oracle::BtcTxFeesPerByte;
 
 use async_trait::async_trait;
 use core::marker::PhantomData;
-use futures::{stream::StreamExt, FutureExt, SinkExt};
+use futures::{
+    channel::mpsc,
+    future::{self, Either},
+    stream::{self, Stream, StreamExt},
+    FutureExt, SinkExt,
+};
 use jsonrpsee_types::to_json_value;
 use module_exchange_rate_oracle_rpc_runtime_api::BalanceWrapper;
+use serde::Deserialize;
 use sp_arithmetic::FixedU128;
-use sp_core::H256;
-use sp_runtime::DispatchError;
-use std::{collections::BTreeSet, future::Future, sync::Arc, time::Duration};
+use sp_core::{
+    storage::{StorageData, StorageKey},
+    Bytes, H256,
+};
+use sp_runtime::{
+    generic::Era,
+    traits::{BlakeTwo256, Hash},
+    DispatchError,
+};
+use std::{collections::BTreeSet, convert::Infallible, future::Future, pin::Pin, sync::Arc, time::Duration};
 use substrate_subxt::{
-    sudo::*, Call, Client as SubxtClient, ClientBuilder as SubxtClientBuilder, Error as SubxtError, Event,
-    EventSubscription, EventTypeRegistry, EventsDecoder, RpcClient, RuntimeError as SubxtRuntimeError, Signer,
+    extrinsic::{
+        ChargeTransactionPayment, CheckEra, CheckGenesis, CheckNonce, CheckSpecVersion, CheckTxVersion, CheckWeight,
+        SignedPayload, UncheckedExtrinsic,
+    },
+    sudo::*,
+    system::Phase,
+    Call, Client as SubxtClient, ClientBuilder as SubxtClientBuilder, Encoded, Error as SubxtError, Event,
+    EventTypeRegistry, EventsDecoder, ExtrinsicSuccess, Raw, RawEvent, RpcClient, RuntimeError as SubxtRuntimeError,
+    Signer,
+};
+use tokio::{
+    sync::{Mutex, RwLock},
+    time::{delay_for, timeout},
 };
-use tokio::{sync::RwLock, time::delay_for};
 
 use crate::{
-    btc_relay::*, conn::*, exchange_rate_oracle::*, fee::*, issue::*, pallets::*, redeem::*, refund::*, relay::*,
-    replace::*, retry::*, security::*, timestamp::*, tokens::*, types::*, utility::*, vault_registry::*, AccountId,
-    BlockNumber, CurrencyId, Error, InterBtcRuntime, BTC_RELAY_MODULE, STABLE_BITCOIN_CONFIRMATIONS,
-    STABLE_PARACHAIN_CONFIRMATIONS,
+    btc_relay::*,
+    checkpoint::{BlockAck, CheckpointTracker, EventGate, EventHold},
+    compat::{RuntimeShape, RuntimeVersion, CODE_UPDATED_EVENT, SYSTEM_MODULE},
+    conn::*,
+    exchange_rate_oracle::*,
+    fee::*,
+    frame_system::RemarkCall,
+    issue::*,
+    pallets::*,
+    redeem::*,
+    refund::*,
+    relay::*,
+    replace::*,
+    retry::*,
+    security::*,
+    timestamp::*,
+    tokens::*,
+    transactions::{expiry_timeout, PendingExtrinsics, EXTRINSIC_MORTALITY, MAX_RESUBMISSIONS, STALL_CHECK_INTERVAL},
+    types::*,
+    utility::*,
+    vault_registry::*,
+    AccountId, BlockCheckpoint, BlockNumber, CompatibilityReport, CurrencyId, Error, Index, InterBtcRuntime,
+    BTC_RELAY_MODULE, STABLE_BITCOIN_CONFIRMATIONS, STABLE_PARACHAIN_CONFIRMATIONS,
 };
 
+/// Number of raw events that are buffered for each listener of the shared event subscription.
+/// The subscription waits for listeners whose buffer is full, so no events are dropped, and
+/// counts them in `event_listener_lag_total`.
+const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// Raw event of a finalized block, with the acknowledgement of the block that the listener holds
+/// until it has processed the event.
+type AckedEvent = (Arc<Raw>, Arc<BlockAck>);
+
+type RawEvents = Arc<Vec<AckedEvent>>;
+
+/// Resolves once an extrinsic that has been assigned a nonce is finalized.
+pub type PendingSubmission = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+/// Dispatches the events of finalized blocks to all listeners on the same connection.
+#[derive(Clone)]
+struct EventHub {
+    /// Senders to the listeners, with the name of the event they listen for.
+    subscribers: Vec<(&'static str, mpsc::Sender<AckedEvent>)>,
+    /// Events of the blocks finalized since the checkpoint, replayed to every listener that
+    /// subscribes before the first live block is dispatched, and dropped afterwards.
+    backlog: RawEvents,
+}
+
 #[derive(Clone)]
 pub struct InterBtcParachain {
     rpc_client: RpcClient,
     ext_client: SubxtClient<InterBtcRuntime>,
     signer: Arc<RwLock<InterBtcSigner>>,
+    /// Extrinsics of the signer that are not finalized yet, by nonce.
+    pending_extrinsics: Arc<Mutex<PendingExtrinsics>>,
     account_id: AccountId,
+    /// Version of the runtime that extrinsics are signed for.
+    runtime_version: RuntimeVersion,
+    dry_run: bool,
+    /// Block to read the state at, the latest finalized block if unset.
+    at_block: Option<H256>,
+    /// Shared by all handles on the same connection, set while the event subscription is active.
+    event_hub: Arc<Mutex<Option<EventHub>>>,
+    /// Shared by all handles on the same connection, holds back the dispatch of live blocks.
+    event_gate: EventGate,
+    /// Hold of a handle created by `holding_events`, released once it has subscribed to events.
+    event_hold: Arc<std::sync::Mutex<Option<EventHold>>>,
+    event_checkpoint: Option<BlockCheckpoint>,
+    /// Part of the free balance that is kept for paying extrinsic fees.
+    fee_reserve: u128,
+}
+
+/// Fee estimate, as returned by `payment_queryInfo`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RuntimeDispatchInfo {
+    partial_fee: FeeAmount,
+}
+
+/// Depending on the node version, the fee is encoded as a number or as a (hex) string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FeeAmount {
+    Number(u128),
+    String(String),
+}
+
+impl FeeAmount {
+    fn parse(self) -> Result<u128, Error> {
+        match self {
+            FeeAmount::Number(fee) => Ok(fee),
+            FeeAmount::String(fee) => match fee.strip_prefix("0x") {
+                Some(hex) => u128::from_str_radix(hex, 16),
+                None => fee.parse(),
+            }
+            .map_err(|_| Error::InvalidFeeEstimate(fee)),
+        }
+    }
 }
 
 impl InterBtcParachain {
@@ -39,12 +149,22 @@ impl InterBtcParachain {
             .set_client(rpc_client.clone())
             .build()
             .await?;
+        let runtime_version = rpc_client.request("state_getRuntimeVersion", &[]).await?;
 
         let parachain_rpc = Self {
             rpc_client,
             ext_client,
             signer: Arc::new(RwLock::new(signer)),
+            pending_extrinsics: Default::default(),
             account_id,
+            runtime_version,
+            dry_run: false,
+            at_block: None,
+            event_hub: Default::default(),
+            event_gate: Default::default(),
+            event_hold: Default::default(),
+            event_checkpoint: None,
+            fee_reserve: 0,
         };
         parachain_rpc.refresh_nonce().await;
         Ok(parachain_rpc)
@@ -80,8 +200,92 @@ impl InterBtcParachain {
         Self::new(ws_client, signer).await
     }
 
-    async fn refresh_nonce(&self) {
+    /// Create a handle that signs with a different account, but shares the connection
+    /// and the event subscription with this handle.
+    pub async fn with_signer(&self, signer: InterBtcSigner) -> Self {
+        let parachain_rpc = Self {
+            rpc_client: self.rpc_client.clone(),
+            ext_client: self.ext_client.clone(),
+            account_id: signer.account_id().clone(),
+            signer: Arc::new(RwLock::new(signer)),
+            pending_extrinsics: Default::default(),
+            runtime_version: self.runtime_version.clone(),
+            dry_run: self.dry_run,
+            at_block: self.at_block,
+            event_hub: self.event_hub.clone(),
+            event_gate: self.event_gate.clone(),
+            event_hold: Default::default(),
+            event_checkpoint: self.event_checkpoint.clone(),
+            fee_reserve: self.fee_reserve,
+        };
+        parachain_rpc.refresh_nonce().await;
+        parachain_rpc
+    }
+
+    /// Create a handle whose getters read the state at the given block, rather than at the
+    /// latest finalized block. Extrinsics, event subscriptions and `get_current_chain_height`
+    /// are not affected.
+    pub fn at_block(&self, block_hash: H256) -> Self {
+        Self {
+            at_block: Some(block_hash),
+            ..self.clone()
+        }
+    }
+
+    /// Same as `at_block`, for the finalized block with the given number.
+    pub async fn at_block_number(&self, number: BlockNumber) -> Result<Self, Error> {
+        Ok(self.at_block(self.get_block_hash_by_number(number).await?))
+    }
+
+    /// Persist the last block whose events have been dispatched, and on the first event
+    /// subscription replay the events of all blocks finalized since then.
+    pub fn with_event_checkpoint(mut self, checkpoint: Option<BlockCheckpoint>) -> Self {
+        self.event_checkpoint = checkpoint;
+        self
+    }
+
+    /// Hold back the live events on this connection until the returned hold, and the holds of
+    /// the handles created by `holding_events`, have been dropped. Until then, the events replayed
+    /// since the event checkpoint are kept, so that every listener that subscribes in the meantime
+    /// receives them, and no block counts as processed before these listeners have seen it.
+    pub fn hold_events(&self) -> EventHold {
+        self.event_gate.hold()
+    }
+
+    /// Create a handle for a listener that holds back the live events on this connection, like
+    /// `hold_events`, until it has subscribed to events (or is dropped).
+    pub fn holding_events(&self) -> Self {
+        Self {
+            event_hold: Arc::new(std::sync::Mutex::new(Some(self.hold_events()))),
+            ..self.clone()
+        }
+    }
+
+    /// In dry-run mode, extrinsics are never signed or submitted.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Keep `fee_reserve` of the free balance for paying extrinsic fees, so that it is not
+    /// spent as collateral.
+    pub fn with_fee_reserve(mut self, fee_reserve: u128) -> Self {
+        self.fee_reserve = fee_reserve;
+        self
+    }
+
+    /// Reset the nonce of the signer to the next nonce of the account on chain. Extrinsics that
+    /// are still in flight should be awaited first, or their nonces are handed out again.
+    pub async fn refresh_nonce(&self) {
         let mut signer = self.signer.write().await;
+        let nonce = self.get_account_nonce().await.unwrap_or_default();
+        log::info!("Refreshing nonce: {}", nonce);
+        crate::metrics::NONCE_REFRESHES.inc();
+        signer.set_nonce(nonce);
+    }
+
+    /// Get the nonce of the next extrinsic of the signer that can be included.
+    async fn get_account_nonce(&self) -> Result<Index, Error> {
         // For getting the nonce, use latest, possibly non-finalized block.
         // TODO: we might want to wait until the latest block is actually finalized
         // query account info in order to get the nonce value used for communication
@@ -90,18 +294,21 @@ impl InterBtcParachain {
             self.account_id.clone(),
             Option::<H256>::None,
         )
-        .await
-        .unwrap_or_default();
-        log::info!("Refreshing nonce: {}", account_info.nonce);
-        signer.set_nonce(account_info.nonce);
+        .await?;
+        Ok(account_info.nonce)
     }
 
     /// Gets a copy of the signer with a unique nonce
     async fn with_unique_signer<F, R, T>(&self, call: F) -> Result<T, Error>
     where
         F: Fn(InterBtcSigner) -> R,
-        R: Future<Output = Result<T, SubxtError>>,
+        R: Future<Output = Result<T, Error>>,
     {
+        if self.dry_run {
+            log::info!("Dry run: not submitting extrinsic");
+            return Err(Error::DryRun);
+        }
+
         notify_retry(
             || async {
                 let signer = {
@@ -114,7 +321,7 @@ impl InterBtcParachain {
                 call(signer).await
             },
             |result| async {
-                match result.map_err(Into::<Error>::into) {
+                match result {
                     Ok(ok) => Ok(ok),
                     Err(err) if err.is_outdated_nonce() => {
                         self.refresh_nonce().await;
@@ -127,10 +334,384 @@ impl InterBtcParachain {
         .await
     }
 
+    /// Estimate the fee of submitting the call with the configured signer.
+    pub async fn estimate_fee<C: Call<InterBtcRuntime> + Send + Sync>(&self, call: C) -> Result<u128, Error> {
+        let signer = self.signer.read().await.clone();
+        let extrinsic = self.ext_client.create_signed(call, &signer).await?;
+        let info: RuntimeDispatchInfo = self
+            .rpc_client
+            .request("payment_queryInfo", &[to_json_value(Bytes(extrinsic.encode()))?])
+            .await?;
+        info.partial_fee.parse()
+    }
+
+    /// Submit the call with a unique signer and wait until it is included in a block. The fee
+    /// is estimated beforehand, and the call is not submitted if the free balance cannot cover
+    /// it. Fees are accounted per call, including those of calls that failed on dispatch.
+    async fn submit<C: Call<InterBtcRuntime> + Clone + Send + Sync>(
+        &self,
+        call: C,
+    ) -> Result<ExtrinsicSuccess<InterBtcRuntime>, Error> {
+        if self.dry_run {
+            log::info!("Dry run: not submitting extrinsic");
+            return Err(Error::DryRun);
+        }
+
+        let call_name = format!("{}.{}", C::MODULE, C::FUNCTION);
+        let fee = self.check_fee(call.clone(), &call_name).await?;
+        let call = &self.ext_client.encode(call)?;
+        let call_name = &call_name;
+        let result = self
+            .with_unique_signer(|signer| async move { self.submit_tracked(&signer, call_name, call).await })
+            .await;
+        record_fee(&result, fee, call_name);
+        result
+    }
+
+    /// Like `submit`, but returns as soon as the call has been assigned a nonce, with a future
+    /// that resolves once it is finalized. Calls submitted this way are included in the order in
+    /// which this function returns, even if their futures are polled concurrently. The call is
+    /// not retried if its nonce turns out to be outdated, and the nonce is not refreshed on
+    /// failure since later calls may still be in flight; see `refresh_nonce`.
+    async fn submit_pipelined<C: Call<InterBtcRuntime> + Clone + Send + Sync>(
+        &self,
+        call: C,
+    ) -> Result<PendingSubmission, Error> {
+        if self.dry_run {
+            log::info!("Dry run: not submitting extrinsic");
+            return Err(Error::DryRun);
+        }
+
+        let call_name = format!("{}.{}", C::MODULE, C::FUNCTION);
+        let fee = self.check_fee(call.clone(), &call_name).await?;
+        let call = self.ext_client.encode(call)?;
+        let signer = self.with_unique_signer(|signer| future::ok(signer)).await?;
+        // allocate the nonce before returning, so that it is not taken for a gap
+        let nonce = signer.nonce().unwrap_or_default();
+        self.pending_extrinsics.lock().await.allocate(nonce, &call_name);
+
+        let parachain = self.clone();
+        Ok(Box::pin(async move {
+            let result = parachain.wait_until_finalized(&signer, &call_name, &call).await;
+            record_fee(&result, fee, &call_name);
+            result.map(|_| ())
+        }))
+    }
+
+    /// Estimate the fee of the call and check that the free balance can cover it.
+    async fn check_fee<C: Call<InterBtcRuntime> + Send + Sync>(&self, call: C, call_name: &str) -> Result<u128, Error> {
+        let fee = self.estimate_fee(call).await?;
+        let free = self.get_free_balance().await?;
+        if fee > free {
+            return Err(Error::InsufficientFeeBalance(fee, free));
+        } else if free - fee < self.fee_reserve {
+            log::warn!(
+                "Free balance of {} after paying for {} is below the fee reserve of {}",
+                free - fee,
+                call_name,
+                self.fee_reserve
+            );
+        }
+        Ok(fee)
+    }
+
+    /// Submit the call with the nonce of the signer and wait until it is finalized. If the
+    /// extrinsic is dropped from the pool, it is resubmitted with the same nonce.
+    async fn submit_tracked(
+        &self,
+        signer: &InterBtcSigner,
+        call_name: &str,
+        call: &Encoded,
+    ) -> Result<ExtrinsicSuccess<InterBtcRuntime>, Error> {
+        let nonce = signer.nonce().unwrap_or_default();
+        self.pending_extrinsics.lock().await.allocate(nonce, call_name);
+        self.wait_until_finalized(signer, call_name, call).await
+    }
+
+    /// Submit the call with the already allocated nonce of the signer and wait until it is
+    /// finalized, then release the nonce.
+    async fn wait_until_finalized(
+        &self,
+        signer: &InterBtcSigner,
+        call_name: &str,
+        call: &Encoded,
+    ) -> Result<ExtrinsicSuccess<InterBtcRuntime>, Error> {
+        let nonce = signer.nonce().unwrap_or_default();
+        let result = self.resubmit_until_finalized(signer, nonce, call).await;
+        self.pending_extrinsics.lock().await.remove(nonce);
+        match &result {
+            Ok(success) => log::debug!(
+                "{} with nonce {} finalized in block {:?}",
+                call_name,
+                nonce,
+                success.block
+            ),
+            Err(err) => log::debug!("{} with nonce {} failed: {}", call_name, nonce, err),
+        }
+        result
+    }
+
+    async fn resubmit_until_finalized(
+        &self,
+        signer: &InterBtcSigner,
+        nonce: Index,
+        call: &Encoded,
+    ) -> Result<ExtrinsicSuccess<InterBtcRuntime>, Error> {
+        let signed_after = self.get_finalized_block_number().await?;
+        for submission in 0..=MAX_RESUBMISSIONS {
+            let extrinsic = self.create_mortal_extrinsic(signer, call.clone()).await?;
+            let hash = BlakeTwo256::hash_of(&extrinsic);
+            self.pending_extrinsics.lock().await.submitted(nonce, hash);
+            if submission > 0 {
+                log::info!("Resubmitting extrinsic with nonce {} as {:?}", nonce, hash);
+                crate::metrics::EXTRINSIC_RESUBMISSIONS.inc();
+            }
+
+            let watch = self.ext_client.submit_and_watch_extrinsic(extrinsic);
+            let stall_check = self.fill_nonce_gaps_periodically(signer, nonce);
+            futures::pin_mut!(watch, stall_check);
+            let result = match timeout(expiry_timeout(), future::select(watch, stall_check)).await {
+                Ok(Either::Left((result, _))) => result.map_err(Into::into),
+                Ok(Either::Right((never, _))) => match never {},
+                Err(elapsed) => Err(elapsed.into()),
+            };
+
+            match result {
+                Ok(success) => return Ok(success),
+                // an earlier submission may have been included after all
+                Err(err) if submission > 0 && err.is_outdated_nonce() => {
+                    return self.find_finalized_submission(nonce, signed_after).await;
+                }
+                Err(err) if err.is_dropped_extrinsic() => {
+                    if self.get_account_nonce().await? > nonce {
+                        return self.find_finalized_submission(nonce, signed_after).await;
+                    }
+                    log::warn!("Extrinsic {:?} with nonce {} was dropped: {}", hash, nonce, err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        // give up on the call, but do not let its nonce stall later extrinsics
+        self.fill_nonce(signer, nonce).await?;
+        Err(Error::ExtrinsicDropped(nonce))
+    }
+
+    /// The nonce of the extrinsic was used while it was pending, so look for any of its
+    /// submissions in the blocks finalized after `signed_after`. Only if none of them was
+    /// included, the nonce was used by another extrinsic.
+    async fn find_finalized_submission(
+        &self,
+        nonce: Index,
+        signed_after: BlockNumber,
+    ) -> Result<ExtrinsicSuccess<InterBtcRuntime>, Error> {
+        let finalized = self.get_finalized_block_number().await?;
+        for number in signed_after.saturating_add(1)..=finalized {
+            let block_hash = self.get_block_hash_by_number(number).await?;
+            let block = self
+                .ext_client
+                .block(Some(block_hash))
+                .await?
+                .ok_or(Error::BlockNotFound)?
+                .block;
+            let found = self
+                .pending_extrinsics
+                .lock()
+                .await
+                .find_submission(nonce, block.extrinsics.iter().map(BlakeTwo256::hash_of));
+            if let Some((index, hash)) = found {
+                log::info!(
+                    "Extrinsic {:?} with nonce {} was included in block {}",
+                    hash,
+                    nonce,
+                    number
+                );
+                let events = self.get_extrinsic_events(block_hash, index as u32).await?;
+                return Ok(ExtrinsicSuccess {
+                    block: block_hash,
+                    extrinsic: hash,
+                    events,
+                });
+            }
+        }
+        Err(Error::ExtrinsicNonceUsed(nonce))
+    }
+
+    /// Fetch the events of the extrinsic at `index` in the given block, or its dispatch error.
+    async fn get_extrinsic_events(&self, block_hash: H256, index: u32) -> Result<Vec<RawEvent>, Error> {
+        let decoder =
+            EventsDecoder::<InterBtcRuntime>::new(self.ext_client.metadata().clone(), EventTypeRegistry::new());
+        let mut events = Vec::new();
+        for (phase, raw) in self.get_events_at(block_hash, &decoder).await? {
+            match (phase, raw) {
+                (Phase::ApplyExtrinsic(i), Raw::Event(event)) if i == index => events.push(event),
+                (Phase::ApplyExtrinsic(i), Raw::Error(err)) if i == index => {
+                    return Err(SubxtError::Runtime(err).into())
+                }
+                _ => {}
+            }
+        }
+        Ok(events)
+    }
+
+    async fn get_finalized_block_number(&self) -> Result<BlockNumber, Error> {
+        let head = self.ext_client.finalized_head().await?;
+        Ok(self
+            .ext_client
+            .header(Some(head))
+            .await?
+            .ok_or(Error::BlockNotFound)?
+            .number)
+    }
+
+    /// Sign the call with an era of `EXTRINSIC_MORTALITY` blocks that starts at the latest
+    /// finalized block.
+    async fn create_mortal_extrinsic(
+        &self,
+        signer: &InterBtcSigner,
+        call: Encoded,
+    ) -> Result<UncheckedExtrinsic<InterBtcRuntime>, Error> {
+        let number = self.get_finalized_block_number().await?;
+        let era = Era::mortal(EXTRINSIC_MORTALITY, number.into());
+        let birth = era.birth(number.into()) as BlockNumber;
+        let birth_hash = self.get_block_hash_by_number(birth).await?;
+
+        let extra = (
+            CheckSpecVersion(PhantomData, self.runtime_version.spec_version),
+            CheckTxVersion(PhantomData, self.runtime_version.transaction_version),
+            CheckGenesis(PhantomData, *self.ext_client.genesis()),
+            CheckEra((era, PhantomData), birth_hash),
+            CheckNonce(signer.nonce().unwrap_or_default()),
+            CheckWeight(PhantomData),
+            ChargeTransactionPayment(0),
+        );
+        let payload = SignedPayload::<InterBtcRuntime>::new(call, extra)
+            .map_err(|err| SubxtError::Other(format!("{:?}", err)))?;
+        Ok(signer.sign(payload).await.map_err(SubxtError::Other)?)
+    }
+
+    /// Submit a remark with the given nonce, without waiting for it to be included.
+    async fn fill_nonce(&self, signer: &InterBtcSigner, nonce: Index) -> Result<(), Error> {
+        let mut signer = signer.clone();
+        signer.set_nonce(nonce);
+        let remark = self.ext_client.encode(RemarkCall {
+            _runtime: PhantomData {},
+            remark: vec![],
+        })?;
+        let extrinsic = self.create_mortal_extrinsic(&signer, remark).await?;
+        self.ext_client.submit_extrinsic(extrinsic).await?;
+        crate::metrics::NONCE_GAPS_FILLED.inc();
+        Ok(())
+    }
+
+    /// Fill the nonces below `nonce` that are neither used nor pending, since the extrinsic
+    /// with `nonce` cannot be included before them.
+    async fn fill_nonce_gaps(&self, signer: &InterBtcSigner, nonce: Index) -> Result<(), Error> {
+        let account_nonce = self.get_account_nonce().await?;
+        if account_nonce >= nonce {
+            return Ok(());
+        }
+        let current_block = self.get_current_chain_height().await?;
+        let gaps = self.pending_extrinsics.lock().await.take_gaps(
+            account_nonce,
+            nonce,
+            current_block,
+            current_block + EXTRINSIC_MORTALITY as BlockNumber,
+        );
+        for gap in gaps {
+            log::warn!("Filling nonce {} that stalls the extrinsic with nonce {}", gap, nonce);
+            self.fill_nonce(signer, gap).await?;
+        }
+        Ok(())
+    }
+
+    async fn fill_nonce_gaps_periodically(&self, signer: &InterBtcSigner, nonce: Index) -> Infallible {
+        loop {
+            delay_for(STALL_CHECK_INTERVAL).await;
+            if let Err(err) = self.fill_nonce_gaps(signer, nonce).await {
+                log::warn!("Failed to check for nonce gaps: {}", err);
+            }
+        }
+    }
+
     pub async fn get_latest_block_hash(&self) -> Result<Option<H256>, Error> {
         Ok(Some(self.ext_client.finalized_head().await?))
     }
 
+    /// Get the hash of the block that storage queries read the state at.
+    async fn get_state_block_hash(&self) -> Result<Option<H256>, Error> {
+        match self.at_block {
+            Some(block_hash) => Ok(Some(block_hash)),
+            None => self.get_latest_block_hash().await,
+        }
+    }
+
+    /// Get the number of the block that storage queries read the state at.
+    async fn get_state_block_number(&self) -> Result<BlockNumber, Error> {
+        match self.at_block {
+            Some(block_hash) => Ok(self
+                .ext_client
+                .header(Some(block_hash))
+                .await?
+                .ok_or(Error::BlockNotFound)?
+                .number),
+            None => self.get_finalized_block_number().await,
+        }
+    }
+
+    /// Get the hash of the finalized block with the given number.
+    async fn get_block_hash_by_number(&self, number: BlockNumber) -> Result<H256, Error> {
+        let hash: Option<H256> = self
+            .rpc_client
+            .request("chain_getBlockHash", &[to_json_value(number)?])
+            .await?;
+        hash.ok_or(Error::BlockNotFound)
+    }
+
+    /// Get the hash of the genesis block, which identifies the chain.
+    pub async fn get_genesis_hash(&self) -> Result<H256, Error> {
+        self.get_block_hash_by_number(0).await
+    }
+
+    /// Check that the endpoint serves the chain with the given genesis hash and, if `max_head_age`
+    /// is set, that its latest finalized block was produced at most that long ago.
+    pub async fn check_health(&self, genesis_hash: Option<H256>, max_head_age: Option<Duration>) -> Result<(), Error> {
+        check_endpoint_health(&self.rpc_client, genesis_hash, max_head_age).await
+    }
+
+    /// Check that the calls, events and storage items used by the clients exist in the runtime
+    /// of the latest finalized block, with the shape they are declared with in this crate.
+    pub async fn check_compatibility(&self) -> Result<CompatibilityReport, Error> {
+        let head = self.get_latest_block_hash().await?;
+        let version: RuntimeVersion = self
+            .rpc_client
+            .request("state_getRuntimeVersion", &[to_json_value(head)?])
+            .await?;
+        let metadata: Bytes = self
+            .rpc_client
+            .request("state_getMetadata", &[to_json_value(head)?])
+            .await?;
+        Ok(CompatibilityReport::new(version, &RuntimeShape::decode(&metadata.0)?))
+    }
+
+    /// Wait until the runtime code is updated in a finalized block, and check the compatibility
+    /// of the new runtime. Since this connection still encodes extrinsics and decodes events with
+    /// the metadata and runtime version of the old runtime, it should be reopened afterwards.
+    pub async fn wait_for_runtime_upgrade(&self) -> Result<CompatibilityReport, Error> {
+        // ignore the replayed events, the upgrades in there are already in effect
+        let (_, mut receiver) = self.subscribe_raw_events(CODE_UPDATED_EVENT).await?;
+        while let Some((raw, _)) = receiver.next().await {
+            if let Raw::Event(event) = raw.as_ref() {
+                if event.module == SYSTEM_MODULE && event.variant == CODE_UPDATED_EVENT {
+                    let report = self.check_compatibility().await?;
+                    log::info!("Runtime upgraded, {}", report);
+                    return Ok(report);
+                }
+            }
+        }
+        Err(Error::ChannelClosed)
+    }
+
     pub async fn get_latest_block(&self) -> Result<Option<InterBtcBlock>, Error> {
         let head = self.get_latest_block_hash().await?;
         Ok(self.ext_client.block::<H256>(head).await?)
@@ -149,24 +730,240 @@ impl InterBtcParachain {
     }
 
     /// Subscription service that should listen forever, only returns if the initial subscription
-    /// cannot be established. Calls `on_error` when an error event has been received, or when an
-    /// event has been received that failed to be decoded into a raw event.
+    /// cannot be established. Calls `on_error` when an error event has been received.
     ///
     /// # Arguments
     /// * `on_error` - callback for decoding errors, is not allowed to take too long
     pub async fn on_event_error<E: Fn(SubxtError)>(&self, on_error: E) -> Result<(), Error> {
-        let sub = self.ext_client.subscribe_finalized_events().await?;
+        let stream = self.raw_event_stream("Error").await?;
+        futures::pin_mut!(stream);
+        while let Some((raw, _)) = stream.next().await {
+            if let Raw::Error(err) = raw.as_ref() {
+                on_error(SubxtError::Runtime(err.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch the events of type `T` that were emitted in the finalized blocks after `from` up to
+    /// and including `to`, together with the number of their block.
+    pub async fn get_events_in_range<T: Event<InterBtcRuntime>>(
+        &self,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Result<Vec<(BlockNumber, T)>, Error> {
         let decoder =
             EventsDecoder::<InterBtcRuntime>::new(self.ext_client.metadata().clone(), EventTypeRegistry::new());
+        let mut events = Vec::new();
+        for number in from.saturating_add(1)..=to {
+            for raw in self.get_block_events(number, &decoder).await? {
+                match raw {
+                    Raw::Event(raw_event) if raw_event.module == T::MODULE && raw_event.variant == T::EVENT => {
+                        events.push((number, T::decode(&mut &raw_event.data[..])?));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(events)
+    }
 
-        let mut sub = EventSubscription::<InterBtcRuntime>::new(sub, &decoder);
-        loop {
-            match sub.next().await {
-                Some(Err(err)) => on_error(err), // report error
-                Some(Ok(_)) => {}                // do nothing
-                None => break Ok(()),            // end of stream
+    /// Fetch the events that were emitted in the finalized block with the given number.
+    async fn get_block_events(
+        &self,
+        number: BlockNumber,
+        decoder: &EventsDecoder<InterBtcRuntime>,
+    ) -> Result<Vec<Raw>, Error> {
+        let hash = self.get_block_hash_by_number(number).await?;
+        Ok(self
+            .get_events_at(hash, decoder)
+            .await?
+            .into_iter()
+            .map(|(_, raw)| raw)
+            .collect())
+    }
+
+    /// Fetch the events that were emitted in the block with the given hash, with their phase.
+    async fn get_events_at(
+        &self,
+        hash: H256,
+        decoder: &EventsDecoder<InterBtcRuntime>,
+    ) -> Result<Vec<(Phase, Raw)>, Error> {
+        let mut key = sp_core::twox_128(b"System").to_vec();
+        key.extend(sp_core::twox_128(b"Events").iter());
+        let data: Option<StorageData> = self
+            .rpc_client
+            .request(
+                "state_getStorage",
+                &[to_json_value(StorageKey(key))?, to_json_value(hash)?],
+            )
+            .await?;
+
+        match data {
+            Some(data) => Ok(decoder.decode_events(&mut &data.0[..])?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Get the replayed events and a receiver for the raw events in finalized blocks. All handles
+    /// on the same connection share a single subscription, which is started on first use and
+    /// closes all receivers when it ends. The subscription waits for receivers that are full, so
+    /// every receiver should be consumed, or dropped once it is no longer needed. The event
+    /// checkpoint only advances past a block once every listener has dropped its events. Live
+    /// blocks are only dispatched, and the replayed events only dropped, once the events are no
+    /// longer held (see `hold_events`), which releases the hold of this handle. The `listener`
+    /// names the receiver when it lags behind.
+    async fn subscribe_raw_events(
+        &self,
+        listener: &'static str,
+    ) -> Result<(RawEvents, mpsc::Receiver<AckedEvent>), Error> {
+        let mut event_hub = self.event_hub.lock().await;
+        if let Some(hub) = event_hub.as_mut() {
+            let (sender, receiver) = mpsc::channel(EVENT_BUFFER_SIZE);
+            hub.subscribers.push((listener, sender));
+            self.event_hold.lock().unwrap().take();
+            return Ok((hub.backlog.clone(), receiver));
+        }
+
+        let decoder =
+            EventsDecoder::<InterBtcRuntime>::new(self.ext_client.metadata().clone(), EventTypeRegistry::new());
+        let mut blocks = self.ext_client.subscribe_finalized_blocks().await?;
+        let mut last_block = self.get_current_chain_height().await?;
+        let checkpoint_block = self.event_checkpoint.as_ref().map(|x| x.load()).transpose()?.flatten();
+        let tracker = CheckpointTracker::new(self.event_checkpoint.clone(), checkpoint_block.unwrap_or(last_block));
+
+        // replay the events of the blocks that were finalized while we were offline
+        let mut backlog = Vec::new();
+        if let Some(checkpoint_block) = checkpoint_block {
+            log::info!("Replaying events of blocks {} to {}", checkpoint_block + 1, last_block);
+            for number in checkpoint_block.saturating_add(1)..=last_block {
+                match self.get_block_events(number, &decoder).await {
+                    Ok(events) => {
+                        let ack = CheckpointTracker::dispatch(&tracker, number);
+                        backlog.extend(events.into_iter().map(|event| (Arc::new(event), ack.clone())));
+                    }
+                    Err(err) => {
+                        // e.g. the state of the block has been pruned
+                        log::warn!("Failed to replay events of block {}: {}", number, err);
+                        break;
+                    }
+                }
             }
         }
+
+        let (sender, receiver) = mpsc::channel(EVENT_BUFFER_SIZE);
+        let backlog = Arc::new(backlog);
+        *event_hub = Some(EventHub {
+            subscribers: vec![(listener, sender)],
+            backlog: backlog.clone(),
+        });
+        self.event_hold.lock().unwrap().take();
+
+        let parachain_rpc = self.clone();
+        tokio::spawn(async move {
+            'blocks: while let Some(header) = blocks.next().await {
+                // wait for the listeners that are still being set up
+                parachain_rpc.event_gate.released().await;
+                // fetch the events of each block, in case a notification has been skipped
+                for number in last_block.saturating_add(1)..=header.number {
+                    match parachain_rpc.get_block_events(number, &decoder).await {
+                        Ok(events) => {
+                            let mut subscribers = match parachain_rpc.event_hub.lock().await.as_mut() {
+                                Some(hub) => {
+                                    // listeners that subscribe from now on start with live blocks
+                                    hub.backlog = Default::default();
+                                    hub.subscribers.retain(|(_, subscriber)| !subscriber.is_closed());
+                                    hub.subscribers.clone()
+                                }
+                                None => break 'blocks,
+                            };
+                            let ack = CheckpointTracker::dispatch(&tracker, number);
+                            for event in events.into_iter().map(Arc::new) {
+                                for (listener, subscriber) in subscribers.iter_mut() {
+                                    match subscriber.try_send((event.clone(), ack.clone())) {
+                                        Err(err) if err.is_full() => {
+                                            log::warn!(
+                                                "Listener for {} is {} events behind, waiting for it",
+                                                listener,
+                                                EVENT_BUFFER_SIZE
+                                            );
+                                            crate::metrics::EVENT_LISTENER_LAG.with_label_values(&[*listener]).inc();
+                                            // waits while the buffer of the listener is full, only
+                                            // fails if the listener has been dropped
+                                            let _ = subscriber.send(err.into_inner()).await;
+                                        }
+                                        // sent, or the listener has been dropped
+                                        _ => {}
+                                    }
+                                }
+                            }
+                        }
+                        Err(err) => {
+                            log::error!("Failed to get events of block {}: {}", number, err);
+                            break 'blocks;
+                        }
+                    }
+                    last_block = number;
+                }
+            }
+            // drop all senders so that the listeners are notified
+            parachain_rpc.event_hub.lock().await.take();
+        });
+
+        Ok((backlog, receiver))
+    }
+
+    /// Stream of all decoded events and error events, starting with the replayed ones.
+    async fn raw_event_stream(&self, listener: &'static str) -> Result<impl Stream<Item = AckedEvent>, Error> {
+        let (backlog, receiver) = self.subscribe_raw_events(listener).await?;
+        let replayed = stream::iter(backlog.iter().cloned().collect::<Vec<_>>());
+        Ok(replayed.chain(receiver))
+    }
+
+    /// Stream of the events of type `T` in finalized blocks. All streams on the same connection
+    /// share a single subscription, so events are only fetched and decoded into raw events once.
+    /// Events of blocks finalized since the event checkpoint are replayed first.
+    ///
+    /// Every listener buffers up to `EVENT_BUFFER_SIZE` events; if it falls further behind, the
+    /// subscription waits for it, which holds up all other listeners on the connection.
+    /// Undecodable events yield `Error::CodecError`. The stream ends when the subscription is
+    /// closed. To listen to multiple event types, merge the streams, e.g.
+    ///
+    /// ```ignore
+    /// let requests = parachain_rpc.event_stream::<RequestIssueEvent<_>>().await?.map_ok(Either::Left);
+    /// let executes = parachain_rpc.event_stream::<ExecuteIssueEvent<_>>().await?.map_ok(Either::Right);
+    /// let events = futures::stream::select(requests, executes);
+    /// ```
+    ///
+    /// An event counts as processed for the event checkpoint once the next one is requested.
+    pub async fn event_stream<T: Event<InterBtcRuntime>>(&self) -> Result<impl Stream<Item = Result<T, Error>>, Error> {
+        let events = self.acked_event_stream::<T>().await?;
+        Ok(stream::unfold(
+            (Box::pin(events), None),
+            |(mut events, _processed): (_, Option<Arc<BlockAck>>)| async move {
+                let (event, ack) = events.next().await?;
+                Some((event, (events, Some(ack))))
+            },
+        ))
+    }
+
+    /// Like `event_stream`, but yields the acknowledgement of every event, to be dropped once
+    /// the event has been processed.
+    async fn acked_event_stream<T: Event<InterBtcRuntime>>(
+        &self,
+    ) -> Result<impl Stream<Item = (Result<T, Error>, Arc<BlockAck>)>, Error> {
+        Ok(self
+            .raw_event_stream(T::EVENT)
+            .await?
+            .filter_map(|(raw, ack)| async move {
+                match raw.as_ref() {
+                    Raw::Event(raw_event) if raw_event.module == T::MODULE && raw_event.variant == T::EVENT => {
+                        log::trace!("raw event: {:?}", raw_event);
+                        Some((T::decode(&mut &raw_event.data[..]).map_err(Into::into), ack))
+                    }
+                    _ => None,
+                }
+            }))
     }
 
     /// Subscription service that should listen forever, only returns if the initial subscription
@@ -187,36 +984,38 @@ impl InterBtcParachain {
         R: Future<Output = ()>,
         E: Fn(SubxtError),
     {
-        let sub = self.ext_client.subscribe_finalized_events().await?;
-        let decoder =
-            EventsDecoder::<InterBtcRuntime>::new(self.ext_client.metadata().clone(), EventTypeRegistry::new());
+        self.on_event_at(move |event, _| on_event(event), on_error).await
+    }
 
-        let mut sub = EventSubscription::<InterBtcRuntime>::new(sub, &decoder);
-        sub.filter_event::<T>();
+    /// Like `on_event`, but also passes the number of the block that emitted the event.
+    pub async fn on_event_at<T, F, R, E>(&self, mut on_event: F, on_error: E) -> Result<(), Error>
+    where
+        T: Event<InterBtcRuntime> + core::fmt::Debug,
+        F: FnMut(T, BlockNumber) -> R,
+        R: Future<Output = ()>,
+        E: Fn(SubxtError),
+    {
+        let stream = self.acked_event_stream::<T>().await?;
 
-        let (tx, mut rx) = futures::channel::mpsc::channel::<T>(32);
+        let (tx, mut rx) = futures::channel::mpsc::channel::<(T, Arc<BlockAck>)>(32);
 
         // two tasks: one for event listening and one for callback calling
         futures::future::try_join(
             async move {
                 let tx = &tx;
-                while let Some(result) = sub.next().fuse().await {
-                    if let Ok(raw_event) = result {
-                        log::trace!("raw event: {:?}", raw_event);
-                        let decoded = T::decode(&mut &raw_event.data[..]);
-                        match decoded {
-                            Ok(event) => {
-                                log::trace!("decoded event: {:?}", event);
-                                // send the event to the other task
-                                if tx.clone().send(event).await.is_err() {
-                                    break;
-                                }
+                futures::pin_mut!(stream);
+                while let Some((result, ack)) = stream.next().await {
+                    match result {
+                        Ok(event) => {
+                            log::trace!("decoded event: {:?}", event);
+                            // send the event to the other task
+                            if tx.clone().send((event, ack)).await.is_err() {
+                                break;
                             }
-                            Err(err) => {
-                                on_error(err.into());
-                            }
-                        };
-                    }
+                        }
+                        Err(Error::CodecError(err)) => on_error(err.into()),
+                        Err(err) => log::warn!("Listener for {}: {}", T::EVENT, err),
+                    };
                 }
                 Result::<(), _>::Err(Error::ChannelClosed)
             },
@@ -224,8 +1023,9 @@ impl InterBtcParachain {
                 loop {
                     // block until we receive an event from the other task
                     match rx.next().fuse().await {
-                        Some(event) => {
-                            on_event(event).await;
+                        Some((event, ack)) => {
+                            // the event counts as processed once the callback has completed
+                            on_event(event, ack.number()).await;
                         }
                         None => {
                             return Result::<(), _>::Err(Error::ChannelClosed);
@@ -240,19 +1040,23 @@ impl InterBtcParachain {
     }
 
     async fn sudo<C: Call<InterBtcRuntime> + Clone>(&self, call: C) -> Result<(), Error> {
-        let encoded_call = &self.ext_client.encode(call.clone())?;
-        self.with_unique_signer(|signer| async move { self.ext_client.sudo_and_watch(&signer, encoded_call).await })
-            .await?;
+        let encoded_call = &self.ext_client.encode(call)?;
+        self.submit(SudoCall {
+            _runtime: PhantomData {},
+            call: encoded_call,
+        })
+        .await?;
         Ok(())
     }
 
     async fn batch<C: Call<InterBtcRuntime>>(&self, calls: Vec<C>) -> Result<(), Error> {
-        let encoded_calls = &calls
+        let encoded_calls = calls
             .into_iter()
             .map(|call| self.ext_client.encode(call))
             .collect::<Result<Vec<_>, _>>()?;
-        self.with_unique_signer(|signer| async move {
-            self.ext_client.batch_and_watch(&signer, encoded_calls.clone()).await
+        self.submit(BatchCall {
+            _runtime: PhantomData {},
+            calls: encoded_calls,
         })
         .await?;
         Ok(())
@@ -285,6 +1089,16 @@ impl InterBtcParachain {
     }
 }
 
+/// Account the estimated fee of a call, if it was charged.
+fn record_fee<T>(result: &Result<T, Error>, fee: u128, call_name: &str) {
+    if result.is_ok() || matches!(result, Err(err) if err.module_error().is_some()) {
+        log::debug!("Paid an estimated fee of {} for {}", fee, call_name);
+        crate::metrics::EXTRINSIC_FEES
+            .with_label_values(&[call_name])
+            .inc_by(fee.min(u64::MAX as u128) as u64);
+    }
+}
+
 #[async_trait]
 pub trait UtilFuncs {
     /// Gets the current height of the parachain
@@ -297,12 +1111,8 @@ pub trait UtilFuncs {
 #[async_trait]
 impl UtilFuncs for InterBtcParachain {
     async fn get_current_chain_height(&self) -> Result<u32, Error> {
-        let head = self.get_latest_block_hash().await?;
-        let query_result = self.ext_client.block(head).await?;
-        match query_result {
-            Some(x) => Ok(x.block.header.number),
-            None => Err(Error::BlockNotFound),
-        }
+        // the height is that of the latest finalized block, even if the state is pinned
+        self.get_finalized_block_number().await
     }
 
     fn get_account_id(&self) -> &AccountId {
@@ -314,6 +1124,9 @@ impl UtilFuncs for InterBtcParachain {
 pub trait CollateralBalancesPallet {
     async fn get_free_balance(&self) -> Result<<InterBtcRuntime as Core>::Balance, Error>;
 
+    /// Get the free balance that can be spent without touching the fee reserve.
+    async fn get_spendable_balance(&self) -> Result<<InterBtcRuntime as Core>::Balance, Error>;
+
     async fn get_free_balance_for_id(&self, id: AccountId) -> Result<<InterBtcRuntime as Core>::Balance, Error>;
 
     async fn get_reserved_balance(&self) -> Result<<InterBtcRuntime as Core>::Balance, Error>;
@@ -329,8 +1142,12 @@ impl CollateralBalancesPallet for InterBtcParachain {
         Ok(Self::get_free_balance_for_id(&self, self.account_id.clone()).await?)
     }
 
+    async fn get_spendable_balance(&self) -> Result<<InterBtcRuntime as Core>::Balance, Error> {
+        Ok(self.get_free_balance().await?.saturating_sub(self.fee_reserve))
+    }
+
     async fn get_free_balance_for_id(&self, id: AccountId) -> Result<<InterBtcRuntime as Core>::Balance, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.accounts(id.clone(), CurrencyId::DOT, head).await?.free)
     }
 
@@ -339,7 +1156,7 @@ impl CollateralBalancesPallet for InterBtcParachain {
     }
 
     async fn get_reserved_balance_for_id(&self, id: AccountId) -> Result<<InterBtcRuntime as Core>::Balance, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self
             .ext_client
             .accounts(id.clone(), CurrencyId::DOT, head)
@@ -348,10 +1165,10 @@ impl CollateralBalancesPallet for InterBtcParachain {
     }
 
     async fn transfer_to(&self, recipient: &AccountId, amount: u128) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .transfer_and_watch(&signer, &recipient, CurrencyId::DOT, amount)
-                .await
+        self.submit(TransferCall {
+            dest: recipient,
+            currency_id: CurrencyId::DOT,
+            amount,
         })
         .await?;
         Ok(())
@@ -442,20 +1259,16 @@ pub trait ReplacePallet {
 #[async_trait]
 impl ReplacePallet for InterBtcParachain {
     async fn request_replace(&self, amount: u128, griefing_collateral: u128) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .request_replace_and_watch(&signer, amount, griefing_collateral)
-                .await
+        self.submit(RequestReplaceCall {
+            btc_amount: amount,
+            griefing_collateral,
         })
         .await?;
         Ok(())
     }
 
     async fn withdraw_replace(&self, amount: u128) -> Result<(), Error> {
-        self.with_unique_signer(
-            |signer| async move { self.ext_client.withdraw_replace_and_watch(&signer, amount).await },
-        )
-        .await?;
+        self.submit(WithdrawReplaceCall { amount }).await?;
         Ok(())
     }
 
@@ -466,30 +1279,28 @@ impl ReplacePallet for InterBtcParachain {
         collateral: u128,
         btc_address: BtcAddress,
     ) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .accept_replace_and_watch(&signer, old_vault, amount_btc, collateral, btc_address)
-                .await
+        self.submit(AcceptReplaceCall {
+            old_vault,
+            amount_btc,
+            collateral,
+            btc_address,
         })
         .await?;
         Ok(())
     }
 
     async fn execute_replace(&self, replace_id: H256, merkle_proof: &[u8], raw_tx: &[u8]) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .execute_replace_and_watch(&signer, replace_id, merkle_proof, raw_tx)
-                .await
+        self.submit(ExecuteReplaceCall {
+            replace_id,
+            merkle_proof,
+            raw_tx,
         })
         .await?;
         Ok(())
     }
 
     async fn cancel_replace(&self, replace_id: H256) -> Result<(), Error> {
-        self.with_unique_signer(
-            |signer| async move { self.ext_client.cancel_replace_and_watch(&signer, replace_id).await },
-        )
-        .await?;
+        self.submit(CancelReplaceCall { replace_id }).await?;
         Ok(())
     }
 
@@ -498,7 +1309,7 @@ impl ReplacePallet for InterBtcParachain {
         &self,
         account_id: AccountId,
     ) -> Result<Vec<(H256, InterBtcReplaceRequest)>, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let result: Vec<(H256, InterBtcReplaceRequest)> = self
             .rpc_client
             .request(
@@ -515,7 +1326,7 @@ impl ReplacePallet for InterBtcParachain {
         &self,
         account_id: AccountId,
     ) -> Result<Vec<(H256, InterBtcReplaceRequest)>, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let result: Vec<(H256, InterBtcReplaceRequest)> = self
             .rpc_client
             .request(
@@ -528,7 +1339,7 @@ impl ReplacePallet for InterBtcParachain {
     }
 
     async fn get_replace_period(&self) -> Result<u32, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.replace_period(head).await?)
     }
 
@@ -542,12 +1353,12 @@ impl ReplacePallet for InterBtcParachain {
     }
 
     async fn get_replace_request(&self, replace_id: H256) -> Result<InterBtcReplaceRequest, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.replace_requests(replace_id, head).await?)
     }
 
     async fn get_replace_dust_amount(&self) -> Result<u128, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.replace_btc_dust_value(head).await?)
     }
 }
@@ -561,7 +1372,7 @@ pub trait TimestampPallet {
 impl TimestampPallet for InterBtcParachain {
     /// Get the current time as defined by the `timestamp` pallet.
     async fn get_time_now(&self) -> Result<u64, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.now(head).await?)
     }
 }
@@ -588,7 +1399,7 @@ impl ExchangeRateOraclePallet for InterBtcParachain {
     /// Returns the last exchange rate in planck per satoshis, the time at which it was set
     /// and the configured max delay.
     async fn get_exchange_rate_info(&self) -> Result<(FixedU128, u64, u64), Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let get_rate = self.ext_client.exchange_rate(head);
         let get_time = self.ext_client.last_exchange_rate_time(head);
         let get_delay = self.ext_client.max_delay(head);
@@ -604,10 +1415,9 @@ impl ExchangeRateOraclePallet for InterBtcParachain {
     /// # Arguments
     /// * `collateral_per_wrapped` - the current exchange rate
     async fn set_exchange_rate_info(&self, collateral_per_wrapped: FixedU128) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .set_exchange_rate_and_watch(&signer, collateral_per_wrapped)
-                .await
+        self.submit(SetExchangeRateCall {
+            rate: collateral_per_wrapped,
+            _runtime: PhantomData {},
         })
         .await?;
         Ok(())
@@ -636,10 +1446,11 @@ impl ExchangeRateOraclePallet for InterBtcParachain {
     /// * `half` - The estimated Satoshis per bytes to get included in the next 3 blocks (~half hour)
     /// * `hour` - The estimated Satoshis per bytes to get included in the next 6 blocks (~hour)
     async fn set_btc_tx_fees_per_byte(&self, fast: u32, half: u32, hour: u32) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .set_btc_tx_fees_per_byte_and_watch(&signer, fast, half, hour)
-                .await
+        self.submit(SetBtcTxFeesPerByteCall {
+            fast,
+            half,
+            hour,
+            _runtime: PhantomData {},
         })
         .await?;
         Ok(())
@@ -648,13 +1459,13 @@ impl ExchangeRateOraclePallet for InterBtcParachain {
     /// Gets the estimated Satoshis per bytes required to get a Bitcoin transaction included in
     /// in the next x blocks
     async fn get_btc_tx_fees_per_byte(&self) -> Result<BtcTxFeesPerByte, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.satoshi_per_bytes(head).await?)
     }
 
     /// Converts the amount in btc to dot, based on the current set exchange rate.
     async fn wrapped_to_collateral(&self, amount: u128) -> Result<u128, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let result: BalanceWrapper<_> = self
             .rpc_client
             .request(
@@ -668,7 +1479,7 @@ impl ExchangeRateOraclePallet for InterBtcParachain {
 
     /// Converts the amount in dot to btc, based on the current set exchange rate.
     async fn collateral_to_wrapped(&self, amount: u128) -> Result<u128, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let result: BalanceWrapper<_> = self
             .rpc_client
             .request(
@@ -692,6 +1503,8 @@ pub trait RelayPallet {
     async fn store_block_header(&self, header: RawBlockHeader) -> Result<(), Error>;
 
     async fn store_block_headers(&self, headers: Vec<RawBlockHeader>) -> Result<(), Error>;
+
+    async fn store_block_headers_pipelined(&self, headers: Vec<RawBlockHeader>) -> Result<PendingSubmission, Error>;
 }
 
 #[async_trait]
@@ -705,10 +1518,10 @@ impl RelayPallet for InterBtcParachain {
     /// * `merkle_proof` - merkle proof to verify inclusion
     /// * `raw_tx` - raw transaction
     async fn report_vault_theft(&self, vault_id: &AccountId, merkle_proof: &[u8], raw_tx: &[u8]) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .report_vault_theft_and_watch(&signer, vault_id, merkle_proof, raw_tx)
-                .await
+        self.submit(ReportVaultTheftCall {
+            vault_id,
+            merkle_proof,
+            raw_tx,
         })
         .await?;
         Ok(())
@@ -725,7 +1538,7 @@ impl RelayPallet for InterBtcParachain {
     /// * `vault_id` - vault account which features in vin
     /// * `raw_tx` - raw Bitcoin transaction
     async fn is_transaction_invalid(&self, vault_id: &AccountId, raw_tx: &[u8]) -> Result<bool, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(matches!(
             self.rpc_client
                 .request(
@@ -747,9 +1560,11 @@ impl RelayPallet for InterBtcParachain {
     async fn initialize_btc_relay(&self, header: RawBlockHeader, height: BitcoinBlockHeight) -> Result<(), Error> {
         // TODO: can we initialize the relay through the chain-spec?
         // we would also need to consider re-initialization per governance
-        self.with_unique_signer(
-            |signer| async move { self.ext_client.initialize_and_watch(&signer, header, height).await },
-        )
+        self.submit(InitializeCall {
+            _runtime: PhantomData {},
+            raw_block_header: header,
+            block_height: height,
+        })
         .await?;
         Ok(())
     }
@@ -759,9 +1574,10 @@ impl RelayPallet for InterBtcParachain {
     /// # Arguments
     /// * `header` - raw block header
     async fn store_block_header(&self, header: RawBlockHeader) -> Result<(), Error> {
-        self.with_unique_signer(
-            |signer| async move { self.ext_client.store_block_header_and_watch(&signer, header).await },
-        )
+        self.submit(StoreBlockHeaderCall {
+            _runtime: PhantomData {},
+            raw_block_header: header,
+        })
         .await?;
         Ok(())
     }
@@ -782,6 +1598,28 @@ impl RelayPallet for InterBtcParachain {
         )
         .await
     }
+
+    /// Stores multiple block headers in the BTC-Relay without waiting for them to be
+    /// finalized. Batches stored this way are included in the order of the calls.
+    ///
+    /// # Arguments
+    /// * `headers` - raw block headers
+    async fn store_block_headers_pipelined(&self, headers: Vec<RawBlockHeader>) -> Result<PendingSubmission, Error> {
+        let calls = headers
+            .into_iter()
+            .map(|header| {
+                self.ext_client.encode(StoreBlockHeaderCall {
+                    _runtime: PhantomData {},
+                    raw_block_header: header,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.submit_pipelined(BatchCall {
+            _runtime: PhantomData {},
+            calls,
+        })
+        .await
+    }
 }
 
 #[async_trait]
@@ -799,18 +1637,18 @@ impl SecurityPallet for InterBtcParachain {
     /// Get the current security status of the parachain.
     /// Should be one of; `Running`, `Error` or `Shutdown`.
     async fn get_parachain_status(&self) -> Result<StatusCode, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.parachain_status(head).await?)
     }
     /// Return any `ErrorCode`s set in the security module.
     async fn get_error_codes(&self) -> Result<BTreeSet<ErrorCode>, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.errors(head).await?)
     }
 
     /// Gets the current active block number of the parachain
     async fn get_current_active_block_number(&self) -> Result<u32, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.active_block_count(head).await?)
     }
 }
@@ -852,35 +1690,37 @@ impl IssuePallet for InterBtcParachain {
         griefing_collateral: u128,
     ) -> Result<InterBtcRequestIssueEvent, Error> {
         let result = self
-            .with_unique_signer(|signer| async move {
-                self.ext_client
-                    .request_issue_and_watch(&signer, amount, vault_id, griefing_collateral)
-                    .await
+            .submit(RequestIssueCall {
+                amount,
+                vault_id,
+                griefing_collateral,
             })
             .await?;
         result.request_issue()?.ok_or(Error::RequestIssueIDNotFound)
     }
 
     async fn execute_issue(&self, issue_id: H256, merkle_proof: &[u8], raw_tx: &[u8]) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .execute_issue_and_watch(&signer, issue_id, merkle_proof, raw_tx)
-                .await
+        self.submit(ExecuteIssueCall {
+            issue_id,
+            merkle_proof,
+            raw_tx,
+            _runtime: PhantomData {},
         })
         .await?;
         Ok(())
     }
 
     async fn cancel_issue(&self, issue_id: H256) -> Result<(), Error> {
-        self.with_unique_signer(
-            |signer| async move { self.ext_client.cancel_issue_and_watch(&signer, issue_id).await },
-        )
+        self.submit(CancelIssueCall {
+            issue_id,
+            _runtime: PhantomData {},
+        })
         .await?;
         Ok(())
     }
 
     async fn get_issue_request(&self, issue_id: H256) -> Result<InterBtcIssueRequest, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.issue_requests(issue_id, head).await?)
     }
 
@@ -888,7 +1728,7 @@ impl IssuePallet for InterBtcParachain {
         &self,
         account_id: AccountId,
     ) -> Result<Vec<(H256, InterBtcIssueRequest)>, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let result: Vec<(H256, InterBtcIssueRequest)> = self
             .rpc_client
             .request(
@@ -901,7 +1741,7 @@ impl IssuePallet for InterBtcParachain {
     }
 
     async fn get_issue_period(&self) -> Result<u32, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.issue_period(head).await?)
     }
 
@@ -915,11 +1755,11 @@ impl IssuePallet for InterBtcParachain {
     }
 
     async fn get_all_active_issues(&self) -> Result<Vec<(H256, InterBtcIssueRequest)>, Error> {
-        let current_height = self.get_current_chain_height().await?;
+        let current_height = self.get_state_block_number().await?;
         let issue_period = self.get_issue_period().await?;
 
         let mut issue_requests = Vec::new();
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let mut iter = self.ext_client.issue_requests_iter(head).await?;
         while let Some((issue_id, request)) = iter.next().await? {
             if request.status == IssueRequestStatus::Pending && request.opentime + issue_period > current_height {
@@ -961,10 +1801,10 @@ pub trait RedeemPallet {
 impl RedeemPallet for InterBtcParachain {
     async fn request_redeem(&self, amount: u128, btc_address: BtcAddress, vault_id: &AccountId) -> Result<H256, Error> {
         let result = self
-            .with_unique_signer(|signer| async move {
-                self.ext_client
-                    .request_redeem_and_watch(&signer, amount, btc_address, vault_id)
-                    .await
+            .submit(RequestRedeemCall {
+                amount,
+                btc_address,
+                vault_id,
             })
             .await?;
         if let Some(event) = result.request_redeem()? {
@@ -975,27 +1815,28 @@ impl RedeemPallet for InterBtcParachain {
     }
 
     async fn execute_redeem(&self, redeem_id: H256, merkle_proof: &[u8], raw_tx: &[u8]) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .execute_redeem_and_watch(&signer, redeem_id, merkle_proof, raw_tx)
-                .await
+        self.submit(ExecuteRedeemCall {
+            redeem_id,
+            merkle_proof,
+            raw_tx,
+            _runtime: PhantomData {},
         })
         .await?;
         Ok(())
     }
 
     async fn cancel_redeem(&self, redeem_id: H256, reimburse: bool) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .cancel_redeem_and_watch(&signer, redeem_id, reimburse)
-                .await
+        self.submit(CancelRedeemCall {
+            redeem_id,
+            reimburse,
+            _runtime: PhantomData {},
         })
         .await?;
         Ok(())
     }
 
     async fn get_redeem_request(&self, redeem_id: H256) -> Result<InterBtcRedeemRequest, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.redeem_requests(redeem_id, head).await?)
     }
 
@@ -1003,7 +1844,7 @@ impl RedeemPallet for InterBtcParachain {
         &self,
         account_id: AccountId,
     ) -> Result<Vec<(H256, InterBtcRedeemRequest)>, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let requests: Vec<(H256, InterBtcRedeemRequest)> = self
             .rpc_client
             .request(
@@ -1016,7 +1857,7 @@ impl RedeemPallet for InterBtcParachain {
     }
 
     async fn get_redeem_period(&self) -> Result<BlockNumber, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.redeem_period(head).await?)
     }
 
@@ -1045,10 +1886,11 @@ pub trait RefundPallet {
 #[async_trait]
 impl RefundPallet for InterBtcParachain {
     async fn execute_refund(&self, refund_id: H256, merkle_proof: &[u8], raw_tx: &[u8]) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .execute_refund_and_watch(&signer, refund_id, merkle_proof, raw_tx)
-                .await
+        self.submit(ExecuteRefundCall {
+            refund_id,
+            merkle_proof,
+            raw_tx,
+            _runtime: PhantomData {},
         })
         .await?;
         Ok(())
@@ -1058,7 +1900,7 @@ impl RefundPallet for InterBtcParachain {
         &self,
         account_id: AccountId,
     ) -> Result<Vec<(H256, InterBtcRefundRequest)>, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let result: Vec<(H256, InterBtcRefundRequest)> = self
             .rpc_client
             .request(
@@ -1104,13 +1946,13 @@ pub trait BtcRelayPallet {
 impl BtcRelayPallet for InterBtcParachain {
     /// Get the hash of the current best tip.
     async fn get_best_block(&self) -> Result<H256Le, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.best_block(head).await?)
     }
 
     /// Get the current best known height.
     async fn get_best_block_height(&self) -> Result<u32, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.best_block_height(head).await?)
     }
 
@@ -1119,7 +1961,7 @@ impl BtcRelayPallet for InterBtcParachain {
     /// # Arguments
     /// * `height` - chain height
     async fn get_block_hash(&self, height: u32) -> Result<H256Le, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.chains_hashes(0, height, head).await?)
     }
 
@@ -1128,13 +1970,13 @@ impl BtcRelayPallet for InterBtcParachain {
     /// # Arguments
     /// * `hash` - little endian block hash
     async fn get_block_header(&self, hash: H256Le) -> Result<InterBtcRichBlockHeader, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.block_headers(hash, head).await?)
     }
 
     /// Get the global security parameter k for stable Bitcoin transactions
     async fn get_bitcoin_confirmations(&self) -> Result<u32, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.stable_bitcoin_confirmations(head).await?)
     }
 
@@ -1146,7 +1988,7 @@ impl BtcRelayPallet for InterBtcParachain {
 
     /// Get the global security parameter for stable parachain confirmations
     async fn get_parachain_confirmations(&self) -> Result<BlockNumber, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.stable_parachain_confirmations(head).await?)
     }
 
@@ -1181,7 +2023,7 @@ impl BtcRelayPallet for InterBtcParachain {
     /// check that the block with the given block is included in the main chain of the relay, with sufficient
     /// confirmations
     async fn verify_block_header_inclusion(&self, block_hash: H256Le) -> Result<(), Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let result: Result<(), DispatchError> = self
             .rpc_client
             .request(
@@ -1234,7 +2076,7 @@ impl VaultRegistryPallet for InterBtcParachain {
     /// * `VaultLiquidated` - if the vault is liquidated
     /// * `VaultCommittedTheft` - if the vault is stole BTC
     async fn get_vault(&self, vault_id: AccountId) -> Result<InterBtcVault, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         match self.ext_client.vaults(vault_id.clone(), head).await {
             Ok(InterBtcVault {
                 status: VaultStatus::Liquidated,
@@ -1253,7 +2095,7 @@ impl VaultRegistryPallet for InterBtcParachain {
     /// Fetch all active vaults.
     async fn get_all_vaults(&self) -> Result<Vec<InterBtcVault>, Error> {
         let mut vaults = Vec::new();
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let mut iter = self.ext_client.vaults_iter(head).await?;
         while let Some((_, account)) = iter.next().await? {
             if let VaultStatus::Active(..) = account.status {
@@ -1269,13 +2111,7 @@ impl VaultRegistryPallet for InterBtcParachain {
     /// * `collateral` - deposit
     /// * `public_key` - Bitcoin public key
     async fn register_vault(&self, collateral: u128, public_key: BtcPublicKey) -> Result<(), Error> {
-        let public_key = &public_key.clone();
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .register_vault_and_watch(&signer, collateral, public_key.clone())
-                .await
-        })
-        .await?;
+        self.submit(RegisterVaultCall { collateral, public_key }).await?;
         Ok(())
     }
 
@@ -1285,10 +2121,7 @@ impl VaultRegistryPallet for InterBtcParachain {
     /// # Arguments
     /// * `amount` - the amount of extra collateral to lock
     async fn deposit_collateral(&self, amount: u128) -> Result<(), Error> {
-        self.with_unique_signer(
-            |signer| async move { self.ext_client.deposit_collateral_and_watch(&signer, amount).await },
-        )
-        .await?;
+        self.submit(DepositCollateralCall { amount }).await?;
         Ok(())
     }
 
@@ -1302,10 +2135,7 @@ impl VaultRegistryPallet for InterBtcParachain {
     /// # Arguments
     /// * `amount` - the amount of collateral to withdraw
     async fn withdraw_collateral(&self, amount: u128) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client.withdraw_collateral_and_watch(&signer, amount).await
-        })
-        .await?;
+        self.submit(WithdrawCollateralCall { amount }).await?;
         Ok(())
     }
 
@@ -1314,13 +2144,7 @@ impl VaultRegistryPallet for InterBtcParachain {
     /// # Arguments
     /// * `public_key` - the new public key of the vault
     async fn update_public_key(&self, public_key: BtcPublicKey) -> Result<(), Error> {
-        let public_key = &public_key.clone();
-        self.with_unique_signer(|signer| async move {
-            self.ext_client
-                .update_public_key_and_watch(&signer, public_key.clone())
-                .await
-        })
-        .await?;
+        self.submit(UpdatePublicKeyCall { public_key }).await?;
         Ok(())
     }
 
@@ -1329,10 +2153,7 @@ impl VaultRegistryPallet for InterBtcParachain {
     /// # Arguments
     /// * `btc_address` - the new btc address of the vault
     async fn register_address(&self, btc_address: BtcAddress) -> Result<(), Error> {
-        self.with_unique_signer(|signer| async move {
-            self.ext_client.register_address_and_watch(&signer, btc_address).await
-        })
-        .await?;
+        self.submit(RegisterAddressCall { btc_address }).await?;
         Ok(())
     }
 
@@ -1341,7 +2162,7 @@ impl VaultRegistryPallet for InterBtcParachain {
     /// # Arguments
     /// * `amount_btc` - amount of btc to cover
     async fn get_required_collateral_for_wrapped(&self, amount_btc: u128) -> Result<u128, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let result: BalanceWrapper<_> = self
             .rpc_client
             .request(
@@ -1359,7 +2180,7 @@ impl VaultRegistryPallet for InterBtcParachain {
     /// Get the amount of collateral required for the given vault to be at the
     /// current SecureCollateralThreshold with the current exchange rate
     async fn get_required_collateral_for_vault(&self, vault_id: AccountId) -> Result<u128, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let result: BalanceWrapper<_> = self
             .rpc_client
             .request(
@@ -1372,7 +2193,7 @@ impl VaultRegistryPallet for InterBtcParachain {
     }
 
     async fn get_vault_total_collateral(&self, vault_id: AccountId) -> Result<u128, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         let result: BalanceWrapper<_> = self
             .rpc_client
             .request(
@@ -1395,17 +2216,17 @@ pub trait FeePallet {
 #[async_trait]
 impl FeePallet for InterBtcParachain {
     async fn get_issue_griefing_collateral(&self) -> Result<FixedU128, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.issue_griefing_collateral(head).await?)
     }
 
     async fn get_issue_fee(&self) -> Result<FixedU128, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.issue_fee(head).await?)
     }
 
     async fn get_replace_griefing_collateral(&self) -> Result<FixedU128, Error> {
-        let head = self.get_latest_block_hash().await?;
+        let head = self.get_state_block_hash().await?;
         Ok(self.ext_client.replace_griefing_collateral(head).await?)
     }
 }
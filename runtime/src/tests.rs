@@ -2,9 +2,10 @@
 
 use super::{
     BtcAddress, BtcPublicKey, BtcRelayPallet, CollateralBalancesPallet, ExchangeRateOraclePallet, FixedPointNumber,
-    FixedU128, RelayPallet, ReplacePallet, SecurityPallet, StatusCode, VaultRegistryPallet,
+    FixedU128, RelayPallet, ReplacePallet, SecurityPallet, StatusCode, UtilFuncs, VaultRegistryPallet,
 };
 use crate::{exchange_rate_oracle::SetExchangeRateEvent, integration::*, InterBtcRuntime};
+use futures::StreamExt;
 use module_bitcoin::{
     formatter::TryFormattable,
     types::{BlockBuilder, RawBlockHeader},
@@ -50,6 +51,84 @@ async fn test_getters() {
     );
 }
 
+#[tokio::test]
+async fn test_pinned_getters() {
+    let (client, _tmp_dir) = default_provider_client(AccountKeyring::Alice).await;
+    let oracle_provider = setup_provider(client.clone(), AccountKeyring::Bob).await;
+    let old_rate = FixedU128::saturating_from_rational(1u128, 100u128);
+    let new_rate = FixedU128::saturating_from_rational(1u128, 50u128);
+
+    oracle_provider.set_exchange_rate_info(old_rate).await.unwrap();
+    let pinned_height = oracle_provider.get_current_chain_height().await.unwrap();
+    let pinned = oracle_provider.at_block_number(pinned_height).await.unwrap();
+    oracle_provider.set_exchange_rate_info(new_rate).await.unwrap();
+    let current_height = oracle_provider.get_current_chain_height().await.unwrap();
+    assert!(current_height > pinned_height);
+
+    // storage is read at the pinned block, but the chain height is the current one
+    assert_eq!(pinned.get_exchange_rate_info().await.unwrap().0, old_rate);
+    assert_eq!(oracle_provider.get_exchange_rate_info().await.unwrap().0, new_rate);
+    assert!(pinned.get_current_chain_height().await.unwrap() >= current_height);
+}
+
+#[tokio::test]
+async fn test_held_events_reach_late_listener() {
+    let (client, _tmp_dir) = default_provider_client(AccountKeyring::Alice).await;
+    let path = std::env::temp_dir().join(format!("event-checkpoint-{}", std::process::id()));
+    let checkpoint = crate::BlockCheckpoint::new(path.clone());
+    let parachain_rpc = setup_provider(client.clone(), AccountKeyring::Alice)
+        .await
+        .with_event_checkpoint(Some(checkpoint.clone()));
+    let oracle_provider = setup_provider(client, AccountKeyring::Bob).await;
+
+    // the upgrade watcher subscribes first, and drops every event right away
+    let hold = parachain_rpc.hold_events();
+    let upgrade_watcher = parachain_rpc.wait_for_runtime_upgrade();
+    futures::pin_mut!(upgrade_watcher);
+
+    let listener = async {
+        oracle_provider
+            .set_exchange_rate_info(FixedU128::saturating_from_rational(1u128, 100u128))
+            .await
+            .unwrap();
+        let height = oracle_provider.get_current_chain_height().await.unwrap();
+        tokio::time::delay_for(Duration::from_secs(2)).await;
+        assert!(checkpoint.load().unwrap().map_or(true, |stored| stored < height));
+
+        // the listener joins after the block has been finalized
+        let listener_rpc = parachain_rpc.holding_events();
+        drop(hold);
+        let mut events = Box::pin(
+            listener_rpc
+                .event_stream::<SetExchangeRateEvent<InterBtcRuntime>>()
+                .await
+                .unwrap(),
+        );
+        tokio::time::timeout(Duration::from_secs(30), events.next())
+            .await
+            .expect("the held event was not dispatched")
+            .unwrap()
+            .unwrap();
+        // the listener has not processed the event yet
+        assert!(checkpoint.load().unwrap().map_or(true, |stored| stored < height));
+        drop(events);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while checkpoint.load().unwrap().map_or(true, |stored| stored < height) {
+                tokio::time::delay_for(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("the checkpoint did not advance");
+    };
+    futures::pin_mut!(listener);
+
+    match futures::future::select(listener, upgrade_watcher).await {
+        futures::future::Either::Left(_) => {}
+        futures::future::Either::Right((result, _)) => panic!("upgrade watcher ended: {:?}", result.map(|_| ())),
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
 #[tokio::test]
 #[ignore]
 async fn test_outdated_nonce_matching() {
@@ -0,0 +1,161 @@
+//! Bookkeeping of the extrinsics of a signer that are submitted but not yet finalized.
+//!
+//! Extrinsics are signed with a mortal era, so an extrinsic that was dropped from the pool
+//! becomes invalid after a bounded number of blocks, after which it is resubmitted with the same
+//! nonce. Since the nonces of an account are used in order, a nonce that was allocated but never
+//! submitted stalls all extrinsics with higher nonces in the future queue of the pool. Such gaps
+//! are filled with remarks.
+
+use crate::{BlockNumber, Index, MILLISECS_PER_BLOCK};
+use sp_core::H256;
+use std::{collections::BTreeMap, time::Duration};
+
+/// Number of blocks an extrinsic is valid for after it is signed.
+pub(crate) const EXTRINSIC_MORTALITY: u64 = 64;
+
+/// Number of times a dropped extrinsic is resubmitted before its nonce is given up.
+pub(crate) const MAX_RESUBMISSIONS: u32 = 3;
+
+/// Interval at which the nonce of the account is checked for gaps while an extrinsic is pending.
+pub(crate) const STALL_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Time after which an extrinsic that was not finalized is considered dropped. By then its era
+/// has expired, so it can no longer be included.
+pub(crate) fn expiry_timeout() -> Duration {
+    Duration::from_millis(2 * EXTRINSIC_MORTALITY * MILLISECS_PER_BLOCK)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PendingExtrinsic {
+    /// Name of the call, as `Module.function`.
+    pub call: String,
+    /// Hashes of all submissions, since any of them may be included once its nonce is used.
+    pub hashes: Vec<H256>,
+    /// Number of times the extrinsic has been submitted.
+    pub submissions: u32,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct PendingExtrinsics {
+    by_nonce: BTreeMap<Index, PendingExtrinsic>,
+    /// Nonces that were filled with a remark, with the block at which the remark expires.
+    filled: BTreeMap<Index, BlockNumber>,
+}
+
+impl PendingExtrinsics {
+    /// Register the nonce as soon as it is allocated, so that it is not mistaken for a gap.
+    pub fn allocate(&mut self, nonce: Index, call: &str) {
+        self.by_nonce.insert(
+            nonce,
+            PendingExtrinsic {
+                call: call.to_string(),
+                hashes: vec![],
+                submissions: 0,
+            },
+        );
+    }
+
+    /// Record a (re)submission of the extrinsic with the given nonce.
+    pub fn submitted(&mut self, nonce: Index, hash: H256) {
+        if let Some(pending) = self.by_nonce.get_mut(&nonce) {
+            pending.hashes.push(hash);
+            pending.submissions += 1;
+        }
+    }
+
+    pub fn remove(&mut self, nonce: Index) -> Option<PendingExtrinsic> {
+        self.by_nonce.remove(&nonce)
+    }
+
+    pub fn get(&self, nonce: Index) -> Option<&PendingExtrinsic> {
+        self.by_nonce.get(&nonce)
+    }
+
+    /// Find the submission of the extrinsic with the given nonce among the hashes of the
+    /// extrinsics of a block, returning its index in the block.
+    pub fn find_submission<I: IntoIterator<Item = H256>>(&self, nonce: Index, extrinsics: I) -> Option<(usize, H256)> {
+        let pending = self.by_nonce.get(&nonce)?;
+        extrinsics
+            .into_iter()
+            .enumerate()
+            .find(|(_, hash)| pending.hashes.contains(hash))
+    }
+
+    /// Nonces below `nonce` that are not yet used on chain, as of `account_nonce`, but are
+    /// neither pending nor filled with a remark that is still valid at `current_block`. The
+    /// returned nonces are marked as filled with a remark that expires at `expires_at`.
+    pub fn take_gaps(
+        &mut self,
+        account_nonce: Index,
+        nonce: Index,
+        current_block: BlockNumber,
+        expires_at: BlockNumber,
+    ) -> Vec<Index> {
+        self.filled
+            .retain(|&filled, &mut expiry| filled >= account_nonce && expiry > current_block);
+        let gaps: Vec<_> = (account_nonce..nonce)
+            .filter(|gap| !self.by_nonce.contains_key(gap) && !self.filled.contains_key(gap))
+            .collect();
+        for gap in gaps.iter() {
+            self.filled.insert(*gap, expires_at);
+        }
+        gaps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_nonces_are_not_gaps() {
+        let mut pending = PendingExtrinsics::default();
+        pending.allocate(5, "Issue.execute_issue");
+        pending.allocate(7, "Redeem.execute_redeem");
+
+        // nonce 4 is already used on chain, 6 was allocated but never submitted
+        assert_eq!(pending.take_gaps(5, 7, 100, 164), vec![6]);
+        // the remark for nonce 6 is still valid
+        assert!(pending.take_gaps(5, 7, 101, 165).is_empty());
+    }
+
+    #[test]
+    fn test_expired_remarks_are_refilled() {
+        let mut pending = PendingExtrinsics::default();
+        pending.allocate(3, "Relay.store_block_header");
+
+        assert_eq!(pending.take_gaps(1, 3, 100, 164), vec![1, 2]);
+        // the remark for nonce 1 was included, the one for nonce 2 expired
+        assert_eq!(pending.take_gaps(2, 3, 164, 228), vec![2]);
+    }
+
+    #[test]
+    fn test_submissions_are_counted() {
+        let mut pending = PendingExtrinsics::default();
+        pending.allocate(0, "Issue.cancel_issue");
+        pending.submitted(0, H256::repeat_byte(1));
+        pending.submitted(0, H256::repeat_byte(2));
+
+        let extrinsic = pending.remove(0).unwrap();
+        assert_eq!(extrinsic.hashes, vec![H256::repeat_byte(1), H256::repeat_byte(2)]);
+        assert_eq!(extrinsic.submissions, 2);
+        assert_eq!(pending.get(0), None);
+    }
+
+    #[test]
+    fn test_earlier_submissions_are_found() {
+        let mut pending = PendingExtrinsics::default();
+        pending.allocate(0, "Issue.cancel_issue");
+        pending.submitted(0, H256::repeat_byte(1));
+        pending.submitted(0, H256::repeat_byte(2));
+
+        // the first submission was included after all, while the second was rejected
+        let block = vec![H256::repeat_byte(9), H256::repeat_byte(1)];
+        assert_eq!(
+            pending.find_submission(0, block.clone()),
+            Some((1, H256::repeat_byte(1)))
+        );
+        assert_eq!(pending.find_submission(1, block), None);
+        assert_eq!(pending.find_submission(0, vec![H256::repeat_byte(3)]), None);
+    }
+}
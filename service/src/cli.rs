@@ -1,5 +1,5 @@
 use clap::Clap;
-use std::str::FromStr;
+use std::{net::SocketAddr, str::FromStr};
 
 #[derive(Clone, Debug)]
 pub enum RestartPolicy {
@@ -63,4 +63,33 @@ pub struct ServiceConfig {
     /// Telemetry endpoint.
     #[clap(long)]
     pub telemetry_url: Option<String>,
+
+    /// Observe only: follow all events, but never submit extrinsics or broadcast
+    /// bitcoin transactions. Decisions are logged with the `decision` target.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Prometheus metrics settings.
+    #[clap(flatten)]
+    pub metrics: MetricsConfig,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct MetricsConfig {
+    /// Address to serve Prometheus metrics on, e.g. 0.0.0.0:9615. Disabled if unset.
+    #[clap(long)]
+    pub metrics_addr: Option<SocketAddr>,
+}
+
+impl MetricsConfig {
+    /// Spawn the metrics server in the background, if enabled.
+    pub fn spawn_server(&self) {
+        if let Some(addr) = self.metrics_addr {
+            tokio::spawn(async move {
+                if let Err(err) = crate::serve_metrics(addr).await {
+                    tracing::error!("Metrics server failed: {}", err);
+                }
+            });
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use bitcoin::Error as BitcoinError;
 use hyper::{http::Error as HyperHttpError, Error as HyperError};
+use prometheus::Error as PrometheusError;
 use runtime::Error as RuntimeError;
 use serde_json::Error as SerdeJsonError;
 use thiserror::Error;
@@ -17,6 +18,8 @@ pub enum Error {
     HyperError(#[from] HyperError),
     #[error("HyperHttpError: {0}")]
     HyperHttpError(#[from] HyperHttpError),
+    #[error("PrometheusError: {0}")]
+    PrometheusError(#[from] PrometheusError),
 
     #[error("RuntimeError: {0}")]
     RuntimeError(#[from] RuntimeError),
@@ -6,21 +6,26 @@ use runtime::{
     InterBtcParachain as BtcParachain, InterBtcSigner,
 };
 use sp_core::crypto::Ss58Codec;
-use std::marker::PhantomData;
+use std::{marker::PhantomData, time::Duration};
 
 mod cli;
 mod error;
+mod metrics;
 mod telemetry;
 mod trace;
 
 use telemetry::TelemetryClient;
 
-pub use cli::{LoggingFormat, RestartPolicy, ServiceConfig};
+pub use cli::{LoggingFormat, MetricsConfig, RestartPolicy, ServiceConfig};
 pub use error::Error;
+pub use metrics::serve_metrics;
 pub use trace::init_subscriber;
 
 pub type ShutdownSender = tokio::sync::broadcast::Sender<Option<()>>;
 
+/// Time the tasks of a service are given to stop before reconnecting.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[async_trait]
 pub trait Service<Config> {
     const NAME: &'static str;
@@ -74,29 +79,63 @@ impl<Config: Clone + Send + 'static, S: Service<Config>> ConnectionManager<Confi
             tokio::spawn(async move { telemetry::do_update(&telemetry_client, S::NAME, S::VERSION).await });
         }
 
+        self.service_config.metrics.spawn_server();
+
         tracing::info!("AccountId: {}", self.signer.account_id().to_ss58check());
 
+        // all endpoints must serve the chain of the first connection
+        let mut genesis_hash = None;
+
         loop {
             let config = self.config.clone();
             let (shutdown_tx, _) = tokio::sync::broadcast::channel(16);
 
-            let bitcoin_core = self.bitcoin_config.new_client(self.wallet_name.clone())?;
+            let bitcoin_core = self
+                .bitcoin_config
+                .new_client(self.wallet_name.clone())?
+                .with_dry_run(self.service_config.dry_run);
             bitcoin_core.connect().await?;
             bitcoin_core.sync().await?;
 
             // only open connection to parachain after bitcoind sync to prevent timeout
             let signer = self.signer.clone();
-            let btc_parachain = BtcParachain::from_url_and_config_with_retry(
-                &self.parachain_config.btc_parachain_url,
-                signer,
-                self.parachain_config.max_concurrent_requests,
-                self.parachain_config.max_notifs_per_subscription,
-                self.parachain_config.btc_parachain_connection_timeout_ms,
-            )
-            .await?;
-
-            let service = S::new_service(btc_parachain, bitcoin_core, config, shutdown_tx);
-            if let Err(outer) = service.start().await {
+            let (endpoint, btc_parachain) = self.parachain_config.try_connect_healthy(signer, genesis_hash).await?;
+            genesis_hash = Some(btc_parachain.get_genesis_hash().await?);
+            let btc_parachain = btc_parachain
+                .with_dry_run(self.service_config.dry_run)
+                .with_event_checkpoint(self.parachain_config.event_checkpoint());
+
+            let service = S::new_service(btc_parachain.clone(), bitcoin_core, config, shutdown_tx.clone());
+            let run_service = service.start();
+            let failover = self.parachain_config.wait_for_failover(&btc_parachain, endpoint);
+            let runtime_upgrade = btc_parachain.wait_for_runtime_upgrade();
+            futures::pin_mut!(run_service);
+            futures::pin_mut!(failover);
+            futures::pin_mut!(runtime_upgrade);
+            let reconnect = futures::future::select(failover, runtime_upgrade);
+
+            let result = match futures::future::select(run_service, reconnect).await {
+                Either::Left((result, _)) => result,
+                Either::Right((Either::Left(_), run_service)) => {
+                    // switching endpoints is not a failure, so the restart policy does not apply
+                    tracing::info!("Switching parachain endpoint");
+                    shut_down(&shutdown_tx, run_service).await;
+                    continue;
+                }
+                Either::Right((Either::Right((Ok(report), _)), run_service)) => {
+                    // reconnect to use the new metadata, the service checks whether it is
+                    // still compatible when it starts
+                    tracing::info!("Reconnecting after runtime upgrade, {}", report);
+                    shut_down(&shutdown_tx, run_service).await;
+                    continue;
+                }
+                Either::Right((Either::Right((Err(err), _)), run_service)) => {
+                    shut_down(&shutdown_tx, run_service).await;
+                    Err(err.into())
+                }
+            };
+
+            if let Err(outer) = result {
                 match outer {
                     Error::BitcoinError(ref inner)
                         if inner.is_connection_aborted()
@@ -113,12 +152,26 @@ impl<Config: Clone + Send + 'static, S: Service<Config>> ConnectionManager<Confi
 
             match self.service_config.restart_policy {
                 RestartPolicy::Never => return Err(Error::ClientShutdown),
-                RestartPolicy::Always => continue,
+                RestartPolicy::Always => {
+                    metrics::RESTARTS.inc();
+                    continue;
+                }
             };
         }
     }
 }
 
+/// Signals the tasks of the service to stop and waits until they have, so that they do not
+/// overlap with the tasks of the next connection.
+async fn shut_down(shutdown_tx: &ShutdownSender, service: impl Future<Output = Result<(), Error>>) {
+    let _ = shutdown_tx.send(Some(()));
+    match tokio::time::timeout(SHUTDOWN_TIMEOUT, service).await {
+        Ok(Err(err)) => tracing::info!("Service stopped: {}", err),
+        Ok(Ok(())) => {}
+        Err(_) => tracing::warn!("Service did not stop within {:?}", SHUTDOWN_TIMEOUT),
+    }
+}
+
 pub async fn wait_or_shutdown<F>(shutdown_tx: ShutdownSender, future2: F)
 where
     F: Future<Output = Result<(), Error>>,
@@ -0,0 +1,36 @@
+use crate::Error;
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter, Encoder, IntCounter, TextEncoder};
+use std::net::SocketAddr;
+
+lazy_static! {
+    pub(crate) static ref RESTARTS: IntCounter =
+        register_int_counter!("service_restarts_total", "Number of times the service was restarted").unwrap();
+}
+
+/// Serve all registered metrics in the Prometheus text format on `/metrics`.
+pub async fn serve_metrics(addr: SocketAddr) -> Result<(), Error> {
+    let make_service = make_service_fn(|_| async { Ok::<_, Error>(service_fn(handle_request)) });
+    tracing::info!("Serving metrics on {}", addr);
+    Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
+
+async fn handle_request(request: Request<Body>) -> Result<Response<Body>, Error> {
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        return Ok(Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty())?);
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder.encode(&prometheus::gather(), &mut buffer)?;
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buffer))?)
+}
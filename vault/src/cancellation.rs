@@ -206,6 +206,13 @@ impl<P: IssuePallet + ReplacePallet + UtilFuncs + SecurityPallet + Clone> Cancel
         let cancellable_requests = drain_expired(active_requests, self.parachain_height, self.bitcoin_height);
 
         for request in cancellable_requests {
+            decision!(
+                action = "cancel",
+                request_type = T::TYPE_NAME,
+                request_id = ?request.id,
+                "Cancelling {}",
+                T::TYPE_NAME
+            );
             match T::cancel_request(&self.parachain_rpc, request.id).await {
                 Ok(_) => tracing::info!("Canceled {} #{:?}", T::TYPE_NAME, request.id),
                 Err(e) => {
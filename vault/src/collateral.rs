@@ -65,13 +65,12 @@ pub async fn lock_required_collateral<P: VaultRegistryPallet + CollateralBalance
     let (required_collateral, maximum_collateral) = future::try_join(
         async { Ok(parachain_rpc.get_required_collateral_for_vault(vault_id).await?) },
         async {
-            if let Some(max) = maximum_collateral {
-                Ok(max)
-            } else {
-                // allow all balance to be used as collateral
-                let free = parachain_rpc.get_free_balance().await?;
-                free.checked_add(actual_collateral).ok_or(Error::ArithmeticOverflow)
-            }
+            // allow all balance to be used as collateral, except for the fee reserve
+            let spendable = parachain_rpc.get_spendable_balance().await?;
+            let available = spendable
+                .checked_add(actual_collateral)
+                .ok_or(Error::ArithmeticOverflow)?;
+            Ok(maximum_collateral.map_or(available, |max| max.min(available)))
         },
     )
     .await?;
@@ -120,6 +119,21 @@ pub async fn lock_required_collateral<P: VaultRegistryPallet + CollateralBalance
     }
 }
 
+/// Fails with `InsufficientFunds` if locking `amount` as collateral would take from the fee
+/// reserve, which `lock_required_collateral` keeps as well.
+pub async fn ensure_spendable<P: CollateralBalancesPallet>(parachain_rpc: &P, amount: u128) -> Result<(), Error> {
+    let spendable = parachain_rpc.get_spendable_balance().await?;
+    if amount > spendable {
+        tracing::error!(
+            "Cannot lock {} as collateral, only {} can be spent without touching the fee reserve",
+            amount,
+            spendable
+        );
+        return Err(Error::InsufficientFunds);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -169,6 +183,7 @@ mod tests {
         #[async_trait]
         pub trait CollateralBalancesPallet {
             async fn get_free_balance(&self) -> Result<InterBtcBalance, RuntimeError>;
+            async fn get_spendable_balance(&self) -> Result<InterBtcBalance, RuntimeError>;
             async fn get_free_balance_for_id(&self, id: AccountId) -> Result<InterBtcBalance, RuntimeError>;
             async fn get_reserved_balance(&self) -> Result<InterBtcBalance, RuntimeError>;
             async fn get_reserved_balance_for_id(&self, id: AccountId) -> Result<InterBtcBalance, RuntimeError>;
@@ -201,6 +216,10 @@ mod tests {
             .expect_get_vault_total_collateral()
             .returning(move |_| Ok(actual));
 
+        parachain_rpc
+            .expect_get_spendable_balance()
+            .returning(|| Ok(u64::MAX as u128));
+
         parachain_rpc
     }
     #[tokio::test]
@@ -297,6 +316,44 @@ mod tests {
         assert_ok!(lock_required_collateral(parachain_rpc, AccountId::default(), Some(200)).await);
     }
 
+    #[tokio::test]
+    async fn test_lock_required_collateral_keeps_fee_reserve() {
+        // required = 100, actual = 25, max = 200, spendable = 50:
+        // should only add the spendable 50, and return err
+        let mut parachain_rpc = MockProvider::default();
+        parachain_rpc
+            .expect_get_required_collateral_for_vault()
+            .returning(|_| Ok(100));
+        parachain_rpc.expect_get_vault().returning(|x| {
+            Ok(InterBtcVault {
+                id: x,
+                status: VaultStatus::Active(true),
+                ..Default::default()
+            })
+        });
+        parachain_rpc.expect_get_vault_total_collateral().returning(|_| Ok(25));
+        parachain_rpc.expect_get_spendable_balance().returning(|| Ok(50));
+        parachain_rpc
+            .expect_deposit_collateral()
+            .withf(|&amount| amount == 50)
+            .times(1)
+            .returning(|_| Ok(()));
+
+        assert_err!(
+            lock_required_collateral(parachain_rpc, AccountId::default(), Some(200)).await,
+            Error::InsufficientFunds
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ensure_spendable_keeps_fee_reserve() {
+        let mut parachain_rpc = MockProvider::default();
+        parachain_rpc.expect_get_spendable_balance().returning(|| Ok(50));
+
+        assert_ok!(ensure_spendable(&parachain_rpc, 50).await);
+        assert_err!(ensure_spendable(&parachain_rpc, 51).await, Error::InsufficientFunds);
+    }
+
     #[tokio::test]
     async fn test_lock_required_collateral_with_unregistered_vault_fails() {
         let mut parachain_rpc = MockProvider::default();
@@ -313,4 +370,43 @@ mod tests {
             Error::RuntimeError(runtime::Error::VaultNotFound)
         );
     }
+
+    #[tokio::test]
+    async fn test_lock_required_collateral_on_fake_parachain() {
+        use runtime::{ExchangeRateOraclePallet, FakeParachain, FixedPointNumber, FixedU128, IssuePallet};
+        use sp_keyring::AccountKeyring;
+
+        let vault_id = AccountKeyring::Bob.to_account_id();
+        let parachain = FakeParachain::new(vault_id.clone());
+        parachain.set_free_balance(vault_id.clone(), 2_000_000);
+        parachain
+            .register_vault(1_000_000, BtcPublicKey([2; 33]))
+            .await
+            .unwrap();
+        let user_id = AccountKeyring::Dave.to_account_id();
+        let user = parachain.with_account(user_id.clone());
+        user.set_free_balance(user_id, 1_000);
+        user.request_issue(100_000, &vault_id, 100).await.unwrap();
+
+        // the price of the issued tokens rises, so the vault needs 1_500_000 collateral
+        parachain
+            .insert_authorized_oracle(vault_id.clone(), "oracle".to_string())
+            .await
+            .unwrap();
+        parachain
+            .set_exchange_rate_info(FixedU128::saturating_from_integer(10))
+            .await
+            .unwrap();
+
+        assert_err!(
+            lock_required_collateral(parachain.clone(), vault_id.clone(), Some(1_200_000)).await,
+            Error::InsufficientFunds
+        );
+        assert_eq!(
+            parachain.get_vault_total_collateral(vault_id.clone()).await.unwrap(),
+            1_200_000
+        );
+        assert_ok!(lock_required_collateral(parachain.clone(), vault_id.clone(), None).await);
+        assert_eq!(parachain.get_vault_total_collateral(vault_id).await.unwrap(), 1_500_000);
+    }
 }
@@ -0,0 +1,556 @@
+use crate::{
+    collateral::ensure_spendable,
+    deposit_collateral,
+    error::Error,
+    keys::{rotate_public_key, sweep_funds},
+    system::is_registered,
+    theft_archive::{ReportStatus, TheftArchive, TheftEvidence},
+};
+use bitcoin::BitcoinCoreApi;
+use clap::Clap;
+use runtime::{
+    AccountId, BlockNumber, BtcPublicKey, CollateralBalancesPallet, Error as RuntimeError, InterBtcIssueRequest,
+    InterBtcParachain, InterBtcRedeemRequest, InterBtcRefundRequest, InterBtcReplaceRequest, InterBtcVault,
+    IssuePallet, IssueRequestStatus, RedeemPallet, RedeemRequestStatus, RefundPallet, ReplacePallet,
+    ReplaceRequestStatus, UtilFuncs, VaultRegistryPallet,
+};
+use sp_core::{crypto::Ss58Codec, H256};
+use std::{fmt, future::Future, path::PathBuf};
+
+#[derive(Clap, Debug, Clone)]
+pub struct AmountOpts {
+    /// The amount, in the smallest unit of the currency.
+    pub amount: u128,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct RequestReplaceOpts {
+    /// The amount of wrapped tokens (in satoshi) to be replaced.
+    pub amount: u128,
+
+    /// The amount of griefing collateral to lock for the request.
+    pub griefing_collateral: u128,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct QueryOpts {
+    /// Read the state at the parachain block with this number instead of the latest one.
+    #[clap(long)]
+    pub at_block: Option<BlockNumber>,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct TheftArchiveOpts {
+    /// The archive written by the vault, as given by `--theft-archive-file`.
+    pub file: PathBuf,
+
+    /// Only list reports that have not succeeded (yet).
+    #[clap(long)]
+    pub failed: bool,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct UpdatePublicKeyOpts {
+    /// Also move all funds in the wallet to a newly registered address.
+    #[clap(long)]
+    pub sweep_funds: bool,
+}
+
+/// One-shot administrative operations, run instead of the long-running vault service.
+#[derive(Clap, Debug, Clone)]
+pub enum AdminCommand {
+    /// Register the vault with the given amount of collateral and a newly generated public key.
+    Register(AmountOpts),
+
+    /// Lock additional collateral.
+    DepositCollateral(AmountOpts),
+
+    /// Withdraw collateral that is not needed to back issued tokens.
+    WithdrawCollateral(AmountOpts),
+
+    /// Request to be replaced by other vaults.
+    RequestReplace(RequestReplaceOpts),
+
+    /// Withdraw (part of) an open replace request.
+    WithdrawReplace(AmountOpts),
+
+    /// Generate a new public key in the wallet and register it with the parachain.
+    UpdatePublicKey(UpdatePublicKeyOpts),
+
+    /// Print the registration, collateral and wallet status of the vault.
+    Status(QueryOpts),
+
+    /// List the open issue, redeem, replace and refund requests of the vault.
+    ListRequests(QueryOpts),
+
+    /// List the transactions found by the theft reporting and the outcome of the reports.
+    ListTheftReports(TheftArchiveOpts),
+}
+
+impl AdminCommand {
+    /// Run the command against the given connections, returns once the extrinsic (if any)
+    /// has been included. Only the commands that use the wallet connect to Bitcoin Core with
+    /// `bitcoin_core`.
+    pub async fn execute<B, F>(&self, parachain_rpc: &InterBtcParachain, bitcoin_core: F) -> Result<(), Error>
+    where
+        B: BitcoinCoreApi + Clone + Send + Sync + 'static,
+        F: Future<Output = Result<B, Error>>,
+    {
+        let vault_id = parachain_rpc.get_account_id().clone();
+        match self {
+            Self::Register(AmountOpts { amount }) => {
+                if is_registered(parachain_rpc, vault_id).await? {
+                    return Err(Error::VaultAlreadyRegistered);
+                }
+                ensure_spendable(parachain_rpc, *amount).await?;
+                let bitcoin_core = bitcoin_core.await?;
+                load_wallet(&bitcoin_core).await?;
+                let public_key: BtcPublicKey = bitcoin_core.get_new_public_key().await?;
+                parachain_rpc.register_vault(*amount, public_key.clone()).await?;
+                tracing::info!("Registered vault with public key {:?}", public_key);
+            }
+            Self::DepositCollateral(AmountOpts { amount }) => {
+                deposit_collateral(parachain_rpc, *amount).await?;
+            }
+            Self::WithdrawCollateral(AmountOpts { amount }) => {
+                parachain_rpc.withdraw_collateral(*amount).await?;
+                tracing::info!("Withdrew collateral; amount {}", amount);
+            }
+            Self::RequestReplace(RequestReplaceOpts {
+                amount,
+                griefing_collateral,
+            }) => {
+                parachain_rpc.request_replace(*amount, *griefing_collateral).await?;
+                tracing::info!(
+                    "Requested replacement of {} with griefing collateral {}",
+                    amount,
+                    griefing_collateral
+                );
+            }
+            Self::WithdrawReplace(AmountOpts { amount }) => {
+                parachain_rpc.withdraw_replace(*amount).await?;
+                tracing::info!("Withdrew replace request; amount {}", amount);
+            }
+            Self::UpdatePublicKey(UpdatePublicKeyOpts { sweep_funds: sweep }) => {
+                let bitcoin_core = bitcoin_core.await?;
+                load_wallet(&bitcoin_core).await?;
+                rotate_public_key(parachain_rpc, &bitcoin_core).await?;
+                if *sweep {
+                    sweep_funds(parachain_rpc, &bitcoin_core).await?;
+                }
+            }
+            Self::Status(opts) => {
+                print_status(&opts.query_handle(parachain_rpc).await?, bitcoin_core, vault_id).await?
+            }
+            Self::ListRequests(opts) => print_open_requests(&opts.query_handle(parachain_rpc).await?, vault_id).await?,
+            Self::ListTheftReports(opts) => opts.print()?,
+        }
+        Ok(())
+    }
+}
+
+impl QueryOpts {
+    async fn query_handle(&self, parachain_rpc: &InterBtcParachain) -> Result<InterBtcParachain, Error> {
+        match self.at_block {
+            Some(number) => Ok(parachain_rpc.at_block_number(number).await?),
+            None => Ok(parachain_rpc.clone()),
+        }
+    }
+}
+
+async fn load_wallet<B: BitcoinCoreApi>(bitcoin_core: &B) -> Result<(), Error> {
+    bitcoin_core
+        .create_or_load_wallet()
+        .await
+        .map_err(Error::WalletInitializationFailure)
+}
+
+/// Prints the status of the vault, only connects to Bitcoin Core if the vault is registered.
+async fn print_status<B, F>(
+    parachain_rpc: &InterBtcParachain,
+    bitcoin_core: F,
+    vault_id: AccountId,
+) -> Result<(), Error>
+where
+    B: BitcoinCoreApi + Clone + Send + Sync + 'static,
+    F: Future<Output = Result<B, Error>>,
+{
+    let free_balance = parachain_rpc.get_free_balance().await?;
+    let registration = match parachain_rpc.get_vault(vault_id.clone()).await {
+        Ok(vault) => {
+            let (total_collateral, required_collateral) = futures::future::try_join(
+                parachain_rpc.get_vault_total_collateral(vault_id.clone()),
+                parachain_rpc.get_required_collateral_for_vault(vault_id.clone()),
+            )
+            .await?;
+
+            let bitcoin_core = bitcoin_core.await?;
+            load_wallet(&bitcoin_core).await?;
+            Ok(RegisteredVault {
+                has_public_key: bitcoin_core.wallet_has_public_key(vault.wallet.public_key.0).await?,
+                bitcoin_height: bitcoin_core.get_block_count().await?,
+                vault,
+                total_collateral,
+                required_collateral,
+            })
+        }
+        Err(RuntimeError::VaultNotFound) => Err("not registered"),
+        Err(RuntimeError::VaultLiquidated) => Err("liquidated"),
+        Err(RuntimeError::VaultCommittedTheft) => Err("committed theft"),
+        Err(err) => return Err(err.into()),
+    };
+
+    print!(
+        "{}",
+        VaultReport {
+            vault_id,
+            free_balance,
+            registration,
+        }
+    );
+    Ok(())
+}
+
+/// The status of a vault, as printed by `status`.
+struct VaultReport {
+    vault_id: AccountId,
+    free_balance: u128,
+    /// The reason the vault is not active if it is not registered (anymore).
+    registration: Result<RegisteredVault, &'static str>,
+}
+
+struct RegisteredVault {
+    vault: InterBtcVault,
+    total_collateral: u128,
+    required_collateral: u128,
+    has_public_key: bool,
+    bitcoin_height: u64,
+}
+
+impl fmt::Display for VaultReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Account: {}", self.vault_id.to_ss58check())?;
+        writeln!(f, "Free balance: {}", self.free_balance)?;
+
+        let registered = match &self.registration {
+            Ok(registered) => registered,
+            Err(status) => return writeln!(f, "Status: {}", status),
+        };
+        let vault = &registered.vault;
+        writeln!(f, "Status: {:?}", vault.status)?;
+        writeln!(f, "Banned until: {:?}", vault.banned_until)?;
+        writeln!(f, "Total collateral: {}", registered.total_collateral)?;
+        writeln!(f, "Required collateral: {}", registered.required_collateral)?;
+        writeln!(f, "Issued tokens: {}", vault.issued_tokens)?;
+        writeln!(f, "To be issued tokens: {}", vault.to_be_issued_tokens)?;
+        writeln!(f, "To be redeemed tokens: {}", vault.to_be_redeemed_tokens)?;
+        writeln!(f, "To be replaced tokens: {}", vault.to_be_replaced_tokens)?;
+        writeln!(f, "Public key: {:?}", vault.wallet.public_key)?;
+        writeln!(f, "Public key in wallet: {}", registered.has_public_key)?;
+        writeln!(f, "Registered addresses: {}", vault.wallet.addresses.len())?;
+        writeln!(f, "Bitcoin height: {}", registered.bitcoin_height)
+    }
+}
+
+async fn print_open_requests(parachain_rpc: &InterBtcParachain, vault_id: AccountId) -> Result<(), Error> {
+    let (issue, redeem, old_replace, new_replace, refund) = futures::try_join!(
+        parachain_rpc.get_vault_issue_requests(vault_id.clone()),
+        parachain_rpc.get_vault_redeem_requests(vault_id.clone()),
+        parachain_rpc.get_old_vault_replace_requests(vault_id.clone()),
+        parachain_rpc.get_new_vault_replace_requests(vault_id.clone()),
+        parachain_rpc.get_vault_refund_requests(vault_id),
+    )?;
+
+    print!(
+        "{}",
+        OpenRequests {
+            issue,
+            redeem,
+            old_replace,
+            new_replace,
+            refund,
+        }
+    );
+    Ok(())
+}
+
+/// The requests of a vault, of which the open ones are printed by `list-requests`.
+struct OpenRequests {
+    issue: Vec<(H256, InterBtcIssueRequest)>,
+    redeem: Vec<(H256, InterBtcRedeemRequest)>,
+    old_replace: Vec<(H256, InterBtcReplaceRequest)>,
+    new_replace: Vec<(H256, InterBtcReplaceRequest)>,
+    refund: Vec<(H256, InterBtcRefundRequest)>,
+}
+
+impl fmt::Display for OpenRequests {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (id, request) in self
+            .issue
+            .iter()
+            .filter(|(_, request)| request.status == IssueRequestStatus::Pending)
+        {
+            writeln!(
+                f,
+                "Issue #{:?}: amount {}, fee {}, opened at {}, address {:?}",
+                id, request.amount, request.fee, request.opentime, request.btc_address
+            )?;
+        }
+
+        for (id, request) in self
+            .redeem
+            .iter()
+            .filter(|(_, request)| request.status == RedeemRequestStatus::Pending)
+        {
+            writeln!(
+                f,
+                "Redeem #{:?}: amount {}, opened at {}, address {:?}",
+                id, request.amount_btc, request.opentime, request.btc_address
+            )?;
+        }
+
+        for (id, request) in self
+            .old_replace
+            .iter()
+            .filter(|(_, request)| request.status == ReplaceRequestStatus::Pending)
+        {
+            writeln!(
+                f,
+                "Replace (outgoing) #{:?}: amount {}, accepted at {}, address {:?}",
+                id, request.amount, request.accept_time, request.btc_address
+            )?;
+        }
+
+        for (id, request) in self
+            .new_replace
+            .iter()
+            .filter(|(_, request)| request.status == ReplaceRequestStatus::Pending)
+        {
+            writeln!(
+                f,
+                "Replace (incoming) #{:?}: amount {}, accepted at {}, address {:?}",
+                id, request.amount, request.accept_time, request.btc_address
+            )?;
+        }
+
+        for (id, request) in self.refund.iter().filter(|(_, request)| !request.completed) {
+            writeln!(
+                f,
+                "Refund #{:?}: amount {}, address {:?}",
+                id, request.amount_btc, request.btc_address
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TheftArchiveOpts {
+    /// Prints the listed reports of the archive, does not need any connection.
+    pub fn print(&self) -> Result<(), Error> {
+        for evidence in TheftArchive::load(&self.file)?
+            .into_iter()
+            .filter(|evidence| self.lists(evidence))
+        {
+            println!("{}", evidence);
+        }
+        Ok(())
+    }
+
+    /// Whether the report of `evidence` is listed, i.e. it has not succeeded if only failed
+    /// reports are listed.
+    fn lists(&self, evidence: &TheftEvidence) -> bool {
+        !self.failed || evidence.status.is_retryable() || evidence.status == ReportStatus::Failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runtime::{BtcAddress, VaultStatus};
+    use sp_keyring::AccountKeyring;
+
+    #[test]
+    fn test_format_status_of_unregistered_vault() {
+        let vault_id = AccountKeyring::Bob.to_account_id();
+        let report = VaultReport {
+            vault_id: vault_id.clone(),
+            free_balance: 5,
+            registration: Err("liquidated"),
+        };
+
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "Account: {}\nFree balance: 5\nStatus: liquidated\n",
+                vault_id.to_ss58check()
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_status_of_registered_vault() {
+        let vault_id = AccountKeyring::Bob.to_account_id();
+        let vault = InterBtcVault {
+            id: vault_id.clone(),
+            status: VaultStatus::Active(true),
+            issued_tokens: 10,
+            to_be_issued_tokens: 2,
+            to_be_redeemed_tokens: 3,
+            ..Default::default()
+        };
+        let public_key = format!("{:?}", vault.wallet.public_key);
+        let report = VaultReport {
+            vault_id: vault_id.clone(),
+            free_balance: 5,
+            registration: Ok(RegisteredVault {
+                vault,
+                total_collateral: 100,
+                required_collateral: 80,
+                has_public_key: true,
+                bitcoin_height: 700,
+            }),
+        };
+
+        assert_eq!(
+            report.to_string().lines().collect::<Vec<_>>(),
+            vec![
+                format!("Account: {}", vault_id.to_ss58check()),
+                "Free balance: 5".to_string(),
+                "Status: Active(true)".to_string(),
+                "Banned until: None".to_string(),
+                "Total collateral: 100".to_string(),
+                "Required collateral: 80".to_string(),
+                "Issued tokens: 10".to_string(),
+                "To be issued tokens: 2".to_string(),
+                "To be redeemed tokens: 3".to_string(),
+                "To be replaced tokens: 0".to_string(),
+                format!("Public key: {}", public_key),
+                "Public key in wallet: true".to_string(),
+                "Registered addresses: 0".to_string(),
+                "Bitcoin height: 700".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_open_requests_lists_pending_requests() {
+        let address = format!("{:?}", BtcAddress::default());
+        let requests = OpenRequests {
+            issue: vec![
+                (
+                    H256::from_low_u64_be(1),
+                    InterBtcIssueRequest {
+                        amount: 10,
+                        fee: 1,
+                        opentime: 50,
+                        status: IssueRequestStatus::Pending,
+                        ..Default::default()
+                    },
+                ),
+                (
+                    H256::from_low_u64_be(2),
+                    InterBtcIssueRequest {
+                        status: IssueRequestStatus::Completed(None),
+                        ..Default::default()
+                    },
+                ),
+            ],
+            redeem: vec![(
+                H256::from_low_u64_be(3),
+                InterBtcRedeemRequest {
+                    amount_btc: 20,
+                    opentime: 60,
+                    status: RedeemRequestStatus::Pending,
+                    ..Default::default()
+                },
+            )],
+            old_replace: vec![(
+                H256::from_low_u64_be(4),
+                InterBtcReplaceRequest {
+                    amount: 30,
+                    accept_time: 70,
+                    status: ReplaceRequestStatus::Pending,
+                    ..Default::default()
+                },
+            )],
+            new_replace: vec![(
+                H256::from_low_u64_be(5),
+                InterBtcReplaceRequest {
+                    status: ReplaceRequestStatus::Cancelled,
+                    ..Default::default()
+                },
+            )],
+            refund: vec![
+                (
+                    H256::from_low_u64_be(6),
+                    InterBtcRefundRequest {
+                        amount_btc: 40,
+                        completed: false,
+                        ..Default::default()
+                    },
+                ),
+                (
+                    H256::from_low_u64_be(7),
+                    InterBtcRefundRequest {
+                        completed: true,
+                        ..Default::default()
+                    },
+                ),
+            ],
+        };
+
+        assert_eq!(
+            requests.to_string().lines().collect::<Vec<_>>(),
+            vec![
+                format!(
+                    "Issue #{:?}: amount 10, fee 1, opened at 50, address {}",
+                    H256::from_low_u64_be(1),
+                    address
+                ),
+                format!(
+                    "Redeem #{:?}: amount 20, opened at 60, address {}",
+                    H256::from_low_u64_be(3),
+                    address
+                ),
+                format!(
+                    "Replace (outgoing) #{:?}: amount 30, accepted at 70, address {}",
+                    H256::from_low_u64_be(4),
+                    address
+                ),
+                format!("Refund #{:?}: amount 40, address {}", H256::from_low_u64_be(6), address),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_only_failed_theft_reports() {
+        let opts = |failed| TheftArchiveOpts {
+            file: PathBuf::new(),
+            failed,
+        };
+        let evidence = |status| {
+            let mut evidence = TheftEvidence::new(
+                "a".to_string(),
+                "00".to_string(),
+                AccountKeyring::Bob.to_account_id(),
+                vec![],
+                vec![],
+            );
+            evidence.status = status;
+            evidence
+        };
+
+        for status in &[ReportStatus::Retrying, ReportStatus::DryRun, ReportStatus::Failed] {
+            assert!(opts(true).lists(&evidence(*status)));
+        }
+        for status in &[
+            ReportStatus::Pending,
+            ReportStatus::Reported,
+            ReportStatus::AlreadyReported,
+        ] {
+            assert!(!opts(true).lists(&evidence(*status)));
+            assert!(opts(false).lists(&evidence(*status)));
+        }
+    }
+}
@@ -1,10 +1,12 @@
-use crate::relay::Error as RelayError;
 use bitcoin::Error as BitcoinError;
 use hex::FromHexError;
 use jsonrpc_core_client::RpcError;
 use parity_scale_codec::Error as CodecError;
+use relay::Error as RelayError;
 use runtime::{substrate_subxt::Error as SubxtError, Error as RuntimeError};
+use serde_json::Error as SerdeJsonError;
 use service::Error as ServiceError;
+use std::io::Error as IoError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -25,6 +27,8 @@ pub enum Error {
     TryIntoIntError(#[from] std::num::TryFromIntError),
     #[error("Deadline has expired")]
     DeadlineExpired,
+    #[error("Vault is already registered")]
+    VaultAlreadyRegistered,
 
     #[error("ServiceError: {0}")]
     ServiceError(#[from] ServiceError),
@@ -42,4 +46,8 @@ pub enum Error {
     CodecError(#[from] CodecError),
     #[error("RelayError: {0}")]
     RelayError(#[from] RelayError),
+    #[error("Error encoding json data: {0}")]
+    SerdeJsonError(#[from] SerdeJsonError),
+    #[error("Io error: {0}")]
+    IoError(#[from] IoError),
 }
@@ -1,18 +1,51 @@
-use crate::error::Error;
+use crate::{error::Error, metrics};
 use bitcoin::{
-    BitcoinCoreApi, Transaction, TransactionExt, TransactionMetadata, BLOCK_INTERVAL as BITCOIN_BLOCK_INTERVAL,
+    BitcoinCoreApi, Error as BitcoinError, Transaction, TransactionExt, TransactionMetadata, Txid,
+    BLOCK_INTERVAL as BITCOIN_BLOCK_INTERVAL,
 };
 use futures::{stream::StreamExt, try_join};
+use lazy_static::lazy_static;
 use runtime::{
     pallets::refund::RequestRefundEvent, BtcAddress, BtcRelayPallet, H256Le, InterBtcParachain, InterBtcRedeemRequest,
     InterBtcRefundRequest, InterBtcReplaceRequest, InterBtcRuntime, RedeemPallet, RedeemRequestStatus, RefundPallet,
     ReplacePallet, ReplaceRequestStatus, SecurityPallet, UtilFuncs, VaultRegistryPallet,
 };
 use sp_core::H256;
-use std::{collections::HashMap, convert::TryInto, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+    sync::Mutex,
+    time::Duration,
+};
 use tokio::time::delay_for;
 const ON_FORK_RETRY_DELAY: Duration = Duration::from_secs(10);
 
+lazy_static! {
+    /// Requests that are currently being paid or executed by this process.
+    static ref ACTIVE_REQUESTS: Mutex<HashSet<H256>> = Mutex::new(HashSet::new());
+}
+
+/// Marks a request as being processed until dropped. This prevents paying a request twice
+/// when it is found by `execute_open_requests` and also received from a replayed event.
+pub(crate) struct RequestClaim(H256);
+
+impl RequestClaim {
+    /// Returns `None` if the request is already being processed.
+    pub(crate) fn new(hash: H256) -> Option<Self> {
+        if ACTIVE_REQUESTS.lock().unwrap().insert(hash) {
+            Some(Self(hash))
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for RequestClaim {
+    fn drop(&mut self) {
+        ACTIVE_REQUESTS.lock().unwrap().remove(&self.0);
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 struct Deadline {
     parachain: u32,
@@ -174,17 +207,34 @@ impl Request {
         btc_rpc: B,
         num_confirmations: u32,
     ) -> Result<(), Error> {
-        // ensure the deadline has not expired yet
-        if let Some(ref deadline) = self.deadline {
-            if parachain_rpc.get_current_active_block_number().await? >= deadline.parachain
-                && btc_rpc.get_block_count().await? >= deadline.bitcoin as u64
-            {
-                return Err(Error::DeadlineExpired);
+        let result = async {
+            // ensure the deadline has not expired yet
+            if let Some(ref deadline) = self.deadline {
+                if parachain_rpc.get_current_active_block_number().await? >= deadline.parachain
+                    && btc_rpc.get_block_count().await? >= deadline.bitcoin as u64
+                {
+                    return Err(Error::DeadlineExpired);
+                }
+            }
+
+            if btc_rpc.is_dry_run() {
+                // log the decisions without touching the wallet
+                self.log_payment();
+                decision!(
+                    action = "execute",
+                    request_type = ?self.request_type,
+                    request_id = ?self.hash,
+                    "Executing request once the payment is confirmed"
+                );
+                return Err(BitcoinError::DryRun.into());
             }
-        }
 
-        let tx_metadata = self.transfer_btc(&parachain_rpc, btc_rpc, num_confirmations).await?;
-        self.execute(parachain_rpc, tx_metadata).await
+            let tx_metadata = self.transfer_btc(&parachain_rpc, btc_rpc, num_confirmations).await?;
+            self.execute(parachain_rpc, tx_metadata).await
+        }
+        .await;
+        metrics::record_request_result(self.request_type, &result);
+        result
     }
 
     /// Make a bitcoin transfer to fulfil the request
@@ -205,6 +255,49 @@ impl Request {
         btc_rpc: B,
         num_confirmations: u32,
     ) -> Result<TransactionMetadata, Error> {
+        // the request may have been paid before a restart, e.g. if its event was replayed
+        let txid = match btc_rpc.find_sent_transaction(self.btc_address, self.hash).await? {
+            Some(txid) => {
+                tracing::info!("Request was already paid by {}, awaiting its confirmation", txid);
+                txid
+            }
+            None => self.send_btc(parachain_rpc, &btc_rpc).await?,
+        };
+
+        loop {
+            let tx_metadata = btc_rpc.wait_for_transaction_metadata(txid, num_confirmations).await?;
+
+            tracing::info!("Awaiting parachain confirmations...");
+
+            match parachain_rpc
+                .wait_for_block_in_relay(
+                    H256Le::from_bytes_le(&tx_metadata.block_hash.to_vec()),
+                    Some(num_confirmations),
+                )
+                .await
+            {
+                Ok(_) => {
+                    tracing::info!("Bitcoin successfully sent and relayed");
+                    return Ok(tx_metadata);
+                }
+                Err(e) if e.is_invalid_chain_id() => {
+                    // small delay to prevent spamming
+                    delay_for(ON_FORK_RETRY_DELAY).await;
+                    // re-fetch the metadata - it might be in a different block now
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Sends the payment of the request, registering its return-to-self address if needed
+    async fn send_btc<B: BitcoinCoreApi, P: VaultRegistryPallet + UtilFuncs>(
+        &self,
+        parachain_rpc: &P,
+        btc_rpc: &B,
+    ) -> Result<Txid, Error> {
+        self.log_payment();
         let tx = btc_rpc
             .create_transaction(self.btc_address, self.amount as u64, Some(self.hash))
             .await?;
@@ -233,33 +326,18 @@ impl Request {
             _ => return Err(Error::TooManyReturnToSelfAddresses),
         };
 
-        let txid = btc_rpc.send_transaction(tx).await?;
-
-        loop {
-            let tx_metadata = btc_rpc.wait_for_transaction_metadata(txid, num_confirmations).await?;
-
-            tracing::info!("Awaiting parachain confirmations...");
+        Ok(btc_rpc.send_transaction(tx).await?)
+    }
 
-            match parachain_rpc
-                .wait_for_block_in_relay(
-                    H256Le::from_bytes_le(&tx_metadata.block_hash.to_vec()),
-                    Some(num_confirmations),
-                )
-                .await
-            {
-                Ok(_) => {
-                    tracing::info!("Bitcoin successfully sent and relayed");
-                    return Ok(tx_metadata);
-                }
-                Err(e) if e.is_invalid_chain_id() => {
-                    // small delay to prevent spamming
-                    delay_for(ON_FORK_RETRY_DELAY).await;
-                    // re-fetch the metadata - it might be in a different block now
-                    continue;
-                }
-                Err(e) => return Err(e.into()),
-            }
-        }
+    fn log_payment(&self) {
+        decision!(
+            action = "pay",
+            request_type = ?self.request_type,
+            request_id = ?self.hash,
+            amount = %self.amount,
+            btc_address = ?self.btc_address,
+            "Paying request"
+        );
     }
 
     /// Executes the request. Upon failure it will retry
@@ -268,6 +346,14 @@ impl Request {
         parachain_rpc: P,
         tx_metadata: TransactionMetadata,
     ) -> Result<(), Error> {
+        decision!(
+            action = "execute",
+            request_type = ?self.request_type,
+            request_id = ?self.hash,
+            txid = %tx_metadata.txid,
+            "Executing request"
+        );
+
         // select the execute function based on request_type
         let execute = match self.request_type {
             RequestType::Redeem => RedeemPallet::execute_redeem,
@@ -327,10 +413,16 @@ pub async fn execute_open_requests<B: BitcoinCoreApi + Clone + Send + Sync + 'st
         .filter(|(_, request)| !request.completed)
         .map(|(hash, request)| Request::from_refund_request(hash, request));
 
-    // collect all requests into a hashmap, indexed by their id
+    // collect all requests into a hashmap, indexed by their id, skipping those that are
+    // already being processed
+    let mut claims = HashMap::new();
     let mut open_requests = open_redeems
         .chain(open_replaces)
         .chain(open_refunds)
+        .filter(|x| match RequestClaim::new(x.hash) {
+            Some(claim) => claims.insert(x.hash, claim).is_none(),
+            None => false,
+        })
         .map(|x| (x.hash, x))
         .collect::<HashMap<_, _>>();
 
@@ -364,7 +456,9 @@ pub async fn execute_open_requests<B: BitcoinCoreApi + Clone + Send + Sync + 'st
             // make copies of the variables we move into the task
             let parachain_rpc = parachain_rpc.clone();
             let btc_rpc = btc_rpc.clone();
+            let claim = claims.remove(&request.hash);
             tokio::spawn(async move {
+                let _claim = claim;
                 // Payment has been made, but it might not have been confirmed enough times yet
                 let tx_metadata = btc_rpc
                     .clone()
@@ -389,7 +483,9 @@ pub async fn execute_open_requests<B: BitcoinCoreApi + Clone + Send + Sync + 'st
                             // continue; try to execute anyway
                         }
 
-                        match request.execute(parachain_rpc.clone(), tx_metadata).await {
+                        let result = request.execute(parachain_rpc.clone(), tx_metadata).await;
+                        metrics::record_request_result(request.request_type, &result);
+                        match result {
                             Ok(_) => {
                                 tracing::info!("Executed request #{:?}", request.hash);
                             }
@@ -415,7 +511,9 @@ pub async fn execute_open_requests<B: BitcoinCoreApi + Clone + Send + Sync + 'st
         // make copies of the variables we move into the task
         let parachain_rpc = parachain_rpc.clone();
         let btc_rpc = btc_rpc.clone();
+        let claim = claims.remove(&request.hash);
         tokio::spawn(async move {
+            let _claim = claim;
             tracing::info!(
                 "{:?} request #{:?} found without bitcoin payment - processing...",
                 request.request_type,
@@ -459,8 +557,8 @@ mod tests {
     use super::*;
     use async_trait::async_trait;
     use bitcoin::{
-        Block, BlockHash, BlockHeader, Error as BitcoinError, GetBlockResult, LockedTransaction, PartialAddress,
-        PrivateKey, Transaction, TransactionMetadata, Txid, PUBLIC_KEY_SIZE,
+        Amount, Block, BlockHash, BlockHeader, Error as BitcoinError, GetBlockResult, Hash, LockedTransaction,
+        PartialAddress, PrivateKey, Transaction, TransactionMetadata, Txid, PUBLIC_KEY_SIZE,
     };
     use runtime::{
         AccountId, BlockNumber, BtcPublicKey, Error as RuntimeError, ErrorCode, InterBtcRichBlockHeader, InterBtcVault,
@@ -616,6 +714,7 @@ mod tests {
 
         #[async_trait]
         trait BitcoinCoreApi {
+            fn is_dry_run(&self) -> bool;
             async fn wait_for_block(&self, height: u32, num_confirmations: u32) -> Result<Block, BitcoinError>;
             async fn get_block_count(&self) -> Result<u64, BitcoinError>;
             async fn get_raw_tx(&self, txid: &Txid, block_hash: &BlockHash) -> Result<Vec<u8>, BitcoinError>;
@@ -623,6 +722,7 @@ mod tests {
             async fn get_block_hash(&self, height: u32) -> Result<BlockHash, BitcoinError>;
             async fn is_block_known(&self, block_hash: BlockHash) -> Result<bool, BitcoinError>;
             async fn get_new_address<A: PartialAddress + Send + 'static>(&self) -> Result<A, BitcoinError>;
+            async fn get_balance(&self, min_confirmations: Option<u32>) -> Result<Amount, BitcoinError>;
             async fn get_new_public_key<P: From<[u8; PUBLIC_KEY_SIZE]> + 'static>(&self) -> Result<P, BitcoinError>;
             async fn add_new_deposit_key<P: Into<[u8; PUBLIC_KEY_SIZE]> + Send + Sync + 'static>(
                 &self,
@@ -636,6 +736,8 @@ mod tests {
             async fn get_mempool_transactions<'a>(
                 &'a self,
             ) -> Result<Box<dyn Iterator<Item = Result<Transaction, BitcoinError>> + Send + 'a>, BitcoinError>;
+            async fn get_mempool_txids(&self) -> Result<Vec<Txid>, BitcoinError>;
+            async fn get_mempool_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, BitcoinError>;
             async fn wait_for_transaction_metadata(
                 &self,
                 txid: Txid,
@@ -661,6 +763,8 @@ mod tests {
                 request_id: Option<H256>,
                 num_confirmations: u32,
             ) -> Result<TransactionMetadata, BitcoinError>;
+            async fn sweep_funds<A: PartialAddress + Send + Sync + 'static>(&self, address: A) -> Result<Txid, BitcoinError>;
+            async fn find_sent_transaction<A: PartialAddress + Send + Sync + 'static>(&self, address: A, op_return: H256) -> Result<Option<Txid>, BitcoinError>;
             async fn create_or_load_wallet(&self) -> Result<(), BitcoinError>;
             async fn wallet_has_public_key<P>(&self, public_key: P) -> Result<bool, BitcoinError>
                 where
@@ -745,6 +849,11 @@ mod tests {
                 .expect_get_block_count()
                 .returning(move || Ok(current_bitcoin_height as u64));
 
+            btc_rpc.expect_is_dry_run().returning(|| false);
+            btc_rpc
+                .expect_find_sent_transaction::<BtcAddress>()
+                .returning(|_, _| Ok(None));
+
             btc_rpc.expect_create_transaction::<BtcAddress>().returning(|_, _, _| {
                 Ok(LockedTransaction::new(
                     Transaction {
@@ -863,6 +972,10 @@ mod tests {
             .returning(|_, _| Ok(()));
 
         let mut btc_rpc = MockBitcoin::default();
+        btc_rpc.expect_is_dry_run().returning(|| false);
+        btc_rpc
+            .expect_find_sent_transaction::<BtcAddress>()
+            .returning(|_, _| Ok(None));
         btc_rpc.expect_create_transaction::<BtcAddress>().returning(|_, _, _| {
             Ok(LockedTransaction::new(
                 Transaction {
@@ -902,4 +1015,89 @@ mod tests {
 
         assert_ok!(request.pay_and_execute(parachain_rpc, btc_rpc, 6).await);
     }
+
+    #[tokio::test]
+    async fn should_not_pay_request_that_was_already_paid() {
+        let mut parachain_rpc = MockProvider::default();
+        parachain_rpc
+            .expect_get_current_active_block_number()
+            .times(1)
+            .returning(|| Ok(50));
+        parachain_rpc
+            .expect_execute_redeem()
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        parachain_rpc
+            .expect_wait_for_block_in_relay()
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let mut btc_rpc = MockBitcoin::default();
+        btc_rpc.expect_get_block_count().returning(|| Ok(50));
+        btc_rpc.expect_is_dry_run().returning(|| false);
+        let paid_txid = Txid::from_slice(&[2; 32]).unwrap();
+        btc_rpc
+            .expect_find_sent_transaction::<BtcAddress>()
+            .withf(|_, op_return| op_return == &H256::from_slice(&[1; 32]))
+            .times(1)
+            .returning(move |_, _| Ok(Some(paid_txid)));
+        // omitting create_transaction and send_transaction to test that they do not get called
+        btc_rpc
+            .expect_wait_for_transaction_metadata()
+            .withf(move |txid, _| txid == &paid_txid)
+            .times(1)
+            .returning(|txid, _| {
+                Ok(TransactionMetadata {
+                    txid,
+                    proof: vec![],
+                    raw_tx: vec![],
+                    block_height: 0,
+                    block_hash: BlockHash::default(),
+                })
+            });
+
+        let request = Request {
+            amount: 100,
+            deadline: Some(Deadline {
+                parachain: 100,
+                bitcoin: 100,
+            }),
+            btc_address: BtcAddress::P2SH(H160::from_slice(&[1; 20])),
+            hash: H256::from_slice(&[1; 32]),
+            btc_height: None,
+            request_type: RequestType::Redeem,
+        };
+
+        assert_ok!(request.pay_and_execute(parachain_rpc, btc_rpc, 6).await);
+    }
+
+    #[tokio::test]
+    async fn should_not_touch_wallet_in_dry_run() {
+        let mut parachain_rpc = MockProvider::default();
+        parachain_rpc
+            .expect_get_current_active_block_number()
+            .times(1)
+            .returning(|| Ok(50));
+        let mut btc_rpc = MockBitcoin::default();
+        btc_rpc.expect_get_block_count().returning(|| Ok(50));
+        btc_rpc.expect_is_dry_run().returning(|| true);
+        // omitting the wallet and execute mocks to test that they do not get called
+
+        let request = Request {
+            amount: 100,
+            deadline: Some(Deadline {
+                parachain: 100,
+                bitcoin: 100,
+            }),
+            btc_address: BtcAddress::P2SH(H160::from_slice(&[1; 20])),
+            hash: H256::from_slice(&[1; 32]),
+            btc_height: None,
+            request_type: RequestType::Redeem,
+        };
+
+        assert_err!(
+            request.pay_and_execute(parachain_rpc, btc_rpc, 6).await,
+            Error::BitcoinError(BitcoinError::DryRun)
+        );
+    }
 }
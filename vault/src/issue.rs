@@ -1,4 +1,4 @@
-use crate::{Error, Event, IssueRequests};
+use crate::{metrics, Error, Event, IssueRequests};
 use bitcoin::{BitcoinCoreApi, BlockHash, Transaction, TransactionExt};
 use futures::{channel::mpsc::Sender, future, SinkExt, StreamExt};
 use runtime::{
@@ -148,9 +148,16 @@ async fn process_transaction_and_execute_issue<B: BitcoinCoreApi + Clone + Send
                 let raw_tx = bitcoin_core.get_raw_tx(&txid, &block_hash).await?;
                 let proof = bitcoin_core.get_proof(txid, &block_hash).await?;
 
-                tracing::info!("Executing issue #{:?}", issue_id);
+                decision!(
+                    action = "execute_issue",
+                    issue_id = ?issue_id,
+                    amount = %transferred,
+                    txid = %txid,
+                    "Executing issue #{:?}",
+                    issue_id
+                );
                 match btc_parachain.execute_issue(issue_id, &proof, &raw_tx).await {
-                    Ok(_) => (),
+                    Ok(_) => metrics::ISSUE_EXECUTIONS.inc(),
                     Err(err) if err.is_issue_completed() => {
                         tracing::info!("Issue #{} has already been completed", issue_id);
                     }
@@ -170,6 +177,10 @@ async fn add_new_deposit_key<B: BitcoinCoreApi + Clone + Send + Sync + 'static>(
     secure_id: H256,
     public_key: BtcPublicKey,
 ) -> Result<(), Error> {
+    if bitcoin_core.is_dry_run() {
+        decision!(action = "add_deposit_key", issue_id = ?secure_id, "Importing deposit key");
+        return Ok(());
+    }
     let mut hasher = Sha256::default();
     // input compressed public key
     hasher.input(public_key.0.to_vec());
@@ -0,0 +1,250 @@
+use crate::error::Error;
+use bitcoin::{BitcoinCoreApi, Txid};
+use runtime::{BtcAddress, BtcPublicKey, VaultRegistryPallet};
+
+/// Generate a new public key in the wallet and register it with the parachain.
+///
+/// The private keys of previous public keys remain in the wallet, so deposit keys of issue
+/// requests that were opened before the rotation can still be derived and their payments
+/// are still executed.
+pub async fn rotate_public_key<P: VaultRegistryPallet, B: BitcoinCoreApi + Clone + Send + Sync + 'static>(
+    btc_parachain: &P,
+    bitcoin_core: &B,
+) -> Result<BtcPublicKey, Error> {
+    let public_key: BtcPublicKey = bitcoin_core.get_new_public_key().await?;
+    btc_parachain.update_public_key(public_key.clone()).await?;
+    tracing::info!("Updated public key to {:?}", public_key);
+    Ok(public_key)
+}
+
+/// Move all funds in the wallet to a newly generated address. The address is registered
+/// with the parachain before the transfer, otherwise the transfer would be reported as theft.
+pub async fn sweep_funds<P: VaultRegistryPallet, B: BitcoinCoreApi + Clone + Send + Sync + 'static>(
+    btc_parachain: &P,
+    bitcoin_core: &B,
+) -> Result<Txid, Error> {
+    let address: BtcAddress = bitcoin_core.get_new_address().await?;
+    btc_parachain.register_address(address).await?;
+    tracing::info!("Registered new address {:?}", address);
+
+    let txid = bitcoin_core.sweep_funds(address).await?;
+    tracing::info!("Swept wallet funds to {:?} in transaction {}", address, txid);
+    Ok(txid)
+}
+
+/// Check that the wallet contains the `registered` public key of the vault. If it does not and
+/// `auto_rotate` is set, a new key is registered and returned, otherwise startup fails. Note that
+/// a key is also missing if the wrong wallet is loaded, which `auto_rotate` does not tell apart.
+/// In a dry run the rotation is only logged.
+pub async fn ensure_public_key<P: VaultRegistryPallet, B: BitcoinCoreApi + Clone + Send + Sync + 'static>(
+    btc_parachain: &P,
+    bitcoin_core: &B,
+    registered: BtcPublicKey,
+    auto_rotate: bool,
+) -> Result<Option<BtcPublicKey>, Error> {
+    if bitcoin_core.wallet_has_public_key(registered.0).await? {
+        return Ok(None);
+    }
+    if !auto_rotate {
+        return Err(bitcoin::Error::MissingPublicKey.into());
+    }
+    tracing::warn!("Registered public key not found in wallet, registering a new one");
+    if bitcoin_core.is_dry_run() {
+        decision!(action = "rotate_public_key", "Registering a new public key");
+        return Ok(None);
+    }
+    Ok(Some(rotate_public_key(btc_parachain, bitcoin_core).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use bitcoin::{
+        Amount, Block, BlockHash, BlockHeader, Error as BitcoinError, GetBlockResult, LockedTransaction,
+        PartialAddress, PrivateKey, Transaction, TransactionMetadata, PUBLIC_KEY_SIZE,
+    };
+    use runtime::FakeParachain;
+    use sp_core::{H160, H256};
+    use sp_keyring::AccountKeyring;
+
+    mockall::mock! {
+        Bitcoin {}
+
+        #[async_trait]
+        trait BitcoinCoreApi {
+            fn is_dry_run(&self) -> bool;
+            async fn wait_for_block(&self, height: u32, num_confirmations: u32) -> Result<Block, BitcoinError>;
+            async fn get_block_count(&self) -> Result<u64, BitcoinError>;
+            async fn get_raw_tx(&self, txid: &Txid, block_hash: &BlockHash) -> Result<Vec<u8>, BitcoinError>;
+            async fn get_proof(&self, txid: Txid, block_hash: &BlockHash) -> Result<Vec<u8>, BitcoinError>;
+            async fn get_block_hash(&self, height: u32) -> Result<BlockHash, BitcoinError>;
+            async fn is_block_known(&self, block_hash: BlockHash) -> Result<bool, BitcoinError>;
+            async fn get_new_address<A: PartialAddress + Send + 'static>(&self) -> Result<A, BitcoinError>;
+            async fn get_balance(&self, min_confirmations: Option<u32>) -> Result<Amount, BitcoinError>;
+            async fn get_new_public_key<P: From<[u8; PUBLIC_KEY_SIZE]> + 'static>(&self) -> Result<P, BitcoinError>;
+            async fn add_new_deposit_key<P: Into<[u8; PUBLIC_KEY_SIZE]> + Send + Sync + 'static>(
+                &self,
+                public_key: P,
+                secret_key: Vec<u8>,
+            ) -> Result<(), BitcoinError>;
+            async fn get_best_block_hash(&self) -> Result<BlockHash, BitcoinError>;
+            async fn get_block(&self, hash: &BlockHash) -> Result<Block, BitcoinError>;
+            async fn get_block_header(&self, hash: &BlockHash) -> Result<BlockHeader, BitcoinError>;
+            async fn get_block_info(&self, hash: &BlockHash) -> Result<GetBlockResult, BitcoinError>;
+            async fn get_mempool_transactions<'a>(
+                &'a self,
+            ) -> Result<Box<dyn Iterator<Item = Result<Transaction, BitcoinError>> + Send +'a>, BitcoinError>;
+            async fn get_mempool_txids(&self) -> Result<Vec<Txid>, BitcoinError>;
+            async fn get_mempool_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, BitcoinError>;
+            async fn wait_for_transaction_metadata(
+                &self,
+                txid: Txid,
+                num_confirmations: u32,
+            ) -> Result<TransactionMetadata, BitcoinError>;
+            async fn create_transaction<A: PartialAddress + Send + Sync + 'static>(
+                &self,
+                address: A,
+                sat: u64,
+                request_id: Option<H256>,
+            ) -> Result<LockedTransaction, BitcoinError>;
+            async fn send_transaction(&self, transaction: LockedTransaction) -> Result<Txid, BitcoinError>;
+            async fn create_and_send_transaction<A: PartialAddress + Send + Sync + 'static>(
+                &self,
+                address: A,
+                sat: u64,
+                request_id: Option<H256>,
+            ) -> Result<Txid, BitcoinError>;
+            async fn send_to_address<A: PartialAddress + Send + Sync + 'static>(
+                &self,
+                address: A,
+                sat: u64,
+                request_id: Option<H256>,
+                num_confirmations: u32,
+            ) -> Result<TransactionMetadata, BitcoinError>;
+            async fn sweep_funds<A: PartialAddress + Send + Sync + 'static>(&self, address: A) -> Result<Txid, BitcoinError>;
+            async fn find_sent_transaction<A: PartialAddress + Send + Sync + 'static>(&self, address: A, op_return: H256) -> Result<Option<Txid>, BitcoinError>;
+            async fn create_or_load_wallet(&self) -> Result<(), BitcoinError>;
+            async fn wallet_has_public_key<P>(&self, public_key: P) -> Result<bool, BitcoinError>
+                where
+                    P: Into<[u8; PUBLIC_KEY_SIZE]> + From<[u8; PUBLIC_KEY_SIZE]> + Clone + PartialEq + Send + Sync + 'static;
+            async fn import_private_key(&self, privkey: PrivateKey) -> Result<(), BitcoinError>;
+            async fn rescan_blockchain(&self, start_height: usize) -> Result<(), BitcoinError>;
+        }
+    }
+
+    impl Clone for MockBitcoin {
+        fn clone(&self) -> Self {
+            // NOTE: expectations dropped
+            Self::default()
+        }
+    }
+
+    const OLD_KEY: [u8; PUBLIC_KEY_SIZE] = [2; PUBLIC_KEY_SIZE];
+    const NEW_KEY: [u8; PUBLIC_KEY_SIZE] = [3; PUBLIC_KEY_SIZE];
+
+    async fn registered_vault() -> FakeParachain {
+        let vault_id = AccountKeyring::Bob.to_account_id();
+        let parachain = FakeParachain::new(vault_id.clone());
+        parachain.set_free_balance(vault_id, 2_000);
+        parachain.register_vault(1_000, BtcPublicKey(OLD_KEY)).await.unwrap();
+        parachain
+    }
+
+    async fn registered_key(parachain: &FakeParachain) -> BtcPublicKey {
+        parachain
+            .get_vault(AccountKeyring::Bob.to_account_id())
+            .await
+            .unwrap()
+            .wallet
+            .public_key
+    }
+
+    #[tokio::test]
+    async fn test_rotate_public_key_registers_new_key() {
+        let parachain = registered_vault().await;
+        let mut bitcoin = MockBitcoin::default();
+        bitcoin
+            .expect_get_new_public_key::<BtcPublicKey>()
+            .times(1)
+            .returning(|| Ok(BtcPublicKey(NEW_KEY)));
+
+        assert_eq!(
+            rotate_public_key(&parachain, &bitcoin).await.unwrap(),
+            BtcPublicKey(NEW_KEY)
+        );
+        assert_eq!(registered_key(&parachain).await, BtcPublicKey(NEW_KEY));
+    }
+
+    #[tokio::test]
+    async fn test_ensure_public_key_keeps_key_in_wallet() {
+        let parachain = registered_vault().await;
+        let mut bitcoin = MockBitcoin::default();
+        bitcoin
+            .expect_wallet_has_public_key::<[u8; PUBLIC_KEY_SIZE]>()
+            .returning(|_| Ok(true));
+        bitcoin.expect_get_new_public_key::<BtcPublicKey>().never();
+
+        let rotated = ensure_public_key(&parachain, &bitcoin, BtcPublicKey(OLD_KEY), true).await;
+        assert_eq!(rotated.unwrap(), None);
+        assert_eq!(registered_key(&parachain).await, BtcPublicKey(OLD_KEY));
+    }
+
+    #[tokio::test]
+    async fn test_ensure_public_key_fails_on_missing_key_unless_asked() {
+        let parachain = registered_vault().await;
+        let mut bitcoin = MockBitcoin::default();
+        bitcoin
+            .expect_wallet_has_public_key::<[u8; PUBLIC_KEY_SIZE]>()
+            .returning(|_| Ok(false));
+        bitcoin.expect_is_dry_run().returning(|| false);
+        bitcoin
+            .expect_get_new_public_key::<BtcPublicKey>()
+            .times(1)
+            .returning(|| Ok(BtcPublicKey(NEW_KEY)));
+
+        assert!(matches!(
+            ensure_public_key(&parachain, &bitcoin, BtcPublicKey(OLD_KEY), false).await,
+            Err(Error::BitcoinError(BitcoinError::MissingPublicKey))
+        ));
+        assert_eq!(registered_key(&parachain).await, BtcPublicKey(OLD_KEY));
+
+        let rotated = ensure_public_key(&parachain, &bitcoin, BtcPublicKey(OLD_KEY), true).await;
+        assert_eq!(rotated.unwrap(), Some(BtcPublicKey(NEW_KEY)));
+        assert_eq!(registered_key(&parachain).await, BtcPublicKey(NEW_KEY));
+    }
+
+    #[tokio::test]
+    async fn test_ensure_public_key_does_not_rotate_in_dry_run() {
+        let parachain = registered_vault().await;
+        let mut bitcoin = MockBitcoin::default();
+        bitcoin
+            .expect_wallet_has_public_key::<[u8; PUBLIC_KEY_SIZE]>()
+            .returning(|_| Ok(false));
+        bitcoin.expect_is_dry_run().returning(|| true);
+        bitcoin.expect_get_new_public_key::<BtcPublicKey>().never();
+
+        let rotated = ensure_public_key(&parachain, &bitcoin, BtcPublicKey(OLD_KEY), true).await;
+        assert_eq!(rotated.unwrap(), None);
+        assert_eq!(registered_key(&parachain).await, BtcPublicKey(OLD_KEY));
+    }
+
+    #[tokio::test]
+    async fn test_sweep_funds_registers_address_first() {
+        let parachain = registered_vault().await;
+        let address = BtcAddress::P2PKH(H160::repeat_byte(1));
+        let mut bitcoin = MockBitcoin::default();
+        bitcoin
+            .expect_get_new_address::<BtcAddress>()
+            .returning(move || Ok(address));
+        bitcoin
+            .expect_sweep_funds::<BtcAddress>()
+            .withf(move |to| *to == address)
+            .times(1)
+            .returning(|_| Ok(Txid::default()));
+
+        assert_eq!(sweep_funds(&parachain, &bitcoin).await.unwrap(), Txid::default());
+        let vault = parachain.get_vault(AccountKeyring::Bob.to_account_id()).await.unwrap();
+        assert!(vault.wallet.addresses.contains(&address));
+    }
+}
@@ -1,21 +1,31 @@
 #![recursion_limit = "256"]
 
+/// Log a decision taken by the vault under the `decision` target, so that the
+/// actions of a dry run (see `--dry-run`) can be compared against a live vault.
+macro_rules! decision {
+    ($($arg:tt)+) => {
+        tracing::info!(target: "decision", $($arg)+)
+    };
+}
+
 mod cancellation;
 mod collateral;
+mod commands;
 mod error;
 mod execution;
 mod faucet;
 mod issue;
+mod keys;
+mod metrics;
 mod redeem;
 mod refund;
-mod relay;
 mod replace;
 mod system;
+mod theft_archive;
 mod types;
 mod vaults;
 
 use runtime::{InterBtcParachain, VaultRegistryPallet};
-use std::time::Duration;
 
 pub mod service {
     pub use crate::{
@@ -25,22 +35,35 @@ pub mod service {
         issue::{
             listen_for_issue_cancels, listen_for_issue_executes, listen_for_issue_requests, process_issue_requests,
         },
+        metrics::update_balance_metrics,
         redeem::listen_for_redeem_requests,
         refund::listen_for_refund_requests,
-        relay::{Config, Runner},
         replace::{listen_for_accept_replace, listen_for_execute_replace, listen_for_replace_requests},
-        vaults::{listen_for_vaults_registered, listen_for_wallet_updates, report_vault_thefts},
+        vaults::{
+            listen_for_vaults_registered, listen_for_wallet_updates, reconcile_vault_index, report_vault_thefts,
+            retry_theft_reports, sync_vault_index, watch_mempool_for_thefts,
+        },
+    };
+    pub use relay::{
+        run_relay_monitor, run_relayer, BackingSource, ChainParams, Checkpoint, Config, QuorumBacking, RelayMonitor,
+        RelayMonitorConfig, Runner,
     };
 }
-pub use crate::{cancellation::Event, error::Error, system::*, types::IssueRequests};
-pub use vaults::Vaults;
+pub use crate::{
+    cancellation::Event,
+    commands::AdminCommand,
+    error::Error,
+    keys::{rotate_public_key, sweep_funds},
+    system::*,
+    theft_archive::{ReportStatus, TheftArchive, TheftEvidence},
+    types::IssueRequests,
+};
+pub use vaults::{SuspiciousTransactions, Vaults};
 
 pub(crate) async fn deposit_collateral(api: &InterBtcParachain, amount: u128) -> Result<(), Error> {
+    collateral::ensure_spendable(api, amount).await?;
+    decision!(action = "lock_collateral", amount = %amount, "Locking additional collateral");
     let result = api.deposit_collateral(amount).await;
     tracing::info!("Locking additional collateral; amount {}: {:?}", amount, result);
     Ok(result?)
 }
-
-/// At startup we wait until a new block has arrived before we start event listeners.
-/// This constant defines the rate at which we check whether the chain height has increased.
-pub const CHAIN_HEIGHT_POLLING_INTERVAL: Duration = Duration::from_millis(500);
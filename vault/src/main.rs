@@ -2,7 +2,9 @@ use clap::Clap;
 use runtime::{substrate_subxt::PairSigner, InterBtcRuntime};
 use service::{ConnectionManager, ServiceConfig};
 
-use vault::{Error, VaultService, VaultServiceConfig, ABOUT, AUTHORS, NAME, VERSION};
+use vault::{
+    AdminCommand, Error, VaultConfig, VaultIdentity, VaultService, VaultServiceConfig, ABOUT, AUTHORS, NAME, VERSION,
+};
 
 #[derive(Clap, Debug, Clone)]
 #[clap(name = NAME, version = VERSION, author = AUTHORS, about = ABOUT)]
@@ -11,6 +13,11 @@ pub struct Opts {
     #[clap(flatten)]
     pub account_info: runtime::cli::ProviderUserOpts,
 
+    /// The name of another account from the keyfile to run as a vault in this process,
+    /// using the wallet of the same name. Can be given multiple times.
+    #[clap(long, requires = "keyfile")]
+    pub additional_keyname: Vec<String>,
+
     /// Connection settings for the BTC Parachain.
     #[clap(flatten)]
     pub parachain: runtime::cli::ConnectionOpts,
@@ -26,22 +33,58 @@ pub struct Opts {
     /// General service settings.
     #[clap(flatten)]
     pub service: ServiceConfig,
+
+    /// Run a single administrative command instead of the vault service.
+    #[clap(subcommand)]
+    pub cmd: Option<AdminCommand>,
 }
 
 async fn start() -> Result<(), Error> {
     let opts: Opts = Opts::parse();
     opts.service.logging_format.init_subscriber();
 
-    let (pair, wallet_name) = opts.account_info.get_key_pair()?;
+    if let Some(AdminCommand::ListTheftReports(archive)) = &opts.cmd {
+        return archive.print();
+    }
+
+    let mut key_pairs = opts.account_info.get_key_pairs(&opts.additional_keyname)?;
+    let (pair, wallet_name) = key_pairs.remove(0);
     let signer = PairSigner::<InterBtcRuntime, _>::new(pair);
 
+    if let Some(cmd) = opts.cmd {
+        let btc_parachain = opts
+            .parachain
+            .try_connect(signer)
+            .await?
+            .with_dry_run(opts.service.dry_run);
+        // only connected by the commands that use the wallet
+        let (bitcoin, dry_run) = (&opts.bitcoin, opts.service.dry_run);
+        let bitcoin_core = async {
+            let bitcoin_core = bitcoin.new_client(Some(wallet_name.to_string()))?.with_dry_run(dry_run);
+            bitcoin_core.connect().await?;
+            Ok::<_, Error>(bitcoin_core)
+        };
+        return cmd.execute(&btc_parachain, bitcoin_core).await;
+    }
+
+    let additional_vaults = key_pairs
+        .into_iter()
+        .map(|(pair, wallet_name)| VaultIdentity {
+            signer: PairSigner::<InterBtcRuntime, _>::new(pair),
+            wallet_name,
+        })
+        .collect();
+
     ConnectionManager::<_, VaultService>::new(
         signer.clone(),
         Some(wallet_name.to_string()),
         opts.bitcoin,
         opts.parachain,
         opts.service,
-        opts.vault,
+        VaultConfig {
+            service: opts.vault,
+            additional_vaults,
+        },
     )
     .start()
     .await?;
@@ -0,0 +1,103 @@
+use crate::{error::Error, execution::RequestType};
+use bitcoin::BitcoinCoreApi;
+use lazy_static::lazy_static;
+use prometheus::{
+    register_gauge_vec, register_int_counter, register_int_counter_vec, register_int_gauge, GaugeVec, IntCounter,
+    IntCounterVec, IntGauge,
+};
+use runtime::{InterBtcParachain, UtilFuncs, VaultRegistryPallet};
+use service::Error as ServiceError;
+use sp_core::crypto::Ss58Codec;
+use std::time::Duration;
+use tokio::time::delay_for;
+
+/// Interval at which the collateral and wallet balance gauges are updated.
+const BALANCE_UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    pub(crate) static ref REQUESTS_PROCESSED: IntCounterVec = register_int_counter_vec!(
+        "vault_requests_processed_total",
+        "Number of successfully executed requests",
+        &["request_type"]
+    )
+    .unwrap();
+    pub(crate) static ref REQUESTS_FAILED: IntCounterVec = register_int_counter_vec!(
+        "vault_requests_failed_total",
+        "Number of requests that failed to process",
+        &["request_type"]
+    )
+    .unwrap();
+    pub(crate) static ref ISSUE_EXECUTIONS: IntCounter =
+        register_int_counter!("vault_issue_executions_total", "Number of executed issue requests").unwrap();
+    pub(crate) static ref THEFT_REPORTS: IntCounter =
+        register_int_counter!("vault_theft_reports_total", "Number of submitted theft reports").unwrap();
+    pub(crate) static ref MEMPOOL_THEFTS: IntCounter = register_int_counter!(
+        "vault_mempool_thefts_total",
+        "Number of unconfirmed transactions that spend from a vault without a matching request"
+    )
+    .unwrap();
+    pub(crate) static ref SUSPICIOUS_TRANSACTIONS: IntGauge = register_int_gauge!(
+        "vault_suspicious_transactions",
+        "Number of flagged unconfirmed transactions that have not been confirmed yet"
+    )
+    .unwrap();
+    pub(crate) static ref COLLATERAL: GaugeVec = register_gauge_vec!(
+        "vault_collateral",
+        "Total collateral locked by the vault",
+        &["vault_id"]
+    )
+    .unwrap();
+    pub(crate) static ref REQUIRED_COLLATERAL: GaugeVec = register_gauge_vec!(
+        "vault_required_collateral",
+        "Collateral required to back the tokens issued by the vault",
+        &["vault_id"]
+    )
+    .unwrap();
+    pub(crate) static ref BTC_BALANCE: GaugeVec = register_gauge_vec!(
+        "vault_btc_balance",
+        "Confirmed balance of the bitcoin wallet in BTC",
+        &["vault_id"]
+    )
+    .unwrap();
+}
+
+/// Count the outcome of processing a redeem, replace or refund request.
+pub(crate) fn record_request_result(request_type: RequestType, result: &Result<(), Error>) {
+    let label = format!("{:?}", request_type).to_lowercase();
+    match result {
+        Ok(_) => REQUESTS_PROCESSED.with_label_values(&[&label]).inc(),
+        Err(_) => REQUESTS_FAILED.with_label_values(&[&label]).inc(),
+    }
+}
+
+/// Periodically update the collateral and wallet balance gauges, runs forever.
+pub async fn update_balance_metrics<B: BitcoinCoreApi + Clone + Send + Sync + 'static>(
+    btc_parachain: InterBtcParachain,
+    bitcoin_core: B,
+) -> Result<(), ServiceError> {
+    let vault_id = btc_parachain.get_account_id().clone();
+    let label = vault_id.to_ss58check();
+    loop {
+        match futures::future::try_join(
+            btc_parachain.get_vault_total_collateral(vault_id.clone()),
+            btc_parachain.get_required_collateral_for_vault(vault_id.clone()),
+        )
+        .await
+        {
+            Ok((collateral, required_collateral)) => {
+                COLLATERAL.with_label_values(&[&label]).set(collateral as f64);
+                REQUIRED_COLLATERAL
+                    .with_label_values(&[&label])
+                    .set(required_collateral as f64);
+            }
+            Err(err) => tracing::debug!("Failed to update collateral metrics: {}", err),
+        }
+
+        match bitcoin_core.get_balance(None).await {
+            Ok(balance) => BTC_BALANCE.with_label_values(&[&label]).set(balance.as_btc()),
+            Err(err) => tracing::debug!("Failed to update balance metrics: {}", err),
+        }
+
+        delay_for(BALANCE_UPDATE_INTERVAL).await;
+    }
+}
@@ -1,6 +1,9 @@
 use crate::execution::*;
 use bitcoin::BitcoinCoreApi;
-use runtime::{pallets::redeem::RequestRedeemEvent, InterBtcParachain, InterBtcRuntime, RedeemPallet, UtilFuncs};
+use runtime::{
+    pallets::redeem::RequestRedeemEvent, InterBtcParachain, InterBtcRuntime, RedeemPallet, RedeemRequestStatus,
+    UtilFuncs,
+};
 use service::Error as ServiceError;
 use std::time::Duration;
 
@@ -34,13 +37,22 @@ pub async fn listen_for_redeem_requests<B: BitcoinCoreApi + Clone + Send + Sync
                 let btc_rpc = btc_rpc.clone();
                 // Spawn a new task so that we handle these events concurrently
                 tokio::spawn(async move {
+                    let _claim = match RequestClaim::new(event.redeem_id) {
+                        Some(claim) => claim,
+                        None => {
+                            tracing::info!("Redeem #{:?} is already being processed", event.redeem_id);
+                            return;
+                        }
+                    };
                     tracing::info!("Executing redeem #{:?}", event.redeem_id);
                     let result = async {
-                        let request = Request::from_redeem_request(
-                            event.redeem_id,
-                            parachain_rpc.get_redeem_request(event.redeem_id).await?,
-                            payment_margin,
-                        )?;
+                        let request = parachain_rpc.get_redeem_request(event.redeem_id).await?;
+                        if request.status != RedeemRequestStatus::Pending {
+                            // e.g. the event has been replayed after a restart
+                            tracing::info!("Redeem #{:?} is no longer pending", event.redeem_id);
+                            return Ok(());
+                        }
+                        let request = Request::from_redeem_request(event.redeem_id, request, payment_margin)?;
                         request.pay_and_execute(parachain_rpc, btc_rpc, num_confirmations).await
                     }
                     .await;
@@ -1,6 +1,6 @@
 use crate::execution::*;
 use bitcoin::BitcoinCoreApi;
-use runtime::{pallets::refund::RequestRefundEvent, InterBtcParachain, InterBtcRuntime, UtilFuncs};
+use runtime::{pallets::refund::RequestRefundEvent, InterBtcParachain, InterBtcRuntime, RefundPallet, UtilFuncs};
 use service::Error as ServiceError;
 
 /// Listen for RequestRefundEvent directed at this vault; upon reception, transfer
@@ -32,10 +32,30 @@ pub async fn listen_for_refund_requests<B: BitcoinCoreApi + Clone + Send + Sync
                 let btc_rpc = btc_rpc.clone();
                 // Spawn a new task so that we handle these events concurrently
                 tokio::spawn(async move {
+                    let _claim = match RequestClaim::new(event.refund_id) {
+                        Some(claim) => claim,
+                        None => {
+                            tracing::info!("Refund #{:?} is already being processed", event.refund_id);
+                            return;
+                        }
+                    };
                     tracing::info!("Executing refund #{:?}", event.refund_id);
-                    // prepare the action that will be executed after the bitcoin transfer
-                    let request = Request::from_refund_request_event(&event);
-                    let result = request.pay_and_execute(parachain_rpc, btc_rpc, num_confirmations).await;
+                    let result = async {
+                        let is_pending = parachain_rpc
+                            .get_vault_refund_requests(event.vault_id.clone())
+                            .await?
+                            .into_iter()
+                            .any(|(refund_id, request)| refund_id == event.refund_id && !request.completed);
+                        if !is_pending {
+                            // e.g. the event has been replayed after a restart
+                            tracing::info!("Refund #{:?} is no longer pending", event.refund_id);
+                            return Ok(());
+                        }
+                        // prepare the action that will be executed after the bitcoin transfer
+                        let request = Request::from_refund_request_event(&event);
+                        request.pay_and_execute(parachain_rpc, btc_rpc, num_confirmations).await
+                    }
+                    .await;
 
                     match result {
                         Ok(_) => tracing::info!(
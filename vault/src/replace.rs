@@ -1,9 +1,14 @@
-use crate::{cancellation::Event, error::Error, execution::Request};
+use crate::{
+    cancellation::Event,
+    error::Error,
+    execution::{Request, RequestClaim},
+};
 use bitcoin::BitcoinCoreApi;
 use futures::{channel::mpsc::Sender, future::try_join3, SinkExt};
 use runtime::{
     pallets::replace::{AcceptReplaceEvent, ExecuteReplaceEvent, RequestReplaceEvent},
-    CollateralBalancesPallet, InterBtcParachain, InterBtcRuntime, ReplacePallet, UtilFuncs, VaultRegistryPallet,
+    CollateralBalancesPallet, InterBtcParachain, InterBtcRuntime, ReplacePallet, ReplaceRequestStatus, UtilFuncs,
+    VaultRegistryPallet,
 };
 use service::Error as ServiceError;
 use std::time::Duration;
@@ -39,14 +44,23 @@ pub async fn listen_for_accept_replace<B: BitcoinCoreApi + Clone + Send + Sync +
                 let btc_rpc = btc_rpc.clone();
                 // Spawn a new task so that we handle these events concurrently
                 tokio::spawn(async move {
+                    let _claim = match RequestClaim::new(event.replace_id) {
+                        Some(claim) => claim,
+                        None => {
+                            tracing::info!("Replace #{:?} is already being processed", event.replace_id);
+                            return;
+                        }
+                    };
                     tracing::info!("Executing accept replace #{:?}", event.replace_id);
 
                     let result = async {
-                        let request = Request::from_replace_request(
-                            event.replace_id,
-                            parachain_rpc.get_replace_request(event.replace_id).await?,
-                            payment_margin,
-                        )?;
+                        let request = parachain_rpc.get_replace_request(event.replace_id).await?;
+                        if request.status != ReplaceRequestStatus::Pending {
+                            // e.g. the event has been replayed after a restart
+                            tracing::info!("Replace #{:?} is no longer pending", event.replace_id);
+                            return Ok(());
+                        }
+                        let request = Request::from_replace_request(event.replace_id, request, payment_margin)?;
                         request.pay_and_execute(parachain_rpc, btc_rpc, num_confirmations).await
                     }
                     .await;
@@ -133,18 +147,25 @@ pub async fn handle_replace_request<
     btc_rpc: B,
     event: &RequestReplaceEvent<InterBtcRuntime>,
 ) -> Result<(), Error> {
-    let (required_collateral, free_balance, minimum_replace) = try_join3(
+    let (required_collateral, spendable_balance, minimum_replace) = try_join3(
         parachain_rpc.get_required_collateral_for_wrapped(event.amount_btc),
-        parachain_rpc.get_free_balance(),
+        parachain_rpc.get_spendable_balance(),
         parachain_rpc.get_replace_dust_amount(),
     )
     .await?;
 
     if required_collateral <= minimum_replace {
         Err(Error::BelowDustAmount)
-    } else if free_balance < required_collateral {
+    } else if spendable_balance < required_collateral {
         Err(Error::InsufficientFunds)
     } else {
+        decision!(
+            action = "accept_replace",
+            old_vault_id = %event.old_vault_id,
+            amount = %event.amount_btc,
+            collateral = %required_collateral,
+            "Accepting replace request"
+        );
         Ok(parachain_rpc
             .accept_replace(
                 &event.old_vault_id,
@@ -189,8 +210,8 @@ mod tests {
     use super::*;
     use async_trait::async_trait;
     use bitcoin::{
-        Block, BlockHash, BlockHeader, Error as BitcoinError, GetBlockResult, LockedTransaction, PartialAddress,
-        PrivateKey, Transaction, TransactionMetadata, Txid, PUBLIC_KEY_SIZE,
+        Amount, Block, BlockHash, BlockHeader, Error as BitcoinError, GetBlockResult, LockedTransaction,
+        PartialAddress, PrivateKey, Transaction, TransactionMetadata, Txid, PUBLIC_KEY_SIZE,
     };
     use runtime::{
         pallets::Core, AccountId, BtcAddress, BtcPublicKey, Error as RuntimeError, InterBtcReplaceRequest,
@@ -220,6 +241,7 @@ mod tests {
             async fn get_block_hash(&self, height: u32) -> Result<BlockHash, BitcoinError>;
             async fn is_block_known(&self, block_hash: BlockHash) -> Result<bool, BitcoinError>;
             async fn get_new_address<A: PartialAddress + Send + 'static>(&self) -> Result<A, BitcoinError>;
+            async fn get_balance(&self, min_confirmations: Option<u32>) -> Result<Amount, BitcoinError>;
             async fn get_new_public_key<P: From<[u8; PUBLIC_KEY_SIZE]> + 'static>(&self) -> Result<P, BitcoinError>;
             async fn add_new_deposit_key<P: Into<[u8; PUBLIC_KEY_SIZE]> + Send + Sync + 'static>(
                 &self,
@@ -233,6 +255,8 @@ mod tests {
             async fn get_mempool_transactions<'a>(
                 &'a self,
             ) -> Result<Box<dyn Iterator<Item = Result<Transaction, BitcoinError>> + Send + 'a>, BitcoinError>;
+            async fn get_mempool_txids(&self) -> Result<Vec<Txid>, BitcoinError>;
+            async fn get_mempool_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, BitcoinError>;
             async fn wait_for_transaction_metadata(
                 &self,
                 txid: Txid,
@@ -258,6 +282,8 @@ mod tests {
                 request_id: Option<H256>,
                 num_confirmations: u32,
             ) -> Result<TransactionMetadata, BitcoinError>;
+            async fn sweep_funds<A: PartialAddress + Send + Sync + 'static>(&self, address: A) -> Result<Txid, BitcoinError>;
+            async fn find_sent_transaction<A: PartialAddress + Send + Sync + 'static>(&self, address: A, op_return: H256) -> Result<Option<Txid>, BitcoinError>;
             async fn create_or_load_wallet(&self) -> Result<(), BitcoinError>;
             async fn wallet_has_public_key<P>(&self, public_key: P) -> Result<bool, BitcoinError>
                 where
@@ -326,6 +352,7 @@ mod tests {
         #[async_trait]
         pub trait CollateralBalancesPallet {
             async fn get_free_balance(&self) -> Result<<InterBtcRuntime as Core>::Balance, RuntimeError>;
+            async fn get_spendable_balance(&self) -> Result<<InterBtcRuntime as Core>::Balance, RuntimeError>;
             async fn get_free_balance_for_id(&self, id: AccountId) -> Result<<InterBtcRuntime as Core>::Balance, RuntimeError>;
             async fn get_reserved_balance(&self) -> Result<<InterBtcRuntime as Core>::Balance, RuntimeError>;
             async fn get_reserved_balance_for_id(&self, id: AccountId) -> Result<<InterBtcRuntime as Core>::Balance, RuntimeError>;
@@ -349,7 +376,7 @@ mod tests {
         parachain_rpc
             .expect_get_required_collateral_for_wrapped()
             .returning(|_| Ok(100));
-        parachain_rpc.expect_get_free_balance().returning(|| Ok(50));
+        parachain_rpc.expect_get_spendable_balance().returning(|| Ok(50));
         parachain_rpc
             .expect_get_replace_dust_amount()
             .times(1)
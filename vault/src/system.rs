@@ -1,6 +1,9 @@
 use crate::{
-    collateral::lock_required_collateral, faucet, issue, relay::run_relayer, service::*, Error, Event, IssueRequests,
-    Vaults, CHAIN_HEIGHT_POLLING_INTERVAL,
+    collateral::{ensure_spendable, lock_required_collateral},
+    faucet, issue,
+    keys::ensure_public_key,
+    service::*,
+    Error, Event, IssueRequests, SuspiciousTransactions, TheftArchive, Vaults,
 };
 use async_trait::async_trait;
 use bitcoin::{stream_blocks, BitcoinCore, BitcoinCoreApi};
@@ -8,18 +11,18 @@ use clap::Clap;
 use futures::{
     channel::{mpsc, mpsc::Sender},
     executor::block_on,
-    Future, SinkExt, TryStreamExt,
+    Future, SinkExt, StreamExt, TryStreamExt,
 };
 use git_version::git_version;
 use runtime::{
     cli::{parse_duration_minutes, parse_duration_ms},
     pallets::{security::UpdateActiveBlockEvent, sla::UpdateVaultSLAEvent},
-    AccountId, BtcRelayPallet, Error as RuntimeError, InterBtcParachain, InterBtcRuntime, UtilFuncs,
-    VaultRegistryPallet,
+    AccountId, BtcRelayPallet, Error as RuntimeError, EventHold, InterBtcParachain, InterBtcRuntime, InterBtcSigner,
+    Subsystem, UtilFuncs, VaultRegistryPallet,
 };
 use service::{wait_or_shutdown, Error as ServiceError, Service, ShutdownSender};
-use std::{sync::Arc, time::Duration};
-use tokio::time::delay_for;
+use sp_core::crypto::Ss58Codec;
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 pub const VERSION: &str = git_version!(args = ["--tags"]);
 pub const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
@@ -45,6 +48,12 @@ pub struct VaultServiceConfig {
     #[clap(long)]
     pub no_startup_collateral_increase: bool,
 
+    /// Register a new public key if the loaded wallet does not contain the registered one,
+    /// instead of exiting. Make sure the right wallet is configured, since a key missing from
+    /// another wallet also triggers the rotation.
+    #[clap(long)]
+    pub auto_rotate_public_key: bool,
+
     /// Don't try to execute issues.
     #[clap(long)]
     pub no_issue_execution: bool,
@@ -80,57 +89,126 @@ pub struct VaultServiceConfig {
     #[clap(long, parse(try_from_str = parse_duration_ms), default_value = "6000")]
     pub bitcoin_poll_interval_ms: Duration,
 
-    /// Starting height to relay block headers, if not defined
-    /// use the best height as reported by the relay module.
+    /// Height to initialize the relay at, if it is not initialized yet. Defaults to the last
+    /// difficulty adjustment at least 6 blocks below the Bitcoin tip.
     #[clap(long)]
     pub bitcoin_relay_start_height: Option<u32>,
 
+    /// Json file with the `height`, `hash` and `header` of the block to initialize the relay
+    /// at, if it is not initialized yet. The block must be on the Bitcoin main chain.
+    #[clap(long, conflicts_with("bitcoin-relay-start-height"))]
+    pub bitcoin_relay_checkpoint: Option<PathBuf>,
+
     /// Max batch size for combined block header submission.
     #[clap(long, default_value = "16")]
     pub max_batch_size: u32,
 
+    /// Max number of block header batches that are submitted before the first is included.
+    #[clap(long, default_value = "4")]
+    pub max_batches_in_flight: u32,
+
     /// Number of confirmations a block needs to have before it is submitted.
     #[clap(long, default_value = "0")]
     pub bitcoin_relay_confirmations: u32,
 
+    /// Additional Bitcoin sources to cross-check relayed block headers against, either
+    /// `esplora=<url>` or `bitcoind=<url>;<user>;<password-file>`, where the file contains the
+    /// RPC password. Can be given multiple times.
+    #[clap(long)]
+    pub relay_backing: Vec<BackingSource>,
+
+    /// Number of Bitcoin sources, including the node given by `--bitcoin-rpc-url`, that must
+    /// agree on a block for it to be relayed. Defaults to all sources.
+    #[clap(long)]
+    pub relay_quorum: Option<usize>,
+
     /// Don't relay bitcoin block headers.
     #[clap(long)]
     pub no_bitcoin_block_relay: bool,
 
+    /// Alerting on relay lag and divergence from Bitcoin.
+    #[clap(flatten)]
+    pub relay_monitor: RelayMonitorConfig,
+
     /// Don't monitor vault thefts.
     #[clap(long)]
     pub no_vault_theft_report: bool,
+
+    /// Don't watch the mempool for vault thefts, i.e. only detect them once they are confirmed.
+    #[clap(long)]
+    pub no_mempool_theft_check: bool,
+
+    /// File in which the index of vault addresses is kept, such that it does not have to be
+    /// fetched from the parachain on every start.
+    #[clap(long)]
+    pub vault_index_file: Option<PathBuf>,
+
+    /// Interval in milliseconds at which the vault address index is reconciled with the
+    /// parachain storage.
+    #[clap(long, parse(try_from_str = parse_duration_ms), default_value = "600000")]
+    pub vault_index_reconcile_interval_ms: Duration,
+
+    /// File in which the transactions checked for theft and the outcome of the reports are
+    /// kept, one json record per line, such that failed reports are retried after a restart.
+    #[clap(long)]
+    pub theft_archive_file: Option<PathBuf>,
+}
+
+/// Key pair and bitcoin wallet of an additional vault account run by this process.
+#[derive(Clone)]
+pub struct VaultIdentity {
+    pub signer: InterBtcSigner,
+    pub wallet_name: String,
+}
+
+/// Vault settings together with the accounts that are run in addition to the account
+/// the connection was opened with. All accounts use the same settings.
+#[derive(Clone)]
+pub struct VaultConfig {
+    pub service: VaultServiceConfig,
+    pub additional_vaults: Vec<VaultIdentity>,
 }
 
-async fn active_block_listener(parachain_rpc: InterBtcParachain, block_tx: Sender<Event>) -> Result<(), ServiceError> {
-    let block_tx = &block_tx;
-    parachain_rpc
-        .on_event::<UpdateActiveBlockEvent<InterBtcRuntime>, _, _, _>(
-            |event| async move {
-                let _ = block_tx.clone().send(Event::ParachainBlock(event.height)).await;
-            },
-            |err| tracing::error!("Error (UpdateActiveBlockEvent): {}", err.to_string()),
-        )
+async fn active_block_listener(
+    parachain_rpc: InterBtcParachain,
+    block_txs: Vec<Sender<Event>>,
+) -> Result<(), ServiceError> {
+    let events = parachain_rpc
+        .event_stream::<UpdateActiveBlockEvent<InterBtcRuntime>>()
         .await?;
-    Ok(())
+    futures::pin_mut!(events);
+    while let Some(result) = events.next().await {
+        match result {
+            Ok(event) => {
+                for block_tx in block_txs.iter() {
+                    let _ = block_tx.clone().send(Event::ParachainBlock(event.height)).await;
+                }
+            }
+            Err(err) => tracing::error!("Error (UpdateActiveBlockEvent): {}", err.to_string()),
+        }
+    }
+    Err(RuntimeError::ChannelClosed.into())
 }
 
 pub struct VaultService {
     btc_parachain: InterBtcParachain,
     bitcoin_core: BitcoinCore,
-    config: VaultServiceConfig,
+    config: VaultConfig,
     shutdown: ShutdownSender,
+    /// Holds back the events on the connection until all listeners have been created, taken
+    /// when the service is created so that no other subscriber sees the events first.
+    event_hold: std::sync::Mutex<Option<EventHold>>,
 }
 
 #[async_trait]
-impl Service<VaultServiceConfig> for VaultService {
+impl Service<VaultConfig> for VaultService {
     const NAME: &'static str = NAME;
     const VERSION: &'static str = VERSION;
 
     fn new_service(
         btc_parachain: InterBtcParachain,
         bitcoin_core: BitcoinCore,
-        config: VaultServiceConfig,
+        config: VaultConfig,
         shutdown: ShutdownSender,
     ) -> Self {
         VaultService::new(btc_parachain, bitcoin_core, config, shutdown)
@@ -156,10 +234,11 @@ impl VaultService {
     fn new(
         btc_parachain: InterBtcParachain,
         bitcoin_core: BitcoinCore,
-        config: VaultServiceConfig,
+        config: VaultConfig,
         shutdown: ShutdownSender,
     ) -> Self {
         Self {
+            event_hold: std::sync::Mutex::new(Some(btc_parachain.hold_events())),
             btc_parachain,
             bitcoin_core,
             config,
@@ -168,52 +247,262 @@ impl VaultService {
     }
 
     async fn run_service(&self) -> Result<(), Error> {
-        let bitcoin_core = self.bitcoin_core.clone();
-
-        // load wallet. Exit on failure, since without wallet we can't do a lot
-        bitcoin_core
-            .create_or_load_wallet()
-            .await
-            .map_err(Error::WalletInitializationFailure)?;
-
-        let vault_id = self.btc_parachain.get_account_id().clone();
+        let config = &self.config.service;
+        // the replayed and live events are held back until the listeners below have subscribed,
+        // each of them holds the events itself until then
+        let event_hold = self.event_hold.lock().unwrap().take();
+
+        // refuse to start if the runtime changed in a way that breaks request handling,
+        // the optional subsystems are disabled instead
+        let compatibility = self.btc_parachain.check_compatibility().await?;
+        tracing::info!("Connected to {}", compatibility);
+        compatibility.require(&[
+            Subsystem::Core,
+            Subsystem::Vault,
+            Subsystem::Issue,
+            Subsystem::Redeem,
+            Subsystem::Replace,
+            Subsystem::Refund,
+        ])?;
+        let is_enabled = |subsystem, enabled: bool| {
+            if enabled && !compatibility.supports(subsystem) {
+                tracing::warn!("Disabling {:?}, it is not supported by the runtime", subsystem);
+                return false;
+            }
+            enabled
+        };
+        let run_theft_reporting = is_enabled(Subsystem::TheftReporting, !config.no_vault_theft_report);
+        let relay_enabled = is_enabled(Subsystem::Relay, !config.no_bitcoin_block_relay);
 
-        let num_confirmations = match self.config.btc_confirmations {
+        let num_confirmations = match config.btc_confirmations {
             Some(x) => x,
             None => self.btc_parachain.get_bitcoin_confirmations().await?,
         };
         tracing::info!("Using {} bitcoin confirmations", num_confirmations);
 
-        if let Some(collateral) = self.config.auto_register_with_collateral {
-            if !is_registered(&self.btc_parachain, vault_id.clone()).await? {
+        // the additional vaults share the parachain connection (and thereby the event
+        // subscription) but sign with their own key and use their own wallet
+        let mut vaults = vec![(self.btc_parachain.clone(), self.bitcoin_core.clone())];
+        for identity in self.config.additional_vaults.iter() {
+            vaults.push((
+                self.btc_parachain.with_signer(identity.signer.clone()).await,
+                self.bitcoin_core.for_wallet(identity.wallet_name.clone())?,
+            ));
+        }
+
+        for (btc_parachain, bitcoin_core) in vaults.iter() {
+            self.initialize_vault(btc_parachain, bitcoin_core, num_confirmations)
+                .await?;
+        }
+
+        // events finalized while the vault was offline are replayed if an event checkpoint is
+        // configured, the handlers skip requests that have already been processed
+        let startup_height = self.btc_parachain.get_current_chain_height().await?;
+
+        // issue handling, the set contains the open issue requests of all vaults
+        let issue_set = Arc::new(IssueRequests::new());
+        let oldest_issue_btc_height =
+            issue::initialize_issue_set(&self.bitcoin_core, &self.btc_parachain, &issue_set).await?;
+        let initial_btc_height = self.bitcoin_core.get_block_count().await? as u32;
+
+        let issue_cancel_listener = wait_or_shutdown(
+            self.shutdown.clone(),
+            listen_for_issue_cancels(self.btc_parachain.holding_events(), issue_set.clone()),
+        );
+
+        let issue_executor = maybe_run_task(
+            !config.no_issue_execution,
+            wait_or_shutdown(
+                self.shutdown.clone(),
+                issue::process_issue_requests(
+                    self.bitcoin_core.clone(),
+                    self.btc_parachain.clone(),
+                    issue_set.clone(),
+                    oldest_issue_btc_height,
+                    num_confirmations,
+                ),
+            ),
+        );
+
+        // per-vault request handling, block events are fanned out to the cancellation schedulers
+        let mut block_txs = Vec::new();
+        let mut vault_tasks = Vec::new();
+        for (btc_parachain, bitcoin_core) in vaults.into_iter() {
+            let (event_txs, tasks) = self.run_vault(
+                btc_parachain,
+                bitcoin_core,
+                num_confirmations,
+                startup_height,
+                initial_btc_height,
+                issue_set.clone(),
+            );
+            block_txs.extend(event_txs);
+            vault_tasks.push(tasks);
+        }
+
+        let block_listener = wait_or_shutdown(
+            self.shutdown.clone(),
+            active_block_listener(self.btc_parachain.holding_events(), block_txs.clone()),
+        );
+
+        // listen for bitcoin blocks, used for cancellation
+        let bitcoin_block_listener_btc_rpc = self.bitcoin_core.clone();
+        let bitcoin_block_listener = wait_or_shutdown(self.shutdown.clone(), async move {
+            let block_txs = &block_txs;
+            stream_blocks(bitcoin_block_listener_btc_rpc.clone(), initial_btc_height, 1)
+                .await
+                .try_for_each(|_| async {
+                    let height = bitcoin_block_listener_btc_rpc.get_block_count().await? as u32;
+                    for block_tx in block_txs.iter() {
+                        let _ = block_tx.clone().send(Event::BitcoinBlock(height)).await;
+                    }
+                    Ok(())
+                })
+                .await
+                .map_err(Into::into)
+        });
+
+        let err_provider = self.btc_parachain.clone();
+        let err_listener = wait_or_shutdown(self.shutdown.clone(), async move {
+            err_provider
+                .on_event_error(|e| tracing::debug!("Received error event: {}", e))
+                .await?;
+            Ok(())
+        });
+
+        // watch vault address registration and report potential thefts
+        let vaults_listener = maybe_run_task(run_theft_reporting, self.start_theft_reporting().await?);
+
+        // relay bitcoin block headers to the relay
+        let checkpoint = config
+            .bitcoin_relay_checkpoint
+            .as_ref()
+            .map(Checkpoint::load)
+            .transpose()?;
+        let relay_backing =
+            QuorumBacking::connect(self.bitcoin_core.clone(), &config.relay_backing, config.relay_quorum)?;
+        let relayer = maybe_run_task(
+            relay_enabled,
+            wait_or_shutdown(
+                self.shutdown.clone(),
+                run_relayer(Runner::new(
+                    relay_backing,
+                    self.btc_parachain.clone(),
+                    Config {
+                        start_height: config.bitcoin_relay_start_height,
+                        checkpoint,
+                        max_batch_size: config.max_batch_size,
+                        max_batches_in_flight: config.max_batches_in_flight,
+                        interval: Some(config.bitcoin_poll_interval_ms),
+                        btc_confirmations: config.bitcoin_relay_confirmations,
+                        chain_params: Some(ChainParams::new(self.bitcoin_core.network())),
+                    },
+                )),
+            ),
+        );
+
+        // compare the relay with Bitcoin and raise alerts if it falls behind
+        let relay_monitor = maybe_run_task(
+            config.relay_monitor.relay_monitor,
+            wait_or_shutdown(
+                self.shutdown.clone(),
+                run_relay_monitor(RelayMonitor::new(
+                    self.bitcoin_core.clone(),
+                    self.btc_parachain.clone(),
+                    RelayMonitorConfig {
+                        btc_confirmations: config.bitcoin_relay_confirmations,
+                        ..config.relay_monitor.clone()
+                    },
+                )),
+            ),
+        );
+
+        // starts all the tasks
+        tracing::info!("Starting to listen for events...");
+        drop(event_hold);
+        let _ = tokio::join!(
+            // runs error listener to log errors
+            tokio::spawn(async move { err_listener.await }),
+            // replace & issue cancellation helpers
+            tokio::spawn(async move { block_listener.await }),
+            tokio::spawn(async move { bitcoin_block_listener.await }),
+            // issue handling
+            tokio::spawn(async move { issue_cancel_listener.await }),
+            tokio::spawn(async move { issue_executor.await }),
+            // request handling of the individual vaults
+            tokio::spawn(futures::future::join_all(vault_tasks)),
+            // runs vault theft checks
+            tokio::spawn(async move { vaults_listener.await }),
+            // relayer process
+            tokio::task::spawn_blocking(move || block_on(relayer)),
+            // relay monitoring
+            tokio::task::spawn_blocking(move || block_on(relay_monitor))
+        );
+
+        Ok(())
+    }
+
+    /// Load the wallet, register the vault if configured to do so, and process the requests
+    /// that were opened while the client was offline.
+    async fn initialize_vault(
+        &self,
+        btc_parachain: &InterBtcParachain,
+        bitcoin_core: &BitcoinCore,
+        num_confirmations: u32,
+    ) -> Result<(), Error> {
+        let config = &self.config.service;
+        let vault_id = btc_parachain.get_account_id().clone();
+        tracing::info!("Initializing vault {}", vault_id.to_ss58check());
+
+        // load wallet. Exit on failure, since without wallet we can't do a lot
+        match bitcoin_core.create_or_load_wallet().await {
+            Err(bitcoin::Error::DryRun) => decision!(action = "create_wallet", "Creating wallet"),
+            result => result.map_err(Error::WalletInitializationFailure)?,
+        }
+
+        if let Some(collateral) = config.auto_register_with_collateral {
+            if !is_registered(btc_parachain, vault_id.clone()).await? {
                 tracing::info!("Automatically registering vault");
-                // bitcoin core is currently blocking, no need to try_join
-                let public_key = bitcoin_core.get_new_public_key().await?;
-                self.btc_parachain.register_vault(collateral, public_key).await?;
+                ensure_spendable(btc_parachain, collateral).await?;
+                if bitcoin_core.is_dry_run() {
+                    decision!(action = "register", collateral = %collateral, "Registering vault");
+                } else {
+                    // bitcoin core is currently blocking, no need to try_join
+                    let public_key = bitcoin_core.get_new_public_key().await?;
+                    btc_parachain.register_vault(collateral, public_key).await?;
+                }
             } else {
                 tracing::info!("Not registering vault -- already registered");
             }
-        } else if let Some(faucet_url) = &self.config.auto_register_with_faucet_url {
-            if !is_registered(&self.btc_parachain, vault_id.clone()).await? {
-                faucet::fund_and_register(&self.btc_parachain, &bitcoin_core, faucet_url, vault_id.clone()).await?;
+        } else if let Some(faucet_url) = &config.auto_register_with_faucet_url {
+            if !is_registered(btc_parachain, vault_id.clone()).await? {
+                if bitcoin_core.is_dry_run() {
+                    decision!(action = "register", faucet_url = %faucet_url, "Registering vault with faucet funds");
+                } else {
+                    faucet::fund_and_register(btc_parachain, bitcoin_core, faucet_url, vault_id.clone()).await?;
+                }
             } else {
                 tracing::info!("Not registering vault -- already registered");
             }
         }
 
-        if let Ok(vault) = self.btc_parachain.get_vault(vault_id.clone()).await {
-            if !bitcoin_core.wallet_has_public_key(vault.wallet.public_key.0).await? {
-                return Err(bitcoin::Error::MissingPublicKey.into());
-            }
+        if let Ok(vault) = btc_parachain.get_vault(vault_id.clone()).await {
+            ensure_public_key(
+                btc_parachain,
+                bitcoin_core,
+                vault.wallet.public_key,
+                config.auto_rotate_public_key,
+            )
+            .await?;
         }
 
-        issue::add_keys_from_past_issue_request(&bitcoin_core, &self.btc_parachain).await?;
+        issue::add_keys_from_past_issue_request(bitcoin_core, btc_parachain).await?;
 
         let open_request_executor = execute_open_requests(
-            self.btc_parachain.clone(),
+            btc_parachain.clone(),
             bitcoin_core.clone(),
             num_confirmations,
-            self.config.payment_margin_minutes,
+            config.payment_margin_minutes,
         );
         tokio::spawn(async move {
             tracing::info!("Checking for open requests...");
@@ -223,44 +512,51 @@ impl VaultService {
             }
         });
 
-        if !self.config.no_startup_collateral_increase {
+        if !config.no_startup_collateral_increase {
             // check if the vault is registered
-            match lock_required_collateral(self.btc_parachain.clone(), vault_id.clone(), self.config.max_collateral)
-                .await
-            {
+            match lock_required_collateral(btc_parachain.clone(), vault_id, config.max_collateral).await {
                 Err(Error::RuntimeError(runtime::Error::VaultNotFound)) => {} // not registered
                 Err(e) => tracing::error!("Failed to lock required additional collateral: {}", e),
                 _ => {} // collateral level now OK
             };
         }
 
+        Ok(())
+    }
+
+    /// Create the listeners that handle the requests of a single vault. Returns the channels
+    /// that parachain and bitcoin block events need to be sent to, and the future running
+    /// all listeners.
+    fn run_vault(
+        &self,
+        btc_parachain: InterBtcParachain,
+        bitcoin_core: BitcoinCore,
+        num_confirmations: u32,
+        startup_height: u32,
+        initial_btc_height: u32,
+        issue_set: Arc<IssueRequests>,
+    ) -> (Vec<Sender<Event>>, impl Future<Output = ()>) {
+        let config = &self.config.service;
+        let vault_id = btc_parachain.get_account_id().clone();
+
         let collateral_maintainer = wait_or_shutdown(
             self.shutdown.clone(),
-            maintain_collateralization_rate(self.btc_parachain.clone(), self.config.max_collateral),
+            maintain_collateralization_rate(btc_parachain.holding_events(), config.max_collateral),
         );
 
-        // wait for a new block to arrive, to prevent processing an event that potentially
-        // has been processed already prior to restarting
-        tracing::info!("Waiting for new block...");
-        let startup_height = self.btc_parachain.get_current_chain_height().await?;
-        while startup_height == self.btc_parachain.get_current_chain_height().await? {
-            delay_for(CHAIN_HEIGHT_POLLING_INTERVAL).await;
-        }
-        tracing::info!("Got new block...");
+        let metrics_updater = wait_or_shutdown(
+            self.shutdown.clone(),
+            update_balance_metrics(btc_parachain.clone(), bitcoin_core.clone()),
+        );
 
         // issue handling
-        let issue_set = Arc::new(IssueRequests::new());
-        let oldest_issue_btc_height =
-            issue::initialize_issue_set(&bitcoin_core, &self.btc_parachain, &issue_set).await?;
-        let initial_btc_height = bitcoin_core.get_block_count().await? as u32;
-
         let (issue_event_tx, issue_event_rx) = mpsc::channel::<Event>(32);
 
         let issue_request_listener = wait_or_shutdown(
             self.shutdown.clone(),
             listen_for_issue_requests(
                 bitcoin_core.clone(),
-                self.btc_parachain.clone(),
+                btc_parachain.holding_events(),
                 issue_event_tx.clone(),
                 issue_set.clone(),
             ),
@@ -268,26 +564,16 @@ impl VaultService {
 
         let issue_execute_listener = wait_or_shutdown(
             self.shutdown.clone(),
-            listen_for_issue_executes(self.btc_parachain.clone(), issue_event_tx.clone(), issue_set.clone()),
-        );
-
-        let issue_cancel_listener = wait_or_shutdown(
-            self.shutdown.clone(),
-            listen_for_issue_cancels(self.btc_parachain.clone(), issue_set.clone()),
+            listen_for_issue_executes(btc_parachain.holding_events(), issue_event_tx.clone(), issue_set),
         );
 
         let mut issue_cancellation_scheduler = CancellationScheduler::new(
-            self.btc_parachain.clone(),
+            btc_parachain.clone(),
             startup_height,
             initial_btc_height,
             vault_id.clone(),
         );
 
-        let issue_block_listener = wait_or_shutdown(
-            self.shutdown.clone(),
-            active_block_listener(self.btc_parachain.clone(), issue_event_tx.clone()),
-        );
-
         let issue_cancel_scheduler = wait_or_shutdown(self.shutdown.clone(), async move {
             issue_cancellation_scheduler
                 .handle_cancellation::<IssueCanceller>(issue_event_rx)
@@ -295,59 +581,36 @@ impl VaultService {
             Ok(())
         });
 
-        let issue_executor = maybe_run_task(
-            !self.config.no_issue_execution,
-            wait_or_shutdown(
-                self.shutdown.clone(),
-                issue::process_issue_requests(
-                    bitcoin_core.clone(),
-                    self.btc_parachain.clone(),
-                    issue_set.clone(),
-                    oldest_issue_btc_height,
-                    num_confirmations,
-                ),
-            ),
-        );
-
         // replace handling
         let (replace_event_tx, replace_event_rx) = mpsc::channel::<Event>(16);
 
         let request_replace_listener = wait_or_shutdown(
             self.shutdown.clone(),
             listen_for_replace_requests(
-                self.btc_parachain.clone(),
+                btc_parachain.holding_events(),
                 bitcoin_core.clone(),
                 replace_event_tx.clone(),
-                !self.config.no_auto_replace,
+                !config.no_auto_replace,
             ),
         );
 
         let accept_replace_listener = wait_or_shutdown(
             self.shutdown.clone(),
             listen_for_accept_replace(
-                self.btc_parachain.clone(),
+                btc_parachain.holding_events(),
                 bitcoin_core.clone(),
                 num_confirmations,
-                self.config.payment_margin_minutes,
+                config.payment_margin_minutes,
             ),
         );
 
         let execute_replace_listener = wait_or_shutdown(
             self.shutdown.clone(),
-            listen_for_execute_replace(self.btc_parachain.clone(), replace_event_tx.clone()),
+            listen_for_execute_replace(btc_parachain.holding_events(), replace_event_tx.clone()),
         );
 
-        let mut replace_cancellation_scheduler = CancellationScheduler::new(
-            self.btc_parachain.clone(),
-            startup_height,
-            initial_btc_height,
-            vault_id.clone(),
-        );
-
-        let replace_block_listener = wait_or_shutdown(
-            self.shutdown.clone(),
-            active_block_listener(self.btc_parachain.clone(), replace_event_tx.clone()),
-        );
+        let mut replace_cancellation_scheduler =
+            CancellationScheduler::new(btc_parachain.clone(), startup_height, initial_btc_height, vault_id);
 
         let replace_cancel_scheduler = wait_or_shutdown(self.shutdown.clone(), async move {
             replace_cancellation_scheduler
@@ -356,39 +619,24 @@ impl VaultService {
             Ok(())
         });
 
-        // listen for bitcoin blocks, used for cancellation
-        let bitcoin_block_listener_btc_rpc = bitcoin_core.clone();
-        let bitcoin_block_listener = wait_or_shutdown(self.shutdown.clone(), async move {
-            stream_blocks(bitcoin_block_listener_btc_rpc.clone(), initial_btc_height, 1)
-                .await
-                .try_for_each(|_| async {
-                    let height = bitcoin_block_listener_btc_rpc.get_block_count().await? as u32;
-                    let _ = replace_event_tx.clone().send(Event::BitcoinBlock(height)).await;
-                    let _ = issue_event_tx.clone().send(Event::BitcoinBlock(height)).await;
-                    Ok(())
-                })
-                .await
-                .map_err(Into::into)
-        });
-
         // redeem handling
         let redeem_listener = wait_or_shutdown(
             self.shutdown.clone(),
             listen_for_redeem_requests(
-                self.btc_parachain.clone(),
+                btc_parachain.holding_events(),
                 bitcoin_core.clone(),
                 num_confirmations,
-                self.config.payment_margin_minutes,
+                config.payment_margin_minutes,
             ),
         );
 
         // refund handling
         let refund_listener = wait_or_shutdown(
             self.shutdown.clone(),
-            listen_for_refund_requests(self.btc_parachain.clone(), bitcoin_core.clone(), num_confirmations),
+            listen_for_refund_requests(btc_parachain.holding_events(), bitcoin_core, num_confirmations),
         );
 
-        let sla_provider = self.btc_parachain.clone();
+        let sla_provider = btc_parachain.holding_events();
         let sla_listener = wait_or_shutdown(self.shutdown.clone(), async move {
             let vault_id = sla_provider.get_account_id();
             sla_provider
@@ -404,98 +652,52 @@ impl VaultService {
             Ok(())
         });
 
-        let err_provider = self.btc_parachain.clone();
-        let err_listener = wait_or_shutdown(self.shutdown.clone(), async move {
-            err_provider
-                .on_event_error(|e| tracing::debug!("Received error event: {}", e))
-                .await?;
-            Ok(())
-        });
-
-        // watch vault address registration and report potential thefts
-        let vaults_listener = maybe_run_task(!self.config.no_vault_theft_report, self.start_theft_reporting().await?);
-
-        // relay bitcoin block headers to the relay
-        let relayer = maybe_run_task(
-            !self.config.no_bitcoin_block_relay,
-            wait_or_shutdown(
-                self.shutdown.clone(),
-                run_relayer(Runner::new(
-                    bitcoin_core.clone(),
-                    self.btc_parachain.clone(),
-                    Config {
-                        start_height: self.config.bitcoin_relay_start_height,
-                        max_batch_size: self.config.max_batch_size,
-                        interval: Some(self.config.bitcoin_poll_interval_ms),
-                        btc_confirmations: self.config.bitcoin_relay_confirmations,
-                    },
-                )),
-            ),
-        );
-
-        // starts all the tasks
-        tracing::info!("Starting to listen for events...");
-        let _ = tokio::join!(
-            // runs error listener to log errors
-            tokio::spawn(async move { err_listener.await }),
-            // runs sla listener to log events
-            tokio::spawn(async move { sla_listener.await }),
-            // maintain collateralization rate
-            tokio::spawn(async move {
-                collateral_maintainer.await;
-            }),
-            // replace & issue cancellation helper
-            tokio::spawn(async move { bitcoin_block_listener.await }),
-            // issue handling
-            tokio::spawn(async move { issue_request_listener.await }),
-            tokio::spawn(async move { issue_execute_listener.await }),
-            tokio::spawn(async move { issue_cancel_listener.await }),
-            tokio::spawn(async move { issue_block_listener.await }),
-            tokio::spawn(async move { issue_cancel_scheduler.await }),
-            tokio::spawn(async move { issue_executor.await }),
-            // replace handling
-            tokio::spawn(async move { request_replace_listener.await }),
-            tokio::spawn(async move { accept_replace_listener.await }),
-            tokio::spawn(async move { execute_replace_listener.await }),
-            tokio::spawn(async move { replace_block_listener.await }),
-            tokio::spawn(async move { replace_cancel_scheduler.await }),
-            // redeem handling
-            tokio::spawn(async move { redeem_listener.await }),
-            // refund handling
-            tokio::spawn(async move { refund_listener.await }),
-            // runs vault theft checks
-            tokio::spawn(async move { vaults_listener.await }),
-            // relayer process
-            tokio::task::spawn_blocking(move || block_on(relayer))
-        );
+        let tasks = async move {
+            let _ = tokio::join!(
+                // runs sla listener to log events
+                tokio::spawn(async move { sla_listener.await }),
+                // maintain collateralization rate
+                tokio::spawn(async move {
+                    collateral_maintainer.await;
+                }),
+                // update collateral and balance metrics
+                tokio::spawn(async move { metrics_updater.await }),
+                // issue handling
+                tokio::spawn(async move { issue_request_listener.await }),
+                tokio::spawn(async move { issue_execute_listener.await }),
+                tokio::spawn(async move { issue_cancel_scheduler.await }),
+                // replace handling
+                tokio::spawn(async move { request_replace_listener.await }),
+                tokio::spawn(async move { accept_replace_listener.await }),
+                tokio::spawn(async move { execute_replace_listener.await }),
+                tokio::spawn(async move { replace_cancel_scheduler.await }),
+                // redeem handling
+                tokio::spawn(async move { redeem_listener.await }),
+                // refund handling
+                tokio::spawn(async move { refund_listener.await }),
+            );
+        };
 
-        Ok(())
+        (vec![issue_event_tx, replace_event_tx], tasks)
     }
 
     pub(crate) async fn start_theft_reporting(&self) -> Result<impl Future, Error> {
         // TODO: don't fetch vaults if reporting is disabled
-        tracing::info!("Fetching all active vaults...");
-        let vaults = self
-            .btc_parachain
-            .get_all_vaults()
-            .await?
-            .into_iter()
-            .flat_map(|vault| {
-                vault
-                    .wallet
-                    .addresses
-                    .iter()
-                    .map(|addr| (*addr, vault.id.clone()))
-                    .collect::<Vec<_>>()
-            })
-            .collect();
-
-        // store vaults in Arc<RwLock>
-        let vaults = Arc::new(Vaults::from(vaults));
+        let vaults = Arc::new(Vaults::open(self.config.service.vault_index_file.clone())?);
+        match vaults.height().await {
+            Some(height) => tracing::info!("Loaded vault index in sync at height {}", height),
+            None => tracing::info!("Fetching all active vaults..."),
+        }
+        sync_vault_index(&self.btc_parachain, &vaults).await?;
+
+        // thefts flagged in the mempool, reported by `report_vault_thefts` once confirmed
+        let suspicious = Arc::new(SuspiciousTransactions::default());
+        let archive = Arc::new(TheftArchive::open(self.config.service.theft_archive_file.clone())?);
 
         // scan from custom height or the current tip
         let bitcoin_theft_start_height = self
             .config
+            .service
             .bitcoin_theft_start_height
             .unwrap_or(self.bitcoin_core.get_block_count().await? as u32 + 1);
 
@@ -506,25 +708,61 @@ impl VaultService {
                 self.btc_parachain.clone(),
                 bitcoin_theft_start_height,
                 vaults.clone(),
+                suspicious.clone(),
+                archive.clone(),
+            ),
+        );
+
+        // resubmit reports that failed transiently
+        let retry_listener = wait_or_shutdown(
+            self.shutdown.clone(),
+            retry_theft_reports(self.bitcoin_core.clone(), self.btc_parachain.clone(), archive),
+        );
+
+        // flag thefts as soon as they appear in the mempool
+        let mempool_listener = maybe_run_task(
+            !self.config.service.no_mempool_theft_check,
+            wait_or_shutdown(
+                self.shutdown.clone(),
+                watch_mempool_for_thefts(
+                    self.bitcoin_core.clone(),
+                    self.btc_parachain.clone(),
+                    vaults.clone(),
+                    suspicious,
+                    self.config.service.bitcoin_poll_interval_ms,
+                ),
+            ),
+        );
+
+        // recover from missed registration events
+        let vault_index_listener = wait_or_shutdown(
+            self.shutdown.clone(),
+            reconcile_vault_index(
+                self.btc_parachain.clone(),
+                vaults.clone(),
+                self.config.service.vault_index_reconcile_interval_ms,
             ),
         );
 
         // keep track of all registered vaults (i.e. keep the `vaults` map up-to-date)
         let vaults_registration_listener = wait_or_shutdown(
             self.shutdown.clone(),
-            listen_for_vaults_registered(self.btc_parachain.clone(), vaults.clone()),
+            listen_for_vaults_registered(self.btc_parachain.holding_events(), vaults.clone()),
         );
 
         // keep vault wallets up-to-date
         let wallet_update_listener = wait_or_shutdown(
             self.shutdown.clone(),
-            listen_for_wallet_updates(self.btc_parachain.clone(), vaults.clone()),
+            listen_for_wallet_updates(self.btc_parachain.holding_events(), vaults.clone()),
         );
 
-        Ok(futures::future::join3(
-            vaults_listener,
-            vaults_registration_listener,
-            wallet_update_listener,
+        Ok(futures::future::join(
+            futures::future::join3(vaults_listener, mempool_listener, retry_listener),
+            futures::future::join3(
+                vault_index_listener,
+                vaults_registration_listener,
+                wallet_update_listener,
+            ),
         ))
     }
 }
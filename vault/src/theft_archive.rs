@@ -0,0 +1,374 @@
+use crate::error::Error;
+use runtime::{write_atomically, AccountId};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sp_core::crypto::Ss58Codec;
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Number of times a report is submitted before it is given up.
+pub const MAX_REPORT_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    /// Not checked or reported yet.
+    Pending,
+    /// The transaction belongs to a request, so there is nothing to report.
+    Valid,
+    Reported,
+    /// The vault was already reported, e.g. by another client.
+    AlreadyReported,
+    /// Checking or reporting failed, but may succeed when retried.
+    Retrying,
+    /// The report was only logged in dry-run mode, so it is submitted once the client runs
+    /// without `--dry-run`.
+    DryRun,
+    Failed,
+}
+
+impl ReportStatus {
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ReportStatus::Retrying | ReportStatus::DryRun)
+    }
+
+    /// Whether nothing is left to do for the transaction.
+    pub fn is_settled(&self) -> bool {
+        !matches!(
+            self,
+            ReportStatus::Pending | ReportStatus::Retrying | ReportStatus::DryRun
+        )
+    }
+}
+
+/// A transaction from a vault address found on the Bitcoin chain, together with everything
+/// needed to (re-)submit the theft report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TheftEvidence {
+    pub txid: String,
+    pub block_hash: String,
+    pub vault_id: AccountId,
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub raw_tx: Vec<u8>,
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub proof: Vec<u8>,
+    /// Result of `is_transaction_invalid`, if the check succeeded.
+    pub is_invalid: Option<bool>,
+    pub status: ReportStatus,
+    pub attempts: u32,
+    /// Error of the last failed attempt.
+    pub error: Option<String>,
+    /// Unix timestamp of when the transaction was found.
+    pub found_at: u64,
+}
+
+impl TheftEvidence {
+    pub fn new(txid: String, block_hash: String, vault_id: AccountId, raw_tx: Vec<u8>, proof: Vec<u8>) -> Self {
+        Self {
+            txid,
+            block_hash,
+            vault_id,
+            raw_tx,
+            proof,
+            is_invalid: None,
+            status: ReportStatus::Pending,
+            attempts: 0,
+            error: None,
+            found_at: now(),
+        }
+    }
+
+    fn key(&self) -> RecordKey {
+        (self.txid.clone(), self.vault_id.clone())
+    }
+}
+
+impl fmt::Display for TheftEvidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Transaction {} in block {} from vault {}: {:?}, invalid {:?}, {} attempt(s), found at {}",
+            self.txid,
+            self.block_hash,
+            self.vault_id.to_ss58check(),
+            self.status,
+            self.is_invalid,
+            self.attempts,
+            self.found_at
+        )?;
+        if let Some(error) = &self.error {
+            write!(f, ", last error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+fn to_hex<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(bytes))
+}
+
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    hex::decode(hex).map_err(serde::de::Error::custom)
+}
+
+/// Number of seconds a settled report is kept after the transaction was found.
+pub const SETTLED_RETENTION_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// The file is rewritten once it holds this many times more lines than there are records.
+const COMPACTION_FACTOR: usize = 2;
+
+type RecordKey = (String, AccountId);
+
+#[derive(Default)]
+struct Records {
+    by_tx: HashMap<RecordKey, TheftEvidence>,
+    /// Number of lines in the file, including those of records that were replaced since.
+    lines: usize,
+}
+
+/// Record of the suspicious and invalid transactions found by the theft reporting and the
+/// outcome of the reports, kept in memory and, if a path is given, in a file with one json
+/// record per line. Updates are appended, and the file is compacted once it grows too large.
+#[derive(Default)]
+pub struct TheftArchive {
+    path: Option<PathBuf>,
+    records: Mutex<Records>,
+}
+
+impl TheftArchive {
+    /// Opens the archive at `path`, starting with the records stored there by earlier runs.
+    pub fn open(path: Option<PathBuf>) -> Result<Self, Error> {
+        let by_tx = match &path {
+            Some(path) => Self::read(path)?,
+            None => HashMap::new(),
+        };
+        let archive = Self {
+            path,
+            records: Mutex::new(Records { by_tx, lines: 0 }),
+        };
+        {
+            let mut records = archive.records.lock().unwrap();
+            prune(&mut records.by_tx, now());
+            archive.compact(&mut records)?;
+        }
+        Ok(archive)
+    }
+
+    /// Reads the records stored at `path`, returns nothing if the file does not exist yet.
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<TheftEvidence>, Error> {
+        Ok(sorted(Self::read(path)?.into_iter().map(|(_, evidence)| evidence)))
+    }
+
+    fn read(path: impl AsRef<Path>) -> Result<HashMap<RecordKey, TheftEvidence>, Error> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let records: Vec<TheftEvidence> = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        // later lines replace earlier records of the same transaction and vault
+        Ok(records.into_iter().map(|evidence| (evidence.key(), evidence)).collect())
+    }
+
+    pub fn records(&self) -> Vec<TheftEvidence> {
+        sorted(self.records.lock().unwrap().by_tx.values().cloned())
+    }
+
+    /// Records whose report should be (re-)submitted.
+    pub fn retryable(&self) -> Vec<TheftEvidence> {
+        sorted(
+            self.records
+                .lock()
+                .unwrap()
+                .by_tx
+                .values()
+                .filter(|evidence| evidence.status.is_retryable())
+                .cloned(),
+        )
+    }
+
+    /// Returns the record for the same transaction and vault, if any.
+    pub fn get(&self, evidence: &TheftEvidence) -> Option<TheftEvidence> {
+        self.records.lock().unwrap().by_tx.get(&evidence.key()).cloned()
+    }
+
+    /// Adds the record, or replaces the one for the same transaction and vault, and appends it
+    /// to the file. Valid transactions are not archived, since there is nothing to report.
+    pub fn record(&self, evidence: TheftEvidence) -> Result<(), Error> {
+        let mut records = self.records.lock().unwrap();
+        let key = evidence.key();
+        let mut pruned = prune(&mut records.by_tx, now());
+        if evidence.status == ReportStatus::Valid {
+            pruned |= records.by_tx.remove(&key).is_some();
+        } else {
+            records.by_tx.insert(key, evidence.clone());
+        }
+
+        if pruned || records.lines >= COMPACTION_FACTOR * records.by_tx.len().max(1) {
+            self.compact(&mut records)
+        } else if evidence.status != ReportStatus::Valid {
+            self.append(&mut records, &evidence)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn append(&self, records: &mut Records, evidence: &TheftEvidence) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", serde_json::to_string(evidence)?)?;
+            file.sync_data()?;
+            records.lines += 1;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the file with one line per record.
+    fn compact(&self, records: &mut Records) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            let mut content = String::new();
+            for evidence in sorted(records.by_tx.values().cloned()) {
+                content.push_str(&serde_json::to_string(&evidence)?);
+                content.push('\n');
+            }
+            write_atomically(path, content)?;
+            records.lines = records.by_tx.len();
+        }
+        Ok(())
+    }
+}
+
+/// Drops the reports that were settled longer than `SETTLED_RETENTION_SECS` ago.
+fn prune(by_tx: &mut HashMap<RecordKey, TheftEvidence>, now: u64) -> bool {
+    let len = by_tx.len();
+    by_tx.retain(|_, evidence| {
+        !evidence.status.is_settled() || evidence.found_at.saturating_add(SETTLED_RETENTION_SECS) > now
+    });
+    by_tx.len() != len
+}
+
+fn sorted(records: impl Iterator<Item = TheftEvidence>) -> Vec<TheftEvidence> {
+    let mut records: Vec<_> = records.collect();
+    records.sort_by(|a, b| (a.found_at, &a.txid).cmp(&(b.found_at, &b.txid)));
+    records
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sp_keyring::AccountKeyring;
+
+    fn evidence(txid: &str) -> TheftEvidence {
+        TheftEvidence::new(
+            txid.to_string(),
+            "00".to_string(),
+            AccountKeyring::Bob.to_account_id(),
+            vec![1, 2, 3],
+            vec![4, 5],
+        )
+    }
+
+    #[test]
+    fn test_records_survive_restart() {
+        let path = std::env::temp_dir().join(format!("theft-archive-{}.json", std::process::id()));
+        let archive = TheftArchive::open(Some(path.clone())).unwrap();
+        assert!(archive.records().is_empty());
+
+        let mut reported = evidence("a");
+        archive.record(reported.clone()).unwrap();
+        reported.status = ReportStatus::Reported;
+        archive.record(reported.clone()).unwrap();
+        let mut failed = evidence("b");
+        failed.status = ReportStatus::Retrying;
+        archive.record(failed.clone()).unwrap();
+
+        let archive = TheftArchive::open(Some(path.clone())).unwrap();
+        assert_eq!(archive.records(), vec![reported, failed.clone()]);
+        assert_eq!(archive.retryable(), vec![failed]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_valid_and_old_settled_records_are_dropped() {
+        let path = std::env::temp_dir().join(format!("theft-archive-prune-{}.json", std::process::id()));
+        let archive = TheftArchive::open(Some(path.clone())).unwrap();
+
+        let mut valid = evidence("a");
+        valid.status = ReportStatus::Retrying;
+        archive.record(valid.clone()).unwrap();
+        valid.status = ReportStatus::Valid;
+        archive.record(valid.clone()).unwrap();
+        assert_eq!(archive.get(&valid), None);
+
+        let mut old = evidence("b");
+        old.status = ReportStatus::Reported;
+        old.found_at -= SETTLED_RETENTION_SECS + 1;
+        let mut pending = old.clone();
+        pending.txid = "c".to_string();
+        pending.status = ReportStatus::Retrying;
+        let mut recent = evidence("d");
+        recent.status = ReportStatus::Failed;
+        fs::write(
+            &path,
+            [&old, &pending, &recent]
+                .iter()
+                .map(|evidence| serde_json::to_string(evidence).unwrap() + "\n")
+                .collect::<String>(),
+        )
+        .unwrap();
+
+        let archive = TheftArchive::open(Some(path.clone())).unwrap();
+        assert_eq!(archive.records(), vec![pending, recent]);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_updates_are_appended_and_compacted() {
+        let path = std::env::temp_dir().join(format!("theft-archive-append-{}.json", std::process::id()));
+        let archive = TheftArchive::open(Some(path.clone())).unwrap();
+        let line_count = || fs::read_to_string(&path).unwrap().lines().count();
+
+        let mut first = evidence("a");
+        first.status = ReportStatus::Retrying;
+        archive.record(first.clone()).unwrap();
+        archive.record(evidence("b")).unwrap();
+        assert_eq!(line_count(), 2);
+
+        first.attempts += 1;
+        archive.record(first.clone()).unwrap();
+        assert_eq!(line_count(), 3);
+        first.attempts += 1;
+        archive.record(first.clone()).unwrap();
+        assert_eq!(line_count(), 4);
+        first.attempts += 1;
+        archive.record(first.clone()).unwrap();
+        // the file held twice as many lines as records
+        assert_eq!(line_count(), 2);
+
+        let archive = TheftArchive::open(Some(path.clone())).unwrap();
+        assert_eq!(archive.get(&first), Some(first));
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,38 +1,361 @@
-use crate::error::Error;
-use bitcoin::{BitcoinCoreApi, BlockHash, Transaction, TransactionExt as _};
+use crate::{
+    error::Error,
+    metrics,
+    theft_archive::{ReportStatus, TheftArchive, TheftEvidence, MAX_REPORT_ATTEMPTS},
+};
+use bitcoin::{serialize, BitcoinCoreApi, BlockHash, Transaction, TransactionExt as _, Txid};
 use futures::stream::{iter, StreamExt};
+use parity_scale_codec::{Decode, Encode};
 use runtime::{
     pallets::vault_registry::{RegisterAddressEvent, RegisterVaultEvent},
-    AccountId, BtcAddress, BtcRelayPallet, Error as RuntimeError, H256Le, InterBtcParachain, InterBtcRuntime,
-    InterBtcVault, RelayPallet, VaultRegistryPallet,
+    write_atomically, AccountId, BlockNumber, BtcAddress, BtcRelayPallet, Error as RuntimeError, H256Le,
+    InterBtcParachain, InterBtcRuntime, InterBtcVault, RelayPallet, UtilFuncs, VaultRegistryPallet,
 };
 use service::Error as ServiceError;
 use sp_core::crypto::Ss58Codec;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::RwLock;
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::RwLock, time::delay_for};
+
+/// Interval at which theft reports that failed transiently are resubmitted.
+const REPORT_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of blocks the vault index is caught up with from their events. If the index is further
+/// behind, all vaults are fetched instead, since pruned nodes do not keep older blocks' state.
+const MAX_CATCH_UP_BLOCKS: BlockNumber = 256;
 
+/// Index of the Bitcoin addresses of all vaults. Every address is stamped with the parachain
+/// height at which it was added, and the index with the height up to which it is in sync with
+/// chain storage, such that addresses added by events after that height are not dropped.
 #[derive(Default)]
-pub struct Vaults(RwLock<HashMap<BtcAddress, AccountId>>);
+pub struct Vaults {
+    index: RwLock<VaultIndex>,
+    /// File the index is persisted in, if any.
+    file: Option<Arc<IndexFile>>,
+}
+
+#[derive(Default)]
+struct VaultIndex {
+    /// Height the index is in sync with, `None` if the index was never reconciled.
+    height: Option<BlockNumber>,
+    addresses: HashMap<BtcAddress, (AccountId, BlockNumber)>,
+}
+
+impl VaultIndex {
+    fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let (height, addresses): (Option<BlockNumber>, Vec<(BtcAddress, AccountId, BlockNumber)>) =
+            Decode::decode(&mut &bytes[..])?;
+        Ok(Self {
+            height,
+            addresses: addresses
+                .into_iter()
+                .map(|(address, vault_id, since)| (address, (vault_id, since)))
+                .collect(),
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let addresses: Vec<_> = self
+            .addresses
+            .iter()
+            .map(|(address, (vault_id, since))| (*address, vault_id.clone(), *since))
+            .collect();
+        (self.height, addresses).encode()
+    }
+
+    fn insert(&mut self, address: BtcAddress, vault_id: AccountId, since: BlockNumber) {
+        match self.addresses.entry(address) {
+            Entry::Occupied(mut entry) => {
+                let (id, stamp) = entry.get_mut();
+                *id = vault_id;
+                *stamp = (*stamp).min(since);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((vault_id, since));
+            }
+        }
+    }
+}
+
+/// Writes the index on a blocking thread. Snapshots that are taken while a write is in progress
+/// replace each other, so only the latest one is written next.
+struct IndexFile {
+    path: PathBuf,
+    pending: Mutex<Option<Vec<u8>>>,
+    writing: Mutex<()>,
+}
+
+impl IndexFile {
+    fn schedule(self: &Arc<Self>, bytes: Vec<u8>) {
+        if self.pending.lock().unwrap().replace(bytes).is_none() {
+            let file = self.clone();
+            tokio::task::spawn_blocking(move || file.write_pending());
+        }
+    }
+
+    fn write_pending(&self) {
+        let _writing = self.writing.lock().unwrap();
+        let bytes = self.pending.lock().unwrap().take();
+        if let Some(bytes) = bytes {
+            if let Err(err) = write_atomically(&self.path, bytes) {
+                tracing::error!("Failed to store the vault index: {}", err);
+            }
+        }
+    }
+}
 
 impl Vaults {
     pub fn from(vaults: HashMap<BtcAddress, AccountId>) -> Self {
-        Self(RwLock::new(vaults))
+        Self {
+            index: RwLock::new(VaultIndex {
+                height: None,
+                addresses: vaults
+                    .into_iter()
+                    .map(|(address, vault_id)| (address, (vault_id, 0)))
+                    .collect(),
+            }),
+            file: None,
+        }
     }
 
-    pub async fn write(&self, key: BtcAddress, value: AccountId) {
-        self.0.write().await.insert(key, value);
+    /// Opens the index persisted at `path`, which is empty if the file does not exist yet.
+    pub fn open(path: Option<PathBuf>) -> Result<Self, Error> {
+        let index = match &path {
+            Some(path) => match fs::read(path) {
+                Ok(bytes) => VaultIndex::decode(&bytes)?,
+                Err(err) if err.kind() == ErrorKind::NotFound => VaultIndex::default(),
+                Err(err) => return Err(err.into()),
+            },
+            None => VaultIndex::default(),
+        };
+        Ok(Self {
+            index: RwLock::new(index),
+            file: path.map(|path| {
+                Arc::new(IndexFile {
+                    path,
+                    pending: Default::default(),
+                    writing: Default::default(),
+                })
+            }),
+        })
+    }
+
+    /// Height the index is in sync with chain storage.
+    pub async fn height(&self) -> Option<BlockNumber> {
+        self.index.read().await.height
+    }
+
+    pub async fn write(&self, key: BtcAddress, value: AccountId, since: BlockNumber) {
+        let mut index = self.index.write().await;
+        index.addresses.insert(key, (value, since));
+        self.persist(&index);
     }
 
-    pub async fn add_vault(&self, vault: InterBtcVault) {
-        let mut vaults = self.0.write().await;
+    pub async fn add_vault(&self, vault: InterBtcVault, since: BlockNumber) {
+        let mut index = self.index.write().await;
         for address in vault.wallet.addresses {
-            vaults.insert(address, vault.id.clone());
+            index.addresses.insert(address, (vault.id.clone(), since));
         }
+        self.persist(&index);
+    }
+
+    /// Brings the index in line with the `addresses` in chain storage at `height`, returns the
+    /// number of added and removed addresses. Addresses added after `height` are kept.
+    pub async fn reconcile(&self, addresses: HashMap<BtcAddress, AccountId>, height: BlockNumber) -> (usize, usize) {
+        let mut index = self.index.write().await;
+
+        let before = index.addresses.len();
+        index
+            .addresses
+            .retain(|address, (_, since)| addresses.contains_key(address) || *since > height);
+        let removed = before - index.addresses.len();
+
+        let before = index.addresses.len();
+        for (address, vault_id) in addresses {
+            index.insert(address, vault_id, height);
+        }
+        let added = index.addresses.len() - before;
+        index.height = Some(height);
+
+        self.persist(&index);
+        (added, removed)
+    }
+
+    /// Adds the `addresses` registered after the height of the index, each with the height at
+    /// which it was registered, and advances the index to `height`. Returns the number of
+    /// addresses that were missing.
+    pub async fn catch_up(&self, addresses: Vec<(BtcAddress, AccountId, BlockNumber)>, height: BlockNumber) -> usize {
+        let mut index = self.index.write().await;
+        let before = index.addresses.len();
+        for (address, vault_id, since) in addresses {
+            index.insert(address, vault_id, since);
+        }
+        let added = index.addresses.len() - before;
+        index.height = Some(index.height.unwrap_or_default().max(height));
+
+        self.persist(&index);
+        added
     }
 
     pub async fn contains_key(&self, key: BtcAddress) -> Option<AccountId> {
-        let vaults = self.0.read().await;
-        vaults.get(&key).cloned()
+        let index = self.index.read().await;
+        index.addresses.get(&key).map(|(vault_id, _)| vault_id.clone())
+    }
+
+    /// Waits until the latest state of the index is written.
+    pub async fn flush(&self) {
+        if let Some(file) = &self.file {
+            let file = file.clone();
+            if let Err(err) = tokio::task::spawn_blocking(move || file.write_pending()).await {
+                tracing::error!("Failed to store the vault index: {}", err);
+            }
+        }
+    }
+
+    /// Stores the index without blocking the caller, which still holds the lock on it.
+    fn persist(&self, index: &VaultIndex) {
+        if let Some(file) = &self.file {
+            file.schedule(index.encode());
+        }
+    }
+}
+
+/// Brings the vault index in sync with chain storage at the current height. If the index is
+/// recent, it is caught up with the registrations since its height, otherwise it is reconciled
+/// with all vaults.
+pub async fn sync_vault_index(btc_parachain: &InterBtcParachain, vaults: &Vaults) -> Result<(), Error> {
+    let height = btc_parachain.get_current_chain_height().await?;
+    match vaults.height().await {
+        Some(synced) if synced >= height => Ok(()),
+        Some(synced) if height - synced <= MAX_CATCH_UP_BLOCKS => {
+            catch_up_vault_index(btc_parachain, vaults, synced, height).await
+        }
+        _ => reconcile_all_vaults(btc_parachain, vaults, height).await,
+    }
+}
+
+async fn catch_up_vault_index(
+    btc_parachain: &InterBtcParachain,
+    vaults: &Vaults,
+    synced: BlockNumber,
+    height: BlockNumber,
+) -> Result<(), Error> {
+    let mut addresses: Vec<_> = btc_parachain
+        .get_events_in_range::<RegisterAddressEvent<InterBtcRuntime>>(synced, height)
+        .await?
+        .into_iter()
+        .map(|(since, event)| (event.btc_address, event.vault_id, since))
+        .collect();
+    for (since, event) in btc_parachain
+        .get_events_in_range::<RegisterVaultEvent<InterBtcRuntime>>(synced, height)
+        .await?
+    {
+        let vault = btc_parachain.get_vault(event.account_id).await?;
+        addresses.extend(
+            vault
+                .wallet
+                .addresses
+                .into_iter()
+                .map(|address| (address, vault.id.clone(), since)),
+        );
+    }
+
+    let added = vaults.catch_up(addresses, height).await;
+    if added > 0 {
+        tracing::info!(
+            "Caught up vault index from height {} to {}: {} missed addresses added",
+            synced,
+            height,
+            added
+        );
+    }
+    Ok(())
+}
+
+async fn reconcile_all_vaults(
+    btc_parachain: &InterBtcParachain,
+    vaults: &Vaults,
+    height: BlockNumber,
+) -> Result<(), Error> {
+    let addresses = btc_parachain
+        .at_block_number(height)
+        .await?
+        .get_all_vaults()
+        .await?
+        .into_iter()
+        .flat_map(|vault| {
+            vault
+                .wallet
+                .addresses
+                .iter()
+                .map(|addr| (*addr, vault.id.clone()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let (added, removed) = vaults.reconcile(addresses, height).await;
+    if added > 0 || removed > 0 {
+        tracing::info!(
+            "Reconciled vault index at height {}: {} addresses added, {} removed",
+            height,
+            added,
+            removed
+        );
+    }
+    Ok(())
+}
+
+/// Periodically brings the vault index in sync with chain storage, to recover from events that
+/// were missed, runs forever.
+pub async fn reconcile_vault_index(
+    btc_parachain: InterBtcParachain,
+    vaults: Arc<Vaults>,
+    interval: Duration,
+) -> Result<(), ServiceError> {
+    loop {
+        delay_for(interval).await;
+        match sync_vault_index(&btc_parachain, &vaults).await {
+            Ok(()) => {}
+            Err(Error::RuntimeError(ref err)) if err.is_rpc_disconnect_error() => {
+                return Err(ServiceError::ClientShutdown);
+            }
+            Err(err) => tracing::error!("Failed to sync the vault index: {}", err),
+        }
+    }
+}
+
+/// Unconfirmed transactions that spend from a vault address without belonging to an open
+/// redeem, replace or refund request. They are reported once they are confirmed and relayed.
+#[derive(Default)]
+pub struct SuspiciousTransactions(Mutex<HashMap<Txid, Vec<AccountId>>>);
+
+impl SuspiciousTransactions {
+    fn insert(&self, txid: Txid, vault_ids: Vec<AccountId>) {
+        let mut transactions = self.0.lock().unwrap();
+        transactions.insert(txid, vault_ids);
+        metrics::SUSPICIOUS_TRANSACTIONS.set(transactions.len() as i64);
+    }
+
+    /// Removes the transaction, returns the vaults it was flagged for.
+    fn remove(&self, txid: &Txid) -> Option<Vec<AccountId>> {
+        let mut transactions = self.0.lock().unwrap();
+        let vault_ids = transactions.remove(txid);
+        metrics::SUSPICIOUS_TRANSACTIONS.set(transactions.len() as i64);
+        vault_ids
+    }
+
+    /// Drops the transactions that are no longer in the mempool, e.g. because they were
+    /// replaced or evicted. Confirmed ones are reported from their block regardless.
+    fn retain_in_mempool(&self, mempool: &HashSet<Txid>) {
+        let mut transactions = self.0.lock().unwrap();
+        transactions.retain(|txid, _| mempool.contains(txid));
+        metrics::SUSPICIOUS_TRANSACTIONS.set(transactions.len() as i64);
     }
 }
 
@@ -41,8 +364,12 @@ pub async fn report_vault_thefts<P: RelayPallet + BtcRelayPallet, B: BitcoinCore
     btc_parachain: P,
     btc_height: u32,
     vaults: Arc<Vaults>,
+    suspicious: Arc<SuspiciousTransactions>,
+    archive: Arc<TheftArchive>,
 ) -> Result<(), ServiceError> {
     match VaultTheftMonitor::new(bitcoin_core, btc_parachain, btc_height, vaults)
+        .with_suspicious_transactions(suspicious)
+        .with_archive(archive)
         .process_blocks()
         .await
     {
@@ -51,11 +378,48 @@ pub async fn report_vault_thefts<P: RelayPallet + BtcRelayPallet, B: BitcoinCore
     }
 }
 
+/// Polls the mempool for transactions from vault addresses that don't match any open request,
+/// runs forever. The transactions are only flagged, `report_vault_thefts` reports them once
+/// they are confirmed.
+pub async fn watch_mempool_for_thefts<P: RelayPallet + BtcRelayPallet, B: BitcoinCoreApi + Clone>(
+    bitcoin_core: B,
+    btc_parachain: P,
+    vaults: Arc<Vaults>,
+    suspicious: Arc<SuspiciousTransactions>,
+    poll_interval: Duration,
+) -> Result<(), ServiceError> {
+    let monitor =
+        VaultTheftMonitor::new(bitcoin_core, btc_parachain, 0, vaults).with_suspicious_transactions(suspicious);
+    loop {
+        if let Err(err) = monitor.check_mempool().await {
+            tracing::error!("Failed to check the mempool for vault thefts: {}", err);
+        }
+        delay_for(poll_interval).await;
+    }
+}
+
+/// Resubmits the theft reports in `archive` that failed transiently, runs forever.
+pub async fn retry_theft_reports<P: RelayPallet + BtcRelayPallet, B: BitcoinCoreApi + Clone>(
+    bitcoin_core: B,
+    btc_parachain: P,
+    archive: Arc<TheftArchive>,
+) -> Result<(), ServiceError> {
+    let monitor = VaultTheftMonitor::new(bitcoin_core, btc_parachain, 0, Default::default()).with_archive(archive);
+    loop {
+        monitor.retry_reports().await;
+        delay_for(REPORT_RETRY_INTERVAL).await;
+    }
+}
+
 pub struct VaultTheftMonitor<P: RelayPallet + BtcRelayPallet, B: BitcoinCoreApi + Clone> {
     bitcoin_core: B,
     btc_parachain: P,
     btc_height: u32,
     vaults: Arc<Vaults>,
+    suspicious: Arc<SuspiciousTransactions>,
+    archive: Arc<TheftArchive>,
+    /// Mempool transactions that have already been checked.
+    seen: Mutex<HashSet<Txid>>,
 }
 
 impl<P: RelayPallet + BtcRelayPallet, B: BitcoinCoreApi + Clone> VaultTheftMonitor<P, B> {
@@ -65,20 +429,164 @@ impl<P: RelayPallet + BtcRelayPallet, B: BitcoinCoreApi + Clone> VaultTheftMonit
             btc_parachain,
             btc_height,
             vaults,
+            suspicious: Default::default(),
+            archive: Default::default(),
+            seen: Default::default(),
+        }
+    }
+
+    /// Record the checked transactions and the outcome of the reports in `archive`.
+    pub fn with_archive(self, archive: Arc<TheftArchive>) -> Self {
+        Self { archive, ..self }
+    }
+
+    /// Share the transactions flagged in the mempool with other monitors.
+    pub fn with_suspicious_transactions(self, suspicious: Arc<SuspiciousTransactions>) -> Self {
+        Self { suspicious, ..self }
+    }
+
+    /// Flags the transactions in the mempool that spend from a vault address but are not part
+    /// of an open request.
+    async fn check_mempool(&self) -> Result<(), Error> {
+        let txids: HashSet<_> = self.bitcoin_core.get_mempool_txids().await?.into_iter().collect();
+
+        let unseen: Vec<_> = {
+            let mut seen = self.seen.lock().unwrap();
+            // forget transactions that left the mempool
+            seen.retain(|txid| txids.contains(txid));
+            self.suspicious.retain_in_mempool(&txids);
+            txids.into_iter().filter(|txid| seen.insert(*txid)).collect()
+        };
+
+        // only the transactions that have not been checked yet are fetched
+        for txid in unseen {
+            let result = match self.bitcoin_core.get_mempool_transaction(&txid).await {
+                Ok(Some(tx)) => self.check_mempool_transaction(tx).await,
+                // no longer in the mempool
+                Ok(None) => Ok(()),
+                Err(err) => Err(err.into()),
+            };
+            if let Err(err) = result {
+                tracing::error!("Failed to check unconfirmed transaction {}: {}", txid, err);
+                // retry in the next round
+                self.seen.lock().unwrap().remove(&txid);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check_mempool_transaction(&self, tx: Transaction) -> Result<(), Error> {
+        let vault_ids = filter_matching_vaults(tx.extract_input_addresses(), &self.vaults).await;
+        if vault_ids.is_empty() {
+            return Ok(());
+        }
+
+        let txid = tx.txid();
+        let raw_tx = serialize(&tx);
+        let mut thieves = Vec::new();
+        for vault_id in vault_ids {
+            if self.btc_parachain.is_transaction_invalid(&vault_id, &raw_tx).await? {
+                tracing::warn!(
+                    "Unconfirmed transaction {} spends from vault {} without a matching request",
+                    txid,
+                    vault_id.to_ss58check()
+                );
+                metrics::MEMPOOL_THEFTS.inc();
+                thieves.push(vault_id);
+            }
         }
+        if !thieves.is_empty() {
+            self.suspicious.insert(txid, thieves);
+        }
+
+        Ok(())
     }
 
-    async fn report_invalid(&self, vault_id: &AccountId, proof: &[u8], raw_tx: &[u8]) -> Result<(), Error> {
+    /// Checks whether the transaction is part of a request and reports the vault if it is not,
+    /// the result of the check and the outcome are stored in `evidence`.
+    async fn report_invalid(&self, evidence: &mut TheftEvidence) -> Result<(), Error> {
+        let vault_id = &evidence.vault_id;
         tracing::info!("Found tx from vault {}", vault_id.to_ss58check());
         // check if matching redeem or replace request
-        if self.btc_parachain.is_transaction_invalid(vault_id, &raw_tx).await? {
-            tracing::info!("Transaction is invalid");
-            self.btc_parachain.report_vault_theft(vault_id, proof, raw_tx).await?;
+        let is_invalid = self
+            .btc_parachain
+            .is_transaction_invalid(vault_id, &evidence.raw_tx)
+            .await?;
+        evidence.is_invalid = Some(is_invalid);
+        if !is_invalid {
+            evidence.status = ReportStatus::Valid;
+            return Ok(());
+        }
+
+        tracing::info!("Transaction is invalid");
+        decision!(
+            action = "report_theft",
+            vault_id = %vault_id.to_ss58check(),
+            "Reporting vault theft"
+        );
+        match self
+            .btc_parachain
+            .report_vault_theft(vault_id, &evidence.proof, &evidence.raw_tx)
+            .await
+        {
+            Ok(()) => {
+                metrics::THEFT_REPORTS.inc();
+                evidence.status = ReportStatus::Reported;
+            }
+            Err(err) if err.is_vault_already_reported() => {
+                tracing::info!("Vault {} has already been reported", vault_id.to_ss58check());
+                evidence.status = ReportStatus::AlreadyReported;
+            }
+            Err(err) => return Err(err.into()),
         }
 
         Ok(())
     }
 
+    /// Makes an attempt to report the vault and archives the outcome.
+    async fn submit_report(&self, mut evidence: TheftEvidence) {
+        evidence.attempts += 1;
+        evidence.error = None;
+        match self.report_invalid(&mut evidence).await {
+            Ok(()) => {}
+            Err(Error::RuntimeError(RuntimeError::DryRun)) => {
+                // not an attempt, the report is submitted by a client that is not in dry-run mode
+                evidence.attempts -= 1;
+                evidence.status = ReportStatus::DryRun;
+            }
+            Err(err) => {
+                tracing::error!(
+                    "Failed to report theft of vault {} in transaction {}: {}",
+                    evidence.vault_id.to_ss58check(),
+                    evidence.txid,
+                    err
+                );
+                evidence.status = if is_transient(&err) && evidence.attempts < MAX_REPORT_ATTEMPTS {
+                    ReportStatus::Retrying
+                } else {
+                    ReportStatus::Failed
+                };
+                evidence.error = Some(err.to_string());
+            }
+        }
+
+        if let Err(err) = self.archive.record(evidence) {
+            tracing::error!("Failed to archive theft evidence: {}", err);
+        }
+    }
+
+    async fn retry_reports(&self) {
+        for evidence in self.archive.retryable() {
+            tracing::info!(
+                "Retrying theft report for vault {} in transaction {}",
+                evidence.vault_id.to_ss58check(),
+                evidence.txid
+            );
+            self.submit_report(evidence).await;
+        }
+    }
+
     async fn check_transaction(
         &self,
         tx: Transaction,
@@ -100,7 +608,26 @@ impl<P: RelayPallet + BtcRelayPallet, B: BitcoinCoreApi + Clone> VaultTheftMonit
         let vault_ids = filter_matching_vaults(addresses, &self.vaults).await;
 
         for vault_id in vault_ids {
-            self.report_invalid(&vault_id, &proof, &raw_tx).await?;
+            let evidence = TheftEvidence::new(
+                tx_id.to_string(),
+                block_hash.to_string(),
+                vault_id,
+                raw_tx.clone(),
+                proof.clone(),
+            );
+            // already checked, e.g. before a restart
+            if self.archive.get(&evidence).is_some() {
+                continue;
+            }
+            self.submit_report(evidence).await;
+        }
+
+        if let Some(vault_ids) = self.suspicious.remove(&tx_id) {
+            tracing::info!(
+                "Transaction {} flagged in the mempool for {} vault(s) is confirmed",
+                tx_id,
+                vault_ids.len()
+            );
         }
 
         Ok(())
@@ -130,15 +657,16 @@ pub async fn listen_for_wallet_updates(
     vaults: Arc<Vaults>,
 ) -> Result<(), ServiceError> {
     let vaults = &vaults;
+    let btc_parachain = &btc_parachain;
     btc_parachain
-        .on_event::<RegisterAddressEvent<InterBtcRuntime>, _, _, _>(
-            |event| async move {
+        .on_event_at::<RegisterAddressEvent<InterBtcRuntime>, _, _, _>(
+            |event, since| async move {
                 tracing::info!(
                     "Added new btc address {:?} for vault {}",
                     event.btc_address,
                     event.vault_id.to_ss58check()
                 );
-                vaults.write(event.btc_address, event.vault_id).await;
+                vaults.write(event.btc_address, event.vault_id, since).await;
             },
             |err| tracing::error!("Error (RegisterAddressEvent): {}", err.to_string()),
         )
@@ -150,13 +678,15 @@ pub async fn listen_for_vaults_registered(
     btc_parachain: InterBtcParachain,
     vaults: Arc<Vaults>,
 ) -> Result<(), ServiceError> {
+    let vaults = &vaults;
+    let btc_parachain = &btc_parachain;
     btc_parachain
-        .on_event::<RegisterVaultEvent<InterBtcRuntime>, _, _, _>(
-            |event| async {
+        .on_event_at::<RegisterVaultEvent<InterBtcRuntime>, _, _, _>(
+            |event, since| async move {
                 match btc_parachain.get_vault(event.account_id).await {
                     Ok(vault) => {
                         tracing::info!("Vault registered: {}", vault.id.to_ss58check());
-                        vaults.add_vault(vault).await;
+                        vaults.add_vault(vault, since).await;
                     }
                     Err(err) => tracing::error!("Error getting vault: {}", err.to_string()),
                 };
@@ -167,6 +697,14 @@ pub async fn listen_for_vaults_registered(
     Ok(())
 }
 
+/// Dispatch errors are permanent, others (e.g. connection errors) may not recur.
+fn is_transient(err: &Error) -> bool {
+    match err {
+        Error::RuntimeError(err) => err.module_error().is_none(),
+        _ => true,
+    }
+}
+
 async fn filter_matching_vaults(addresses: Vec<BtcAddress>, vaults: &Vaults) -> Vec<AccountId> {
     iter(addresses)
         .filter_map(|addr| vaults.contains_key(addr))
@@ -179,12 +717,12 @@ mod tests {
     use super::*;
     use async_trait::async_trait;
     use bitcoin::{
-        Block, BlockHeader, Error as BitcoinError, GetBlockResult, LockedTransaction, PartialAddress, PrivateKey,
-        Transaction, TransactionMetadata, Txid, PUBLIC_KEY_SIZE,
+        Amount, Block, BlockHeader, Error as BitcoinError, GetBlockResult, LockedTransaction, OutPoint, PartialAddress,
+        PrivateKey, Script, Transaction, TransactionMetadata, TxIn, Txid, PUBLIC_KEY_SIZE,
     };
     use runtime::{
         AccountId, BitcoinBlockHeight, BlockNumber, Error as RuntimeError, H256Le, InterBtcRichBlockHeader,
-        RawBlockHeader,
+        PendingSubmission, RawBlockHeader,
     };
     use sp_core::{H160, H256};
     use sp_keyring::AccountKeyring;
@@ -204,6 +742,7 @@ mod tests {
             async fn initialize_btc_relay(&self, header: RawBlockHeader, height: BitcoinBlockHeight) -> Result<(), RuntimeError>;
             async fn store_block_header(&self, header: RawBlockHeader) -> Result<(), RuntimeError>;
             async fn store_block_headers(&self, headers: Vec<RawBlockHeader>) -> Result<(), RuntimeError>;
+            async fn store_block_headers_pipelined(&self, headers: Vec<RawBlockHeader>) -> Result<PendingSubmission, RuntimeError>;
         }
 
         #[async_trait]
@@ -244,6 +783,7 @@ mod tests {
             async fn get_block_hash(&self, height: u32) -> Result<BlockHash, BitcoinError>;
             async fn is_block_known(&self, block_hash: BlockHash) -> Result<bool, BitcoinError>;
             async fn get_new_address<A: PartialAddress + Send + 'static>(&self) -> Result<A, BitcoinError>;
+            async fn get_balance(&self, min_confirmations: Option<u32>) -> Result<Amount, BitcoinError>;
             async fn get_new_public_key<P: From<[u8; PUBLIC_KEY_SIZE]> + 'static>(&self) -> Result<P, BitcoinError>;
             async fn add_new_deposit_key<P: Into<[u8; PUBLIC_KEY_SIZE]> + Send + Sync + 'static>(
                 &self,
@@ -257,6 +797,8 @@ mod tests {
             async fn get_mempool_transactions<'a>(
                 &'a self,
             ) -> Result<Box<dyn Iterator<Item = Result<Transaction, BitcoinError>> + Send +'a>, BitcoinError>;
+            async fn get_mempool_txids(&self) -> Result<Vec<Txid>, BitcoinError>;
+            async fn get_mempool_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, BitcoinError>;
             async fn wait_for_transaction_metadata(
                 &self,
                 txid: Txid,
@@ -282,6 +824,8 @@ mod tests {
                 request_id: Option<H256>,
                 num_confirmations: u32,
             ) -> Result<TransactionMetadata, BitcoinError>;
+            async fn sweep_funds<A: PartialAddress + Send + Sync + 'static>(&self, address: A) -> Result<Txid, BitcoinError>;
+            async fn find_sent_transaction<A: PartialAddress + Send + Sync + 'static>(&self, address: A, op_return: H256) -> Result<Option<Txid>, BitcoinError>;
             async fn create_or_load_wallet(&self) -> Result<(), BitcoinError>;
             async fn wallet_has_public_key<P>(&self, public_key: P) -> Result<bool, BitcoinError>
                 where
@@ -320,6 +864,68 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_reconcile_keeps_addresses_added_later() {
+        let address = |byte| BtcAddress::P2PKH(H160::from_slice(&[byte; 20]));
+        let bob = AccountKeyring::Bob.to_account_id();
+        let vaults = Vaults::default();
+        vaults.write(address(1), bob.clone(), 10).await;
+        vaults.write(address(2), bob.clone(), 30).await;
+
+        // chain storage at height 20 knows neither address, but a third one
+        let (added, removed) = vaults
+            .reconcile(vec![(address(3), bob.clone())].into_iter().collect(), 20)
+            .await;
+        assert_eq!((added, removed), (1, 1));
+        assert_eq!(vaults.height().await, Some(20));
+        assert_eq!(vaults.contains_key(address(1)).await, None);
+        assert_eq!(vaults.contains_key(address(2)).await, Some(bob.clone()));
+        assert_eq!(vaults.contains_key(address(3)).await, Some(bob));
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_keeps_earliest_stamp() {
+        let address = |byte| BtcAddress::P2PKH(H160::from_slice(&[byte; 20]));
+        let bob = AccountKeyring::Bob.to_account_id();
+        let vaults = Vaults::default();
+        vaults.write(address(1), bob.clone(), 10).await;
+        vaults
+            .reconcile(vec![(address(1), bob.clone())].into_iter().collect(), 20)
+            .await;
+
+        // the event of the first address was already seen, the second one was missed
+        let added = vaults
+            .catch_up(vec![(address(1), bob.clone(), 25), (address(2), bob.clone(), 30)], 40)
+            .await;
+        assert_eq!(added, 1);
+        assert_eq!(vaults.height().await, Some(40));
+
+        // neither address is in storage, but the second was registered after the compared block
+        let (added, removed) = vaults.reconcile(HashMap::new(), 20).await;
+        assert_eq!((added, removed), (0, 1));
+        assert_eq!(vaults.contains_key(address(2)).await, Some(bob));
+    }
+
+    #[tokio::test]
+    async fn test_vault_index_survives_restart() {
+        let path = std::env::temp_dir().join(format!("vault-index-{}", std::process::id()));
+        let address = BtcAddress::P2PKH(H160::from_slice(&[0; 20]));
+        let bob = AccountKeyring::Bob.to_account_id();
+
+        let vaults = Vaults::open(Some(path.clone())).unwrap();
+        assert_eq!(vaults.height().await, None);
+        vaults
+            .reconcile(vec![(address, bob.clone())].into_iter().collect(), 42)
+            .await;
+        vaults.flush().await;
+
+        let vaults = Vaults::open(Some(path.clone())).unwrap();
+        assert_eq!(vaults.height().await, Some(42));
+        assert_eq!(vaults.contains_key(address).await, Some(bob));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
     #[tokio::test]
     async fn test_report_valid_transaction() {
         let mut parachain = MockProvider::default();
@@ -331,10 +937,10 @@ mod tests {
 
         let monitor = VaultTheftMonitor::new(MockBitcoin::default(), parachain, 0, Arc::new(Vaults::default()));
 
-        monitor
-            .report_invalid(&AccountKeyring::Bob.to_account_id(), &vec![], &vec![])
-            .await
-            .unwrap();
+        let mut evidence = dummy_evidence();
+        monitor.report_invalid(&mut evidence).await.unwrap();
+        assert_eq!(evidence.is_invalid, Some(false));
+        assert_eq!(evidence.status, ReportStatus::Valid);
     }
 
     #[tokio::test]
@@ -345,9 +951,212 @@ mod tests {
 
         let monitor = VaultTheftMonitor::new(MockBitcoin::default(), parachain, 0, Arc::new(Vaults::default()));
 
-        monitor
-            .report_invalid(&AccountKeyring::Bob.to_account_id(), &vec![], &vec![])
-            .await
-            .unwrap();
+        let mut evidence = dummy_evidence();
+        monitor.report_invalid(&mut evidence).await.unwrap();
+        assert_eq!(evidence.is_invalid, Some(true));
+        assert_eq!(evidence.status, ReportStatus::Reported);
+    }
+
+    #[tokio::test]
+    async fn test_transient_report_failure_is_retried() {
+        let mut parachain = MockProvider::default();
+        parachain.expect_is_transaction_invalid().returning(|_, _| Ok(true));
+        let mut attempts = 0;
+        parachain
+            .expect_report_vault_theft()
+            .times(2)
+            .returning(move |_, _, _| {
+                attempts += 1;
+                if attempts == 1 {
+                    Err(RuntimeError::Timeout)
+                } else {
+                    Ok(())
+                }
+            });
+
+        let monitor = VaultTheftMonitor::new(MockBitcoin::default(), parachain, 0, Arc::new(Vaults::default()));
+        monitor.submit_report(dummy_evidence()).await;
+        let evidence = monitor.archive.get(&dummy_evidence()).unwrap();
+        assert_eq!(evidence.status, ReportStatus::Retrying);
+        assert_eq!(
+            evidence.error,
+            Some(Error::RuntimeError(RuntimeError::Timeout).to_string())
+        );
+
+        monitor.retry_reports().await;
+        let evidence = monitor.archive.get(&dummy_evidence()).unwrap();
+        assert_eq!(evidence.status, ReportStatus::Reported);
+        assert_eq!(evidence.attempts, 2);
+        assert_eq!(evidence.error, None);
+        assert!(monitor.archive.retryable().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_report_is_submitted_later() {
+        let archive = Arc::new(TheftArchive::default());
+        let mut dry_run = MockProvider::default();
+        dry_run.expect_is_transaction_invalid().returning(|_, _| Ok(true));
+        dry_run
+            .expect_report_vault_theft()
+            .returning(|_, _, _| Err(RuntimeError::DryRun));
+        let monitor = VaultTheftMonitor::new(MockBitcoin::default(), dry_run, 0, Arc::new(Vaults::default()))
+            .with_archive(archive.clone());
+        monitor.submit_report(dummy_evidence()).await;
+        let evidence = archive.get(&dummy_evidence()).unwrap();
+        assert_eq!(evidence.status, ReportStatus::DryRun);
+        assert_eq!(evidence.attempts, 0);
+        assert!(!evidence.status.is_settled());
+
+        let mut parachain = MockProvider::default();
+        parachain.expect_is_transaction_invalid().returning(|_, _| Ok(true));
+        parachain.expect_report_vault_theft().once().returning(|_, _, _| Ok(()));
+        let monitor = VaultTheftMonitor::new(MockBitcoin::default(), parachain, 0, Arc::new(Vaults::default()))
+            .with_archive(archive.clone());
+        monitor.retry_reports().await;
+        assert_eq!(archive.get(&dummy_evidence()).unwrap().status, ReportStatus::Reported);
+    }
+
+    fn dummy_evidence() -> TheftEvidence {
+        TheftEvidence::new(
+            "txid".to_string(),
+            "block".to_string(),
+            AccountKeyring::Bob.to_account_id(),
+            vec![],
+            vec![],
+        )
+    }
+
+    fn dummy_vault_tx() -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Script::new(),
+                sequence: 0,
+                // p2wpkh: signature and public key
+                witness: vec![vec![1; 71], vec![2; 33]],
+            }],
+            output: vec![],
+        }
+    }
+
+    fn monitor_with_mempool(parachain: MockProvider, tx: Transaction) -> VaultTheftMonitor<MockProvider, MockBitcoin> {
+        let mut bitcoin = MockBitcoin::default();
+        let txid = tx.txid();
+        bitcoin.expect_get_mempool_txids().returning(move || Ok(vec![txid]));
+        let mempool_tx = tx.clone();
+        bitcoin
+            .expect_get_mempool_transaction()
+            .times(1)
+            .returning(move |_| Ok(Some(mempool_tx.clone())));
+
+        let vaults = Vaults::from(
+            tx.extract_input_addresses::<BtcAddress>()
+                .into_iter()
+                .map(|address| (address, AccountKeyring::Bob.to_account_id()))
+                .collect(),
+        );
+        VaultTheftMonitor::new(bitcoin, parachain, 0, Arc::new(vaults))
+    }
+
+    #[tokio::test]
+    async fn test_check_mempool_flags_invalid_transaction_once() {
+        let mut parachain = MockProvider::default();
+        parachain
+            .expect_is_transaction_invalid()
+            .times(1)
+            .returning(|_, _| Ok(true));
+        parachain
+            .expect_report_vault_theft()
+            .never()
+            .returning(|_, _, _| Ok(()));
+
+        let tx = dummy_vault_tx();
+        let monitor = monitor_with_mempool(parachain, tx.clone());
+        monitor.check_mempool().await.unwrap();
+        // already checked, not checked again
+        monitor.check_mempool().await.unwrap();
+
+        assert_eq!(
+            monitor.suspicious.remove(&tx.txid()),
+            Some(vec![AccountKeyring::Bob.to_account_id()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_mempool_ignores_valid_transaction() {
+        let mut parachain = MockProvider::default();
+        parachain.expect_is_transaction_invalid().returning(|_, _| Ok(false));
+
+        let tx = dummy_vault_tx();
+        let monitor = monitor_with_mempool(parachain, tx.clone());
+        monitor.check_mempool().await.unwrap();
+
+        assert_eq!(monitor.suspicious.remove(&tx.txid()), None);
+    }
+
+    #[tokio::test]
+    async fn test_check_mempool_skips_transactions_that_fail() {
+        let mut parachain = MockProvider::default();
+        parachain.expect_is_transaction_invalid().returning(|_, _| Ok(true));
+
+        let tx = dummy_vault_tx();
+        let failing_txid = Txid::default();
+        let mut bitcoin = MockBitcoin::default();
+        let txids = vec![failing_txid, tx.txid()];
+        bitcoin.expect_get_mempool_txids().returning(move || Ok(txids.clone()));
+        let mempool_tx = tx.clone();
+        bitcoin.expect_get_mempool_transaction().returning(move |txid| {
+            if *txid == failing_txid {
+                Err(BitcoinError::ConnectionRefused)
+            } else {
+                Ok(Some(mempool_tx.clone()))
+            }
+        });
+        let vaults = Vaults::from(
+            tx.extract_input_addresses::<BtcAddress>()
+                .into_iter()
+                .map(|address| (address, AccountKeyring::Bob.to_account_id()))
+                .collect(),
+        );
+        let monitor = VaultTheftMonitor::new(bitcoin, parachain, 0, Arc::new(vaults));
+
+        monitor.check_mempool().await.unwrap();
+        assert!(monitor.suspicious.0.lock().unwrap().contains_key(&tx.txid()));
+        // the failed transaction is fetched again in the next round
+        assert!(!monitor.seen.lock().unwrap().contains(&failing_txid));
+    }
+
+    #[tokio::test]
+    async fn test_check_mempool_forgets_transactions_that_left() {
+        let mut parachain = MockProvider::default();
+        parachain.expect_is_transaction_invalid().returning(|_, _| Ok(true));
+
+        let tx = dummy_vault_tx();
+        let mut bitcoin = MockBitcoin::default();
+        let txid = tx.txid();
+        let mut polls = 0;
+        bitcoin.expect_get_mempool_txids().returning(move || {
+            polls += 1;
+            Ok(if polls == 1 { vec![txid] } else { vec![] })
+        });
+        let mempool_tx = tx.clone();
+        bitcoin
+            .expect_get_mempool_transaction()
+            .returning(move |_| Ok(Some(mempool_tx.clone())));
+        let vaults = Vaults::from(
+            tx.extract_input_addresses::<BtcAddress>()
+                .into_iter()
+                .map(|address| (address, AccountKeyring::Bob.to_account_id()))
+                .collect(),
+        );
+        let monitor = VaultTheftMonitor::new(bitcoin, parachain, 0, Arc::new(vaults));
+
+        monitor.check_mempool().await.unwrap();
+        assert!(monitor.suspicious.0.lock().unwrap().contains_key(&tx.txid()));
+        // e.g. replaced by a transaction that pays to the redeemer
+        monitor.check_mempool().await.unwrap();
+        assert!(monitor.suspicious.0.lock().unwrap().is_empty());
     }
 }
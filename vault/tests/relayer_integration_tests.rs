@@ -61,7 +61,14 @@ async fn test_report_vault_theft_succeeds() {
 
     test_service(
         join(
-            vault::service::report_vault_thefts(btc_rpc.clone(), relayer_provider.clone(), 0, vaults.clone()),
+            vault::service::report_vault_thefts(
+                btc_rpc.clone(),
+                relayer_provider.clone(),
+                0,
+                vaults.clone(),
+                Default::default(),
+                Default::default(),
+            ),
             vault::service::listen_for_wallet_updates(relayer_provider.clone(), vaults.clone()),
         ),
         async {